@@ -0,0 +1,181 @@
+use std::ops::Range;
+
+use ropey::Rope;
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+// Partners are looked for at most this many characters away, so a stray bracket in a huge
+// file doesn't scan all of it on every cursor move
+const MAX_SCAN_CHARS: usize = 50_000;
+// Lines parsed before the cursor to tell whether it's in a string or comment. Starting
+// without the state of earlier lines can misjudge long block comments, but stays fast.
+pub const SCOPE_LOOKBACK_LINES: usize = 100;
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+const QUOTES: [char; 3] = ['"', '\'', '`'];
+
+/// The character auto-inserted after typing `c`: the closing bracket, or the same quote.
+pub fn closer_for(c: char) -> Option<char> {
+    if QUOTES.contains(&c) {
+        return Some(c);
+    }
+    BRACKETS.iter().find(|(open, _)| *open == c).map(|&(_, close)| close)
+}
+
+/// Whether typing `opener` between `previous` and `next` (the characters around the cursor
+/// on its line) should also insert the closer. Not before a word, so wrapping existing text
+/// doesn't leave a stray closer, and no quote pair right after a word, as in "don't". A `'`
+/// after `&` or `<` starts a lifetime, as in `&'a` and `<'a>`, so it isn't paired either.
+pub fn should_auto_close(opener: char, previous: Option<char>, next: Option<char>) -> bool {
+    let next_allows = next.is_none_or(|c| {
+        c.is_whitespace() || BRACKETS.iter().any(|&(_, close)| close == c) || matches!(c, ';' | ',' | ':')
+    });
+    if QUOTES.contains(&opener) {
+        let previous_allows = previous.is_none_or(|c| {
+            !c.is_alphanumeric() && c != '\\' && c != opener && !(opener == '\'' && matches!(c, '&' | '<'))
+        });
+        return next_allows && previous_allows;
+    }
+    next_allows
+}
+
+/// Whether the cursor sits between an empty pair such as `()` or `""`, which Backspace
+/// deletes at once.
+pub fn is_empty_pair(previous: Option<char>, next: Option<char>) -> bool {
+    match (previous, next) {
+        (Some(previous), Some(next)) => closer_for(previous) == Some(next),
+        _ => false,
+    }
+}
+
+/// The bracket next to `cursor` (right after it first, then right before it) and its
+/// partner, as character offsets. `None` if neither is a bracket or the partner is missing.
+pub fn matching_bracket(rope: &Rope, cursor: usize) -> Option<(usize, usize)> {
+    matching_bracket_where(rope, cursor, |_| true)
+}
+
+/// `matching_bracket`, counting only the brackets at offsets `counts` accepts, e.g. those
+/// outside strings and comments.
+pub fn matching_bracket_where(rope: &Rope, cursor: usize, counts: impl Fn(usize) -> bool) -> Option<(usize, usize)> {
+    let after = Some(cursor).filter(|&at| at < rope.len_chars());
+    let before = cursor.checked_sub(1);
+    [after, before]
+        .into_iter()
+        .flatten()
+        .filter(|&at| counts(at))
+        .find_map(|at| partner(rope, at, &counts).map(|partner| (at, partner)))
+}
+
+/// Where Ctrl+M moves the cursor: next to the partner of the bracket beside it, on the same
+/// side as it was of the first bracket, so pressing it again jumps back.
+pub fn jump_target(rope: &Rope, cursor: usize) -> Option<usize> {
+    let (bracket, partner) = matching_bracket(rope, cursor)?;
+    Some(if bracket == cursor { partner } else { partner + 1 })
+}
+
+fn partner(rope: &Rope, at: usize, counts: &impl Fn(usize) -> bool) -> Option<usize> {
+    let c = rope.char(at);
+    let mut depth = 0usize;
+    if let Some(&(open, close)) = BRACKETS.iter().find(|(open, _)| *open == c) {
+        for (i, ch) in rope.chars_at(at).enumerate().take(MAX_SCAN_CHARS) {
+            if (ch == open || ch == close) && !counts(at + i) {
+                continue;
+            }
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(at + i);
+                }
+            }
+        }
+    } else if let Some(&(open, close)) = BRACKETS.iter().find(|(_, close)| *close == c) {
+        let mut chars = rope.chars_at(at + 1);
+        for i in (at.saturating_sub(MAX_SCAN_CHARS)..=at).rev() {
+            let ch = chars.prev()?;
+            if (ch == open || ch == close) && !counts(i) {
+                continue;
+            }
+            if ch == close {
+                depth += 1;
+            } else if ch == open {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Whether the end of `text` is inside a string or comment of `syntax`. `text` should run
+/// from the start of a line (a few lines back) up to the cursor.
+pub fn in_string_or_comment(ss: &SyntaxSet, syntax: &SyntaxReference, text: &str) -> bool {
+    // The scopes wanted are those of a character typed at the cursor. A space stands in for
+    // it, so a line comment running to the end still covers it and a just-closed string
+    // doesn't.
+    let (before, last_line) = text.split_at(text.rfind('\n').map_or(0, |i| i + 1));
+    let probe = format!("{} ", last_line);
+    let lines = before.split_inclusive('\n').chain(std::iter::once(probe.as_str()));
+
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        let is_probe = std::ptr::eq(line, probe.as_str());
+        let Ok(ops) = state.parse_line(line, ss) else {
+            return false;
+        };
+        for (offset, op) in ops {
+            if is_probe && offset > last_line.len() {
+                break;
+            }
+            if stack.apply(&op).is_err() {
+                return false;
+            }
+        }
+    }
+    is_string_or_comment(&stack)
+}
+
+/// Character ranges of `text` inside strings and comments of `syntax`. `text` should start
+/// at the start of a line.
+pub fn string_and_comment_ranges(ss: &SyntaxSet, syntax: &SyntaxReference, text: &str) -> Vec<Range<usize>> {
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut ranges = Vec::new();
+    // Start of the string or comment being read
+    let mut open = None;
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        let Ok(ops) = state.parse_line(line, ss) else {
+            break;
+        };
+        for (offset, op) in ops {
+            if stack.apply(&op).is_err() {
+                break;
+            }
+            let at = line_start + line[..offset].chars().count();
+            match (open, is_string_or_comment(&stack)) {
+                (None, true) => open = Some(at),
+                (Some(start), false) => {
+                    ranges.push(start..at);
+                    open = None;
+                }
+                _ => {}
+            }
+        }
+        line_start += line.chars().count();
+    }
+    if let Some(start) = open {
+        ranges.push(start..line_start);
+    }
+    ranges
+}
+
+fn is_string_or_comment(stack: &ScopeStack) -> bool {
+    stack.as_slice().iter().any(|scope| {
+        let name = scope.build_string();
+        name.starts_with("string") || name.starts_with("comment")
+    })
+}
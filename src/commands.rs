@@ -0,0 +1,133 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+// Recently used commands listed first in the palette (and kept in the session)
+pub const MAX_RECENT_COMMANDS: usize = 8;
+
+/// An application action as shown in the command palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    /// Action name without the `app.` prefix
+    pub action: String,
+    /// String parameter for actions that take one, e.g. `convert-indentation`
+    pub target: Option<String>,
+    pub title: String,
+}
+
+impl Command {
+    /// Detailed action name, e.g. `convert-indentation::tabs`; used to remember recent commands.
+    pub fn id(&self) -> String {
+        match &self.target {
+            Some(target) => format!("{}::{}", self.action, target),
+            None => self.action.clone(),
+        }
+    }
+}
+
+/// A command matching the palette query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandMatch {
+    pub command: Command,
+    /// Character indices in the title matched by the query
+    pub indices: Vec<usize>,
+}
+
+/// Titles for the commands in the palette, plus the recently used ones.
+///
+/// Subsystems register titles for their actions here; actions nobody registered still
+/// appear, titled after their name.
+#[derive(Debug, Default)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+    /// Command ids, most recent first
+    recent: Vec<String>,
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, action: &str, title: &str) {
+        self.add(Command { action: action.to_string(), target: None, title: title.to_string() });
+    }
+
+    /// Register one entry of an action taking a string parameter.
+    pub fn register_with_target(&mut self, action: &str, target: &str, title: &str) {
+        self.add(Command { action: action.to_string(), target: Some(target.to_string()), title: title.to_string() });
+    }
+
+    fn add(&mut self, command: Command) {
+        self.commands.retain(|c| c.id() != command.id());
+        self.commands.push(command);
+    }
+
+    /// Registered commands plus one for each of `action_names` that has none, titled after
+    /// the action name.
+    pub fn commands_for(&self, action_names: impl IntoIterator<Item = String>) -> Vec<Command> {
+        let mut commands = self.commands.clone();
+        for action in action_names {
+            if !self.commands.iter().any(|c| c.action == action) {
+                let title = title_from_action_name(&action);
+                commands.push(Command { action, target: None, title });
+            }
+        }
+        commands
+    }
+
+    pub fn record_use(&mut self, id: &str) {
+        self.recent.retain(|r| r != id);
+        self.recent.insert(0, id.to_string());
+        self.recent.truncate(MAX_RECENT_COMMANDS);
+    }
+
+    pub fn recent(&self) -> &[String] {
+        &self.recent
+    }
+
+    pub fn set_recent(&mut self, recent: Vec<String>) {
+        self.recent = recent;
+        self.recent.truncate(MAX_RECENT_COMMANDS);
+    }
+
+    /// Fuzzy-filter `commands` by title. Recently used commands come first when the query
+    /// is empty and win ties otherwise; the rest are alphabetical.
+    pub fn rank(&self, matcher: &SkimMatcherV2, query: &str, commands: &[Command]) -> Vec<CommandMatch> {
+        let query = query.trim();
+        let recency = |command: &Command| {
+            let id = command.id();
+            self.recent.iter().position(|r| *r == id).unwrap_or(usize::MAX)
+        };
+
+        let mut matches: Vec<(i64, CommandMatch)> = commands
+            .iter()
+            .filter_map(|command| {
+                let (score, indices) = if query.is_empty() {
+                    (0, Vec::new())
+                } else {
+                    matcher.fuzzy_indices(&command.title, query)?
+                };
+                Some((score, CommandMatch { command: command.clone(), indices }))
+            })
+            .collect();
+        matches.sort_by(|(score_a, a), (score_b, b)| {
+            score_b
+                .cmp(score_a)
+                .then(recency(&a.command).cmp(&recency(&b.command)))
+                .then_with(|| a.command.title.cmp(&b.command.title))
+        });
+        matches.into_iter().map(|(_, m)| m).collect()
+    }
+}
+
+/// Title for an action nobody registered: `explorer-new-file` becomes "Explorer New File".
+pub fn title_from_action_name(action: &str) -> String {
+    action
+        .split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
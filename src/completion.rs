@@ -0,0 +1,452 @@
+use std::collections::HashSet;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ropey::Rope;
+use serde_json::Value;
+
+use crate::lsp::protocol::{self, Position, PositionEncoding, Range};
+
+/// Most items listed in the popup at once
+pub const MAX_ITEMS: usize = 200;
+/// Most distinct buffer words collected for one completion
+const MAX_WORDS: usize = 10_000;
+
+/// What a completion item is, from LSP's `CompletionItemKind`, plus words found in the open
+/// buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompletionKind {
+    Text,
+    Method,
+    Function,
+    Constructor,
+    Field,
+    Variable,
+    Class,
+    Interface,
+    Module,
+    Property,
+    Unit,
+    Value,
+    Enum,
+    Keyword,
+    Snippet,
+    Color,
+    File,
+    Reference,
+    Folder,
+    EnumMember,
+    Constant,
+    Struct,
+    Event,
+    Operator,
+    TypeParameter,
+    /// A word from an open buffer
+    Word,
+}
+
+impl CompletionKind {
+    pub fn from_lsp(value: Option<u64>) -> Self {
+        const KINDS: [CompletionKind; 25] = [
+            CompletionKind::Text,
+            CompletionKind::Method,
+            CompletionKind::Function,
+            CompletionKind::Constructor,
+            CompletionKind::Field,
+            CompletionKind::Variable,
+            CompletionKind::Class,
+            CompletionKind::Interface,
+            CompletionKind::Module,
+            CompletionKind::Property,
+            CompletionKind::Unit,
+            CompletionKind::Value,
+            CompletionKind::Enum,
+            CompletionKind::Keyword,
+            CompletionKind::Snippet,
+            CompletionKind::Color,
+            CompletionKind::File,
+            CompletionKind::Reference,
+            CompletionKind::Folder,
+            CompletionKind::EnumMember,
+            CompletionKind::Constant,
+            CompletionKind::Struct,
+            CompletionKind::Event,
+            CompletionKind::Operator,
+            CompletionKind::TypeParameter,
+        ];
+        value.and_then(|value| KINDS.get((value as usize).checked_sub(1)?).copied()).unwrap_or(Self::Text)
+    }
+
+    /// Short badge shown before the label.
+    pub fn badge(self) -> &'static str {
+        match self {
+            Self::Text => "abc",
+            Self::Method | Self::Function => "fn",
+            Self::Constructor => "new",
+            Self::Field | Self::Property => "fld",
+            Self::Variable | Self::Reference => "var",
+            Self::Class | Self::Struct => "st",
+            Self::Interface => "tr",
+            Self::Module => "mod",
+            Self::Unit | Self::Value | Self::Color => "val",
+            Self::Enum => "en",
+            Self::EnumMember => "ev",
+            Self::Keyword => "kw",
+            Self::Snippet => "sn",
+            Self::File => "file",
+            Self::Folder => "dir",
+            Self::Constant => "cst",
+            Self::Event => "evt",
+            Self::Operator => "op",
+            Self::TypeParameter => "ty",
+            Self::Word => "w",
+        }
+    }
+
+    /// Group the badge is colored by, as a CSS class.
+    pub fn category(self) -> &'static str {
+        match self {
+            Self::Method | Self::Function | Self::Constructor => "function",
+            Self::Field | Self::Property | Self::Variable | Self::Reference => "variable",
+            Self::Class | Self::Struct | Self::Interface | Self::Enum | Self::TypeParameter => "type",
+            Self::Constant | Self::EnumMember | Self::Unit | Self::Value | Self::Color => "constant",
+            Self::Module | Self::File | Self::Folder => "module",
+            Self::Keyword | Self::Operator => "keyword",
+            Self::Snippet => "snippet",
+            Self::Text | Self::Event | Self::Word => "text",
+        }
+    }
+}
+
+/// A replacement of the chars `start..end` of a document with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Type or signature, shown after the label
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+    /// What the typed text is matched against
+    pub filter_text: String,
+    /// Orders items matching equally well
+    pub sort_text: String,
+    pub insert_text: String,
+    /// Whether `insert_text` is a snippet with tab stops (see `parse_snippet`)
+    pub is_snippet: bool,
+    /// Char offset the inserted text replaces from, up to the cursor; the start of the typed
+    /// word when the server didn't say
+    pub replace_start: Option<usize>,
+    /// Edits elsewhere in the document made along with it, such as an import
+    pub additional_edits: Vec<TextEdit>,
+}
+
+impl CompletionItem {
+    /// A word found in an open buffer, inserted as is.
+    pub fn word(word: &str) -> Self {
+        Self {
+            label: word.to_string(),
+            kind: CompletionKind::Word,
+            detail: None,
+            documentation: None,
+            filter_text: word.to_string(),
+            sort_text: word.to_string(),
+            insert_text: word.to_string(),
+            is_snippet: false,
+            replace_start: None,
+            additional_edits: Vec::new(),
+        }
+    }
+}
+
+/// Whether `c` belongs to the words completed and collected from buffers.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Convert an LSP `CompletionItem`. `text` is the document the server knows, for turning the
+/// positions of its edits into char offsets; without it, the edits are dropped.
+pub fn from_lsp(item: &Value, text: Option<&Rope>, encoding: PositionEncoding) -> Option<CompletionItem> {
+    let label = item["label"].as_str()?.to_string();
+    let to_offset = |position: Position| text.map(|text| protocol::char_at(text, position, encoding));
+
+    // An `InsertReplaceEdit` has both ranges; inserting keeps the text after the cursor
+    let edit = &item["textEdit"];
+    let range = if edit["range"].is_object() { &edit["range"] } else { &edit["insert"] };
+    let replace_start = serde_json::from_value::<Range>(range.clone()).ok().and_then(|range| to_offset(range.start));
+    let insert_text = edit["newText"].as_str().or(item["insertText"].as_str()).unwrap_or(&label).to_string();
+
+    let additional_edits = item["additionalTextEdits"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|edit| {
+            let range: Range = serde_json::from_value(edit["range"].clone()).ok()?;
+            let text = edit["newText"].as_str()?.to_string();
+            Some(TextEdit { start: to_offset(range.start)?, end: to_offset(range.end)?, text })
+        })
+        .collect();
+    // Plain strings or `MarkupContent`
+    let documentation = match &item["documentation"] {
+        Value::String(documentation) => Some(documentation.clone()),
+        documentation => documentation["value"].as_str().map(str::to_string),
+    };
+    let non_empty = |text: Option<String>| text.filter(|text| !text.trim().is_empty());
+
+    Some(CompletionItem {
+        kind: CompletionKind::from_lsp(item["kind"].as_u64()),
+        detail: non_empty(item["detail"].as_str().map(str::to_string)),
+        documentation: non_empty(documentation),
+        filter_text: item["filterText"].as_str().unwrap_or(&label).to_string(),
+        sort_text: item["sortText"].as_str().unwrap_or(&label).to_string(),
+        insert_text,
+        is_snippet: item["insertTextFormat"].as_u64() == Some(2),
+        replace_start,
+        additional_edits,
+        label,
+    })
+}
+
+/// Items of a `textDocument/completion` result, which is a list of items or a
+/// `CompletionList`, and whether the server wants to be asked again as typing goes on.
+pub fn from_lsp_response(result: &Value, text: Option<&Rope>, encoding: PositionEncoding) -> (Vec<CompletionItem>, bool) {
+    let (items, incomplete) = match result {
+        Value::Array(items) => (items.as_slice(), false),
+        list => (list["items"].as_array().map(Vec::as_slice).unwrap_or_default(), list["isIncomplete"] == true),
+    };
+    (items.iter().filter_map(|item| from_lsp(item, text, encoding)).collect(), incomplete)
+}
+
+/// Distinct words of `texts`, sorted: runs of word chars at least two long that don't start
+/// with a digit, leaving out `typed`, the word being completed.
+pub fn buffer_words<'a>(texts: impl IntoIterator<Item = &'a Rope>, typed: &str) -> Vec<String> {
+    let mut words = HashSet::new();
+    let mut word = String::new();
+    'texts: for text in texts {
+        // The trailing space ends the text's last word
+        for c in text.chars().chain(std::iter::once(' ')) {
+            if is_word_char(c) {
+                word.push(c);
+                continue;
+            }
+            let starts_with_digit = word.chars().next().is_some_and(|c| c.is_ascii_digit());
+            if word.chars().nth(1).is_some() && !starts_with_digit && word != typed {
+                words.insert(std::mem::take(&mut word));
+                if words.len() >= MAX_WORDS {
+                    break 'texts;
+                }
+            }
+            word.clear();
+        }
+    }
+    let mut words: Vec<String> = words.into_iter().collect();
+    words.sort();
+    words
+}
+
+/// An item matching the typed text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionMatch {
+    /// Index into the filtered items
+    pub index: usize,
+    pub score: i64,
+    /// Char indices in the label matched by the typed text
+    pub indices: Vec<usize>,
+}
+
+/// Fuzzy-rank `items` against `typed`, best first, at most `MAX_ITEMS`. Ties go to the
+/// server's items before buffer words, then by sort text. Nothing typed yet lists every item.
+pub fn filter(matcher: &SkimMatcherV2, items: &[CompletionItem], typed: &str) -> Vec<CompletionMatch> {
+    let mut matches: Vec<CompletionMatch> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            if typed.is_empty() {
+                return Some(CompletionMatch { index, score: 0, indices: Vec::new() });
+            }
+            let (score, indices) = matcher.fuzzy_indices(&item.filter_text, typed)?;
+            // Bold the label only where it is what was matched
+            let indices = if item.filter_text == item.label { indices } else { Vec::new() };
+            Some(CompletionMatch { index, score, indices })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        let (item_a, item_b) = (&items[a.index], &items[b.index]);
+        b.score
+            .cmp(&a.score)
+            .then((item_a.kind == CompletionKind::Word).cmp(&(item_b.kind == CompletionKind::Word)))
+            .then_with(|| item_a.sort_text.cmp(&item_b.sort_text))
+            .then_with(|| item_a.label.cmp(&item_b.label))
+    });
+    matches.truncate(MAX_ITEMS);
+    matches
+}
+
+/// `text` as inserted on a line indented with `indent`: its later lines get the same
+/// indentation, and its tabs become `unit`, the document's indentation step.
+pub fn adjust_indentation(text: &str, indent: &str, unit: &str) -> String {
+    text.replace('\t', unit).replace('\n', &format!("\n{}", indent))
+}
+
+/// A snippet expanded to plain text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub text: String,
+    /// Char ranges of the tab stops in `text`, in the order Tab visits them; the last one,
+    /// `$0` or the end of the text, is where the cursor is left
+    pub tab_stops: Vec<(usize, usize)>,
+}
+
+/// Expand an LSP snippet: `$1`, `${1}` and `${1:placeholder}` tab stops (placeholders may
+/// nest), `${1|one,two|}` choices as their first option, and variables (`$TM_FILENAME`,
+/// `${NAME:default}`) as their default. A tab stop used twice keeps its first place.
+pub fn parse_snippet(snippet: &str) -> Snippet {
+    let mut parser = SnippetParser { chars: snippet.chars().collect(), pos: 0, text: String::new(), len: 0, stops: Vec::new() };
+    parser.parse_until(None);
+
+    let mut stops = parser.stops;
+    // `$0` goes last, whatever its number says
+    stops.sort_by_key(|&(index, _, _)| if index == 0 { u32::MAX } else { index });
+    if stops.last().map(|&(index, _, _)| index) != Some(0) {
+        stops.push((0, parser.len, parser.len));
+    }
+    Snippet { text: parser.text, tab_stops: stops.into_iter().map(|(_, start, end)| (start, end)).collect() }
+}
+
+struct SnippetParser {
+    chars: Vec<char>,
+    pos: usize,
+    text: String,
+    /// Length of `text` in chars
+    len: usize,
+    /// Tab stop number and char range
+    stops: Vec<(u32, usize, usize)>,
+}
+
+impl SnippetParser {
+    /// Expand up to an unescaped `end`, which is consumed, or to the end of the snippet.
+    fn parse_until(&mut self, end: Option<char>) {
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '\\' if matches!(self.chars.get(self.pos), Some('$' | '}' | '\\')) => {
+                    let escaped = self.chars[self.pos];
+                    self.pos += 1;
+                    self.push(escaped);
+                }
+                '$' => self.parse_dollar(),
+                c if Some(c) == end => return,
+                c => self.push(c),
+            }
+        }
+    }
+
+    /// After a `$`: a tab stop, a variable, or a plain `$`.
+    fn parse_dollar(&mut self) {
+        if let Some(index) = self.number() {
+            self.add_stop(index, self.len, self.len);
+            return;
+        }
+        if self.chars.get(self.pos) != Some(&'{') {
+            // Variables aren't known here; a lone `$` is kept
+            if self.name().is_none() {
+                self.push('$');
+            }
+            return;
+        }
+
+        let brace = self.pos;
+        self.pos += 1;
+        let start = self.len;
+        let index = self.number();
+        if index.is_none() && self.name().is_none() {
+            self.pos = brace;
+            self.push('$');
+            return;
+        }
+        match self.chars.get(self.pos) {
+            Some('}') => self.pos += 1,
+            Some(':') => {
+                self.pos += 1;
+                self.parse_until(Some('}'));
+            }
+            Some('|') if index.is_some() => {
+                self.pos += 1;
+                self.parse_choice();
+            }
+            _ => {
+                // Not a valid placeholder: keep it as typed
+                self.pos = brace;
+                self.push('$');
+                return;
+            }
+        }
+        if let Some(index) = index {
+            self.add_stop(index, start, self.len);
+        }
+    }
+
+    /// The options of a choice after `${1|`, up to and including `|}`; the first one is
+    /// inserted.
+    fn parse_choice(&mut self) {
+        let mut first = true;
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '\\' if matches!(self.chars.get(self.pos), Some(',' | '|' | '\\')) => {
+                    let escaped = self.chars[self.pos];
+                    self.pos += 1;
+                    if first {
+                        self.push(escaped);
+                    }
+                }
+                ',' => first = false,
+                '|' => {
+                    if self.chars.get(self.pos) == Some(&'}') {
+                        self.pos += 1;
+                    }
+                    return;
+                }
+                c if first => self.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let start = self.pos;
+        if !self.chars.get(self.pos).is_some_and(|c| c.is_ascii_alphabetic() || *c == '_') {
+            return None;
+        }
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.len += 1;
+    }
+
+    fn add_stop(&mut self, index: u32, start: usize, end: usize) {
+        if !self.stops.iter().any(|&(existing, _, _)| existing == index) {
+            self.stops.push((index, start, end));
+        }
+    }
+}
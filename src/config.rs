@@ -1,6 +1,9 @@
+use serde::{Deserialize, Serialize};
+
 pub const APP_ID: &str = "org.gtk_rs.Fikby";
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ThemeMode {
     Light,
     Dark,
@@ -14,6 +17,13 @@ impl ThemeMode {
         }
     }
     
+    pub fn toggled(&self) -> ThemeMode {
+        match self {
+            ThemeMode::Light => ThemeMode::Dark,
+            ThemeMode::Dark => ThemeMode::Light,
+        }
+    }
+
     pub fn syntax_theme_name(&self) -> &'static str {
         match self {
             ThemeMode::Light => "base16-ocean.light",
@@ -91,6 +101,49 @@ popover {
     background: #ffffff;
     color: #000000;
 }
+.palette {
+    border: 1px solid #cccccc;
+}
+.palette list {
+    background: #ffffff;
+}
+.find-bar {
+    background: #f3f3f3;
+    border-bottom: 1px solid #cccccc;
+    padding: 4px 8px;
+}
+.find-in-files {
+    padding: 4px 8px;
+}
+.completion-kind {
+    font-family: monospace;
+    font-size: smaller;
+    color: #6a6a6a;
+}
+.completion-kind.function {
+    color: #795e26;
+}
+.completion-kind.variable {
+    color: #001080;
+}
+.completion-kind.type {
+    color: #267f99;
+}
+.completion-kind.constant {
+    color: #0070c1;
+}
+.completion-kind.module {
+    color: #af00db;
+}
+.completion-kind.keyword {
+    color: #0000ff;
+}
+.completion-kind.snippet {
+    color: #a31515;
+}
+.completion-info {
+    color: #444444;
+}
 "#;
 
 // Dark theme CSS
@@ -164,7 +217,56 @@ popover {
     background: #2b2b2b;
     color: #cccccc;
 }
+.palette {
+    border: 1px solid #3e3e3e;
+}
+.palette list {
+    background: #252526;
+}
+.find-bar {
+    background: #252526;
+    border-bottom: 1px solid #3e3e3e;
+    padding: 4px 8px;
+}
+.find-in-files {
+    padding: 4px 8px;
+}
+.completion-kind {
+    font-family: monospace;
+    font-size: smaller;
+    color: #8c8c8c;
+}
+.completion-kind.function {
+    color: #dcdcaa;
+}
+.completion-kind.variable {
+    color: #9cdcfe;
+}
+.completion-kind.type {
+    color: #4ec9b0;
+}
+.completion-kind.constant {
+    color: #4fc1ff;
+}
+.completion-kind.module {
+    color: #c586c0;
+}
+.completion-kind.keyword {
+    color: #569cd6;
+}
+.completion-kind.snippet {
+    color: #ce9178;
+}
+.completion-info {
+    color: #bbbbbb;
+}
 "#;
 
-// Highlighting cutoff to avoid UI stalls on huge files
-pub const HIGHLIGHT_CHAR_CUTOFF: usize = 200_000;
\ No newline at end of file
+// Default for `Settings::large_file_threshold_bytes`. Files at least this large open in
+// large-file mode: fed into the buffer in chunks, highlighted only where visible, and with
+// expensive features switched off
+pub const LARGE_FILE_THRESHOLD_BYTES: u64 = 4 * 1024 * 1024;
+
+// Default for `Settings::large_file_warn_bytes`. Opening a file at least this large asks
+// for confirmation first
+pub const LARGE_FILE_WARN_BYTES: u64 = 64 * 1024 * 1024;
\ No newline at end of file
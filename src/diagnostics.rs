@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ropey::Rope;
+use serde_json::{json, Value};
+
+use crate::lsp::protocol::{self, Position, PositionEncoding, Range};
+
+/// Most severe first, so sorting puts errors on top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// LSP's `DiagnosticSeverity`; a missing one counts as an error.
+    pub fn from_lsp(value: Option<u64>) -> Self {
+        match value {
+            Some(2) => Self::Warning,
+            Some(3) => Self::Information,
+            Some(4) => Self::Hint,
+            _ => Self::Error,
+        }
+    }
+
+    /// LSP's `DiagnosticSeverity` of the severity.
+    pub fn to_lsp(self) -> u64 {
+        match self {
+            Self::Error => 1,
+            Self::Warning => 2,
+            Self::Information => 3,
+            Self::Hint => 4,
+        }
+    }
+
+    /// The `level` of a rustc message, `None` for levels that aren't problems of their own.
+    pub fn from_rustc(level: &str) -> Option<Self> {
+        match level {
+            "error" | "error: internal compiler error" => Some(Self::Error),
+            "warning" => Some(Self::Warning),
+            "note" | "help" => Some(Self::Information),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Information => "info",
+            Self::Hint => "hint",
+        }
+    }
+}
+
+/// A problem in a file. Lines and columns are zero-based, columns counted in chars.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Who reported it, e.g. `rustc` or `clippy`
+    pub source: Option<String>,
+    pub code: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Diagnostic {
+    /// Whether the diagnostic covers char `column` of `line`; an empty range covers the
+    /// char after it.
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        let at = (line, column);
+        let (start, end) = ((self.line, self.column), (self.end_line, self.end_column));
+        if start == end {
+            return at == start;
+        }
+        start <= at && at < end
+    }
+}
+
+/// Convert an LSP `Diagnostic`. `text` is the document the server knows, for turning its
+/// columns into chars; without it, columns are taken as chars.
+pub fn from_lsp(diagnostic: &Value, text: Option<&Rope>, encoding: PositionEncoding) -> Option<Diagnostic> {
+    let range: Range = serde_json::from_value(diagnostic["range"].clone()).ok()?;
+    let to_chars = |position: Position| match text {
+        Some(text) => {
+            let offset = protocol::char_at(text, position, encoding);
+            let line = text.char_to_line(offset);
+            (line, offset - text.line_to_char(line))
+        }
+        None => (position.line as usize, position.character as usize),
+    };
+    let (line, column) = to_chars(range.start);
+    let (end_line, end_column) = to_chars(range.end);
+    let code = match &diagnostic["code"] {
+        Value::String(code) => Some(code.clone()),
+        Value::Number(code) => Some(code.to_string()),
+        _ => None,
+    };
+    Some(Diagnostic {
+        severity: Severity::from_lsp(diagnostic["severity"].as_u64()),
+        message: diagnostic["message"].as_str()?.to_string(),
+        source: diagnostic["source"].as_str().map(str::to_string),
+        code,
+        line,
+        column,
+        end_line,
+        end_column,
+    })
+}
+
+/// `diagnostic` as an LSP `Diagnostic` in `text`, the document the server knows, e.g. for
+/// the context of a code action request.
+pub fn to_lsp(diagnostic: &Diagnostic, text: &Rope, encoding: PositionEncoding) -> Value {
+    let position = |line: usize, column: usize| {
+        let offset = if line < text.len_lines() {
+            let line_chars = text.line(line).len_chars();
+            text.line_to_char(line) + column.min(line_chars)
+        } else {
+            text.len_chars()
+        };
+        protocol::position_at(text, offset, encoding)
+    };
+    let range = Range {
+        start: position(diagnostic.line, diagnostic.column),
+        end: position(diagnostic.end_line, diagnostic.end_column),
+    };
+    let mut lsp = json!({
+        "range": range,
+        "severity": diagnostic.severity.to_lsp(),
+        "message": diagnostic.message,
+    });
+    if let Some(source) = &diagnostic.source {
+        lsp["source"] = Value::String(source.clone());
+    }
+    if let Some(code) = &diagnostic.code {
+        lsp["code"] = Value::String(code.clone());
+    }
+    lsp
+}
+
+/// Parse the output of `cargo check --message-format=json` run in `root`, by file. Each
+/// diagnostic sits on its primary span; its help and notes are appended to the message.
+pub fn parse_cargo_messages(output: &str, root: &Path) -> BTreeMap<PathBuf, Vec<Diagnostic>> {
+    let mut files: BTreeMap<PathBuf, Vec<Diagnostic>> = BTreeMap::new();
+    for line in output.lines() {
+        let Ok(record) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        if record["reason"] != "compiler-message" {
+            continue;
+        }
+        let message = &record["message"];
+        let Some(severity) = message["level"].as_str().and_then(Severity::from_rustc) else {
+            continue;
+        };
+        // Summaries like "aborting due to 2 previous errors" have no spans
+        let Some(span) = message["spans"].as_array().and_then(|spans| spans.iter().find(|span| span["is_primary"] == true)) else {
+            continue;
+        };
+        let (Some(file_name), Some(text)) = (span["file_name"].as_str(), message["message"].as_str()) else {
+            continue;
+        };
+
+        let mut text = text.to_string();
+        for child in message["children"].as_array().into_iter().flatten() {
+            if let (Some(level), Some(child_text)) = (child["level"].as_str(), child["message"].as_str()) {
+                text.push_str(&format!("\n{}: {}", level, child_text));
+            }
+        }
+        let code = message["code"]["code"].as_str().map(str::to_string);
+        let source = if code.as_deref().is_some_and(|code| code.starts_with("clippy::")) { "clippy" } else { "rustc" };
+        // rustc counts one-based lines and chars
+        let position = |key: &str| span[key].as_u64().unwrap_or(1).saturating_sub(1) as usize;
+        let diagnostic = Diagnostic {
+            severity,
+            message: text,
+            source: Some(source.to_string()),
+            code,
+            line: position("line_start"),
+            column: position("column_start"),
+            end_line: position("line_end"),
+            end_column: position("column_end"),
+        };
+
+        // Targets sharing a file (lib and tests) report the same problems twice
+        let diagnostics = files.entry(root.join(file_name)).or_default();
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    files
+}
+
+/// Run `cargo check` in `root` and collect its diagnostics by file; blocks until cargo
+/// finishes. Fails if cargo can't run, or fails without reporting any problem (e.g. when
+/// there's no `Cargo.toml`).
+pub fn cargo_check(root: &Path) -> io::Result<BTreeMap<PathBuf, Vec<Diagnostic>>> {
+    let output = Command::new("cargo")
+        .args(["check", "--workspace", "--all-targets", "--message-format=json"])
+        .current_dir(root)
+        .output()?;
+    let files = parse_cargo_messages(&String::from_utf8_lossy(&output.stdout), root);
+    if !output.status.success() && files.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("cargo check failed");
+        return Err(io::Error::other(reason.trim().to_string()));
+    }
+    Ok(files)
+}
+
+/// Diagnostics of every file, kept apart by origin (a language server, `cargo check`), so an
+/// update from one origin leaves the others' in place.
+#[derive(Debug, Default)]
+pub struct DiagnosticStore {
+    files: BTreeMap<PathBuf, BTreeMap<String, Vec<Diagnostic>>>,
+}
+
+impl DiagnosticStore {
+    /// Replace what `origin` reported for `path`.
+    pub fn set(&mut self, path: &Path, origin: &str, diagnostics: Vec<Diagnostic>) {
+        let by_origin = self.files.entry(path.to_path_buf()).or_default();
+        if diagnostics.is_empty() {
+            by_origin.remove(origin);
+        } else {
+            by_origin.insert(origin.to_string(), diagnostics);
+        }
+        if by_origin.is_empty() {
+            self.files.remove(path);
+        }
+    }
+
+    /// Replace everything `origin` reported with `files`. Returns the paths whose
+    /// diagnostics changed.
+    pub fn replace_origin(&mut self, origin: &str, files: BTreeMap<PathBuf, Vec<Diagnostic>>) -> Vec<PathBuf> {
+        let previous: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|(_, by_origin)| by_origin.contains_key(origin))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut changed = Vec::new();
+        for path in previous {
+            if !files.contains_key(&path) {
+                self.set(&path, origin, Vec::new());
+                changed.push(path);
+            }
+        }
+        for (path, diagnostics) in files {
+            self.set(&path, origin, diagnostics);
+            changed.push(path);
+        }
+        changed
+    }
+
+    /// Diagnostics of `path` from all origins, in order of position, then severity.
+    pub fn for_file(&self, path: &Path) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> =
+            self.files.get(path).into_iter().flat_map(|by_origin| by_origin.values().flatten().cloned()).collect();
+        diagnostics.sort_by_key(|d| (d.line, d.column, d.severity));
+        diagnostics
+    }
+
+    /// Every file with diagnostics, by path.
+    pub fn files(&self) -> Vec<(PathBuf, Vec<Diagnostic>)> {
+        self.files.keys().map(|path| (path.clone(), self.for_file(path))).collect()
+    }
+
+    /// Numbers of errors and warnings across all files.
+    pub fn counts(&self) -> (usize, usize) {
+        let all = self.files.values().flat_map(|by_origin| by_origin.values().flatten());
+        all.fold((0, 0), |(errors, warnings), d| match d.severity {
+            Severity::Error => (errors + 1, warnings),
+            Severity::Warning => (errors, warnings + 1),
+            _ => (errors, warnings),
+        })
+    }
+}
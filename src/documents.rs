@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Key identifying a file however it was reached: symlinks and `..` resolved. Paths that
+/// don't exist (yet, or any more) resolve their parent directory instead.
+pub fn canonical_key(path: &Path) -> PathBuf {
+    if let Ok(canonical) = std::fs::canonicalize(path) {
+        return canonical;
+    }
+    match (path.parent().and_then(|p| std::fs::canonicalize(p).ok()), path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Open documents by canonical path, so a file is only ever open in one tab.
+#[derive(Debug)]
+pub struct DocumentRegistry<T> {
+    documents: HashMap<PathBuf, T>,
+}
+
+impl<T> Default for DocumentRegistry<T> {
+    fn default() -> Self {
+        Self { documents: HashMap::new() }
+    }
+}
+
+impl<T: Clone> DocumentRegistry<T> {
+    pub fn get(&self, path: &Path) -> Option<T> {
+        self.documents.get(&canonical_key(path)).cloned()
+    }
+
+    /// Register `document` as the one showing `path`, replacing any previous one.
+    pub fn insert(&mut self, path: &Path, document: T) {
+        self.documents.insert(canonical_key(path), document);
+    }
+
+    /// Drop every entry for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.documents.retain(|_, document| keep(document));
+    }
+
+    /// Move the documents at `old` or inside the directory `old` to `new`. Returns each moved
+    /// document with its new path. `old` must be canonical already, since it no longer exists.
+    pub fn rename(&mut self, old: &Path, new: &Path) -> Vec<(T, PathBuf)> {
+        let new = canonical_key(new);
+        let moved: Vec<PathBuf> = self.documents.keys().filter(|key| key.starts_with(old)).cloned().collect();
+
+        moved
+            .into_iter()
+            .filter_map(|key| {
+                let document = self.documents.remove(&key)?;
+                let relative = key.strip_prefix(old).unwrap_or(Path::new(""));
+                let path = if relative.as_os_str().is_empty() { new.clone() } else { new.join(relative) };
+                self.documents.insert(path.clone(), document.clone());
+                Some((document, path))
+            })
+            .collect()
+    }
+
+    /// Documents at `path` or inside the directory `path`, e.g. after it was deleted.
+    /// `path` must be canonical.
+    pub fn under(&self, path: &Path) -> Vec<T> {
+        self.documents
+            .iter()
+            .filter(|(key, _)| key.starts_with(path))
+            .map(|(_, document)| document.clone())
+            .collect()
+    }
+}
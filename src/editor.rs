@@ -1,20 +1,49 @@
 use gtk4::prelude::*;
 use gtk4::{Align, Box as GtkBox, Image, Label, ScrolledWindow, TextBuffer, TextView, WrapMode, PolicyType, Button};
-use gtk4::{gdk, EventControllerKey, Inhibit, DrawingArea, Overlay};
-use gtk4::TextTag;
-use glib::clone;
-use std::cell::RefCell;
+use gtk4::{gdk, gio, EventControllerFocus, EventControllerKey, Inhibit, DrawingArea, InfoBar, MessageType, Overlay, ResponseType};
+use gtk4::{EventSequenceState, GestureClick, GestureDrag, PropagationPhase, TextIter, TextWindowType};
+use gtk4::{pango, TextMark, TextTag};
+use gtk4::glib::{self, clone};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::ops::Range;
 use std::path::PathBuf;
-use std::rc::Rc;
-use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use syntect::parsing::SyntaxSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
 use syntect::highlighting::Theme;
 
 use ropey::Rope;
 
-use crate::highlight;
+use crate::brackets;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::external_change::{self, DiskChange};
+use crate::file_format::{self, FileFormat, LineEnding};
+use crate::highlight::{self, LineEdit};
+use crate::indent::{self, IndentStyle};
+use crate::language;
+use crate::lsp::client::Attachment;
+use crate::multi_cursor::{self, Selection};
+use crate::find_in_files::Replacement;
+use crate::folding::{self, FoldRegion};
+use crate::search::SearchMatch;
+use crate::settings::Settings;
+use crate::workspace_edit::Edit;
+
+// Fold regions are recomputed once typing pauses for this long
+const FOLD_REFRESH_DELAY: Duration = Duration::from_millis(300);
+// Brackets are matched again once typing pauses for this long
+const BRACKET_REFRESH_DELAY: Duration = Duration::from_millis(100);
+// Width of the fold triangles' column at the right of the gutter
+const FOLD_MARKER_WIDTH: i32 = 14;
+// Width of the diagnostic markers' column at the left of the gutter
+const DIAGNOSTIC_MARKER_WIDTH: i32 = 10;
+// Pixels the pointer moves before an Alt+click becomes an Alt+drag
+const DRAG_THRESHOLD: f64 = 4.0;
+// Lines at the start and at the end of the file that syntax detection reads
+const DETECTION_LINES: usize = 5;
 
 /// Strongly typed TabId (newtype)
 #[allow(dead_code)]
@@ -28,6 +57,22 @@ pub enum FileState {
     Saved(PathBuf),
 }
 
+/// What the status bar shows for an editor.
+#[derive(Debug, Clone)]
+pub struct EditorStatus {
+    /// Zero-based line and column of the cursor
+    pub line: i32,
+    pub column: i32,
+    pub language: String,
+    /// Picked from the language menu rather than detected
+    pub language_overridden: bool,
+    pub large_file: bool,
+    pub indent: IndentStyle,
+    pub format: FileFormat,
+    /// Path and size of the file
+    pub info: String,
+}
+
 /// Editor encapsulates a text editor view, line number drawing area, buffers and tag cache.
 #[allow(dead_code)]
 pub struct Editor {
@@ -38,20 +83,356 @@ pub struct Editor {
     pub header: GtkBox,
     pub tab_label: Label,
     pub close_button: Button,
+    /// Shown above the text when the file changed on disk while the buffer had edits
+    pub info_bar: InfoBar,
+    info_label: Label,
+    reload_button: Button,
+    diff_button: Button,
     pub current_file: Rc<RefCell<Option<PathBuf>>>,
     pub dirty: Rc<RefCell<bool>>,
     pub tag_cache: Rc<RefCell<HashMap<String, TextTag>>>,
-    ss: Rc<SyntaxSet>,
-    theme: Rc<RefCell<Rc<Theme>>>,
+    /// Syntax name picked from the language menu; `None` means auto-detect.
+    pub syntax_override: Rc<RefCell<Option<String>>>,
+    /// Large-file mode: chunked loading, viewport-only highlighting, no word wrap.
+    pub large_file: Rc<RefCell<bool>>,
+    /// Indentation used by Tab, Enter and auto-dedent, resolved by `refresh_indent`.
+    pub indent: Rc<Cell<IndentStyle>>,
+    /// Encoding and line ending the file is saved with; the buffer always uses `\n`
+    pub format: Rc<Cell<FileFormat>>,
+    settings: Rc<RefCell<Settings>>,
+    file_monitor: Rc<RefCell<Option<gio::FileMonitor>>>,
+    /// Modification time of the file when it was last loaded or saved
+    disk_mtime: Rc<Cell<Option<SystemTime>>>,
+    ss: Arc<SyntaxSet>,
+    theme: Rc<RefCell<Arc<Theme>>>,
+    rope: Rc<RefCell<Rope>>,
+    highlighter: Rc<highlight::Highlighter>,
+    /// Syntax the highlighter was last reset with, to notice when detection changes.
+    highlighted_syntax: Rc<RefCell<String>>,
+    /// Index in `ss` of the syntax `syntax()` resolves to; cleared when the path, the
+    /// override or the lines detection reads change
+    detected_syntax: Rc<Cell<Option<usize>>>,
+    /// Foldable regions and folded ones; folds hide text in the buffer, so in every view
+    folding: Rc<Folding>,
+    /// Bracket pair highlighted at the cursor
+    brackets: Rc<BracketHighlight>,
+    /// Language server the document is open in, told about every edit
+    lsp: Rc<RefCell<Option<Attachment>>>,
+    /// Problems reported in the document, underlined in the text and marked in the gutter
+    diagnostics: Rc<RefCell<Vec<MarkedDiagnostic>>>,
+    /// Every view of this document (see `split_view`), including this one
+    views: Rc<RefCell<Vec<Weak<Editor>>>>,
+    /// View that last had the keyboard focus; only it follows the cursor after edits
+    active_view: Rc<RefCell<Weak<Editor>>>,
+    /// This view's cursor and selection while another view of the buffer has the focus
+    view_insert: TextMark,
+    view_bound: TextMark,
+    /// This view's handlers on the shared buffer, disconnected when the view closes
+    buffer_handlers: RefCell<Vec<glib::SignalHandlerId>>,
+    /// Carets besides the buffer's own cursor, added with Alt+click, Ctrl+D or Alt+drag
+    extra_carets: RefCell<Vec<Caret>>,
+    /// Draws the extra carets and their selections over the text
+    carets_area: DrawingArea,
+    /// Set while edits run at the carets or undo/redo replays history, so the text they insert
+    /// isn't repeated at the extra carets
+    editing_carets: Cell<bool>,
+    /// Line whose gutter shows a lightbulb: code actions are available at this view's cursor
+    lightbulb: Rc<Cell<Option<i32>>>,
+    /// Closers inserted along with their opener, which typing that closer steps over. Marks
+    /// sit right before them and are dropped once the cursor leaves their line.
+    auto_closed: RefCell<Vec<TextMark>>,
+}
+
+/// An extra caret, with the selection it extends (`insert` and `bound` coincide when empty).
+#[derive(Clone)]
+struct Caret {
+    insert: TextMark,
+    bound: TextMark,
+}
+
+/// A diagnostic shown in the buffer, with marks keeping its range in place through edits.
+struct MarkedDiagnostic {
+    diagnostic: Diagnostic,
+    start: TextMark,
+    end: TextMark,
+}
+
+/// A folded region: the hidden text runs from the start of the line after the fold's first
+/// line up to `end`, the start of the line after its last.
+struct Fold {
+    start: TextMark,
+    end: TextMark,
+}
+
+#[derive(Default)]
+struct Folding {
+    /// Foldable regions, recomputed in the background shortly after edits
+    regions: RefCell<Vec<FoldRegion>>,
+    folds: RefCell<Vec<Fold>>,
+    /// Pending recomputation of `regions`
+    refresh: RefCell<Option<glib::SourceId>>,
+    /// Bumped on every edit; `regions` are up to date while `computed` equals it
+    generation: Cell<u64>,
+    computed: Cell<u64>,
+}
+
+#[derive(Default)]
+struct BracketHighlight {
+    /// Marks around the highlighted bracket and its partner
+    tagged: RefCell<Vec<(TextMark, TextMark)>>,
+    /// Strings and comments last found near the cursor; dropped on every edit
+    scopes: RefCell<Option<ParsedScopes>>,
+    /// Pending highlight after an edit
+    refresh: RefCell<Option<glib::SourceId>>,
+}
+
+/// Lines parsed for strings and comments, and the char ranges of those in them, counted from
+/// the first line's start.
+struct ParsedScopes {
+    lines: Range<usize>,
+    skipped: Vec<Range<usize>>,
+}
+
+/// State shared by all views of one document: the buffer and everything derived from the
+/// file. Each `Editor` holds clones of these next to its own widgets.
+struct Document {
+    buffer: TextBuffer,
+    current_file: Rc<RefCell<Option<PathBuf>>>,
+    dirty: Rc<RefCell<bool>>,
+    tag_cache: Rc<RefCell<HashMap<String, TextTag>>>,
+    syntax_override: Rc<RefCell<Option<String>>>,
+    large_file: Rc<RefCell<bool>>,
+    indent: Rc<Cell<IndentStyle>>,
+    format: Rc<Cell<FileFormat>>,
+    settings: Rc<RefCell<Settings>>,
+    file_monitor: Rc<RefCell<Option<gio::FileMonitor>>>,
+    disk_mtime: Rc<Cell<Option<SystemTime>>>,
+    ss: Arc<SyntaxSet>,
+    theme: Rc<RefCell<Arc<Theme>>>,
     rope: Rc<RefCell<Rope>>,
-    highlight_gen: Arc<AtomicU64>,
-    highlight_sender: glib::Sender<(u64, String)>,
+    highlighter: Rc<highlight::Highlighter>,
+    highlighted_syntax: Rc<RefCell<String>>,
+    detected_syntax: Rc<Cell<Option<usize>>>,
+    folding: Rc<Folding>,
+    brackets: Rc<BracketHighlight>,
+    lsp: Rc<RefCell<Option<Attachment>>>,
+    diagnostics: Rc<RefCell<Vec<MarkedDiagnostic>>>,
+    views: Rc<RefCell<Vec<Weak<Editor>>>>,
+    active_view: Rc<RefCell<Weak<Editor>>>,
 }
 
 impl Editor {
-    pub fn new(title: &str, initial_text: Option<String>, path: Option<PathBuf>, ss: Rc<SyntaxSet>, theme: Rc<Theme>) -> Rc<Self> {
+    pub fn new(title: &str, initial_text: Option<String>, path: Option<PathBuf>, ss: Arc<SyntaxSet>, theme: Arc<Theme>) -> Rc<Self> {
+        let main_buffer = TextBuffer::new(None);
+
+        // Enable undo/redo
+        main_buffer.set_enable_undo(true);
+
+        if let Some(t) = initial_text.as_ref() {
+            main_buffer.set_text(t);
+        }
+
+        let tag_cache = Rc::new(RefCell::new(HashMap::new()));
+        let rope = Rc::new(RefCell::new(match &initial_text {
+            Some(s) => Rope::from_str(s.as_str()),
+            None => Rope::from_str(""),
+        }));
+        let highlighter = Rc::new(highlight::Highlighter::new(ss.clone(), &main_buffer, tag_cache.clone()));
+
+        let editor = Self::with_document(title, Document {
+            buffer: main_buffer,
+            current_file: Rc::new(RefCell::new(path)),
+            dirty: Rc::new(RefCell::new(false)),
+            tag_cache,
+            syntax_override: Rc::new(RefCell::new(None)),
+            large_file: Rc::new(RefCell::new(false)),
+            indent: Rc::new(Cell::new(IndentStyle::spaces(4))),
+            format: Rc::new(Cell::new(FileFormat::default())),
+            settings: Rc::new(RefCell::new(Settings::default())),
+            file_monitor: Rc::new(RefCell::new(None)),
+            disk_mtime: Rc::new(Cell::new(None)),
+            ss,
+            theme: Rc::new(RefCell::new(theme)),
+            rope,
+            highlighter,
+            highlighted_syntax: Rc::new(RefCell::new(String::new())),
+            detected_syntax: Rc::new(Cell::new(None)),
+            folding: Rc::new(Folding::default()),
+            brackets: Rc::new(BracketHighlight::default()),
+            lsp: Rc::new(RefCell::new(None)),
+            diagnostics: Rc::new(RefCell::new(Vec::new())),
+            views: Rc::new(RefCell::new(Vec::new())),
+            active_view: Rc::new(RefCell::new(Weak::new())),
+        });
+        *editor.active_view.borrow_mut() = Rc::downgrade(&editor);
+
+        // Keep the rope and the language server in sync edit by edit and forward line-level
+        // edits to the highlighter. These handlers run before the default one, so the iters
+        // still describe the old text.
+        {
+            let rope_cl = editor.rope.clone();
+            let highlighter_cl = editor.highlighter.clone();
+            let lsp_cl = editor.lsp.clone();
+            let detected_syntax = editor.detected_syntax.clone();
+
+            editor.main_buffer.connect_insert_text(move |_, iter, text| {
+                let offset = iter.offset() as usize;
+                if let Some(lsp) = lsp_cl.borrow().as_ref() {
+                    lsp.client.did_insert(&lsp.uri, offset, text);
+                }
+                let mut rope = rope_cl.borrow_mut();
+                let start_line = rope.char_to_line(offset);
+                if touches_detection_lines(&rope, start_line, start_line) {
+                    detected_syntax.set(None);
+                }
+                rope.insert(offset, text);
+                let end_line = rope.char_to_line(offset + text.chars().count());
+
+                highlighter_cl.edit(rope.clone(), LineEdit {
+                    start_line,
+                    removed_lines: 0,
+                    added_lines: end_line - start_line,
+                });
+            });
+        }
+
+        {
+            let rope_cl = editor.rope.clone();
+            let highlighter_cl = editor.highlighter.clone();
+            let lsp_cl = editor.lsp.clone();
+            let detected_syntax = editor.detected_syntax.clone();
+
+            editor.main_buffer.connect_delete_range(move |_, start, end| {
+                let (start, end) = (start.offset() as usize, end.offset() as usize);
+                if let Some(lsp) = lsp_cl.borrow().as_ref() {
+                    lsp.client.did_delete(&lsp.uri, start, end);
+                }
+                let mut rope = rope_cl.borrow_mut();
+                let start_line = rope.char_to_line(start);
+                let end_line = rope.char_to_line(end);
+                if touches_detection_lines(&rope, start_line, end_line) {
+                    detected_syntax.set(None);
+                }
+                rope.remove(start..end);
+
+                highlighter_cl.edit(rope.clone(), LineEdit {
+                    start_line,
+                    removed_lines: end_line - start_line,
+                    added_lines: 0,
+                });
+            });
+        }
+
+        // Highlight the bracket at the cursor and its partner. Edits move the cursor without
+        // a mark-set signal, so they refresh it too, once typing pauses.
+        {
+            let active_view = editor.active_view.clone();
+            editor.main_buffer.connect_mark_set(move |buffer, _, mark| {
+                let editor = active_view.borrow().upgrade();
+                if let Some(editor) = editor.filter(|_| *mark == buffer.get_insert()) {
+                    // A pending refresh looks at the cursor where it ends up
+                    if editor.brackets.refresh.borrow().is_none() {
+                        editor.highlight_brackets(buffer);
+                    }
+                }
+            });
+            let brackets = editor.brackets.clone();
+            let active_view = editor.active_view.clone();
+            editor.main_buffer.connect_changed(move |buffer| {
+                brackets.scopes.take();
+                if let Some(source) = brackets.refresh.take() {
+                    source.remove();
+                }
+                let (buffer, brackets_cl, active_view) = (buffer.clone(), brackets.clone(), active_view.clone());
+                let source = glib::timeout_add_local_once(BRACKET_REFRESH_DELAY, move || {
+                    brackets_cl.refresh.take();
+                    let editor = active_view.borrow().upgrade();
+                    if let Some(editor) = editor {
+                        editor.highlight_brackets(&buffer);
+                    }
+                });
+                *brackets.refresh.borrow_mut() = Some(source);
+            });
+        }
+
+        // Folds follow the text; fold regions are recomputed once typing pauses, and a
+        // cursor moved into folded text (by Go to Line or Find) unfolds it
+        {
+            let views = editor.views.clone();
+            editor.main_buffer.connect_changed(move |_| {
+                if let Some(view) = views.borrow().iter().find_map(Weak::upgrade) {
+                    view.check_folds();
+                    view.schedule_fold_refresh();
+                }
+            });
+            let views = editor.views.clone();
+            editor.main_buffer.connect_mark_set(move |buffer, _, mark| {
+                if *mark == buffer.get_insert() {
+                    if let Some(view) = views.borrow().iter().find_map(Weak::upgrade) {
+                        view.unfold_at_cursor_position();
+                    }
+                }
+            });
+        }
+
+        editor.rehighlight();
+        editor.watch_file();
+
+        editor
+    }
+
+    /// Another view of this editor's document, for showing it in a second split pane. Edits,
+    /// saving and highlighting are shared; cursor, scroll position and gutter are its own.
+    pub fn split_view(&self) -> Rc<Self> {
+        // Remember where this view's cursor is, so focusing it again goes back there
+        self.save_cursor();
+
+        let view = Self::with_document(&self.display_name(), self.document());
+        view.main_view.set_wrap_mode(self.main_view.wrap_mode());
+        view.main_view.set_editable(self.main_view.is_editable());
+        view.set_tab_stops(self.indent.get());
+        view.refresh_tab_label();
+
+        let buffer = &self.main_buffer;
+        buffer.move_mark(&view.view_insert, &buffer.iter_at_mark(&self.view_insert));
+        buffer.move_mark(&view.view_bound, &buffer.iter_at_mark(&self.view_bound));
+        view
+    }
+
+    fn document(&self) -> Document {
+        Document {
+            buffer: self.main_buffer.clone(),
+            current_file: self.current_file.clone(),
+            dirty: self.dirty.clone(),
+            tag_cache: self.tag_cache.clone(),
+            syntax_override: self.syntax_override.clone(),
+            large_file: self.large_file.clone(),
+            indent: self.indent.clone(),
+            format: self.format.clone(),
+            settings: self.settings.clone(),
+            file_monitor: self.file_monitor.clone(),
+            disk_mtime: self.disk_mtime.clone(),
+            ss: self.ss.clone(),
+            theme: self.theme.clone(),
+            rope: self.rope.clone(),
+            highlighter: self.highlighter.clone(),
+            highlighted_syntax: self.highlighted_syntax.clone(),
+            detected_syntax: self.detected_syntax.clone(),
+            folding: self.folding.clone(),
+            brackets: self.brackets.clone(),
+            lsp: self.lsp.clone(),
+            diagnostics: self.diagnostics.clone(),
+            views: self.views.clone(),
+            active_view: self.active_view.clone(),
+        }
+    }
+
+    /// Build the widgets of one view of `document`: text view, gutter, info bar and tab header.
+    fn with_document(title: &str, document: Document) -> Rc<Self> {
+        let path = document.current_file.borrow().clone();
+
         // main TextView
-        let main_view = TextView::new();
+        let main_view = TextView::with_buffer(&document.buffer);
         main_view.set_wrap_mode(WrapMode::None);
         main_view.set_hexpand(true);
         main_view.set_vexpand(true);
@@ -64,17 +445,14 @@ impl Editor {
         main_view.set_pixels_inside_wrap(0);
         main_view.set_top_margin(0);
         main_view.set_bottom_margin(0);
-        main_view.set_left_margin(60);  // Leave space for line numbers
+        main_view.set_left_margin(60 + FOLD_MARKER_WIDTH + DIAGNOSTIC_MARKER_WIDTH);  // Leave space for line numbers, fold triangles and diagnostic markers
         main_view.set_right_margin(4);
         
-        let main_buffer = main_view.buffer();
-        
-        // Enable undo/redo
-        main_buffer.set_enable_undo(true);
+        let main_buffer = document.buffer.clone();
 
         // Create DrawingArea for line numbers - this is the robust approach
         let line_numbers = DrawingArea::new();
-        line_numbers.set_width_request(55);  // Fixed width for line numbers
+        line_numbers.set_width_request(55 + FOLD_MARKER_WIDTH + DIAGNOSTIC_MARKER_WIDTH);  // Fixed width for line numbers
         line_numbers.set_vexpand(true);
         line_numbers.set_valign(Align::Fill);
         line_numbers.style_context().add_class("gutter");
@@ -95,7 +473,28 @@ impl Editor {
         line_numbers.set_halign(Align::Start);
         line_numbers.set_valign(Align::Fill);
 
-        let content_row = GtkBox::new(gtk4::Orientation::Horizontal, 0);
+        // Extra carets are drawn on top of the text; clicks go through to it
+        let carets_area = DrawingArea::new();
+        carets_area.set_can_target(false);
+        carets_area.set_hexpand(true);
+        carets_area.set_vexpand(true);
+        overlay.add_overlay(&carets_area);
+
+        // Warning about changes made on disk by other programs, hidden until needed
+        let info_bar = InfoBar::new();
+        info_bar.set_message_type(MessageType::Warning);
+        info_bar.set_show_close_button(false);
+        info_bar.set_revealed(false);
+        let info_label = Label::new(None);
+        info_label.set_hexpand(true);
+        info_label.set_xalign(0.0);
+        info_bar.add_child(&info_label);
+        let reload_button = info_bar.add_button("Reload", ResponseType::Accept);
+        info_bar.add_button("Keep Mine", ResponseType::Reject);
+        let diff_button = info_bar.add_button("Diff", ResponseType::Apply);
+
+        let content_row = GtkBox::new(gtk4::Orientation::Vertical, 0);
+        content_row.append(&info_bar);
         content_row.append(&overlay);
         content_row.set_hexpand(true);
         content_row.set_vexpand(true);
@@ -152,21 +551,8 @@ impl Editor {
         // This prevents GTK warning: "GtkGizmo (tabs) reported min height -3"
         header.set_height_request(28);
 
-        if let Some(t) = initial_text.clone() {
-            main_buffer.set_text(&t);
-        }
-
-        let current_file = Rc::new(RefCell::new(path.clone()));
-        let dirty = Rc::new(RefCell::new(false));
-        let tag_cache = Rc::new(RefCell::new(HashMap::new()));
-
-        let rope = Rc::new(RefCell::new(match &initial_text {
-            Some(s) => Rope::from_str(s.as_str()),
-            None => Rope::from_str(""),
-        }));
-
-        let highlight_gen = Arc::new(AtomicU64::new(0));
-        let (tx, rx) = glib::MainContext::channel::<(u64, String)>(glib::Priority::default());
+        let current_file = document.current_file.clone();
+        let dirty = document.dirty.clone();
 
         let editor = Rc::new(Self {
             main_view: main_view.clone(),
@@ -176,24 +562,58 @@ impl Editor {
             header,
             tab_label: tab_label.clone(),
             close_button: close_btn.clone(),
+            info_bar: info_bar.clone(),
+            info_label,
+            reload_button,
+            diff_button,
             current_file: current_file.clone(),
             dirty: dirty.clone(),
-            tag_cache,
-            ss: ss.clone(),
-            theme: Rc::new(RefCell::new(theme.clone())),
-            rope: rope.clone(),
-            highlight_gen: highlight_gen.clone(),
-            highlight_sender: tx.clone(),
+            tag_cache: document.tag_cache,
+            syntax_override: document.syntax_override,
+            large_file: document.large_file,
+            indent: document.indent,
+            format: document.format,
+            settings: document.settings,
+            file_monitor: document.file_monitor,
+            disk_mtime: document.disk_mtime,
+            ss: document.ss,
+            theme: document.theme,
+            rope: document.rope,
+            highlighter: document.highlighter,
+            highlighted_syntax: document.highlighted_syntax,
+            detected_syntax: document.detected_syntax,
+            folding: document.folding,
+            brackets: document.brackets,
+            lsp: document.lsp,
+            diagnostics: document.diagnostics,
+            views: document.views,
+            active_view: document.active_view,
+            view_insert: main_buffer.create_mark(None, &main_buffer.start_iter(), false),
+            view_bound: main_buffer.create_mark(None, &main_buffer.start_iter(), false),
+            buffer_handlers: RefCell::new(Vec::new()),
+            extra_carets: RefCell::new(Vec::new()),
+            carets_area: carets_area.clone(),
+            editing_carets: Cell::new(false),
+            auto_closed: RefCell::new(Vec::new()),
+            lightbulb: Rc::new(Cell::new(None)),
         });
+        editor.views.borrow_mut().push(Rc::downgrade(&editor));
 
-        // Set up keyboard event controller for Tab, Enter, and auto-dedent handling
+        // Set up keyboard event controller for Tab, Enter, and auto-dedent handling. With
+        // several carets, these and plain typing apply at each of them as one undo step.
         {
             let key_controller = EventControllerKey::new();
-            let buffer_clone = editor.main_buffer.clone();
+            let editor_weak = Rc::downgrade(&editor);
             
             key_controller.connect_key_pressed(move |_, keyval, _keycode, modifier| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return Inhibit(false);
+                };
                 let shift_pressed = modifier.contains(gdk::ModifierType::SHIFT_MASK);
                 let ctrl_pressed = modifier.contains(gdk::ModifierType::CONTROL_MASK);
+                let alt_pressed = modifier.contains(gdk::ModifierType::ALT_MASK);
+                let indent = editor.indent.get();
+                let multi = editor.has_extra_carets();
                 
                 // Don't interfere with Ctrl shortcuts
                 if ctrl_pressed {
@@ -204,31 +624,66 @@ impl Editor {
                     gdk::Key::Tab => {
                         if shift_pressed {
                             // Shift+Tab: Decrease indent
-                            Self::decrease_indent(&buffer_clone);
+                            editor.edit_at_carets(|buffer| Self::decrease_indent(buffer, indent));
                         } else {
                             // Tab: Increase indent
-                            Self::increase_indent(&buffer_clone);
+                            editor.edit_at_carets(|buffer| Self::increase_indent(buffer, indent));
                         }
                         Inhibit(true)
                     }
                     gdk::Key::Return | gdk::Key::KP_Enter => {
                         // Auto-indent on Enter
-                        Self::auto_indent_newline(&buffer_clone);
+                        editor.edit_at_carets(|buffer| Self::auto_indent_newline(buffer, indent));
+                        Inhibit(true)
+                    }
+                    gdk::Key::parenleft | gdk::Key::bracketleft | gdk::Key::braceleft | gdk::Key::quotedbl
+                    | gdk::Key::apostrophe | gdk::Key::grave
+                        if !alt_pressed =>
+                    {
+                        // Opening brackets and quotes - auto-close
+                        let Some(opener) = keyval.to_unicode() else {
+                            return Inhibit(false);
+                        };
+                        editor.edit_at_carets(|buffer| editor.type_opener(buffer, opener));
                         Inhibit(true)
                     }
                     gdk::Key::braceright => {
                         // } - auto-dedent
-                        Self::handle_closing_bracket(&buffer_clone, '}');
+                        editor.edit_at_carets(|buffer| editor.type_closer(buffer, '}', indent));
                         Inhibit(true)
                     }
                     gdk::Key::bracketright => {
                         // ] - auto-dedent
-                        Self::handle_closing_bracket(&buffer_clone, ']');
+                        editor.edit_at_carets(|buffer| editor.type_closer(buffer, ']', indent));
                         Inhibit(true)
                     }
                     gdk::Key::parenright => {
                         // ) - auto-dedent
-                        Self::handle_closing_bracket(&buffer_clone, ')');
+                        editor.edit_at_carets(|buffer| editor.type_closer(buffer, ')', indent));
+                        Inhibit(true)
+                    }
+                    gdk::Key::Escape if multi => {
+                        editor.clear_extra_carets();
+                        Inhibit(true)
+                    }
+                    gdk::Key::BackSpace if multi || editor.at_empty_pair(&editor.main_buffer) => {
+                        editor.edit_at_carets(|buffer| {
+                            if editor.at_empty_pair(buffer) {
+                                Self::delete_pair(buffer);
+                            } else {
+                                Self::delete_at_cursor(buffer, false);
+                            }
+                        });
+                        Inhibit(true)
+                    }
+                    gdk::Key::Delete | gdk::Key::KP_Delete if multi => {
+                        editor.edit_at_carets(|buffer| Self::delete_at_cursor(buffer, true));
+                        Inhibit(true)
+                    }
+                    gdk::Key::Left | gdk::Key::Right | gdk::Key::Up | gdk::Key::Down | gdk::Key::Home | gdk::Key::End
+                        if multi =>
+                    {
+                        editor.edit_at_carets(|buffer| Self::move_cursor(buffer, keyval, shift_pressed));
                         Inhibit(true)
                     }
                     _ => Inhibit(false)
@@ -238,74 +693,82 @@ impl Editor {
             editor.main_view.add_controller(key_controller);
         }
 
-        // Attach receiver for highlighting
+        // A plain click goes back to a single caret (Ctrl+click is go to definition, handled
+        // by the workspace)
         {
-            let buffer_cl = editor.main_buffer.clone();
-            let tag_cache_cl = editor.tag_cache.clone();
-            let ss_cl = ss.clone();
-            let editor_cl = editor.clone();
-            let gen_cl = highlight_gen.clone();
-
-            rx.attach(None, move |(job_gen, text)| {
-                let cur = gen_cl.load(Ordering::Relaxed);
-                if job_gen != cur {
-                    return glib::Continue(false);
-                }
-                let current_theme = editor_cl.get_theme();
-                highlight::highlight_with_syntect(&buffer_cl, &text, &*tag_cache_cl, &ss_cl, &current_theme);
-                glib::Continue(false)
+            let click = GestureClick::new();
+            click.set_button(gdk::BUTTON_PRIMARY);
+            click.set_propagation_phase(PropagationPhase::Capture);
+            let editor_weak = Rc::downgrade(&editor);
+            click.connect_pressed(move |gesture, _n_press, _x, _y| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return;
+                };
+                if !gesture.current_event_state().contains(gdk::ModifierType::ALT_MASK) {
+                    editor.clear_extra_carets();
+                }
             });
+            editor.main_view.add_controller(click);
         }
 
-        // Buffer change handling
+        // Alt+drag selects a column: the same horizontal range on every line dragged over.
+        // Alt+click without dragging adds a caret (or removes one).
         {
-            let sender = editor.highlight_sender.clone();
-            let gen = editor.highlight_gen.clone();
-            let rope_inner = editor.rope.clone();
-            let buffer_cl = editor.main_buffer.clone();
-            let dirty_cl = editor.dirty.clone();
-
-            const HIGHLIGHT_MAX_CHARS: usize = 200_000;
-
-            editor.main_buffer.connect_changed(move |_| {
-                *dirty_cl.borrow_mut() = true;
-                let job_gen = gen.fetch_add(1, Ordering::Relaxed) + 1;
-
-                let rope_ref = rope_inner.clone();
-                glib::idle_add_local(clone!(@strong buffer_cl, @strong sender => @default-return glib::Continue(false), move || {
-                    let s_iter = buffer_cl.start_iter();
-                    let e_iter = buffer_cl.end_iter();
-                    let text = buffer_cl.text(&s_iter, &e_iter, false);
-
-                    {
-                        let mut r = rope_ref.borrow_mut();
-                        let len = r.len_chars();
-                        if len > 0 {
-                            r.remove(0..len);
-                        }
-                        r.insert(0, &text);
+            let drag = GestureDrag::new();
+            drag.set_propagation_phase(PropagationPhase::Capture);
+            let column_drag = Rc::new(Cell::new(false));
+            // Set once the pointer moved far enough for a column selection
+            let moved = Rc::new(Cell::new(false));
+            let editor_weak = Rc::downgrade(&editor);
+            {
+                let column_drag = column_drag.clone();
+                let moved = moved.clone();
+                drag.connect_drag_begin(move |gesture, _x, _y| {
+                    let is_column = gesture.current_event_state().contains(gdk::ModifierType::ALT_MASK);
+                    column_drag.set(is_column);
+                    moved.set(false);
+                    if !is_column {
+                        gesture.set_state(EventSequenceState::Denied);
+                        return;
                     }
-
-                    if text.chars().count() > HIGHLIGHT_MAX_CHARS {
-                        return glib::Continue(false);
+                    gesture.set_state(EventSequenceState::Claimed);
+                });
+            }
+            {
+                let column_drag = column_drag.clone();
+                let moved = moved.clone();
+                let editor_weak = editor_weak.clone();
+                drag.connect_drag_update(move |gesture, dx, dy| {
+                    if !column_drag.get() || (!moved.get() && dx.hypot(dy) < DRAG_THRESHOLD) {
+                        return;
                     }
-
-                    let sender_for_thread = sender.clone();
-                    let text_owned = text.to_string();
-                    std::thread::spawn(move || {
-                        let _ = sender_for_thread.send((job_gen, text_owned));
-                    });
-
-                    glib::Continue(false)
-                }));
+                    moved.set(true);
+                    if let (Some(editor), Some((x, y))) = (editor_weak.upgrade(), gesture.start_point()) {
+                        editor.select_column((x, y), (x + dx, y + dy));
+                    }
+                });
+            }
+            drag.connect_drag_end(move |gesture, _dx, _dy| {
+                if !column_drag.get() || moved.get() {
+                    return;
+                }
+                let Some((editor, (x, y))) = editor_weak.upgrade().zip(gesture.start_point()) else {
+                    return;
+                };
+                if let Some(iter) = editor.iter_at_widget_coords(x, y) {
+                    editor.toggle_caret(&iter);
+                }
             });
+            editor.main_view.add_controller(drag);
         }
 
-        // Mark dirty
         // Setup draw function for line numbers DrawingArea
         {
             let buffer_clone = main_buffer.clone();
             let view_clone = main_view.clone();
+            let folding = editor.folding.clone();
+            let diagnostics = editor.diagnostics.clone();
+            let lightbulb = editor.lightbulb.clone();
             
             line_numbers.set_draw_func(clone!(@strong buffer_clone, @strong view_clone => move |_area, cr, width, height| {
                 // Only draw visible line numbers for performance
@@ -330,7 +793,7 @@ impl Editor {
                 let layout = gtk4::pango::Layout::new(&pango_context);
                 layout.set_font_description(Some(&font_desc));
                 layout.set_alignment(gtk4::pango::Alignment::Right);
-                layout.set_width((width - 10) * gtk4::pango::SCALE);
+                layout.set_width((width - 10 - FOLD_MARKER_WIDTH) * gtk4::pango::SCALE);
                 
                 // Get text color from theme
                 let style_context = view_clone.style_context();
@@ -342,9 +805,30 @@ impl Editor {
                     fg_color.alpha() as f64
                 );
                 
+                // Lines hidden by folds are skipped; fold starts get a triangle
+                let fold_tag = buffer_clone.tag_table().lookup("folded");
+                let folded_lines: Vec<i32> = folding
+                    .folds
+                    .borrow()
+                    .iter()
+                    .map(|fold| buffer_clone.iter_at_mark(&fold.start).line() - 1)
+                    .collect();
+                let regions = folding.regions.borrow();
+
+                // The most severe diagnostic starting on each line gets a dot
+                let mut markers: HashMap<i32, Severity> = HashMap::new();
+                for marked in diagnostics.borrow().iter() {
+                    let line = buffer_clone.iter_at_mark(&marked.start).line();
+                    let severity = marked.diagnostic.severity;
+                    markers.entry(line).and_modify(|s| *s = (*s).min(severity)).or_insert(severity);
+                }
+
                 // Draw ONLY visible line numbers (typically ~50-100 lines)
                 for line_num in first_line..=last_line {
                     if let Some(iter) = buffer_clone.iter_at_line(line_num) {
+                        if fold_tag.as_ref().is_some_and(|tag| iter.has_tag(tag)) {
+                            continue;
+                        }
                         let location = view_clone.iter_location(&iter);
                         let (_, window_y) = view_clone.buffer_to_window_coords(
                             gtk4::TextWindowType::Widget,
@@ -355,21 +839,124 @@ impl Editor {
                         // Only draw if within the DrawingArea bounds
                         if window_y >= 0 && window_y < height {
                             layout.set_text(&(line_num + 1).to_string());
-                            cr.move_to(5.0, window_y as f64);
+                            cr.move_to(5.0 + DIAGNOSTIC_MARKER_WIDTH as f64, window_y as f64);
                             pangocairo::functions::show_layout(cr, &layout);
+
+                            // The lightbulb takes the place of the line's diagnostic marker
+                            if lightbulb.get() == Some(line_num) {
+                                let y = window_y as f64 + location.height() as f64 / 2.0;
+                                draw_lightbulb(cr, DIAGNOSTIC_MARKER_WIDTH as f64 / 2.0 + 1.0, y);
+                            } else if let Some(severity) = markers.get(&line_num) {
+                                let color = severity_color(*severity);
+                                let y = window_y as f64 + location.height() as f64 / 2.0;
+                                let _ = cr.save();
+                                cr.set_source_rgba(color.red() as f64, color.green() as f64, color.blue() as f64, 1.0);
+                                cr.arc(DIAGNOSTIC_MARKER_WIDTH as f64 / 2.0 + 1.0, y, 3.5, 0.0, std::f64::consts::TAU);
+                                let _ = cr.fill();
+                                let _ = cr.restore();
+                            }
+
+                            let is_region = regions.binary_search_by_key(&(line_num as usize), |r| r.start_line).is_ok();
+                            let is_folded = folded_lines.contains(&line_num);
+                            if is_region || is_folded {
+                                let x = (width - FOLD_MARKER_WIDTH + 3) as f64;
+                                let y = window_y as f64 + location.height() as f64 / 2.0;
+                                if is_folded {
+                                    // ▸
+                                    cr.move_to(x + 1.0, y - 4.0);
+                                    cr.line_to(x + 6.0, y);
+                                    cr.line_to(x + 1.0, y + 4.0);
+                                } else {
+                                    // ▾
+                                    cr.move_to(x, y - 2.5);
+                                    cr.line_to(x + 8.0, y - 2.5);
+                                    cr.line_to(x + 4.0, y + 2.5);
+                                }
+                                cr.close_path();
+                                let _ = cr.fill();
+                            }
                         }
                     }
                 }
             }));
         }
 
+        // Clicking a fold triangle (or anywhere in its column) folds or unfolds the region
+        {
+            let click = GestureClick::new();
+            click.set_button(gdk::BUTTON_PRIMARY);
+            let editor_weak = Rc::downgrade(&editor);
+            click.connect_pressed(move |_, _, x, y| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return;
+                };
+                if x < (editor.line_numbers.width() - FOLD_MARKER_WIDTH) as f64 {
+                    return;
+                }
+                let view = &editor.main_view;
+                let (_, buffer_y) = view.window_to_buffer_coords(TextWindowType::Widget, 0, y as i32);
+                if let Some(iter) = view.iter_at_location(0, buffer_y) {
+                    editor.toggle_fold(iter.line() as usize);
+                }
+            });
+            line_numbers.add_controller(click);
+        }
+
+        // Hovering a squiggle or a gutter marker shows the diagnostics' messages
+        {
+            main_view.set_has_tooltip(true);
+            let editor_weak = Rc::downgrade(&editor);
+            main_view.connect_query_tooltip(move |view, x, y, keyboard, tooltip| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return false;
+                };
+                let buffer = &editor.main_buffer;
+                let iter = if keyboard {
+                    Some(buffer.iter_at_mark(&buffer.get_insert()))
+                } else {
+                    let (x, y) = view.window_to_buffer_coords(TextWindowType::Widget, x, y);
+                    view.iter_at_location(x, y)
+                };
+                let text = iter.and_then(|iter| {
+                    editor.diagnostics_tooltip(|d| d.contains(iter.line() as usize, iter.line_offset() as usize))
+                });
+                match text {
+                    Some(text) => {
+                        tooltip.set_text(Some(&text));
+                        true
+                    }
+                    None => false,
+                }
+            });
+
+            line_numbers.set_has_tooltip(true);
+            let editor_weak = Rc::downgrade(&editor);
+            line_numbers.connect_query_tooltip(move |_, _, y, _, tooltip| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return false;
+                };
+                let view = &editor.main_view;
+                let (_, buffer_y) = view.window_to_buffer_coords(TextWindowType::Widget, 0, y);
+                let line = view.iter_at_location(0, buffer_y).map(|iter| iter.line() as usize);
+                let text = line.and_then(|line| editor.diagnostics_tooltip(|d| d.line == line));
+                match text {
+                    Some(text) => {
+                        tooltip.set_text(Some(&text));
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+
         // Update line numbers when buffer changes
         {
             let line_numbers_clone = line_numbers.clone();
             
-            main_buffer.connect_changed(move |_| {
+            let handler = main_buffer.connect_changed(move |_| {
                 line_numbers_clone.queue_draw();
             });
+            editor.buffer_handlers.borrow_mut().push(handler);
         }
 
         // Update line numbers when scrolling
@@ -382,12 +969,80 @@ impl Editor {
             });
         }
 
+        // Draw the extra carets, and redraw them whenever text, marks or scrolling move them
+        {
+            let editor_weak = Rc::downgrade(&editor);
+            carets_area.set_draw_func(move |_area, cr, _width, _height| {
+                if let Some(editor) = editor_weak.upgrade() {
+                    editor.draw_fold_placeholders(cr);
+                    editor.draw_extra_carets(cr);
+                }
+            });
+
+            let area = carets_area.clone();
+            let handler = main_buffer.connect_changed(move |_| area.queue_draw());
+            editor.buffer_handlers.borrow_mut().push(handler);
+            let area = carets_area.clone();
+            let handler = main_buffer.connect_mark_set(move |_, _, _| area.queue_draw());
+            editor.buffer_handlers.borrow_mut().push(handler);
+            for adjustment in [main_scrolled.vadjustment(), main_scrolled.hadjustment()] {
+                let area = carets_area.clone();
+                adjustment.connect_value_changed(move |_| area.queue_draw());
+            }
+        }
+
+        // Typing at the cursor types at the extra carets too. Hooking the insertion rather than
+        // key presses covers input methods and dead keys. Runs after the default handler, once
+        // the text is in.
+        {
+            let editor_weak = Rc::downgrade(&editor);
+            let handler = main_buffer.connect_local("insert-text", true, move |args| {
+                let editor = editor_weak.upgrade()?;
+                if editor.active_view.borrow().as_ptr() != Rc::as_ptr(&editor) {
+                    return None;
+                }
+                let location = args[1].get::<TextIter>().ok()?;
+                let text = args[2].get::<String>().ok()?;
+                editor.insert_at_extra_carets(&location, &text);
+                None
+            });
+            editor.buffer_handlers.borrow_mut().push(handler);
+
+            // Undo and redo re-insert text themselves
+            for signal in ["undo", "redo"] {
+                for (after, replaying) in [(false, true), (true, false)] {
+                    let editor_weak = Rc::downgrade(&editor);
+                    let handler = main_buffer.connect_local(signal, after, move |_| {
+                        if let Some(editor) = editor_weak.upgrade() {
+                            editor.editing_carets.set(replaying);
+                        }
+                        None
+                    });
+                    editor.buffer_handlers.borrow_mut().push(handler);
+                }
+            }
+        }
+
+        // Tell the highlighter which lines are on screen so they are coloured first
+        {
+            let view_clone = editor.main_view.clone();
+            let highlighter_cl = editor.highlighter.clone();
+            let vadj = main_scrolled.vadjustment();
+
+            let report_viewport = move |_: &gtk4::Adjustment| {
+                let (first, last) = visible_lines(&view_clone);
+                highlighter_cl.set_viewport(first, last);
+            };
+            vadj.connect_value_changed(report_viewport.clone());
+            vadj.connect_changed(report_viewport);
+        }
+
         // Mark dirty on change
         {
             let dirty_clone = dirty.clone();
             let tab_label_clone = tab_label.clone();
             let current_file_clone = current_file.clone();
-            main_buffer.connect_changed(move |_| {
+            let handler = main_buffer.connect_changed(move |_| {
                 *dirty_clone.borrow_mut() = true;
                 let base = current_file_clone
                     .borrow()
@@ -397,38 +1052,84 @@ impl Editor {
                     .to_string();
                 tab_label_clone.set_text(&format!("*{}", base));
             });
+            editor.buffer_handlers.borrow_mut().push(handler);
         }
 
-        // Scroll to cursor
+        // Scroll to cursor, in the view being edited only
         {
             let view_clone = editor.main_view.clone();
             let buffer_clone = editor.main_buffer.clone();
+            let editor_weak = Rc::downgrade(&editor);
             
-            editor.main_buffer.connect_changed(move |_| {
+            let handler = editor.main_buffer.connect_changed(move |_| {
+                let is_active = editor_weak
+                    .upgrade()
+                    .is_some_and(|editor| editor.active_view.borrow().as_ptr() == Rc::as_ptr(&editor));
+                if !is_active {
+                    return;
+                }
                 glib::idle_add_local(clone!(@strong view_clone, @strong buffer_clone => @default-return glib::Continue(false), move || {
                     let insert_mark = buffer_clone.get_insert();
                     view_clone.scroll_to_mark(&insert_mark, 0.0, false, 0.0, 0.0);
                     glib::Continue(false)
                 }));
             });
+            editor.buffer_handlers.borrow_mut().push(handler);
         }
 
         // Initial draw of line numbers
         editor.line_numbers.queue_draw();
 
+        // Reload / Keep Mine / Diff
+        {
+            let editor_weak = Rc::downgrade(&editor);
+            info_bar.connect_response(move |_, response| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return;
+                };
+                match response {
+                    ResponseType::Accept => editor.reload_from_disk(),
+                    ResponseType::Reject => editor.keep_mine(),
+                    ResponseType::Apply => editor.show_disk_diff(),
+                    _ => {}
+                }
+            });
+        }
+
+        // Views of the same buffer share its cursor, so each one keeps its own in marks
+        // while another view has the focus
+        {
+            let focus = EventControllerFocus::new();
+            let editor_weak = Rc::downgrade(&editor);
+            focus.connect_enter(move |_| {
+                let Some(editor) = editor_weak.upgrade() else {
+                    return;
+                };
+                *editor.active_view.borrow_mut() = Rc::downgrade(&editor);
+                if editor.view_count() > 1 {
+                    let buffer = &editor.main_buffer;
+                    buffer.select_range(&buffer.iter_at_mark(&editor.view_insert), &buffer.iter_at_mark(&editor.view_bound));
+                }
+            });
+            let editor_weak = Rc::downgrade(&editor);
+            focus.connect_leave(move |_| {
+                if let Some(editor) = editor_weak.upgrade() {
+                    editor.save_cursor();
+                }
+            });
+            editor.main_view.add_controller(focus);
+        }
+
         editor
     }
 
-    // Tab = 4 spaces
-    const TAB_WIDTH: usize = 4;
-
-    fn increase_indent(buffer: &TextBuffer) {
+    fn increase_indent(buffer: &TextBuffer, style: IndentStyle) {
         let (has_selection, start, end) = buffer.selection_bounds()
             .map(|(s, e)| (true, s, e))
             .unwrap_or_else(|| {
                 let cursor = buffer.get_insert();
                 let iter = buffer.iter_at_mark(&cursor);
-                (false, iter.clone(), iter)
+                (false, iter, iter)
             });
 
         if has_selection {
@@ -439,22 +1140,21 @@ impl Editor {
             buffer.begin_user_action();
             for line_num in start_line..=end_line {
                 let mut line_start = buffer.iter_at_line(line_num).unwrap_or_else(|| buffer.start_iter());
-                buffer.insert(&mut line_start, &" ".repeat(Self::TAB_WIDTH));
+                buffer.insert(&mut line_start, &style.unit());
             }
             buffer.end_user_action();
         } else {
-            // Insert spaces at cursor
-            let spaces = " ".repeat(Self::TAB_WIDTH);
-            buffer.insert_at_cursor(&spaces);
+            // Insert one indentation unit at cursor
+            buffer.insert_at_cursor(&style.unit());
         }
     }
 
-    fn decrease_indent(buffer: &TextBuffer) {
+    fn decrease_indent(buffer: &TextBuffer, style: IndentStyle) {
         let (start, end) = buffer.selection_bounds()
             .unwrap_or_else(|| {
                 let cursor = buffer.get_insert();
                 let iter = buffer.iter_at_mark(&cursor);
-                (iter.clone(), iter)
+                (iter, iter)
             });
 
         let start_line = start.line();
@@ -462,47 +1162,30 @@ impl Editor {
         
         buffer.begin_user_action();
         for line_num in start_line..=end_line {
-            if let Some(mut line_start) = buffer.iter_at_line(line_num) {
-                let mut line_end = line_start.clone();
-                line_end.forward_to_line_end();
-                
-                let line_text = buffer.text(&line_start, &line_end, false);
-                let mut spaces_to_remove = 0;
-                
-                for ch in line_text.chars().take(Self::TAB_WIDTH) {
-                    if ch == ' ' {
-                        spaces_to_remove += 1;
-                    } else {
-                        break;
-                    }
-                }
-                
-                if spaces_to_remove > 0 {
-                    let mut end_of_spaces = line_start.clone();
-                    end_of_spaces.forward_chars(spaces_to_remove);
-                    buffer.delete(&mut line_start, &mut end_of_spaces);
+            if let Some(line_start) = buffer.iter_at_line(line_num) {
+                let line_text = Self::line_text(buffer, &line_start);
+                let whitespace = indent::leading_whitespace(&line_text);
+                if !whitespace.is_empty() {
+                    Self::replace_leading_whitespace(buffer, line_num, whitespace, &style.dedent(whitespace));
                 }
             }
         }
         buffer.end_user_action();
     }
 
-    fn auto_indent_newline(buffer: &TextBuffer) {
+    fn auto_indent_newline(buffer: &TextBuffer, style: IndentStyle) {
         let cursor = buffer.get_insert();
         let iter = buffer.iter_at_mark(&cursor);
         let line = iter.line();
         
         // Get current line start and cursor position
         if let Some(line_start) = buffer.iter_at_line(line) {
-            let cursor_pos = iter.clone();
+            let cursor_pos = iter;
             
             let line_text = buffer.text(&line_start, &cursor_pos, false);
             
-            // Count leading spaces
-            let leading_spaces: String = line_text
-                .chars()
-                .take_while(|&c| c == ' ')
-                .collect();
+            // Keep the current line's indentation, tabs and spaces alike
+            let leading = indent::leading_whitespace(&line_text);
             
             // Check if we need to add extra indentation
             let trimmed = line_text.trim_end();
@@ -511,212 +1194,804 @@ impl Editor {
                 || trimmed.ends_with('(')
                 || trimmed.ends_with(':') // For Python, YAML, etc.
             {
-                " ".repeat(Self::TAB_WIDTH)
+                style.unit()
             } else {
                 String::new()
             };
             
             // Insert newline + indentation
-            let new_line_text = format!("\n{}{}", leading_spaces, extra_indent);
+            let new_line_text = format!("\n{}{}", leading, extra_indent);
             buffer.insert_at_cursor(&new_line_text);
         }
     }
     
-    fn handle_closing_bracket(buffer: &TextBuffer, bracket: char) {
+    fn handle_closing_bracket(buffer: &TextBuffer, bracket: char, style: IndentStyle) {
         // Get cursor position
         let cursor = buffer.get_insert();
         let iter = buffer.iter_at_mark(&cursor);
         let line = iter.line();
         
-        if let Some(mut line_start) = buffer.iter_at_line(line) {
-            let cursor_pos = iter.clone();
+        if let Some(line_start) = buffer.iter_at_line(line) {
+            let cursor_pos = iter;
             
             // Get text from line start to cursor
             let text_before_cursor = buffer.text(&line_start, &cursor_pos, false);
             
-            // Check if line only has whitespace before cursor
-            if text_before_cursor.trim().is_empty() {
-                // Count leading spaces
-                let leading_spaces = text_before_cursor.len();
-                
-                // If we have at least TAB_WIDTH spaces, remove them
-                if leading_spaces >= Self::TAB_WIDTH {
-                    let mut delete_end = line_start.clone();
-                    delete_end.forward_chars(Self::TAB_WIDTH as i32);
-                    buffer.delete(&mut line_start, &mut delete_end);
-                }
+            buffer.begin_user_action();
+            // If only whitespace precedes the cursor, move back one indentation level
+            if !text_before_cursor.is_empty() && text_before_cursor.trim().is_empty() {
+                let dedented = style.dedent(&text_before_cursor);
+                Self::replace_leading_whitespace(buffer, line, &text_before_cursor, &dedented);
             }
             
             // Insert the bracket
             buffer.insert_at_cursor(&bracket.to_string());
+            buffer.end_user_action();
         }
     }
 
-    /// Update the line numbers in the gutter
-    /// Update the status bar with current cursor position and file info
-    fn update_status_bar(&self, status_label: &Label, status_info_label: &Label, content: &str) {
-        let ins = self.main_buffer.get_insert();
-        let it = self.main_buffer.iter_at_mark(&ins);
-        let line = it.line();
-        let col = it.line_offset();
-        status_label.set_text(&format!("Ln {}, Col {}", line + 1, col + 1));
+    /// Type `opener`, an opening bracket or quote, with its closer after the cursor when
+    /// `brackets::should_auto_close` allows it and the cursor isn't in a string or comment.
+    /// A quote typed right before its auto-inserted twin steps over it instead.
+    fn type_opener(&self, buffer: &TextBuffer, opener: char) {
+        if self.step_over_closer(buffer, opener) {
+            return;
+        }
+        buffer.begin_user_action();
+        let had_selection = buffer.delete_selection(true, true);
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let closer = brackets::closer_for(opener).filter(|_| !had_selection && self.should_auto_close(&cursor, opener));
+        buffer.insert_interactive_at_cursor(&opener.to_string(), true);
+        if let Some(closer) = closer {
+            buffer.insert_interactive_at_cursor(&closer.to_string(), true);
+            let mut between = buffer.iter_at_mark(&buffer.get_insert());
+            between.backward_char();
+            buffer.place_cursor(&between);
+            self.auto_closed.borrow_mut().push(buffer.create_mark(None, &between, false));
+        }
+        buffer.end_user_action();
+    }
 
-        let info = if let Some(p) = self.current_file.borrow().as_ref() {
-            if let Ok(meta) = std::fs::metadata(p) {
-                let size = meta.len();
-                format!("{} — {} bytes", p.display(), size)
-            } else {
-                format!("{}", p.display())
-            }
-        } else {
-            let len = content.len();
-            format!("Untitled — {} bytes", len)
-        };
-        status_info_label.set_text(&info);
+    fn should_auto_close(&self, cursor: &TextIter, opener: char) -> bool {
+        if !self.settings.borrow().auto_close_brackets {
+            return false;
+        }
+        // In Rust a lone `'` is far more often a lifetime than a char literal
+        if opener == '\'' && self.syntax().name == "Rust" {
+            return false;
+        }
+        let (previous, next) = Self::chars_around(cursor);
+        if !brackets::should_auto_close(opener, previous, next) {
+            return false;
+        }
+        let first_line = (cursor.line() as usize).saturating_sub(brackets::SCOPE_LOOKBACK_LINES);
+        let start = self.main_buffer.iter_at_line(first_line as i32).unwrap_or_else(|| self.main_buffer.start_iter());
+        let text = self.main_buffer.text(&start, cursor, false);
+        !brackets::in_string_or_comment(&self.ss, self.syntax(), &text)
     }
 
-    /// Trigger syntax highlighting for the current buffer content
-    fn trigger_highlighting(&self, content: &str) {
-        let do_highlight = self
-            .current_file
-            .borrow()
-            .as_ref()
-            .and_then(|p| p.extension().and_then(|s| s.to_str()))
-            .map(|ext| matches!(ext, "rs" | "py" | "js" | "ts" | "json" | "toml" | "md" | "html" | "css" | "xml"))
-            .unwrap_or(true);
+    /// Tag the bracket at the cursor and its partner, untagging only the previous pair, whose
+    /// ranges `brackets.tagged` keeps. Brackets in strings and comments near the cursor don't
+    /// count.
+    fn highlight_brackets(&self, buffer: &TextBuffer) {
+        let tag = match buffer.tag_table().lookup("bracket-match") {
+            Some(tag) => tag,
+            None => {
+                let tag = TextTag::builder()
+                    .name("bracket-match")
+                    .background_rgba(&gdk::RGBA::new(0.5, 0.5, 0.5, 0.3))
+                    .weight(700)
+                    .build();
+                buffer.tag_table().add(&tag);
+                tag
+            }
+        };
+        for (start, end) in self.brackets.tagged.take() {
+            buffer.remove_tag(&tag, &buffer.iter_at_mark(&start), &buffer.iter_at_mark(&end));
+            buffer.delete_mark(&start);
+            buffer.delete_mark(&end);
+        }
 
-        const HIGHLIGHT_MAX_CHARS: usize = 200_000;
-        if content.chars().count() > HIGHLIGHT_MAX_CHARS {
+        let rope = self.rope.borrow();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset() as usize;
+        // While a file is being loaded the rope can briefly lag behind the buffer
+        if cursor > rope.len_chars() {
             return;
         }
-
-        if do_highlight {
-            let buffer = self.main_buffer.clone();
-            let content_clone = content.to_string();
-            let tag_cache = self.tag_cache.clone();
-            let ss = self.ss.clone();
-            let theme = self.theme.clone();
-
-            glib::idle_add_local(clone!(@strong buffer, @strong tag_cache, @strong ss, @strong theme => @default-return glib::Continue(false), move || {
-                let theme_ref = theme.borrow();
-                highlight::highlight_with_syntect(&buffer, &content_clone, &*tag_cache, &ss, &**theme_ref);
-                glib::Continue(false)
-            }));
+        // Lines around the cursor are parsed for strings and comments again only after an
+        // edit, or once the cursor leaves them
+        let line = rope.char_to_line(cursor);
+        let mut scopes = self.brackets.scopes.borrow_mut();
+        if !scopes.as_ref().is_some_and(|scopes| scopes.lines.contains(&line)) {
+            let lines = line.saturating_sub(brackets::SCOPE_LOOKBACK_LINES)
+                ..(line + brackets::SCOPE_LOOKBACK_LINES).min(rope.len_lines());
+            let skipped = if *self.large_file.borrow() {
+                Vec::new()
+            } else {
+                let text = rope.slice(rope.line_to_char(lines.start)..rope.line_to_char(lines.end)).to_string();
+                brackets::string_and_comment_ranges(&self.ss, self.syntax(), &text)
+            };
+            *scopes = Some(ParsedScopes { lines, skipped });
+        }
+        let Some(ParsedScopes { lines, skipped }) = scopes.as_ref() else {
+            return;
+        };
+        let first = rope.line_to_char(lines.start);
+        let in_code = |at: usize| at < first || !skipped.iter().any(|range| range.contains(&(at - first)));
+
+        if let Some((bracket, partner)) = brackets::matching_bracket_where(&rope, cursor, in_code) {
+            let mut marks = Vec::new();
+            for at in [bracket, partner] {
+                let start = buffer.iter_at_offset(at as i32);
+                let end = buffer.iter_at_offset(at as i32 + 1);
+                buffer.apply_tag(&tag, &start, &end);
+                // Text typed right after the bracket takes on its tag; the end mark moves past it
+                marks.push((buffer.create_mark(None, &start, true), buffer.create_mark(None, &end, false)));
+            }
+            *self.brackets.tagged.borrow_mut() = marks;
         }
     }
 
-    pub fn undo(&self) {
-        if self.main_buffer.can_undo() {
-            self.main_buffer.undo();
+    /// Type a closing bracket: over the auto-inserted one after the cursor, otherwise as usual.
+    fn type_closer(&self, buffer: &TextBuffer, closer: char, style: IndentStyle) {
+        if !self.step_over_closer(buffer, closer) {
+            Self::handle_closing_bracket(buffer, closer, style);
         }
     }
 
-    pub fn redo(&self) {
-        if self.main_buffer.can_redo() {
-            self.main_buffer.redo();
+    /// Move the cursor past `closer` if it's right after it and was auto-inserted.
+    fn step_over_closer(&self, buffer: &TextBuffer, closer: char) -> bool {
+        if buffer.has_selection() {
+            return false;
+        }
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let mut auto_closed = self.auto_closed.borrow_mut();
+        auto_closed.retain(|mark| {
+            let keep = !mark.is_deleted() && buffer.iter_at_mark(mark).line() == cursor.line();
+            if !keep {
+                buffer.delete_mark(mark);
+            }
+            keep
+        });
+        let Some(index) = auto_closed.iter().position(|mark| buffer.iter_at_mark(mark) == cursor) else {
+            return false;
+        };
+        if cursor.char() != closer {
+            return false;
         }
+        buffer.delete_mark(&auto_closed.remove(index));
+        let mut after = cursor;
+        after.forward_char();
+        buffer.place_cursor(&after);
+        true
     }
 
-    pub fn cut(&self) {
-        if let Some(display) = gdk::Display::default() {
-            let clipboard = display.clipboard();
-            self.main_buffer.cut_clipboard(&clipboard, true);
+    /// Whether Backspace should delete both halves of an empty pair around the cursor.
+    fn at_empty_pair(&self, buffer: &TextBuffer) -> bool {
+        if !self.settings.borrow().auto_close_brackets || buffer.has_selection() {
+            return false;
         }
+        let (previous, next) = Self::chars_around(&buffer.iter_at_mark(&buffer.get_insert()));
+        brackets::is_empty_pair(previous, next)
     }
 
-    pub fn paste(&self) {
-        if let Some(display) = gdk::Display::default() {
-            let clipboard = display.clipboard();
-            self.main_buffer.paste_clipboard(&clipboard, None, true);
-        }
+    fn delete_pair(buffer: &TextBuffer) {
+        let mut start = buffer.iter_at_mark(&buffer.get_insert());
+        let mut end = start;
+        start.backward_char();
+        end.forward_char();
+        buffer.delete_interactive(&mut start, &mut end, true);
     }
 
-    pub fn find_text(&self, search_text: &str, case_sensitive: bool) -> bool {
-        let flags = if case_sensitive {
-            gtk4::TextSearchFlags::empty()
-        } else {
-            gtk4::TextSearchFlags::CASE_INSENSITIVE
-        };
+    /// The characters before and after `iter` on its line.
+    fn chars_around(iter: &TextIter) -> (Option<char>, Option<char>) {
+        let mut before = *iter;
+        let previous = (!iter.starts_line() && before.backward_char()).then(|| before.char());
+        let next = (!iter.ends_line()).then(|| iter.char());
+        (previous, next)
+    }
 
-        let cursor = self.main_buffer.get_insert();
-        let mut start = self.main_buffer.iter_at_mark(&cursor);
-        
-        // Start searching from cursor position
-        if let Some((mut match_start, match_end)) = start.forward_search(search_text, flags, None) {
-            self.main_buffer.select_range(&match_start, &match_end);
-            self.main_view.scroll_to_iter(&mut match_start, 0.0, false, 0.0, 0.0);
-            true
-        } else {
-            // Wrap around to beginning
-            start = self.main_buffer.start_iter();
-            if let Some((mut match_start, match_end)) = start.forward_search(search_text, flags, None) {
-                self.main_buffer.select_range(&match_start, &match_end);
-                self.main_view.scroll_to_iter(&mut match_start, 0.0, false, 0.0, 0.0);
-                true
-            } else {
-                false
-            }
+    /// Text of the line beginning at `line_start`, without its line break.
+    fn line_text(buffer: &TextBuffer, line_start: &gtk4::TextIter) -> glib::GString {
+        let mut end = *line_start;
+        if !end.ends_line() {
+            end.forward_to_line_end();
         }
+        buffer.text(line_start, &end, false)
     }
 
-    pub fn replace_current(&self, search_text: &str, replace_text: &str, case_sensitive: bool) -> bool {
-        if let Some((mut start, mut end)) = self.main_buffer.selection_bounds() {
-            let selected = self.main_buffer.text(&start, &end, false);
-            let matches = if case_sensitive {
-                selected == search_text
-            } else {
-                selected.to_lowercase() == search_text.to_lowercase()
-            };
-            
-            if matches {
-                self.main_buffer.delete(&mut start, &mut end);
-                self.main_buffer.insert(&mut start, replace_text);
-                return true;
+    /// Replace the leading `old` whitespace of `line` with `new`.
+    fn replace_leading_whitespace(buffer: &TextBuffer, line: i32, old: &str, new: &str) {
+        if old == new {
+            return;
+        }
+        if let Some(mut start) = buffer.iter_at_line(line) {
+            let mut end = start;
+            end.forward_chars(old.chars().count() as i32);
+            buffer.delete(&mut start, &mut end);
+            let mut at = buffer.iter_at_line(line).unwrap_or_else(|| buffer.start_iter());
+            buffer.insert(&mut at, new);
+        }
+    }
+
+    /// Rewrite the indentation of every line with tabs or spaces and switch this buffer to
+    /// that mode. Undoable as a single step.
+    pub fn convert_indentation(&self, use_tabs: bool) {
+        let current = self.indent.get();
+        let style = IndentStyle { use_tabs, width: current.width };
+
+        let buffer = &self.main_buffer;
+        buffer.begin_user_action();
+        for line in 0..buffer.line_count() {
+            if let Some(line_start) = buffer.iter_at_line(line) {
+                let line_text = Self::line_text(buffer, &line_start);
+                let whitespace = indent::leading_whitespace(&line_text);
+                if !whitespace.is_empty() {
+                    // Measure the old indentation with the old style's tab width
+                    let converted = style.whitespace_for(current.columns(whitespace));
+                    Self::replace_leading_whitespace(buffer, line, whitespace, &converted);
+                }
             }
         }
-        false
+        buffer.end_user_action();
+
+        self.set_indent(style);
     }
 
-    pub fn replace_all(&self, search_text: &str, replace_text: &str, case_sensitive: bool) -> i32 {
-        let flags = if case_sensitive {
-            gtk4::TextSearchFlags::empty()
+    /// Backspace or Delete at the cursor: removes the selection if there is one, otherwise the
+    /// character before (or after) the cursor.
+    fn delete_at_cursor(buffer: &TextBuffer, forward: bool) {
+        if buffer.delete_selection(true, true) {
+            return;
+        }
+        let mut iter = buffer.iter_at_mark(&buffer.get_insert());
+        if forward {
+            let mut end = iter;
+            if end.forward_cursor_position() {
+                buffer.delete_interactive(&mut iter, &mut end, true);
+            }
         } else {
-            gtk4::TextSearchFlags::CASE_INSENSITIVE
+            buffer.backspace(&mut iter, true, true);
+        }
+    }
+
+    /// Move the cursor for an arrow, Home or End key, extending the selection if `extend`.
+    fn move_cursor(buffer: &TextBuffer, keyval: gdk::Key, extend: bool) {
+        let mut iter = buffer.iter_at_mark(&buffer.get_insert());
+        match (keyval, buffer.selection_bounds()) {
+            // Without Shift, Left and Right first collapse a selection to its edge
+            (gdk::Key::Left, Some((start, _))) if !extend => iter = start,
+            (gdk::Key::Right, Some((_, end))) if !extend => iter = end,
+            (gdk::Key::Left, _) => {
+                iter.backward_cursor_position();
+            }
+            (gdk::Key::Right, _) => {
+                iter.forward_cursor_position();
+            }
+            (gdk::Key::Up | gdk::Key::Down, _) => {
+                let column = iter.line_offset();
+                let moved = if keyval == gdk::Key::Up { iter.backward_line() } else { iter.forward_line() };
+                if moved {
+                    let mut line_end = iter;
+                    if !line_end.ends_line() {
+                        line_end.forward_to_line_end();
+                    }
+                    iter.set_line_offset(column.min(line_end.line_offset()));
+                }
+            }
+            (gdk::Key::Home, _) => iter.set_line_offset(0),
+            (gdk::Key::End, _) if !iter.ends_line() => {
+                iter.forward_to_line_end();
+            }
+            _ => {}
+        }
+        if extend {
+            buffer.move_mark(&buffer.get_insert(), &iter);
+        } else {
+            buffer.place_cursor(&iter);
+        }
+    }
+
+    pub fn has_extra_carets(&self) -> bool {
+        !self.extra_carets.borrow().is_empty()
+    }
+
+    /// Run `edit` at every caret, with the buffer's cursor and selection moved there, as one
+    /// undo step. With a single caret it simply runs at the cursor.
+    fn edit_at_carets(&self, edit: impl Fn(&TextBuffer)) {
+        let buffer = &self.main_buffer;
+        if !self.has_extra_carets() {
+            edit(buffer);
+            return;
+        }
+
+        buffer.begin_user_action();
+        self.run_at_carets(true, &edit);
+        buffer.end_user_action();
+        self.main_view.scroll_to_mark(&buffer.get_insert(), 0.0, false, 0.0, 0.0);
+    }
+
+    /// Text inserted at the cursor, e.g. typed or committed by an input method, is inserted
+    /// at the extra carets too, replacing their selections like it replaced the cursor's.
+    fn insert_at_extra_carets(&self, location: &TextIter, text: &str) {
+        let buffer = &self.main_buffer;
+        if self.editing_carets.get() || !self.has_extra_carets() || *location != buffer.iter_at_mark(&buffer.get_insert()) {
+            return;
+        }
+        self.run_at_carets(false, &|buffer| {
+            buffer.delete_selection(true, true);
+            buffer.insert_interactive_at_cursor(text, true);
+        });
+    }
+
+    /// Run `edit` at each extra caret, and at the buffer's own one too if `primary`.
+    fn run_at_carets(&self, primary: bool, edit: &dyn Fn(&TextBuffer)) {
+        let buffer = &self.main_buffer;
+        let carets = self.extra_carets.borrow().clone();
+        let own = self.create_caret(&buffer.iter_at_mark(&buffer.get_insert()), &buffer.iter_at_mark(&buffer.selection_bound()));
+        self.editing_carets.set(true);
+        for caret in carets.iter().chain(primary.then_some(&own)) {
+            buffer.select_range(&buffer.iter_at_mark(&caret.insert), &buffer.iter_at_mark(&caret.bound));
+            edit(buffer);
+            buffer.move_mark(&caret.insert, &buffer.iter_at_mark(&buffer.get_insert()));
+            buffer.move_mark(&caret.bound, &buffer.iter_at_mark(&buffer.selection_bound()));
+        }
+        self.editing_carets.set(false);
+
+        buffer.select_range(&buffer.iter_at_mark(&own.insert), &buffer.iter_at_mark(&own.bound));
+        self.delete_caret(&own);
+        self.merge_carets();
+    }
+
+    fn create_caret(&self, insert: &TextIter, bound: &TextIter) -> Caret {
+        Caret {
+            insert: self.main_buffer.create_mark(None, insert, false),
+            bound: self.main_buffer.create_mark(None, bound, false),
+        }
+    }
+
+    fn delete_caret(&self, caret: &Caret) {
+        self.main_buffer.delete_mark(&caret.insert);
+        self.main_buffer.delete_mark(&caret.bound);
+    }
+
+    /// Go back to the buffer's own cursor only.
+    pub fn clear_extra_carets(&self) {
+        let carets = std::mem::take(&mut *self.extra_carets.borrow_mut());
+        for caret in &carets {
+            self.delete_caret(caret);
+        }
+        self.carets_area.queue_draw();
+    }
+
+    /// The buffer's own selection followed by those of the extra carets.
+    fn selections(&self) -> Vec<Selection> {
+        let buffer = &self.main_buffer;
+        let offset = |mark: &TextMark| buffer.iter_at_mark(mark).offset() as usize;
+        let mut selections = vec![Selection::new(offset(&buffer.selection_bound()), offset(&buffer.get_insert()))];
+        selections.extend(
+            self.extra_carets
+                .borrow()
+                .iter()
+                .map(|caret| Selection::new(offset(&caret.bound), offset(&caret.insert))),
+        );
+        selections
+    }
+
+    /// Replace all carets with `selections`; the one at `primary` becomes the buffer's own.
+    fn set_selections(&self, selections: &[Selection], primary: usize) {
+        self.clear_extra_carets();
+        let buffer = &self.main_buffer;
+        let iter = |offset: usize| buffer.iter_at_offset(offset as i32);
+        let mut carets = Vec::new();
+        for (index, selection) in selections.iter().enumerate() {
+            if index == primary {
+                buffer.select_range(&iter(selection.head), &iter(selection.anchor));
+            } else {
+                carets.push(self.create_caret(&iter(selection.head), &iter(selection.anchor)));
+            }
+        }
+        *self.extra_carets.borrow_mut() = carets;
+        self.carets_area.queue_draw();
+    }
+
+    /// Merge carets that ran into each other, e.g. after deleting the text between them.
+    fn merge_carets(&self) {
+        let selections = self.selections();
+        let cursor = selections[0].head;
+        let merged = multi_cursor::merge(selections.clone());
+        if merged.len() == selections.len() {
+            return;
+        }
+        let primary = merged
+            .iter()
+            .position(|s| s.start() <= cursor && cursor <= s.end())
+            .unwrap_or(0);
+        self.set_selections(&merged, primary);
+    }
+
+    /// Add a caret at `iter` (Alt+click), or remove the extra caret that's already there.
+    fn toggle_caret(&self, iter: &TextIter) {
+        let buffer = &self.main_buffer;
+        let existing = self
+            .extra_carets
+            .borrow()
+            .iter()
+            .position(|caret| buffer.iter_at_mark(&caret.insert) == *iter);
+        match existing {
+            Some(index) => {
+                let caret = self.extra_carets.borrow_mut().remove(index);
+                self.delete_caret(&caret);
+            }
+            None => {
+                let caret = self.create_caret(iter, iter);
+                self.extra_carets.borrow_mut().push(caret);
+                self.merge_carets();
+            }
+        }
+        self.carets_area.queue_draw();
+    }
+
+    /// Select the word at the cursor, if it is in or at the end of one.
+    fn select_word_at_cursor(&self) -> bool {
+        let buffer = &self.main_buffer;
+        let mut start = buffer.iter_at_mark(&buffer.get_insert());
+        if !start.inside_word() && !start.ends_word() {
+            return false;
+        }
+        let mut end = start;
+        if !start.starts_word() {
+            start.backward_word_start();
+        }
+        if !end.ends_word() {
+            end.forward_word_end();
+        }
+        buffer.select_range(&end, &start);
+        true
+    }
+
+    /// Ctrl+D: select the word at the cursor, or when something is selected, add the next
+    /// occurrence of it as a new selection. The newest selection holds the buffer's cursor.
+    pub fn add_next_occurrence(&self) {
+        let buffer = &self.main_buffer;
+        let Some((start, end)) = buffer.selection_bounds() else {
+            self.select_word_at_cursor();
+            return;
+        };
+        let needle = buffer.text(&start, &end, false).to_string();
+        let text = self.rope.borrow().to_string();
+        let Some(next) = multi_cursor::next_occurrence(&text, &needle, end.offset() as usize, &self.selections()) else {
+            return;
         };
 
-        let mut count = 0;
-        self.main_buffer.begin_user_action();
-        
-        let mut search_start = self.main_buffer.start_iter();
-        while let Some((mut match_start, mut match_end)) = search_start.forward_search(search_text, flags, None) {
-            self.main_buffer.delete(&mut match_start, &mut match_end);
-            self.main_buffer.insert(&mut match_start, replace_text);
-            search_start = match_start;
-            count += 1;
+        let caret = self.create_caret(&buffer.iter_at_mark(&buffer.get_insert()), &buffer.iter_at_mark(&buffer.selection_bound()));
+        self.extra_carets.borrow_mut().push(caret);
+        buffer.select_range(&buffer.iter_at_offset(next.head as i32), &buffer.iter_at_offset(next.anchor as i32));
+        self.main_view.scroll_to_mark(&buffer.get_insert(), 0.0, false, 0.0, 0.0);
+        self.carets_area.queue_draw();
+    }
+
+    /// Select every occurrence of the selection, or every whole-word occurrence of the word
+    /// at the cursor.
+    pub fn select_all_occurrences(&self) {
+        let buffer = &self.main_buffer;
+        let whole_word = buffer.selection_bounds().is_none();
+        if whole_word && !self.select_word_at_cursor() {
+            return;
+        }
+        let Some((start, end)) = buffer.selection_bounds() else {
+            return;
+        };
+        let needle = buffer.text(&start, &end, false).to_string();
+        let occurrences = multi_cursor::all_occurrences(&self.rope.borrow().to_string(), &needle, whole_word);
+        let primary = occurrences
+            .iter()
+            .position(|s| s.start() == start.offset() as usize)
+            .unwrap_or(0);
+        if !occurrences.is_empty() {
+            self.set_selections(&occurrences, primary);
         }
-        
-        self.main_buffer.end_user_action();
-        count
     }
 
-    /// Update the editor display: line numbers, status bar, and syntax highlighting
-    pub fn update(&self, status_label: &Label, status_info_label: &Label) {
-        // Redraw line numbers
-        self.line_numbers.queue_draw();
+    /// The text position under `x`, `y` in the view's widget coordinates.
+    pub fn iter_at_widget_coords(&self, x: f64, y: f64) -> Option<TextIter> {
+        let (x, y) = self.main_view.window_to_buffer_coords(TextWindowType::Widget, x as i32, y as i32);
+        self.main_view.iter_at_location(x, y)
+    }
 
-        // Get buffer content for status display and syntax highlighting
-        let s = self.main_buffer.start_iter();
-        let e = self.main_buffer.end_iter();
-        let content = self.main_buffer.text(&s, &e, false);
+    /// Alt+drag: select from `from`'s column to `to`'s on every line in between (widget
+    /// coordinates), one caret per line. The caret on `to`'s line is the buffer's own.
+    fn select_column(&self, from: (f64, f64), to: (f64, f64)) {
+        let view = &self.main_view;
+        let (x0, y0) = view.window_to_buffer_coords(TextWindowType::Widget, from.0 as i32, from.1 as i32);
+        let (x1, y1) = view.window_to_buffer_coords(TextWindowType::Widget, to.0 as i32, to.1 as i32);
+        let (Some(first), Some(last)) = (view.iter_at_location(x0, y0), view.iter_at_location(x1, y1)) else {
+            return;
+        };
+
+        let mut selections = Vec::new();
+        for line in first.line().min(last.line())..=first.line().max(last.line()) {
+            let Some(line_start) = self.main_buffer.iter_at_line(line) else {
+                continue;
+            };
+            // Lines shorter than the column get a caret at their end
+            let (y, _) = view.line_yrange(&line_start);
+            let anchor = view.iter_at_location(x0, y).unwrap_or(line_start);
+            let head = view.iter_at_location(x1, y).unwrap_or(line_start);
+            selections.push(Selection::new(anchor.offset() as usize, head.offset() as usize));
+        }
+        let primary = if last.line() >= first.line() { selections.len().saturating_sub(1) } else { 0 };
+        self.set_selections(&selections, primary);
+    }
+
+    /// Draw the extra carets and their selections; the buffer's own cursor is GTK's.
+    fn draw_extra_carets(&self, cr: &gtk4::cairo::Context) {
+        let view = &self.main_view;
+        let buffer = &self.main_buffer;
+        let color = view.style_context().color();
+        let (red, green, blue) = (color.red() as f64, color.green() as f64, color.blue() as f64);
+        let to_widget = |rect: gdk::Rectangle| {
+            let (x, y) = view.buffer_to_window_coords(TextWindowType::Widget, rect.x(), rect.y());
+            (x as f64, y as f64, rect.height() as f64)
+        };
+
+        for caret in self.extra_carets.borrow().iter() {
+            let insert = buffer.iter_at_mark(&caret.insert);
+            let bound = buffer.iter_at_mark(&caret.bound);
+
+            // Selection, one rectangle per line
+            if insert != bound {
+                let (mut line_start, end) = if insert < bound { (insert, bound) } else { (bound, insert) };
+                loop {
+                    let mut line_end = line_start;
+                    if !line_end.ends_line() {
+                        line_end.forward_to_line_end();
+                    }
+                    let segment_end = if end < line_end { end } else { line_end };
+                    let (x0, y, height) = to_widget(view.iter_location(&line_start));
+                    let (x1, _, _) = to_widget(view.iter_location(&segment_end));
+                    // Selected line breaks show as a sliver
+                    cr.rectangle(x0, y, (x1 - x0).max(4.0), height);
+                    if segment_end == end || !line_start.forward_line() {
+                        break;
+                    }
+                }
+                cr.set_source_rgba(red, green, blue, 0.2);
+                let _ = cr.fill();
+            }
+
+            let (x, y, height) = to_widget(view.iter_location(&insert));
+            cr.rectangle(x, y, 1.5, height);
+            cr.set_source_rgba(red, green, blue, 1.0);
+            let _ = cr.fill();
+        }
+    }
+
+    /// What the status bar shows for this editor: cursor position, language and file info.
+    pub fn status(&self) -> EditorStatus {
+        let it = self.main_buffer.iter_at_mark(&self.main_buffer.get_insert());
+        let info = if let Some(p) = self.current_file.borrow().as_ref() {
+            if let Ok(meta) = std::fs::metadata(p) {
+                let size = meta.len();
+                format!("{} — {} bytes", p.display(), size)
+            } else {
+                format!("{}", p.display())
+            }
+        } else {
+            let len = self.rope.borrow().len_bytes();
+            format!("Untitled — {} bytes", len)
+        };
+        EditorStatus {
+            line: it.line(),
+            column: it.line_offset(),
+            language: self.syntax().name.clone(),
+            language_overridden: self.syntax_override.borrow().is_some(),
+            large_file: *self.large_file.borrow(),
+            indent: self.indent.get(),
+            format: self.format.get(),
+            info,
+        }
+    }
+
+    /// Syntax used to highlight this editor: the language-menu override if set, otherwise
+    /// detected from the file name, modeline or first line.
+    pub fn syntax(&self) -> &SyntaxReference {
+        if let Some(syntax) = self.detected_syntax.get().and_then(|index| self.ss.syntaxes().get(index)) {
+            return syntax;
+        }
+        let override_name = self.syntax_override.borrow().clone();
+        let path = self.current_file.borrow().clone();
+        let head = self.detection_text();
+        let syntax = language::detect_syntax(&self.ss, override_name.as_deref(), path.as_deref(), &head);
+        self.detected_syntax.set(self.ss.syntaxes().iter().position(|s| std::ptr::eq(s, syntax)));
+        syntax
+    }
+
+    /// Set (or clear with `None`) the per-tab language override and re-highlight.
+    pub fn set_syntax_override(&self, name: Option<String>) {
+        *self.syntax_override.borrow_mut() = name;
+        self.detected_syntax.set(None);
+        self.rehighlight();
+    }
+
+    /// Re-highlight if the detected syntax changed (e.g. after Save As or editing a shebang).
+    fn refresh_syntax(&self) {
+        if self.syntax().name != *self.highlighted_syntax.borrow() {
+            self.rehighlight();
+            self.refresh_indent();
+        }
+    }
+
+    /// Restart highlighting of the whole buffer with the current syntax and theme.
+    fn rehighlight(&self) {
+        let syntax_name = self.syntax().name.clone();
+        let rope = self.rope.borrow().clone();
+        let visible_only = *self.large_file.borrow();
+        self.highlighter.reset(rope, &syntax_name, self.get_theme(), visible_only);
+        *self.highlighted_syntax.borrow_mut() = syntax_name;
+        self.brackets.scopes.take();
+        self.schedule_fold_refresh();
+    }
+
+    /// First and last few lines of the buffer, which is all language detection looks at.
+    fn detection_text(&self) -> String {
+        let buffer = &self.main_buffer;
+        let (line_count, lines) = (buffer.line_count(), DETECTION_LINES as i32);
+        if line_count <= lines * 2 {
+            return buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).to_string();
+        }
+
+        let head_end = buffer.iter_at_line(lines).unwrap_or_else(|| buffer.end_iter());
+        let tail_start = buffer.iter_at_line(line_count - lines).unwrap_or_else(|| buffer.end_iter());
+        let mut text = buffer.text(&buffer.start_iter(), &head_end, false).to_string();
+        text.push_str(&buffer.text(&tail_start, &buffer.end_iter(), false));
+        text
+    }
+
+    pub fn undo(&self) {
+        if self.main_buffer.can_undo() {
+            self.main_buffer.undo();
+        }
+    }
+
+    pub fn redo(&self) {
+        if self.main_buffer.can_redo() {
+            self.main_buffer.redo();
+        }
+    }
+
+    pub fn cut(&self) {
+        if let Some(display) = gdk::Display::default() {
+            let clipboard = display.clipboard();
+            self.main_buffer.cut_clipboard(&clipboard, true);
+        }
+    }
+
+    pub fn paste(&self) {
+        if let Some(display) = gdk::Display::default() {
+            let clipboard = display.clipboard();
+            self.main_buffer.paste_clipboard(&clipboard, None, true);
+        }
+    }
+
+    /// Highlight every find-bar match, replacing the previous highlights.
+    pub fn highlight_matches(&self, matches: &[SearchMatch]) {
+        let buffer = &self.main_buffer;
+        let tag = match buffer.tag_table().lookup("search-match") {
+            Some(tag) => tag,
+            None => {
+                let tag = TextTag::builder()
+                    .name("search-match")
+                    .background_rgba(&gdk::RGBA::new(1.0, 0.75, 0.0, 0.35))
+                    .build();
+                buffer.tag_table().add(&tag);
+                tag
+            }
+        };
+        buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+        for m in matches {
+            buffer.apply_tag(&tag, &buffer.iter_at_offset(m.start as i32), &buffer.iter_at_offset(m.end as i32));
+        }
+    }
+
+    /// Select `m` and scroll it into view.
+    pub fn select_match(&self, m: SearchMatch) {
+        let buffer = &self.main_buffer;
+        buffer.select_range(&buffer.iter_at_offset(m.end as i32), &buffer.iter_at_offset(m.start as i32));
+        self.main_view.scroll_to_mark(&buffer.get_insert(), 0.1, false, 0.0, 0.0);
+    }
+
+    /// Start and end of the selection as character offsets; both are the cursor's if nothing
+    /// is selected.
+    pub fn selection_offsets(&self) -> (usize, usize) {
+        let buffer = &self.main_buffer;
+        match buffer.selection_bounds() {
+            Some((start, end)) => (start.offset() as usize, end.offset() as usize),
+            None => {
+                let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset() as usize;
+                (cursor, cursor)
+            }
+        }
+    }
+
+    /// Replace each of `matches` with `replacement` of its text, as one undo step. Returns
+    /// how many were replaced.
+    pub fn replace_matches(&self, matches: &[SearchMatch], replacements: &[String]) -> usize {
+        let buffer = &self.main_buffer;
+        buffer.begin_user_action();
+        // Back to front, so the offsets of the matches still to go stay valid
+        for (m, replaced) in matches.iter().zip(replacements).rev() {
+            let mut start = buffer.iter_at_offset(m.start as i32);
+            let mut end = buffer.iter_at_offset(m.end as i32);
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, replaced);
+        }
+        buffer.end_user_action();
+        matches.len()
+    }
 
-        // Update status bar
-        self.update_status_bar(status_label, status_info_label, &content);
+    /// Make Replace in Files `replacements` as one undo step. Returns `false`, changing
+    /// nothing, if any match no longer has the text it was found with.
+    pub fn apply_replacements(&self, replacements: &[Replacement]) -> bool {
+        let buffer = &self.main_buffer;
+        let mut ranges = Vec::with_capacity(replacements.len());
+        for replacement in replacements {
+            let line = replacement.line as i32;
+            let start = buffer.iter_at_line_offset(line, replacement.start_column as i32);
+            let end = buffer.iter_at_line_offset(line, replacement.end_column as i32);
+            let (Some(start), Some(end)) = (start, end) else {
+                return false;
+            };
+            if buffer.text(&start, &end, false).as_str() != replacement.expected {
+                return false;
+            }
+            ranges.push((start.offset(), end.offset(), replacement.text.as_str()));
+        }
 
-        // Trigger syntax highlighting
-        self.trigger_highlighting(&content);
+        // Back to front, so the offsets of the ranges still to go stay valid
+        ranges.sort_by_key(|&(start, _, _)| std::cmp::Reverse(start));
+        buffer.begin_user_action();
+        for (start, end, text) in ranges {
+            let mut start = buffer.iter_at_offset(start);
+            let mut end = buffer.iter_at_offset(end);
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, text);
+        }
+        buffer.end_user_action();
+        true
+    }
+
+    /// Make `edits` (in order and not overlapping) as one undo step, e.g. a rename or a fix
+    /// from the language server.
+    pub fn apply_edits(&self, edits: &[Edit]) {
+        let buffer = &self.main_buffer;
+        buffer.begin_user_action();
+        // Back to front, so the offsets of the edits still to go stay valid
+        for edit in edits.iter().rev() {
+            let mut start = buffer.iter_at_offset(edit.start as i32);
+            let mut end = buffer.iter_at_offset(edit.end as i32);
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &edit.text);
+        }
+        buffer.end_user_action();
+    }
+
+    /// Update the editor display: line numbers and syntax highlighting. Returns what the
+    /// status bar should show.
+    pub fn update(&self) -> EditorStatus {
+        // Redraw line numbers
+        self.line_numbers.queue_draw();
+
+        // Highlighting follows edits on its own; only a change of language needs a restart
+        self.refresh_syntax();
+        self.status()
     }
 
     pub fn toggle_wrap(&self) {
+        // Wrapping needs a layout pass over every line; too slow for large files
+        if *self.large_file.borrow() {
+            return;
+        }
         let current = self.main_view.wrap_mode();
         if current == WrapMode::None {
             self.main_view.set_wrap_mode(WrapMode::Word);
@@ -725,6 +2000,47 @@ impl Editor {
         }
     }
 
+    pub fn set_wrap(&self, wrap: bool) {
+        if *self.large_file.borrow() {
+            return;
+        }
+        self.main_view.set_wrap_mode(if wrap { WrapMode::Word } else { WrapMode::None });
+    }
+
+    /// Apply per-view settings; the font itself comes from the global settings CSS.
+    pub fn apply_settings(&self, settings: &Settings) {
+        *self.settings.borrow_mut() = settings.clone();
+        self.refresh_indent();
+        self.line_numbers.queue_draw();
+    }
+
+    /// Resolve the indentation from the buffer's contents, its language and the settings.
+    pub fn refresh_indent(&self) {
+        let sample = {
+            let rope = self.rope.borrow();
+            let end = rope.line_to_char(indent::DETECT_MAX_LINES.min(rope.len_lines()));
+            rope.slice(..end).to_string()
+        };
+        let style = indent::resolve(&self.settings.borrow(), &self.syntax().name, &sample);
+        self.set_indent(style);
+    }
+
+    fn set_indent(&self, style: IndentStyle) {
+        self.indent.set(style);
+        for view in self.views() {
+            view.set_tab_stops(style);
+        }
+    }
+
+    fn set_tab_stops(&self, style: IndentStyle) {
+        // Tab stops are measured in pixels, so derive them from the current font's space width
+        let layout = self.main_view.create_pango_layout(Some(" "));
+        let (space_width, _) = layout.pixel_size();
+        let mut tabs = pango::TabArray::new(1, true);
+        tabs.set_tab(0, pango::TabAlign::Left, space_width * style.width as i32);
+        self.main_view.set_tabs(&tabs);
+    }
+
     pub fn content_row(&self) -> GtkBox {
         self.content_row.clone()
     }
@@ -737,40 +2053,896 @@ impl Editor {
 
     #[allow(dead_code)]
     pub fn set_text(&self, text: &str) {
+        // The insert/delete handlers keep the rope and highlighter in sync
         self.main_buffer.set_text(text);
-        {
-            let mut r = self.rope.borrow_mut();
-            let len = r.len_chars();
-            if len > 0 {
-                r.remove(0..len);
+    }
+
+    pub fn save_to_path(self: &Rc<Self>, path: &PathBuf) -> Result<(), std::io::Error> {
+        let bytes = file_format::encode(&self.get_text(), self.format.get())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        file_format::write_atomic(path, &bytes)?;
+        let path_changed = self.current_file.borrow().as_ref() != Some(path);
+        *self.current_file.borrow_mut() = Some(path.clone());
+        self.detected_syntax.set(None);
+        self.disk_mtime.set(external_change::modified_time(path));
+        self.hide_info();
+        self.format.set(FileFormat { mixed_line_endings: false, ..self.format.get() });
+        if path_changed {
+            self.watch_file();
+        }
+        self.mark_clean();
+        self.refresh_syntax();
+
+        Ok(())
+    }
+
+    /// Watch the current file so changes made by other programs (git checkout, formatters,
+    /// generators) are noticed. Replaces any previous monitor.
+    pub fn watch_file(self: &Rc<Self>) {
+        if let Some(old) = self.file_monitor.borrow_mut().take() {
+            old.cancel();
+        }
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        self.disk_mtime.set(external_change::modified_time(&path));
+
+        let monitor = match gio::File::for_path(&path).monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE) {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Failed to watch {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        // Any view will do, and this one may close before the others
+        let views = self.views.clone();
+        monitor.connect_changed(move |_, _, _, event| {
+            if matches!(
+                event,
+                gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::Created | gio::FileMonitorEvent::Deleted
+            ) {
+                let editor = views.borrow().iter().find_map(Weak::upgrade);
+                if let Some(editor) = editor {
+                    editor.check_disk();
+                }
             }
-            r.insert(0, text);
+        });
+
+        *self.file_monitor.borrow_mut() = Some(monitor);
+    }
+
+    /// Whether the file was modified on disk since it was loaded or saved.
+    pub fn changed_on_disk(&self) -> bool {
+        match self.current_file.borrow().as_ref() {
+            Some(path) => external_change::check(self.disk_mtime.get(), path) == DiskChange::Modified,
+            None => false,
         }
     }
 
-    pub fn save_to_path(&self, path: &PathBuf) -> Result<(), std::io::Error> {
-        let content = self.get_text();
-        std::fs::write(path, content.as_str())?;
-        *self.current_file.borrow_mut() = Some(path.clone());
+    /// React to a change on disk: clean buffers reload silently, edited ones ask.
+    pub fn check_disk(self: &Rc<Self>) {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        match external_change::check(self.disk_mtime.get(), &path) {
+            DiskChange::Unchanged => {}
+            DiskChange::Modified if !*self.dirty.borrow() => self.reload_from_disk(),
+            DiskChange::Modified => {
+                self.show_info("This file was changed by another program. Your edits are not saved.", true, true);
+            }
+            DiskChange::Deleted => {
+                // Often followed by a Created event from tools that replace files atomically
+                self.show_info("This file was deleted by another program. Save to recreate it.", false, false);
+            }
+        }
+    }
+
+    /// Show the info bar in every view of this document.
+    fn show_info(&self, text: &str, reload: bool, diff: bool) {
+        for view in self.views() {
+            view.info_label.set_text(text);
+            view.reload_button.set_visible(reload);
+            view.diff_button.set_visible(diff);
+            view.info_bar.set_revealed(true);
+        }
+    }
+
+    fn hide_info(&self) {
+        for view in self.views() {
+            view.info_bar.set_revealed(false);
+        }
+    }
+
+    /// Replace the buffer with the file's contents on disk. Undoable, except in large-file
+    /// mode where the file is streamed in again.
+    pub fn reload_from_disk(self: &Rc<Self>) {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        let mtime = external_change::modified_time(&path);
+        let (text, format) = match file_format::read_file(&path) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                self.show_info(&format!("Could not reload this file: {}", e), true, false);
+                return;
+            }
+        };
+        self.disk_mtime.set(mtime);
+
+        let buffer = &self.main_buffer;
+        if *self.large_file.borrow() {
+            buffer.begin_irreversible_action();
+            buffer.set_text("");
+            buffer.end_irreversible_action();
+            self.load_large_file(Rope::from_str(&text));
+        } else {
+            let offset = self.cursor_offset();
+            buffer.begin_user_action();
+            buffer.set_text(&text);
+            buffer.end_user_action();
+            buffer.place_cursor(&buffer.iter_at_offset(offset));
+            self.mark_clean();
+        }
+
+        self.hide_info();
+        self.set_format(format);
+    }
+
+    /// Keep the buffer as it is and accept that saving will overwrite the file on disk.
+    fn keep_mine(&self) {
+        if let Some(path) = self.current_file.borrow().as_ref() {
+            self.disk_mtime.set(external_change::modified_time(path));
+        }
+        self.mark_dirty();
+        self.hide_info();
+    }
+
+    /// Show what differs between the file on disk and the buffer.
+    fn show_disk_diff(&self) {
+        let Some(path) = self.current_file.borrow().clone() else {
+            return;
+        };
+        let disk = match file_format::read_file(&path) {
+            Ok((text, _)) => text,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let diff = external_change::unified_diff(&self.display_name(), &disk, &self.get_text());
+
+        let view = TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.style_context().add_class("editor-view");
+        let buffer = view.buffer();
+        buffer.set_text(&diff);
+
+        // Colour added and removed lines
+        let added = buffer.create_tag(Some("diff-added"), &[("foreground", &"#2e9d4b")]);
+        let removed = buffer.create_tag(Some("diff-removed"), &[("foreground", &"#d04437")]);
+        for (line, text) in diff.lines().enumerate() {
+            let tag = match text.chars().next() {
+                Some('+') if !text.starts_with("+++") => added.as_ref(),
+                Some('-') if !text.starts_with("---") => removed.as_ref(),
+                _ => None,
+            };
+            if let (Some(tag), Some(start)) = (tag, buffer.iter_at_line(line as i32)) {
+                let mut end = start;
+                end.forward_to_line_end();
+                buffer.apply_tag(tag, &start, &end);
+            }
+        }
+
+        let scrolled = ScrolledWindow::builder()
+            .child(&view)
+            .hscrollbar_policy(PolicyType::Automatic)
+            .vscrollbar_policy(PolicyType::Automatic)
+            .build();
+
+        let window = gtk4::Window::builder()
+            .title(format!("Changes in {}", self.display_name()))
+            .default_width(800)
+            .default_height(600)
+            .child(&scrolled)
+            .build();
+        if let Some(parent) = self.main_view.root().and_then(|root| root.downcast::<gtk4::Window>().ok()) {
+            window.set_transient_for(Some(&parent));
+        }
+        window.present();
+    }
+
+    /// Save in `format` from now on. Warns that saving normalizes a file with mixed line
+    /// endings, rather than doing it silently.
+    pub fn set_format(&self, format: FileFormat) {
+        self.format.set(format);
+        if format.mixed_line_endings {
+            let text = format!(
+                "This file mixes LF and CRLF line endings. Saving converts them all to {}.",
+                format.line_ending.label()
+            );
+            self.show_info(&text, false, false);
+        }
+    }
+
+    /// Save with `line_ending` from now on; the buffer counts as modified until then.
+    pub fn set_line_ending(&self, line_ending: LineEnding) {
+        let mut format = self.format.get();
+        if format.line_ending != line_ending {
+            format.line_ending = line_ending;
+            self.set_format(format);
+            self.mark_dirty();
+        }
+    }
+
+    /// Flag the buffer as modified, e.g. when restoring an unsaved snapshot
+    pub fn mark_dirty(&self) {
+        *self.dirty.borrow_mut() = true;
+        self.refresh_tab_label();
+    }
+
+    /// Cursor position as a character offset, for saving the session
+    pub fn cursor_offset(&self) -> i32 {
+        self.main_buffer.iter_at_mark(&self.main_buffer.get_insert()).offset()
+    }
+
+    /// Zero-based line and column of the cursor
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let iter = self.main_buffer.iter_at_mark(&self.main_buffer.get_insert());
+        (iter.line() as usize, iter.line_offset() as usize)
+    }
+
+    /// First visible line, for saving the session
+    pub fn scroll_line(&self) -> i32 {
+        visible_lines(&self.main_view).0 as i32
+    }
+
+    /// Put the cursor back at `cursor_offset` and scroll `scroll_line` to the top.
+    pub fn restore_position(&self, cursor_offset: i32, scroll_line: i32) {
+        let buffer = &self.main_buffer;
+        buffer.place_cursor(&buffer.iter_at_offset(cursor_offset));
+
+        // scroll_to_mark waits for line heights to be computed, unlike scroll_to_iter. The scroll
+        // happens later, so the mark can't be deleted here; one named mark is reused instead.
+        let top = buffer.iter_at_line(scroll_line).unwrap_or_else(|| buffer.start_iter());
+        let mark = match buffer.mark("restore-position") {
+            Some(mark) => {
+                buffer.move_mark(&mark, &top);
+                mark
+            }
+            None => buffer.create_mark(Some("restore-position"), &top, true),
+        };
+        self.main_view.scroll_to_mark(&mark, 0.0, true, 0.0, 0.0);
+    }
+
+    /// Put the cursor on zero-based `line` at character `column`, both clamped to the text,
+    /// and scroll it to the middle of the view.
+    pub fn go_to(&self, line: usize, column: usize) {
+        let buffer = &self.main_buffer;
+        buffer.place_cursor(&self.iter_at_line_column(line, column));
+        self.main_view.scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.5);
+        self.main_view.grab_focus();
+    }
+
+    /// Iter at zero-based `line` and char `column`, clamped to the end of the line and of the
+    /// buffer.
+    fn iter_at_line_column(&self, line: usize, column: usize) -> TextIter {
+        let buffer = &self.main_buffer;
+        let mut iter = buffer.iter_at_line(line as i32).unwrap_or_else(|| buffer.end_iter());
+        let mut line_end = iter;
+        if !line_end.ends_line() {
+            line_end.forward_to_line_end();
+        }
+        iter.set_line_offset(column.min(line_end.line_offset() as usize) as i32);
+        iter
+    }
+
+    /// Move the cursor to the partner of the bracket next to it.
+    pub fn jump_to_matching_bracket(&self) {
+        let target = brackets::jump_target(&self.rope.borrow(), self.cursor_offset() as usize);
+        if let Some(target) = target {
+            let buffer = &self.main_buffer;
+            buffer.place_cursor(&buffer.iter_at_offset(target as i32));
+            self.main_view.scroll_to_mark(&buffer.get_insert(), 0.0, false, 0.0, 0.0);
+        }
+    }
+
+    /// Recompute the fold regions in the background once edits pause. Large files get none.
+    fn schedule_fold_refresh(&self) {
+        let generation = self.folding.generation.get() + 1;
+        self.folding.generation.set(generation);
+        if let Some(source) = self.folding.refresh.borrow_mut().take() {
+            source.remove();
+        }
+        if *self.large_file.borrow() {
+            self.folding.regions.borrow_mut().clear();
+            self.folding.computed.set(generation);
+            return;
+        }
+
+        let views = self.views.clone();
+        let source = glib::timeout_add_local_once(FOLD_REFRESH_DELAY, move || {
+            let Some(view) = views.borrow().iter().find_map(Weak::upgrade) else {
+                return;
+            };
+            view.folding.refresh.borrow_mut().take();
+            let job = view.fold_regions_job();
+            let (tx, rx) = glib::MainContext::channel::<Vec<FoldRegion>>(glib::Priority::default());
+            std::thread::Builder::new()
+                .name("fold-regions".to_string())
+                .spawn(move || {
+                    let _ = tx.send(job());
+                })
+                .expect("failed to spawn folding thread");
+            let (state, views) = (view.folding.clone(), views.clone());
+            rx.attach(None, move |regions| {
+                // Edits made meanwhile will compute their own
+                if state.generation.get() == generation {
+                    *state.regions.borrow_mut() = regions;
+                    state.computed.set(generation);
+                    for view in views.borrow().iter().filter_map(Weak::upgrade) {
+                        view.line_numbers.queue_draw();
+                    }
+                }
+                glib::Continue(false)
+            });
+        });
+        *self.folding.refresh.borrow_mut() = Some(source);
+    }
+
+    /// Computes the fold regions of the current text, on any thread.
+    fn fold_regions_job(&self) -> impl FnOnce() -> Vec<FoldRegion> + Send + 'static {
+        let rope = self.rope.borrow().clone();
+        let ss = self.ss.clone();
+        let syntax_name = self.syntax().name.clone();
+        let tab_width = self.indent.get().width;
+        move || {
+            let syntax = ss.find_syntax_by_name(&syntax_name).unwrap_or_else(|| ss.find_syntax_plain_text());
+            folding::fold_regions(&folding::analyze(&ss, syntax, &rope.to_string(), tab_width))
+        }
+    }
+
+    /// The fold regions of the current text, computed right away if edits made them stale.
+    fn fold_regions(&self) -> Vec<FoldRegion> {
+        let generation = self.folding.generation.get();
+        if self.folding.computed.get() != generation {
+            if let Some(source) = self.folding.refresh.borrow_mut().take() {
+                source.remove();
+            }
+            *self.folding.regions.borrow_mut() = self.fold_regions_job()();
+            self.folding.computed.set(generation);
+        }
+        self.folding.regions.borrow().clone()
+    }
+
+    fn fold_tag(&self) -> TextTag {
+        let buffer = &self.main_buffer;
+        match buffer.tag_table().lookup("folded") {
+            Some(tag) => tag,
+            None => {
+                let tag = TextTag::builder().name("folded").invisible(true).build();
+                buffer.tag_table().add(&tag);
+                tag
+            }
+        }
+    }
+
+    /// The line a fold starts on, which stays visible.
+    fn fold_line(&self, fold: &Fold) -> usize {
+        (self.main_buffer.iter_at_mark(&fold.start).line() - 1).max(0) as usize
+    }
+
+    fn is_folded(&self, line: usize) -> bool {
+        self.folding.folds.borrow().iter().any(|fold| self.fold_line(fold) == line)
+    }
+
+    /// Hide the lines of `region` after its first one.
+    fn fold(&self, region: &FoldRegion) {
+        if self.is_folded(region.start_line) {
+            return;
+        }
+        let buffer = &self.main_buffer;
+        let Some(start) = buffer.iter_at_line(region.start_line as i32 + 1) else {
+            return;
+        };
+        let end = buffer.iter_at_line(region.end_line as i32 + 1).unwrap_or_else(|| buffer.end_iter());
+        if start >= end {
+            return;
+        }
+
+        // Keep the cursor out of the hidden text
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        if start <= cursor && cursor < end {
+            let mut line_end = start;
+            line_end.backward_char();
+            buffer.place_cursor(&line_end);
+        }
+        buffer.apply_tag(&self.fold_tag(), &start, &end);
+        let fold = Fold { start: buffer.create_mark(None, &start, false), end: buffer.create_mark(None, &end, true) };
+        self.folding.folds.borrow_mut().push(fold);
+        self.redraw_folds();
+    }
+
+    /// Unfold the fold starting at `line`, or every fold with `None`.
+    fn unfold(&self, line: Option<usize>) {
+        let removed: Vec<Fold> = {
+            let mut folds = self.folding.folds.borrow_mut();
+            let (removed, kept) = std::mem::take(&mut *folds)
+                .into_iter()
+                .partition(|fold| line.is_none_or(|line| self.fold_line(fold) == line));
+            *folds = kept;
+            removed
+        };
+        if removed.is_empty() {
+            return;
+        }
+        for fold in removed {
+            self.main_buffer.delete_mark(&fold.start);
+            self.main_buffer.delete_mark(&fold.end);
+        }
+        self.apply_folds();
+    }
+
+    /// Re-tag the folded text, e.g. after unfolding a region with folds inside it.
+    fn apply_folds(&self) {
+        let buffer = &self.main_buffer;
+        let tag = self.fold_tag();
+        buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+        for fold in self.folding.folds.borrow().iter() {
+            buffer.apply_tag(&tag, &buffer.iter_at_mark(&fold.start), &buffer.iter_at_mark(&fold.end));
+        }
+        self.redraw_folds();
+    }
+
+    /// Unfold what edits broke: folds emptied, or joined with the lines around them.
+    fn check_folds(&self) {
+        let buffer = &self.main_buffer;
+        let broken: Vec<usize> = self
+            .folding
+            .folds
+            .borrow()
+            .iter()
+            .filter(|fold| {
+                let (start, end) = (buffer.iter_at_mark(&fold.start), buffer.iter_at_mark(&fold.end));
+                !start.starts_line() || !(end.starts_line() || end.is_end()) || start >= end
+            })
+            .map(|fold| self.fold_line(fold))
+            .collect();
+        for line in broken {
+            self.unfold(Some(line));
+        }
+    }
+
+    /// Unfold the folds hiding the cursor, once something moved it there.
+    fn unfold_at_cursor_position(&self) {
+        let buffer = &self.main_buffer;
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let hiding: Vec<usize> = self
+            .folding
+            .folds
+            .borrow()
+            .iter()
+            .filter(|fold| buffer.iter_at_mark(&fold.start) <= cursor && cursor < buffer.iter_at_mark(&fold.end))
+            .map(|fold| self.fold_line(fold))
+            .collect();
+        for line in hiding {
+            self.unfold(Some(line));
+        }
+    }
+
+    fn redraw_folds(&self) {
+        for view in self.views.borrow().iter().filter_map(Weak::upgrade) {
+            view.line_numbers.queue_draw();
+            view.carets_area.queue_draw();
+        }
+    }
+
+    /// Fold the region starting at `line`, or unfold it if folded; for the gutter triangles.
+    fn toggle_fold(&self, line: usize) {
+        if self.is_folded(line) {
+            self.unfold(Some(line));
+        } else if let Some(region) = self.fold_regions().iter().find(|region| region.start_line == line) {
+            self.fold(region);
+        }
+    }
+
+    /// Fold the innermost region around the cursor that isn't folded yet.
+    pub fn fold_at_cursor(&self) {
+        let line = self.cursor_line();
+        let regions = self.fold_regions();
+        if let Some(region) = regions.iter().rev().find(|region| region.contains(line) && !self.is_folded(region.start_line)) {
+            self.fold(region);
+        }
+    }
+
+    /// Unfold the fold on the cursor's line.
+    pub fn unfold_at_cursor(&self) {
+        self.unfold(Some(self.cursor_line()));
+    }
+
+    pub fn fold_all(&self) {
+        for region in self.fold_regions() {
+            self.fold(&region);
+        }
+    }
+
+    pub fn unfold_all(&self) {
+        self.unfold(None);
+    }
+
+    /// Fold every region nested `level` deep, 1 being the top level.
+    pub fn fold_level(&self, level: usize) {
+        for region in self.fold_regions().iter().filter(|region| region.level == level) {
+            self.fold(region);
+        }
+    }
+
+    fn cursor_line(&self) -> usize {
+        self.main_buffer.iter_at_mark(&self.main_buffer.get_insert()).line() as usize
+    }
+
+    /// Draw a "⋯" after the first line of each fold, standing in for the hidden lines.
+    fn draw_fold_placeholders(&self, cr: &gtk4::cairo::Context) {
+        let folds = self.folding.folds.borrow();
+        if folds.is_empty() {
+            return;
+        }
+        let view = &self.main_view;
+        let buffer = &self.main_buffer;
+        let tag = self.fold_tag();
+        let color = view.style_context().color();
+        let (red, green, blue) = (color.red() as f64, color.green() as f64, color.blue() as f64);
+        let layout = view.create_pango_layout(Some("⋯"));
+        let (text_width, text_height) = layout.pixel_size();
+
+        for fold in folds.iter() {
+            // The first line's end; skipped when that line is itself inside an outer fold
+            let mut line_end = buffer.iter_at_mark(&fold.start);
+            if !line_end.backward_char() || line_end.has_tag(&tag) {
+                continue;
+            }
+            let rect = view.iter_location(&line_end);
+            let (x, y) = view.buffer_to_window_coords(TextWindowType::Widget, rect.x(), rect.y());
+            let x = x as f64 + 8.0;
+            let y = y as f64 + (rect.height() - text_height) as f64 / 2.0;
+            cr.rectangle(x - 3.0, y, text_width as f64 + 6.0, text_height as f64);
+            cr.set_source_rgba(red, green, blue, 0.15);
+            let _ = cr.fill();
+            cr.move_to(x, y);
+            cr.set_source_rgba(red, green, blue, 0.8);
+            pangocairo::functions::show_layout(cr, &layout);
+        }
+    }
+
+    /// Clear the dirty flag and the `*` in the tab label
+    fn mark_clean(&self) {
         *self.dirty.borrow_mut() = false;
+        self.refresh_tab_label();
+    }
 
-        let base = path.file_name().and_then(|s| s.to_str()).unwrap_or("Untitled").to_string();
-        self.tab_label.set_text(&base);
+    fn refresh_tab_label(&self) {
+        let name = self.display_name();
+        let text = if *self.dirty.borrow() { format!("*{}", name) } else { name };
+        let path = self.current_file.borrow().clone();
+        for view in self.views() {
+            view.tab_label.set_text(&text);
+            view.tab_label.set_tooltip_text(path.as_ref().and_then(|p| p.to_str()));
+        }
+    }
 
-        Ok(())
+    /// Every open view of this document, including this one.
+    fn views(&self) -> Vec<Rc<Editor>> {
+        self.views.borrow().iter().filter_map(Weak::upgrade).collect()
+    }
+
+    pub fn view_count(&self) -> usize {
+        self.views().len()
+    }
+
+    /// Whether `other` is a view of the same document.
+    pub fn shares_document(&self, other: &Editor) -> bool {
+        self.main_buffer == other.main_buffer
+    }
+
+    /// A snapshot of the document's text.
+    pub fn rope(&self) -> Rope {
+        self.rope.borrow().clone()
+    }
+
+    /// The language server the document is open in, if any.
+    pub fn language_server(&self) -> Option<Attachment> {
+        self.lsp.borrow().clone()
+    }
+
+    /// Open the document in the language server of `attachment`, closing it in the one it
+    /// was open in before; `None` just closes it.
+    pub fn set_language_server(&self, attachment: Option<Attachment>) {
+        let previous = self.lsp.replace(attachment.clone());
+        if let Some(previous) = previous {
+            previous.client.close_document(&previous.uri);
+        }
+        if let Some(attachment) = attachment {
+            attachment.client.open_document(&attachment.uri, self.rope.borrow().clone());
+        }
+    }
+
+    /// Show `diagnostics` in the document, replacing the ones shown before: squiggles under
+    /// their ranges and markers in the gutter. They follow edits until replaced again.
+    pub fn set_diagnostics(&self, diagnostics: Vec<Diagnostic>) {
+        let buffer = &self.main_buffer;
+        for marked in self.diagnostics.borrow_mut().drain(..) {
+            buffer.delete_mark(&marked.start);
+            buffer.delete_mark(&marked.end);
+        }
+        for severity in [Severity::Error, Severity::Warning, Severity::Information, Severity::Hint] {
+            buffer.remove_tag(&diagnostic_tag(buffer, severity), &buffer.start_iter(), &buffer.end_iter());
+        }
+
+        let mut marked = Vec::with_capacity(diagnostics.len());
+        for diagnostic in diagnostics {
+            let mut start = self.iter_at_line_column(diagnostic.line, diagnostic.column);
+            let mut end = self.iter_at_line_column(diagnostic.end_line, diagnostic.end_column);
+            if end < start {
+                end = start;
+            }
+            // Text inserted at either end stays outside the range
+            let start_mark = buffer.create_mark(None, &start, false);
+            let end_mark = buffer.create_mark(None, &end, true);
+            // Empty ranges underline the char after them, or before them at the end of a line
+            if start == end && !end.ends_line() {
+                end.forward_char();
+            } else if start == end {
+                start.backward_char();
+            }
+            buffer.apply_tag(&diagnostic_tag(buffer, diagnostic.severity), &start, &end);
+            marked.push(MarkedDiagnostic { diagnostic, start: start_mark, end: end_mark });
+        }
+        *self.diagnostics.borrow_mut() = marked;
+        for view in self.views() {
+            view.line_numbers.queue_draw();
+        }
+    }
+
+    /// Messages of the diagnostics matching `filter`, where edits since they were reported
+    /// moved them, most severe first, or `None` if there are none.
+    fn diagnostics_tooltip(&self, filter: impl Fn(&Diagnostic) -> bool) -> Option<String> {
+        let diagnostics = self.diagnostics.borrow();
+        let mut matching: Vec<Diagnostic> =
+            diagnostics.iter().map(|marked| self.moved_diagnostic(marked)).filter(|d| filter(d)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by_key(|d| d.severity);
+        let messages: Vec<String> = matching
+            .iter()
+            .map(|d| {
+                let code = d.code.as_ref().map(|code| format!("[{}]", code)).unwrap_or_default();
+                let source = d.source.as_ref().map(|source| format!(" ({})", source)).unwrap_or_default();
+                format!("{}{}{}: {}", d.severity.name(), code, source, d.message)
+            })
+            .collect();
+        Some(messages.join("\n\n"))
+    }
+
+    /// The diagnostics whose range touches chars `start..=end`, where edits since they were
+    /// reported moved them.
+    pub fn diagnostics_in(&self, start: usize, end: usize) -> Vec<Diagnostic> {
+        let buffer = &self.main_buffer;
+        let diagnostics = self.diagnostics.borrow();
+        diagnostics
+            .iter()
+            .filter(|marked| {
+                let (from, to) = (buffer.iter_at_mark(&marked.start), buffer.iter_at_mark(&marked.end));
+                from.offset() as usize <= end && to.offset() as usize >= start
+            })
+            .map(|marked| self.moved_diagnostic(marked))
+            .collect()
+    }
+
+    /// `marked`'s diagnostic positioned where its marks are now.
+    fn moved_diagnostic(&self, marked: &MarkedDiagnostic) -> Diagnostic {
+        let buffer = &self.main_buffer;
+        let (from, to) = (buffer.iter_at_mark(&marked.start), buffer.iter_at_mark(&marked.end));
+        Diagnostic {
+            line: from.line() as usize,
+            column: from.line_offset() as usize,
+            end_line: to.line() as usize,
+            end_column: to.line_offset() as usize,
+            ..marked.diagnostic.clone()
+        }
+    }
+
+    /// Line whose gutter shows the code actions lightbulb.
+    pub fn lightbulb(&self) -> Option<usize> {
+        self.lightbulb.get().map(|line| line as usize)
+    }
+
+    /// Show the code actions lightbulb in the gutter of `line`, or hide it.
+    pub fn set_lightbulb(&self, line: Option<usize>) {
+        let line = line.map(|line| line as i32);
+        if self.lightbulb.replace(line) != line {
+            self.line_numbers.queue_draw();
+        }
+    }
+
+    /// Whether `x`, `y` in the gutter is on the lightbulb.
+    pub fn lightbulb_at(&self, x: f64, y: f64) -> bool {
+        let Some(lightbulb) = self.lightbulb.get() else {
+            return false;
+        };
+        if x >= (DIAGNOSTIC_MARKER_WIDTH + 5) as f64 {
+            return false;
+        }
+        let view = &self.main_view;
+        let (_, buffer_y) = view.window_to_buffer_coords(TextWindowType::Widget, 0, y as i32);
+        view.iter_at_location(0, buffer_y).is_some_and(|iter| iter.line() == lightbulb)
+    }
+
+    /// Detach this view from its document when its tab closes. Closing the last view also
+    /// stops watching the file and closes the document in its language server.
+    pub fn close_view(&self) {
+        for handler in self.buffer_handlers.borrow_mut().drain(..) {
+            self.main_buffer.disconnect(handler);
+        }
+        self.main_buffer.delete_mark(&self.view_insert);
+        self.main_buffer.delete_mark(&self.view_bound);
+        self.clear_extra_carets();
+        self.views.borrow_mut().retain(|view| view.strong_count() > 0 && !std::ptr::eq(view.as_ptr(), self));
+        if self.views.borrow().is_empty() {
+            if let Some(monitor) = self.file_monitor.borrow_mut().take() {
+                monitor.cancel();
+            }
+            self.set_language_server(None);
+        } else if std::ptr::eq(self.active_view.borrow().as_ptr(), self) {
+            *self.active_view.borrow_mut() = self.views.borrow()[0].clone();
+        }
+    }
+
+    /// Remember the cursor and selection as this view's own.
+    fn save_cursor(&self) {
+        let buffer = &self.main_buffer;
+        buffer.move_mark(&self.view_insert, &buffer.iter_at_mark(&buffer.get_insert()));
+        buffer.move_mark(&self.view_bound, &buffer.iter_at_mark(&buffer.selection_bound()));
+    }
+
+    /// Point the tab at `path` after the file was renamed or moved on disk.
+    pub fn set_file_path(self: &Rc<Self>, path: PathBuf) {
+        *self.current_file.borrow_mut() = Some(path);
+        self.detected_syntax.set(None);
+        self.refresh_tab_label();
+        self.watch_file();
+        self.refresh_syntax();
+    }
+
+    /// Switch to large-file mode and stream `rope` into the (empty) buffer in chunks from idle
+    /// callbacks, so the window stays responsive and the top of the file shows up immediately.
+    /// The view is read-only until loading finishes, and loading is not undoable. The file
+    /// itself is already read and decoded; only filling the buffer is spread out.
+    pub fn load_large_file(self: &Rc<Self>, rope: Rope) {
+        const LOAD_CHUNK_CHARS: usize = 512 * 1024;
+
+        *self.large_file.borrow_mut() = true;
+        for view in self.views() {
+            view.main_view.set_wrap_mode(WrapMode::None);
+            view.main_view.set_editable(false);
+        }
+        self.rehighlight();
+
+        let total = rope.len_chars();
+        let loaded = Rc::new(Cell::new(0usize));
+        let editor = self.clone();
+
+        glib::idle_add_local(move || {
+            let start = loaded.get();
+            let mut end = (start + LOAD_CHUNK_CHARS).min(total);
+            // Never split a CRLF pair across two inserts
+            if end < total && rope.char(end - 1) == '\r' {
+                end += 1;
+            }
+
+            let chunk = rope.slice(start..end).to_string();
+            let buffer = &editor.main_buffer;
+            buffer.begin_irreversible_action();
+            buffer.insert(&mut buffer.end_iter(), &chunk);
+            buffer.end_irreversible_action();
+            if start == 0 {
+                // Keep the caret (and the view) at the top while the rest streams in
+                buffer.place_cursor(&buffer.start_iter());
+            }
+            loaded.set(end);
+
+            if end < total {
+                let percent = end * 100 / total.max(1);
+                for view in editor.views() {
+                    view.tab_label.set_text(&format!("{} ({}%)", editor.display_name(), percent));
+                }
+                return glib::Continue(true);
+            }
+
+            for view in editor.views() {
+                view.main_view.set_editable(true);
+            }
+            editor.mark_clean();
+            editor.refresh_indent();
+            glib::Continue(false)
+        });
+    }
+
+    /// File name shown in the tab, or "Untitled"
+    pub fn display_name(&self) -> String {
+        self.current_file
+            .borrow()
+            .as_ref()
+            .and_then(|p| p.file_name().and_then(|s| s.to_str()))
+            .unwrap_or("Untitled")
+            .to_string()
     }
     
     /// Get the current theme
-    fn get_theme(&self) -> Rc<Theme> {
+    fn get_theme(&self) -> Arc<Theme> {
         self.theme.borrow().clone()
     }
     
     /// Update the theme and re-highlight the editor
-    pub fn set_theme(&self, new_theme: Rc<Theme>) {
+    pub fn set_theme(&self, new_theme: Arc<Theme>) {
         *self.theme.borrow_mut() = new_theme;
-        // Trigger re-highlighting by incrementing the generation counter and sending current text
-        let gen = self.highlight_gen.fetch_add(1, Ordering::Relaxed) + 1;
-        let text = self.get_text();
-        let _ = self.highlight_sender.send((gen, text));
+        self.rehighlight();
     }
-}
\ No newline at end of file
+}
+
+/// Whether an edit of lines `start..=end` of `rope` touches the lines syntax detection reads.
+fn touches_detection_lines(rope: &Rope, start: usize, end: usize) -> bool {
+    start < DETECTION_LINES || end + DETECTION_LINES >= rope.len_lines()
+}
+
+/// First and last buffer lines currently visible in `view`.
+fn visible_lines(view: &TextView) -> (usize, usize) {
+    let rect = view.visible_rect();
+    let first = view.iter_at_location(0, rect.y()).map(|it| it.line()).unwrap_or(0);
+    let last = view
+        .iter_at_location(0, rect.y() + rect.height())
+        .map(|it| it.line())
+        .unwrap_or_else(|| view.buffer().line_count() - 1);
+    (first.max(0) as usize, last.max(0) as usize)
+}
+
+/// A lightbulb centred on `x`, `y`, in the diagnostic markers' column.
+fn draw_lightbulb(cr: &gtk4::cairo::Context, x: f64, y: f64) {
+    let _ = cr.save();
+    cr.set_source_rgba(0.98, 0.78, 0.15, 1.0);
+    cr.arc(x, y - 1.5, 3.5, 0.0, std::f64::consts::TAU);
+    let _ = cr.fill();
+    cr.set_source_rgba(0.6, 0.6, 0.6, 1.0);
+    cr.rectangle(x - 1.5, y + 2.0, 3.0, 2.5);
+    let _ = cr.fill();
+    let _ = cr.restore();
+}
+
+/// Colour of squiggles and gutter markers for `severity`.
+pub fn severity_color(severity: Severity) -> gdk::RGBA {
+    match severity {
+        Severity::Error => gdk::RGBA::new(0.90, 0.28, 0.30, 1.0),
+        Severity::Warning => gdk::RGBA::new(0.89, 0.64, 0.21, 1.0),
+        Severity::Information => gdk::RGBA::new(0.23, 0.56, 0.92, 1.0),
+        Severity::Hint => gdk::RGBA::new(0.55, 0.55, 0.55, 1.0),
+    }
+}
+
+/// The tag underlining diagnostics of `severity` with a wavy line.
+fn diagnostic_tag(buffer: &TextBuffer, severity: Severity) -> TextTag {
+    let name = format!("diagnostic-{}", severity.name());
+    match buffer.tag_table().lookup(&name) {
+        Some(tag) => tag,
+        None => {
+            let tag = TextTag::builder()
+                .name(name.as_str())
+                .underline(pango::Underline::Error)
+                .underline_rgba(&severity_color(severity))
+                .build();
+            buffer.tag_table().add(&tag);
+            tag
+        }
+    }
+}
+
@@ -0,0 +1,39 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use similar::TextDiff;
+
+/// State of a file on disk compared to when the editor last loaded or saved it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskChange {
+    Unchanged,
+    Modified,
+    Deleted,
+}
+
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Compare the modification time recorded at load/save with the file's current one.
+pub fn check(recorded: Option<SystemTime>, path: &Path) -> DiskChange {
+    match std::fs::metadata(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => DiskChange::Deleted,
+        Err(_) => DiskChange::Unchanged,
+        Ok(meta) => match (recorded, meta.modified().ok()) {
+            (Some(recorded), Some(current)) if recorded != current => DiskChange::Modified,
+            // The file appeared after we opened it, or reappeared after being deleted
+            (None, Some(_)) => DiskChange::Modified,
+            _ => DiskChange::Unchanged,
+        },
+    }
+}
+
+/// Unified diff from the file on disk to the editor's buffer.
+pub fn unified_diff(name: &str, disk: &str, buffer: &str) -> String {
+    TextDiff::from_lines(disk, buffer)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("{} (on disk)", name), &format!("{} (unsaved)", name))
+        .to_string()
+}
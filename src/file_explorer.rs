@@ -7,11 +7,14 @@ use std::cell::RefCell;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use crate::settings::Settings;
+
 pub struct FileExplorer {
     pub widget: ScrolledWindow,
     tree_view: TreeView,
     tree_store: TreeStore,
     root_path: Option<PathBuf>,
+    settings: Settings,
 }
 
 // Column indices for the TreeStore
@@ -54,14 +57,13 @@ impl FileExplorer {
             .vscrollbar_policy(gtk4::PolicyType::Automatic)
             .build();
 
-        let explorer = Rc::new(RefCell::new(FileExplorer {
+        Rc::new(RefCell::new(FileExplorer {
             widget: scrolled,
             tree_view,
             tree_store,
             root_path: None,
-        }));
-
-        explorer
+            settings: Settings::default(),
+        }))
     }
 
     pub fn set_root_directory(&mut self, path: PathBuf) {
@@ -90,9 +92,9 @@ impl FileExplorer {
                 let file_name = entry.file_name();
                 let file_path = entry.path();
                 
-                // Skip hidden files (starting with .)
+                // Skip dotfiles and names matching the user's hidden patterns
                 if let Some(name_str) = file_name.to_str() {
-                    if name_str.starts_with('.') {
+                    if self.settings.is_hidden(name_str) {
                         continue;
                     }
                 }
@@ -208,7 +210,7 @@ impl FileExplorer {
             loop {
                 // GTK4: Use get() instead of value().get()
                 let path: String = self.tree_store.get(&current_iter, COL_PATH as i32);
-                if PathBuf::from(&path) == target_path {
+                if Path::new(&path) == target_path {
                     return Some(current_iter);
                 }
 
@@ -229,12 +231,41 @@ impl FileExplorer {
         None
     }
 
-    pub fn get_tree_view(&self) -> &TreeView {
-        &self.tree_view
+    pub fn root_directory(&self) -> Option<&Path> {
+        self.root_path.as_deref()
+    }
+
+    /// Directories currently expanded in the tree, parents before children.
+    pub fn expanded_directories(&self) -> Vec<PathBuf> {
+        let mut expanded = Vec::new();
+        self.collect_expanded(None, &mut expanded);
+        expanded
     }
 
-    pub fn get_tree_store(&self) -> &TreeStore {
-        &self.tree_store
+    fn collect_expanded(&self, parent: Option<&TreeIter>, expanded: &mut Vec<PathBuf>) {
+        let Some(iter) = self.tree_store.iter_children(parent) else {
+            return;
+        };
+        loop {
+            let is_dir: bool = self.tree_store.get(&iter, COL_IS_DIR as i32);
+            if is_dir && self.tree_view.row_expanded(&self.tree_store.path(&iter)) {
+                let path: String = self.tree_store.get(&iter, COL_PATH as i32);
+                expanded.push(PathBuf::from(path));
+                self.collect_expanded(Some(&iter), expanded);
+            }
+            if !self.tree_store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+
+    /// Expand `dirs`; parents must come first, since expanding is what loads the children.
+    pub fn expand_directories(&self, dirs: &[PathBuf]) {
+        for dir in dirs {
+            if let Some(iter) = self.find_iter_for_path(dir, None) {
+                self.tree_view.expand_row(&self.tree_store.path(&iter), false);
+            }
+        }
     }
 
     pub fn refresh(&self) {
@@ -244,6 +275,16 @@ impl FileExplorer {
         }
     }
 
+    /// Apply the hidden-file rules from `settings` and rebuild the tree if they changed.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        let changed = self.settings.show_hidden_files != settings.show_hidden_files
+            || self.settings.hidden_patterns != settings.hidden_patterns;
+        self.settings = settings.clone();
+        if changed {
+            self.refresh();
+        }
+    }
+
     pub fn setup_context_menu(&self, _app: &gtk4::Application) {
         let tree_view = self.tree_view.clone();
         let tree_store = self.tree_store.clone();
@@ -0,0 +1,183 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+/// Text encodings Fikby can read and write back unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16Be => "UTF-16 BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn toggled(&self) -> LineEnding {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+}
+
+/// How a file is stored on disk. The buffer itself always uses `\n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    /// The file mixes LF and CRLF; saving it writes `line_ending` everywhere
+    pub mixed_line_endings: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self { encoding: Encoding::Utf8, line_ending: LineEnding::Lf, mixed_line_endings: false }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("'{ch}' on line {line} can't be saved as {encoding}")]
+pub struct UnencodableChar {
+    pub ch: char,
+    pub line: usize,
+    pub encoding: &'static str,
+}
+
+/// Decode file contents, detecting the encoding and line ending, and normalize line endings
+/// to `\n`.
+pub fn decode(bytes: &[u8]) -> (String, FileFormat) {
+    let (encoding, text) = if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        (Encoding::Utf8Bom, String::from_utf8_lossy(rest).into_owned())
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        (Encoding::Utf16Le, decode_utf16(rest, u16::from_le_bytes))
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        (Encoding::Utf16Be, decode_utf16(rest, u16::from_be_bytes))
+    } else if let Some(encoding) = guess_utf16(bytes) {
+        // Checked before UTF-8, since ASCII in UTF-16 is also valid UTF-8 full of NULs
+        let decoder = if encoding == Encoding::Utf16Le { u16::from_le_bytes } else { u16::from_be_bytes };
+        (encoding, decode_utf16(bytes, decoder))
+    } else if let Ok(text) = std::str::from_utf8(bytes) {
+        (Encoding::Utf8, text.to_string())
+    } else {
+        // Every byte sequence is valid Latin-1, and it round-trips exactly
+        (Encoding::Latin1, bytes.iter().map(|&b| b as char).collect())
+    };
+
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    let line_ending = if crlf > lf { LineEnding::CrLf } else { LineEnding::Lf };
+    let text = if crlf > 0 { text.replace("\r\n", "\n") } else { text };
+
+    let mixed_line_endings = crlf > 0 && lf > 0;
+    (text, FileFormat { encoding, line_ending, mixed_line_endings })
+}
+
+/// Encode buffer text (with `\n` line endings) for writing in `format`.
+pub fn encode(text: &str, format: FileFormat) -> Result<Vec<u8>, UnencodableChar> {
+    let text = match format.line_ending {
+        LineEnding::Lf => std::borrow::Cow::Borrowed(text),
+        LineEnding::CrLf => std::borrow::Cow::Owned(text.replace('\n', "\r\n")),
+    };
+
+    Ok(match format.encoding {
+        Encoding::Utf8 => text.as_bytes().to_vec(),
+        Encoding::Utf8Bom => [b"\xEF\xBB\xBF".as_slice(), text.as_bytes()].concat(),
+        Encoding::Utf16Le => std::iter::once(0xFEFF)
+            .chain(text.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+        Encoding::Utf16Be => std::iter::once(0xFEFF)
+            .chain(text.encode_utf16())
+            .flat_map(u16::to_be_bytes)
+            .collect(),
+        Encoding::Latin1 => {
+            for (line, content) in text.lines().enumerate() {
+                if let Some(ch) = content.chars().find(|&c| c as u32 > 0xFF) {
+                    return Err(UnencodableChar { ch, line: line + 1, encoding: format.encoding.label() });
+                }
+            }
+            text.chars().map(|c| c as u8).collect()
+        }
+    })
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| to_unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// BOM-less UTF-16 is recognised by mostly-ASCII text having a zero in every other byte.
+fn guess_utf16(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    if odd_zeros * 10 >= pairs * 7 && even_zeros * 10 < pairs {
+        Some(Encoding::Utf16Le)
+    } else if even_zeros * 10 >= pairs * 7 && odd_zeros * 10 < pairs {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Read and decode a text file.
+pub fn read_file(path: &Path) -> std::io::Result<(String, FileFormat)> {
+    Ok(decode(&std::fs::read(path)?))
+}
+
+/// Write `bytes` to a temporary file next to `path` and rename it over `path`, so a failed
+/// save never leaves a truncated file. The existing file's permissions are kept, and
+/// symlinks are followed so the link itself stays in place.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = target.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    let name = target.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    let temp = dir.join(format!(".{}.fikby-{}.tmp", name, std::process::id()));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        if let Ok(meta) = std::fs::metadata(&target) {
+            std::fs::set_permissions(&temp, meta.permissions())?;
+        }
+        std::fs::rename(&temp, &target)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use thiserror::Error;
+
+use crate::file_format::{self, UnencodableChar};
+use crate::quick_open::{self, slash_path};
+use crate::search::Search;
+use crate::settings::{wildcard_match, Settings};
+
+// Stop after this many matches; a query this broad needs narrowing rather than scrolling
+pub const MAX_MATCHES: usize = 20_000;
+// Files larger than this are skipped: they're almost always generated or data
+const MAX_FILE_BYTES: u64 = 16 * 1024 * 1024;
+// Characters of context kept on either side of a match in long line previews
+const PREVIEW_CONTEXT_CHARS: usize = 60;
+
+/// Which files under the search folder are searched, from the comma-separated "files to
+/// include" and "files to exclude" fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl FileFilter {
+    pub fn new(include: &str, exclude: &str) -> Self {
+        Self { include: parse_patterns(include), exclude: parse_patterns(exclude) }
+    }
+
+    /// Whether `path` (relative to the search folder) is searched. Patterns with a `/` match
+    /// the whole path, others the file name or any folder along the way.
+    pub fn matches(&self, path: &Path) -> bool {
+        let matches_any = |patterns: &[String]| patterns.iter().any(|pattern| pattern_matches(pattern, path));
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+}
+
+fn parse_patterns(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(|pattern| pattern.trim().trim_start_matches("./").trim_matches('/').replace("**", "*"))
+        .filter(|pattern| !pattern.is_empty())
+        .collect()
+}
+
+fn pattern_matches(pattern: &str, path: &Path) -> bool {
+    if pattern.contains('/') {
+        let path = slash_path(path);
+        // "src/ui" also covers everything below it
+        wildcard_match(pattern, &path) || wildcard_match(&format!("{}/*", pattern), &path)
+    } else {
+        path.components().any(|c| wildcard_match(pattern, &c.as_os_str().to_string_lossy()))
+    }
+}
+
+/// One match, positioned by line and column for jumping to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch {
+    /// Zero-based line number
+    pub line: usize,
+    /// Zero-based character columns of the match on its line
+    pub start_column: usize,
+    pub end_column: usize,
+    /// The matched text, to check it's still there when replacing
+    pub matched: String,
+    /// The whole line, which replacements are expanded in
+    pub line_text: String,
+    /// The line with surrounding whitespace removed, shortened around the match if long
+    pub preview: String,
+    /// Character range of the match within `preview`
+    pub preview_match: (usize, usize),
+}
+
+impl LineMatch {
+    /// The match of characters `start..end` of `line`, line number `line_number`. A range
+    /// past the end of the line is cut short.
+    pub fn new(line_number: usize, line: &str, start: usize, end: usize) -> Self {
+        let end = end.min(line.chars().count());
+        let start = start.min(end);
+        let matched = line.chars().skip(start).take(end - start).collect();
+        let (preview, preview_match) = preview(line, start, end);
+        Self {
+            line: line_number,
+            start_column: start,
+            end_column: end,
+            matched,
+            line_text: line.to_string(),
+            preview,
+            preview_match,
+        }
+    }
+}
+
+/// The matches in one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatches {
+    /// Relative to the search folder
+    pub path: PathBuf,
+    pub matches: Vec<LineMatch>,
+}
+
+/// Every match of `search` in `text`, line by line.
+pub fn search_text(search: &Search, text: &str) -> Vec<LineMatch> {
+    let mut matches = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        for m in search.find_all(line) {
+            matches.push(LineMatch::new(line_number, line, m.start, m.end));
+        }
+    }
+    matches
+}
+
+/// `line` trimmed for the results list, and where the match at `start..end` (in characters)
+/// ends up in it.
+fn preview(line: &str, start: usize, end: usize) -> (String, (usize, usize)) {
+    let chars: Vec<char> = line.chars().collect();
+    let first = chars.iter().position(|c| !c.is_whitespace()).unwrap_or(chars.len()).min(start);
+    let last = chars.iter().rposition(|c| !c.is_whitespace()).map_or(0, |i| i + 1).max(end);
+    let from = first.max(start.saturating_sub(PREVIEW_CONTEXT_CHARS));
+    let to = last.min(end + PREVIEW_CONTEXT_CHARS);
+
+    let mut preview = String::new();
+    let mut offset = from;
+    if from > first {
+        preview.push('…');
+        offset -= 1;
+    }
+    preview.extend(&chars[from..to]);
+    if to < last {
+        preview.push('…');
+    }
+    (preview, (start - offset, end - offset))
+}
+
+/// Matches in the file at `path`, or `None` if it can't be read, is too large or looks binary.
+pub fn search_file(search: &Search, path: &Path) -> Option<Vec<LineMatch>> {
+    if std::fs::metadata(path).ok()?.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let (text, _) = file_format::decode(&std::fs::read(path).ok()?);
+    if text.contains('\0') {
+        return None;
+    }
+    Some(search_text(search, &text))
+}
+
+/// Search the files under `root` that `filter` lets through, skipping what Quick Open skips
+/// (`.gitignore`d and hidden files) but not stopping at its file limit. Each file with
+/// matches is passed to `found` as soon as it's searched. Returns whether the search stopped
+/// at `MAX_MATCHES`.
+///
+/// Runs until done or `cancelled` is set; meant for a background thread.
+pub fn search_files(
+    root: &Path,
+    settings: &Settings,
+    filter: &FileFilter,
+    search: &Search,
+    cancelled: &AtomicBool,
+    mut found: impl FnMut(FileMatches),
+) -> bool {
+    let mut total = 0;
+    let mut truncated = false;
+    quick_open::walk_files(root, settings, |path| {
+        if cancelled.load(Ordering::Relaxed) {
+            return false;
+        }
+        if !filter.matches(&path) {
+            return true;
+        }
+        let Some(mut matches) = search_file(search, &root.join(&path)) else {
+            return true;
+        };
+        if matches.is_empty() {
+            return true;
+        }
+        truncated = total + matches.len() >= MAX_MATCHES;
+        matches.truncate(MAX_MATCHES - total);
+        total += matches.len();
+        found(FileMatches { path, matches });
+        !truncated
+    });
+    truncated
+}
+
+/// A match to replace, positioned like the `LineMatch` it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub line: usize,
+    pub start_column: usize,
+    pub end_column: usize,
+    /// Text the match had when searched; the replacement is skipped if it's changed since
+    pub expected: String,
+    pub text: String,
+}
+
+impl Replacement {
+    pub fn new(m: &LineMatch, text: String) -> Self {
+        Self { line: m.line, start_column: m.start_column, end_column: m.end_column, expected: m.matched.clone(), text }
+    }
+}
+
+/// The replacements chosen in one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReplacements {
+    pub path: PathBuf,
+    pub replacements: Vec<Replacement>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplaceError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("the file changed since it was searched")]
+    Changed,
+    #[error(transparent)]
+    Unencodable(#[from] UnencodableChar),
+}
+
+/// `text` with `replacements` made, or `None` if any of them no longer finds its expected
+/// text, in which case nothing is replaced.
+pub fn apply_replacements(text: &str, replacements: &[Replacement]) -> Option<String> {
+    // Byte offset of each line start, and of the end of the text
+    let mut line_starts: Vec<usize> = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    line_starts.push(text.len());
+
+    let mut spans = Vec::with_capacity(replacements.len());
+    for replacement in replacements {
+        let line_start = *line_starts.get(replacement.line)?;
+        let line = &text[line_start..*line_starts.get(replacement.line + 1)?];
+        let byte_at = |column: usize| line.char_indices().nth(column).map_or(line.len(), |(i, _)| i);
+        let (start, end) = (line_start + byte_at(replacement.start_column), line_start + byte_at(replacement.end_column));
+        if text[start..end] != replacement.expected {
+            return None;
+        }
+        spans.push((start, end, replacement.text.as_str()));
+    }
+
+    spans.sort_by_key(|&(start, _, _)| start);
+    if spans.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+        return None;
+    }
+    let mut replaced = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, end, new_text) in spans {
+        replaced.push_str(&text[copied..start]);
+        replaced.push_str(new_text);
+        copied = end;
+    }
+    replaced.push_str(&text[copied..]);
+    Some(replaced)
+}
+
+/// Make `replacements` in the file at `path` on disk, keeping its encoding and line endings.
+/// The file is replaced atomically, and left alone if any match has changed.
+pub fn replace_in_file(path: &Path, replacements: &[Replacement]) -> Result<(), ReplaceError> {
+    let (text, format) = file_format::read_file(path)?;
+    let replaced = apply_replacements(&text, replacements).ok_or(ReplaceError::Changed)?;
+    file_format::write_atomic(path, &file_format::encode(&replaced, format)?)?;
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// What a line holds, as far as folding cares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Blank,
+    Code,
+    /// Only comments, such as a line of a doc comment block
+    Comment,
+    /// An import, `use` or `#include`
+    Import,
+}
+
+/// One line reduced to what folding looks at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineInfo {
+    pub kind: LineKind,
+    /// Indentation width in columns
+    pub indent: usize,
+    /// Brackets outside strings and comments, in order
+    pub brackets: Vec<char>,
+    /// Whether the first of `brackets` is also the first thing on the line, as in `}`
+    pub starts_with_bracket: bool,
+}
+
+/// A foldable range of lines: folding hides the lines after `start_line` up to `end_line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoldRegion {
+    /// The line that stays visible and gets the fold triangle
+    pub start_line: usize,
+    /// Last line hidden when folded
+    pub end_line: usize,
+    /// 1 for top-level regions, 2 for those directly inside one, and so on
+    pub level: usize,
+}
+
+impl FoldRegion {
+    pub fn contains(&self, line: usize) -> bool {
+        self.start_line <= line && line <= self.end_line
+    }
+}
+
+/// Classify every line of `text`, using `syntax`'s scopes to tell code from strings,
+/// comments and imports.
+pub fn analyze(ss: &SyntaxSet, syntax: &SyntaxReference, text: &str, tab_width: usize) -> Vec<LineInfo> {
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+    for line in text.split_inclusive('\n') {
+        let mut ops = state.parse_line(line, ss).unwrap_or_default().into_iter().peekable();
+        let mut scopes = ScopeFlags::of(&stack);
+        let mut info = LineInfo { kind: LineKind::Blank, indent: indent_columns(line, tab_width), brackets: Vec::new(), starts_with_bracket: false };
+        let (mut has_code, mut has_comment) = (false, false);
+
+        for (offset, c) in line.char_indices() {
+            let mut applied = false;
+            while let Some((_, op)) = ops.next_if(|(at, _)| *at <= offset) {
+                let _ = stack.apply(&op);
+                applied = true;
+            }
+            if applied {
+                scopes = ScopeFlags::of(&stack);
+            }
+            if c.is_whitespace() {
+                continue;
+            }
+            let first = !has_code && !has_comment;
+            if scopes.comment {
+                has_comment = true;
+                continue;
+            }
+            if first {
+                let trimmed = line.trim_start();
+                let is_use = scopes.keyword && (trimmed.starts_with("use ") || trimmed.starts_with("pub use "));
+                if scopes.import || is_use {
+                    info.kind = LineKind::Import;
+                }
+            }
+            has_code = true;
+            if !scopes.string && BRACKETS.iter().any(|&(open, close)| c == open || c == close) {
+                info.starts_with_bracket |= first;
+                info.brackets.push(c);
+            }
+        }
+        for (_, op) in ops {
+            let _ = stack.apply(&op);
+        }
+
+        if info.kind != LineKind::Import {
+            info.kind = match (has_code, has_comment) {
+                (true, _) => LineKind::Code,
+                (false, true) => LineKind::Comment,
+                (false, false) => LineKind::Blank,
+            };
+        }
+        lines.push(info);
+    }
+    lines
+}
+
+/// The scopes at a position that matter for folding, worked out once per scope change.
+#[derive(Clone, Copy)]
+struct ScopeFlags {
+    comment: bool,
+    string: bool,
+    import: bool,
+    keyword: bool,
+}
+
+impl ScopeFlags {
+    fn of(stack: &ScopeStack) -> Self {
+        let mut flags = Self { comment: false, string: false, import: false, keyword: false };
+        for scope in stack.as_slice() {
+            let name = scope.build_string();
+            flags.comment |= name.starts_with("comment");
+            flags.string |= name.starts_with("string");
+            flags.import |= name.contains("import") || name.contains("include");
+            flags.keyword |= name.starts_with("keyword");
+        }
+        flags
+    }
+}
+
+fn indent_columns(line: &str, tab_width: usize) -> usize {
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += tab_width - columns % tab_width,
+            _ => break,
+        }
+    }
+    columns
+}
+
+/// The foldable regions of a file, sorted by start line, at most one per start line. They
+/// come from bracket pairs spanning lines, runs of comment or import lines, and indentation,
+/// in that order of preference when two start on the same line.
+pub fn fold_regions(lines: &[LineInfo]) -> Vec<FoldRegion> {
+    let mut ends = BTreeMap::new();
+    let mut add = |start: usize, mut end: usize| {
+        while end > start && lines[end].kind == LineKind::Blank {
+            end -= 1;
+        }
+        if end > start {
+            ends.entry(start).or_insert(end);
+        }
+    };
+
+    // Brackets; a line starting with the closer stays visible below the fold
+    let mut open: Vec<(char, usize)> = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        for (i, &c) in line.brackets.iter().enumerate() {
+            if let Some(&(_, close)) = BRACKETS.iter().find(|(open, _)| *open == c) {
+                open.push((close, number));
+            } else if let Some(at) = open.iter().rposition(|&(close, _)| close == c) {
+                let start = open[at].1;
+                open.truncate(at);
+                let closer_leads = i == 0 && line.starts_with_bracket;
+                add(start, if closer_leads { number.saturating_sub(1) } else { number });
+            }
+        }
+    }
+
+    // Runs of comment lines, and of import lines with blank lines allowed between them
+    for (kind, allow_blanks) in [(LineKind::Comment, false), (LineKind::Import, true)] {
+        let mut run: Option<(usize, usize)> = None;
+        for (number, line) in lines.iter().enumerate() {
+            if line.kind == kind {
+                run = Some((run.map_or(number, |(start, _)| start), number));
+            } else if !(allow_blanks && line.kind == LineKind::Blank) {
+                if let Some((start, end)) = run.take() {
+                    add(start, end);
+                }
+            }
+        }
+        if let Some((start, end)) = run {
+            add(start, end);
+        }
+    }
+
+    // Indentation: a line followed by more indented ones, up to the next line indented as
+    // little as it
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut last_non_blank = 0;
+    for (number, line) in lines.iter().enumerate().filter(|(_, line)| line.kind != LineKind::Blank) {
+        while let Some(&(indent, start)) = stack.last() {
+            if indent < line.indent {
+                break;
+            }
+            stack.pop();
+            add(start, last_non_blank);
+        }
+        stack.push((line.indent, number));
+        last_non_blank = number;
+    }
+    for (_, start) in stack {
+        add(start, last_non_blank);
+    }
+
+    // Levels from nesting
+    let mut enclosing: Vec<usize> = Vec::new();
+    ends.into_iter()
+        .map(|(start_line, end_line)| {
+            while enclosing.last().is_some_and(|&end| end < start_line) {
+                enclosing.pop();
+            }
+            let level = enclosing.len() + 1;
+            enclosing.push(end_line);
+            FoldRegion { start_line, end_line, level }
+        })
+        .collect()
+}
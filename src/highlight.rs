@@ -1,111 +1,519 @@
 use gtk4::prelude::*;
 use gtk4::TextBuffer;
-use gtk4::TextTag;
-use std::cell::RefCell;
+use gtk4::{glib, TextTag};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::Theme;
-use syntect::parsing::SyntaxSet;
-use syntect::util::LinesWithEndings;
-
-use crate::config;
-
-/// Highlight the contents `text` into `buffer` using syntect's `ss` + `theme`.
-/// Tag objects are cached in `tag_cache` keyed by color string.
-/// This implementation is character-aware and clamps ranges to the buffer length,
-/// and uses forward_chars to build TextIters (safe for GTK).
-pub fn highlight_with_syntect(
-    buffer: &TextBuffer,
-    text: &str,
-    tag_cache: &RefCell<HashMap<String, TextTag>>,
-    ss: &SyntaxSet,
-    theme: &Theme,
-) {
-    // Remove previously-applied tags in the cache
-    let buf_start = buffer.start_iter();
-    let buf_end = buffer.end_iter();
-    for tag in tag_cache.borrow().values() {
-        buffer.remove_tag(tag, &buf_start, &buf_end);
-    }
-
-    // Compute total characters (character count, not bytes)
-    let full_start = buffer.start_iter();
-    let full_end = buffer.end_iter();
-    let full_text = buffer.text(&full_start, &full_end, false);
-    let total_chars: i32 = full_text.chars().count() as i32;
-
-    // Skip highlighting for very large buffers to protect UI
-    if (total_chars as usize) > config::HIGHLIGHT_CHAR_CUTOFF {
-        return;
-    }
-
-    // Choose syntax (for now Rust); caller should only call when appropriate
-    let syntax = ss.find_syntax_by_extension("rs").unwrap_or_else(|| ss.find_syntax_plain_text());
-    let mut h = HighlightLines::new(syntax, theme);
-
-    let mut cumulative_chars: usize = 0;
-    for line in LinesWithEndings::from(text) {
-        let ranges = h.highlight_line(line, ss).unwrap_or_else(|_| vec![]);
-        let mut local_char: usize = 0;
-        for (style, slice) in ranges {
-            if slice.is_empty() {
-                continue;
-            }
-            let slice_chars = slice.chars().count() as i32;
-            let mut start_char = (cumulative_chars + local_char) as i32;
-            let mut end_char = start_char + slice_chars;
+use std::rc::Rc;
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Arc;
+
+use ropey::Rope;
+use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter as ThemeHighlighter, Theme};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+
+/// Lines highlighted on the worker between checks for newer requests.
+const CHUNK_LINES: usize = 400;
+
+/// How far back the viewport pass looks for a cached state before starting from scratch.
+const VIEWPORT_LOOKBACK_LINES: usize = 500;
+
+/// Line-level description of a buffer edit: lines `start_line..=start_line + removed_lines`
+/// were replaced by lines `start_line..=start_line + added_lines`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEdit {
+    pub start_line: usize,
+    pub removed_lines: usize,
+    pub added_lines: usize,
+}
+
+impl LineEdit {
+    /// Map a line index from before this edit to after it.
+    /// Returns `None` for lines touched by the edit, since their old highlighting is meaningless.
+    pub fn map_line(&self, line: usize) -> Option<usize> {
+        if line < self.start_line {
+            Some(line)
+        } else if line > self.start_line + self.removed_lines {
+            Some(line - self.removed_lines + self.added_lines)
+        } else {
+            None
+        }
+    }
+}
+
+/// A coloured run inside one line, in character (not byte) columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledSpan {
+    pub start: usize,
+    pub end: usize,
+    pub color: String,
+}
+
+/// Highlighting result for one line of the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineSpans {
+    pub line: usize,
+    pub spans: Vec<StyledSpan>,
+}
 
-            // clamp to buffer boundaries
-            if start_char < 0 {
-                start_char = 0;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineState {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+/// Incremental syntect highlighter over a `Rope`.
+///
+/// Keeps the parse/highlight state at the start of every line so that an edit only needs to
+/// re-highlight from the first edited line until the state converges with the cached one.
+/// Contains no GTK types so it can live on the worker thread.
+///
+/// In visible-only mode (large files) no per-line cache is kept at all and lines are only
+/// highlighted on request through `highlight_range`.
+pub struct LineHighlighter {
+    ss: Arc<SyntaxSet>,
+    syntax_name: String,
+    theme: Arc<Theme>,
+    visible_only: bool,
+    /// `states[i]` is the state at the start of line `i`, if known.
+    states: Vec<Option<LineState>>,
+    /// `(from, until)`: lines from `from` onwards need highlighting, and the pass may only stop
+    /// on convergence once it is past `until`.
+    dirty: Option<(usize, usize)>,
+}
+
+impl LineHighlighter {
+    pub fn with_mode(
+        ss: Arc<SyntaxSet>,
+        syntax_name: &str,
+        theme: Arc<Theme>,
+        line_count: usize,
+        visible_only: bool,
+    ) -> Self {
+        let mut highlighter = Self {
+            ss,
+            syntax_name: syntax_name.to_string(),
+            theme,
+            visible_only,
+            states: Vec::new(),
+            dirty: None,
+        };
+        highlighter.reset(line_count);
+        highlighter
+    }
+
+    /// Forget all cached state and mark the whole document dirty.
+    pub fn reset(&mut self, line_count: usize) {
+        if self.visible_only {
+            self.states = Vec::new();
+            self.dirty = None;
+            return;
+        }
+        self.states = vec![None; line_count.max(1)];
+        self.states[0] = Some(self.initial_state());
+        self.dirty = Some((0, 0));
+    }
+
+    pub fn is_visible_only(&self) -> bool {
+        self.visible_only
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// First line still waiting to be highlighted, if any.
+    pub fn dirty_start(&self) -> Option<usize> {
+        self.dirty.map(|(from, _)| from)
+    }
+
+    /// Splice the state cache for an edit and mark the edited lines dirty.
+    pub fn apply_edit(&mut self, edit: LineEdit) {
+        if self.visible_only {
+            return;
+        }
+        let first_removed = (edit.start_line + 1).min(self.states.len());
+        let last_removed = (edit.start_line + 1 + edit.removed_lines).min(self.states.len());
+        self.states.splice(
+            first_removed..last_removed,
+            std::iter::repeat_n(None, edit.added_lines),
+        );
+
+        let edit_end = edit.start_line + edit.added_lines;
+        self.dirty = Some(match self.dirty {
+            None => (edit.start_line, edit_end),
+            Some((from, until)) => {
+                let until = edit.map_line(until).unwrap_or(edit_end).max(edit_end);
+                (from.min(edit.start_line), until)
             }
-            if end_char < 0 {
-                end_char = 0;
+        });
+    }
+
+    /// Highlight at most `max_lines` dirty lines, stopping early once the state after a line
+    /// matches the cached state for the next line (everything after it is still valid).
+    pub fn highlight_dirty(&mut self, rope: &Rope, max_lines: usize) -> Vec<LineSpans> {
+        let Some((from, until)) = self.dirty else {
+            return Vec::new();
+        };
+
+        // The cache must always mirror the rope; resynchronise defensively if it does not.
+        if self.states.len() != rope.len_lines() {
+            self.states.resize(rope.len_lines().max(1), None);
+        }
+
+        let (start, mut state) = self.nearest_state(from, usize::MAX);
+        let theme = self.theme.clone();
+        let highlighter = ThemeHighlighter::new(&theme);
+        let mut results = Vec::new();
+
+        let mut line_idx = start;
+        while line_idx < self.states.len() {
+            let line = rope.line(line_idx).to_string();
+            let spans = Self::highlight_line(&self.ss, &highlighter, &mut state, &line);
+            results.push(LineSpans { line: line_idx, spans });
+
+            let next = line_idx + 1;
+            if next == self.states.len() {
+                self.dirty = None;
+                return results;
             }
-            if start_char > total_chars {
-                start_char = total_chars;
+            if line_idx >= until && self.states[next].as_ref() == Some(&state) {
+                self.dirty = None;
+                return results;
             }
-            if end_char > total_chars {
-                end_char = total_chars;
+            self.states[next] = Some(state.clone());
+            line_idx = next;
+
+            if results.len() >= max_lines {
+                self.dirty = Some((line_idx, until));
+                return results;
             }
-            if end_char <= start_char {
-                local_char += slice_chars as usize;
-                continue;
+        }
+
+        self.dirty = None;
+        results
+    }
+
+    /// Highlight `first..=last` without touching the cache, starting from the nearest cached
+    /// state if one is close enough and from a fresh state otherwise.
+    ///
+    /// Used to colour the viewport before the sequential pass reaches it; the colours may be
+    /// wrong inside constructs opened above the viewport until that pass catches up.
+    pub fn highlight_range(&self, rope: &Rope, first: usize, last: usize) -> Vec<LineSpans> {
+        let last = last.min(rope.len_lines().saturating_sub(1));
+        let (start, mut state) = self.nearest_state(first, VIEWPORT_LOOKBACK_LINES);
+        let highlighter = ThemeHighlighter::new(&self.theme);
+
+        let mut results = Vec::new();
+        for line_idx in start..=last {
+            let line = rope.line(line_idx).to_string();
+            let spans = Self::highlight_line(&self.ss, &highlighter, &mut state, &line);
+            if line_idx >= first {
+                results.push(LineSpans { line: line_idx, spans });
             }
+        }
+        results
+    }
+
+    /// Closest cached state at or before `line`, looking back at most `lookback` lines.
+    fn nearest_state(&self, line: usize, lookback: usize) -> (usize, LineState) {
+        let lowest = line.saturating_sub(lookback);
+        (lowest..=line)
+            .rev()
+            .find_map(|i| self.states.get(i).and_then(|s| s.clone()).map(|s| (i, s)))
+            .unwrap_or_else(|| (line, self.initial_state()))
+    }
+
+    fn initial_state(&self) -> LineState {
+        let syntax = self
+            .ss
+            .find_syntax_by_name(&self.syntax_name)
+            .unwrap_or_else(|| self.ss.find_syntax_plain_text());
+        let highlighter = ThemeHighlighter::new(&self.theme);
+        LineState {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+        }
+    }
 
-            if style.foreground.a > 0 {
+    fn highlight_line(
+        ss: &SyntaxSet,
+        highlighter: &ThemeHighlighter,
+        state: &mut LineState,
+        line: &str,
+    ) -> Vec<StyledSpan> {
+        let ops = match state.parse.parse_line(line, ss) {
+            Ok(ops) => ops,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut spans = Vec::new();
+        let mut col = 0;
+        for (style, piece) in HighlightIterator::new(&mut state.highlight, &ops, line, highlighter) {
+            let len = piece.chars().count();
+            if len > 0 && style.foreground.a > 0 {
                 let fg = style.foreground;
-                let color = format!("#{:02X}{:02X}{:02X}", fg.r, fg.g, fg.b);
-
-                // Lookup or create tag in cache
-                let tag = {
-                    let mut cache = tag_cache.borrow_mut();
-                    if let Some(existing) = cache.get(&color) {
-                        existing.clone()
-                    } else {
-                        let t = TextTag::builder()
-                            .name(&format!("syn_{}", color.trim_start_matches('#')))
-                            .foreground(&color)
-                            .build();
-                        buffer.tag_table().add(&t);
-                        cache.insert(color.clone(), t.clone());
-                        t
+                spans.push(StyledSpan {
+                    start: col,
+                    end: col + len,
+                    color: format!("#{:02X}{:02X}{:02X}", fg.r, fg.g, fg.b),
+                });
+            }
+            col += len;
+        }
+        spans
+    }
+}
+
+/// Requests sent from the editor (main thread) to its highlight worker.
+pub enum HighlightRequest {
+    /// Start over with a new document, syntax, theme or mode.
+    Reset {
+        version: u64,
+        rope: Rope,
+        syntax_name: String,
+        theme: Arc<Theme>,
+        visible_only: bool,
+    },
+    /// The buffer was edited; `rope` is the text after the edit.
+    Edit { version: u64, rope: Rope, edit: LineEdit },
+    /// Lines currently on screen, highlighted first.
+    Viewport { first: usize, last: usize },
+}
+
+/// Highlighting results for the document as of `version`.
+pub struct HighlightBatch {
+    pub version: u64,
+    pub lines: Vec<LineSpans>,
+}
+
+fn run_worker(ss: Arc<SyntaxSet>, requests: mpsc::Receiver<HighlightRequest>, results: glib::Sender<HighlightBatch>) {
+    let mut engine: Option<LineHighlighter> = None;
+    let mut rope = Rope::new();
+    let mut version = 0;
+    let mut viewport: Option<(usize, usize)> = None;
+    let mut viewport_pending = false;
+
+    loop {
+        // Block while there is nothing to do; otherwise only drain what is already queued
+        let busy = viewport_pending || engine.as_ref().is_some_and(|e| e.is_dirty());
+        let first = if busy {
+            match requests.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        } else {
+            match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return,
+            }
+        };
+
+        let queued = first.into_iter().chain(std::iter::from_fn(|| requests.try_recv().ok()));
+        for request in queued {
+            match request {
+                HighlightRequest::Reset { version: v, rope: r, syntax_name, theme, visible_only } => {
+                    engine = Some(LineHighlighter::with_mode(
+                        ss.clone(),
+                        &syntax_name,
+                        theme,
+                        r.len_lines(),
+                        visible_only,
+                    ));
+                    rope = r;
+                    version = v;
+                    viewport_pending = true;
+                }
+                HighlightRequest::Edit { version: v, rope: r, edit } => {
+                    if let Some(engine) = engine.as_mut() {
+                        engine.apply_edit(edit);
+                        // Without a cache, edits are only ever reflected through the viewport
+                        viewport_pending |= engine.is_visible_only();
+                    }
+                    rope = r;
+                    version = v;
+                }
+                HighlightRequest::Viewport { first, last } => {
+                    viewport = Some((first, last));
+                    viewport_pending = true;
+                }
+            }
+        }
+
+        let Some(engine) = engine.as_mut() else {
+            viewport_pending = false;
+            continue;
+        };
+
+        // Colour the visible lines right away if the sequential pass is far from reaching them
+        if viewport_pending {
+            viewport_pending = false;
+            if let Some((first, last)) = viewport {
+                let far_ahead = engine.dirty_start().is_some_and(|d| d + CHUNK_LINES < first);
+                if engine.is_visible_only() || far_ahead {
+                    let lines = engine.highlight_range(&rope, first, last);
+                    if results.send(HighlightBatch { version, lines }).is_err() {
+                        return;
                     }
-                };
+                }
+            }
+        }
+
+        if engine.is_dirty() {
+            let lines = engine.highlight_dirty(&rope, CHUNK_LINES);
+            if results.send(HighlightBatch { version, lines }).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Main-thread side of the highlighter: forwards edits to a worker thread and applies the
+/// returned spans to the `TextBuffer` line by line.
+pub struct Highlighter {
+    requests: mpsc::Sender<HighlightRequest>,
+    version: Cell<u64>,
+    applier: Rc<BatchApplier>,
+}
+
+impl Highlighter {
+    pub fn new(ss: Arc<SyntaxSet>, buffer: &TextBuffer, tag_cache: Rc<RefCell<HashMap<String, TextTag>>>) -> Self {
+        let (request_tx, request_rx) = mpsc::channel();
+        let (batch_tx, batch_rx) = glib::MainContext::channel::<HighlightBatch>(glib::Priority::default());
+
+        std::thread::Builder::new()
+            .name("highlight".to_string())
+            .spawn(move || run_worker(ss, request_rx, batch_tx))
+            .expect("failed to spawn highlight thread");
+
+        let applier = Rc::new(BatchApplier {
+            buffer: buffer.clone(),
+            tag_cache,
+            edits: RefCell::new(Vec::new()),
+            reset_version: Cell::new(0),
+        });
+
+        let applier_cl = applier.clone();
+        batch_rx.attach(None, move |batch| {
+            applier_cl.apply(batch);
+            glib::Continue(true)
+        });
+
+        Self {
+            requests: request_tx,
+            version: Cell::new(0),
+            applier,
+        }
+    }
+
+    fn next_version(&self) -> u64 {
+        let version = self.version.get() + 1;
+        self.version.set(version);
+        version
+    }
+
+    /// Re-highlight the whole document, e.g. after loading it or changing syntax/theme.
+    /// With `visible_only` (large-file mode) only the lines on screen are ever highlighted.
+    pub fn reset(&self, rope: Rope, syntax_name: &str, theme: Arc<Theme>, visible_only: bool) {
+        let version = self.next_version();
+        self.applier.reset_version.set(version);
+        self.applier.edits.borrow_mut().clear();
+        let _ = self.requests.send(HighlightRequest::Reset {
+            version,
+            rope,
+            syntax_name: syntax_name.to_string(),
+            theme,
+            visible_only,
+        });
+    }
+
+    /// Report an edit; `rope` must already contain it.
+    pub fn edit(&self, rope: Rope, edit: LineEdit) {
+        let version = self.next_version();
+        self.applier.edits.borrow_mut().push((version, edit));
+        let _ = self.requests.send(HighlightRequest::Edit { version, rope, edit });
+    }
+
+    pub fn set_viewport(&self, first: usize, last: usize) {
+        let _ = self.requests.send(HighlightRequest::Viewport { first, last });
+    }
+}
+
+struct BatchApplier {
+    buffer: TextBuffer,
+    tag_cache: Rc<RefCell<HashMap<String, TextTag>>>,
+    /// Edits the worker may not have seen yet, used to map stale line numbers forward.
+    edits: RefCell<Vec<(u64, LineEdit)>>,
+    reset_version: Cell<u64>,
+}
+
+impl BatchApplier {
+    fn apply(&self, batch: HighlightBatch) {
+        if batch.version < self.reset_version.get() {
+            return;
+        }
 
-                // Build character-aware iters by advancing from start_iter()
-                let mut it_start = buffer.start_iter();
-                if start_char > 0 {
-                    let _ = it_start.forward_chars(start_char);
+        {
+            let edits = self.edits.borrow();
+            let newer: Vec<&LineEdit> = edits
+                .iter()
+                .filter(|(v, _)| *v > batch.version)
+                .map(|(_, e)| e)
+                .collect();
+
+            for line in &batch.lines {
+                let mapped = newer.iter().try_fold(line.line, |l, e| e.map_line(l));
+                if let Some(target) = mapped {
+                    self.apply_line(target, &line.spans);
                 }
-                let mut it_end = it_start.clone();
-                let _ = it_end.forward_chars(end_char - start_char);
+            }
+        }
+
+        self.edits.borrow_mut().retain(|(v, _)| *v > batch.version);
+    }
+
+    fn apply_line(&self, line: usize, spans: &[StyledSpan]) {
+        let Some(start) = self.buffer.iter_at_line(line as i32) else {
+            return;
+        };
+        let mut end = start;
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
 
-                buffer.apply_tag(&tag, &it_start, &it_end);
+        for tag in self.tag_cache.borrow().values() {
+            self.buffer.remove_tag(tag, &start, &end);
+        }
+
+        let line_chars = end.line_offset() as usize;
+        for span in spans {
+            let s = span.start.min(line_chars);
+            let e = span.end.min(line_chars);
+            if e <= s {
+                continue;
             }
+            let tag = self.tag_for(&span.color);
+            if let (Some(it_start), Some(it_end)) = (
+                self.buffer.iter_at_line_offset(line as i32, s as i32),
+                self.buffer.iter_at_line_offset(line as i32, e as i32),
+            ) {
+                self.buffer.apply_tag(&tag, &it_start, &it_end);
+            }
+        }
+    }
 
-            local_char += slice_chars as usize;
+    /// Lookup or create the foreground tag for `color` in the cache
+    fn tag_for(&self, color: &str) -> TextTag {
+        let mut cache = self.tag_cache.borrow_mut();
+        if let Some(existing) = cache.get(color) {
+            return existing.clone();
         }
-        cumulative_chars += line.chars().count();
+        let tag = TextTag::builder()
+            .name(format!("syn_{}", color.trim_start_matches('#')))
+            .foreground(color)
+            .build();
+        self.buffer.tag_table().add(&tag);
+        cache.insert(color.to_string(), tag.clone());
+        tag
     }
-}
\ No newline at end of file
+}
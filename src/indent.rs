@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+// Only the start of a file is looked at when detecting its indentation
+pub const DETECT_MAX_LINES: usize = 1000;
+
+/// How one level of indentation is written: a hard tab or `width` spaces.
+///
+/// `width` is also the display width of a tab, used to measure mixed indentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndentStyle {
+    pub use_tabs: bool,
+    pub width: usize,
+}
+
+impl IndentStyle {
+    pub fn spaces(width: usize) -> Self {
+        Self { use_tabs: false, width: width.max(1) }
+    }
+
+    pub fn tabs(width: usize) -> Self {
+        Self { use_tabs: true, width: width.max(1) }
+    }
+
+    /// Text inserted for one level of indentation.
+    pub fn unit(&self) -> String {
+        if self.use_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.width)
+        }
+    }
+
+    /// Visual width of leading whitespace, with tabs advancing to the next tab stop.
+    pub fn columns(&self, whitespace: &str) -> usize {
+        whitespace.chars().fold(0, |col, c| match c {
+            '\t' => (col / self.width + 1) * self.width,
+            _ => col + 1,
+        })
+    }
+
+    /// Whitespace spanning `columns`, written in this style.
+    pub fn whitespace_for(&self, columns: usize) -> String {
+        if self.use_tabs {
+            let mut s = "\t".repeat(columns / self.width);
+            s.push_str(&" ".repeat(columns % self.width));
+            s
+        } else {
+            " ".repeat(columns)
+        }
+    }
+
+    /// `whitespace` moved back to the previous indentation stop.
+    pub fn dedent(&self, whitespace: &str) -> String {
+        let columns = self.columns(whitespace);
+        let target = columns.saturating_sub(1) / self.width * self.width;
+        self.whitespace_for(target)
+    }
+
+    /// Rewrite the leading whitespace of every line of `text` in this style.
+    pub fn convert_text(&self, text: &str) -> String {
+        text.split_inclusive('\n')
+            .map(|line| {
+                let ws = leading_whitespace(line);
+                let mut out = self.whitespace_for(self.columns(ws));
+                out.push_str(&line[ws.len()..]);
+                out
+            })
+            .collect()
+    }
+}
+
+/// Leading spaces and tabs of `line`.
+pub fn leading_whitespace(line: &str) -> &str {
+    let end = line.find(|c| c != ' ' && c != '\t').unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Built-in per-language conventions, used unless the settings override them.
+pub fn language_default(syntax_name: &str, tab_width: usize) -> Option<IndentStyle> {
+    match syntax_name {
+        "YAML" | "JavaScript" | "JSON" | "Ruby" => Some(IndentStyle::spaces(2)),
+        // Recipes must start with a tab; gofmt always uses tabs
+        "Makefile" | "Go" => Some(IndentStyle::tabs(tab_width)),
+        _ => None,
+    }
+}
+
+/// Indentation a file already uses, guessed from its first lines.
+///
+/// Tabs win if more lines start with a tab than with spaces; otherwise the width is the most
+/// common change in indentation between consecutive lines.
+pub fn detect(text: &str) -> Option<IndentStyle> {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut deltas: HashMap<usize, usize> = HashMap::new();
+    let mut previous = 0;
+
+    for line in text.lines().take(DETECT_MAX_LINES) {
+        let trimmed = line.trim_start();
+        // Skip blank lines and the ` * ` continuation lines of block comments
+        if trimmed.is_empty() || trimmed.starts_with('*') {
+            continue;
+        }
+
+        if line.starts_with('\t') {
+            tab_lines += 1;
+            previous = 0;
+            continue;
+        }
+
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        if spaces > 0 {
+            space_lines += 1;
+        }
+        let delta = spaces.abs_diff(previous);
+        // Single-space steps are alignment, not indentation
+        if (2..=8).contains(&delta) {
+            *deltas.entry(delta).or_default() += 1;
+        }
+        previous = spaces;
+    }
+
+    if tab_lines > space_lines {
+        // The tab width can't be seen in the file; `resolve` fills in the configured one
+        return Some(IndentStyle::tabs(1));
+    }
+    deltas
+        .into_iter()
+        .max_by(|(w1, n1), (w2, n2)| n1.cmp(n2).then(w2.cmp(w1)))
+        .map(|(width, _)| IndentStyle::spaces(width))
+}
+
+/// Indentation for a buffer: what the file already uses, else the user's rule for the
+/// language, else the built-in rule for the language, else the global settings.
+pub fn resolve(settings: &Settings, syntax_name: &str, sample: &str) -> IndentStyle {
+    let configured = settings
+        .language_indent
+        .get(syntax_name)
+        .copied()
+        .or_else(|| language_default(syntax_name, settings.tab_width))
+        .unwrap_or(IndentStyle { use_tabs: !settings.insert_spaces, width: settings.tab_width.max(1) });
+
+    match detect(sample) {
+        // Tab width isn't visible in the file, so keep the configured one
+        Some(style) if style.use_tabs => IndentStyle::tabs(configured.width),
+        Some(style) => style,
+        None => configured,
+    }
+}
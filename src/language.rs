@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+/// Number of lines at the top and bottom of a document searched for a modeline.
+const MODELINE_SEARCH_LINES: usize = 5;
+
+/// Choose the syntax definition for a document.
+///
+/// Resolution order:
+/// 1. an explicit user override (syntax name, e.g. "Python")
+/// 2. a vim/emacs modeline in the first or last few lines
+/// 3. the file name / extension of `path` (same rules as `SyntaxSet::find_syntax_for_file`)
+/// 4. the first line of the text (shebangs, `<?xml`, emacs `-*- mode -*-` headers)
+/// 5. plain text
+pub fn detect_syntax<'a>(
+    ss: &'a SyntaxSet,
+    override_name: Option<&str>,
+    path: Option<&Path>,
+    text: &str,
+) -> &'a SyntaxReference {
+    if let Some(syntax) = override_name.and_then(|name| ss.find_syntax_by_name(name)) {
+        return syntax;
+    }
+
+    if let Some(syntax) = modeline_language(text).and_then(|token| ss.find_syntax_by_token(&token)) {
+        return syntax;
+    }
+
+    if let Some(syntax) = path.and_then(|p| syntax_for_path(ss, p)) {
+        return syntax;
+    }
+
+    let first_line = text.lines().next().unwrap_or("");
+    if let Some(syntax) = ss.find_syntax_by_first_line(first_line) {
+        return syntax;
+    }
+
+    ss.find_syntax_plain_text()
+}
+
+/// Look up a syntax by file name (e.g. `Makefile`, `Gemfile`) and then by extension.
+///
+/// This mirrors the path half of `SyntaxSet::find_syntax_for_file` without touching the disk,
+/// because the buffer contents (not the file on disk) are what gets highlighted.
+fn syntax_for_path<'a>(ss: &'a SyntaxSet, path: &Path) -> Option<&'a SyntaxReference> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    ss.find_syntax_by_extension(file_name)
+        .or_else(|| ss.find_syntax_by_extension(extension))
+}
+
+/// Extract a language token from a vim (`vim: set ft=python:`) or emacs (`-*- mode: ruby -*-`)
+/// modeline in the first or last few lines of `text`.
+pub fn modeline_language(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let head = lines.iter().take(MODELINE_SEARCH_LINES);
+    let tail = lines.iter().rev().take(MODELINE_SEARCH_LINES);
+
+    head.chain(tail)
+        .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+}
+
+fn vim_modeline(line: &str) -> Option<String> {
+    // As in Vim, a marker only counts at the start of the line or after whitespace, so the
+    // `ex:` in `regex:` isn't one
+    let idx = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|&(i, _)| i == 0 || line[..i].ends_with(char::is_whitespace))
+            .map(|(i, _)| i + marker.len())
+    })?;
+
+    line[idx..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            match key {
+                "ft" | "filetype" | "syn" | "syntax" if !value.is_empty() => Some(value.to_string()),
+                _ => None,
+            }
+        })
+}
+
+fn emacs_modeline(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let body = line[start..end].trim();
+
+    // Either `-*- rust -*-` or `-*- mode: rust; coding: utf-8 -*-`
+    if !body.contains(':') {
+        return (!body.is_empty()).then(|| body.to_string());
+    }
+
+    body.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case("mode") {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Names of all user-selectable syntaxes, sorted case-insensitively for the language menu.
+pub fn language_names(ss: &SyntaxSet) -> Vec<String> {
+    let mut names: Vec<String> = ss
+        .syntaxes()
+        .iter()
+        .filter(|s| !s.hidden)
+        .map(|s| s.name.clone())
+        .collect();
+    names.sort_by_key(|n| n.to_lowercase());
+    names.dedup();
+    names
+}
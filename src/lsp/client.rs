@@ -0,0 +1,634 @@
+//! One language server process, for one language and project root, and the documents open
+//! in it. The process is restarted when it crashes, up to `MAX_RESTARTS` times in
+//! `RESTART_WINDOW`.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ropey::Rope;
+use serde_json::{json, Value};
+
+use super::protocol::{self, PositionEncoding};
+use super::transport::{self, Incoming, ResponseError};
+use super::ServerConfig;
+
+/// Crashes tolerated within `RESTART_WINDOW` before giving up on a server
+pub const MAX_RESTARTS: usize = 5;
+pub const RESTART_WINDOW: Duration = Duration::from_secs(3 * 60);
+/// Time a server gets to exit once its connection is dropped before it's killed
+const EXIT_GRACE: Duration = Duration::from_secs(2);
+
+/// A message read from a server, or `None` once its output ended, tagged with the
+/// connection it came from so late messages of a crashed process are told apart from its
+/// replacement's.
+#[derive(Debug)]
+pub struct Event {
+    pub connection: u64,
+    pub message: Option<Value>,
+}
+
+/// Where a connection's reader thread delivers `Event`s, e.g. a glib channel to the main loop,
+/// which passes them to `LspClient::handle`.
+pub type Sink = Box<dyn Fn(Event) + Send>;
+
+pub type Callback = Box<dyn FnOnce(Result<Value, ResponseError>)>;
+type NotificationHandler = Rc<dyn Fn(&str, &Value)>;
+type RequestHandler = Rc<dyn Fn(&str, &Value) -> Option<Value>>;
+type StatusHandler = Rc<dyn Fn(&Status)>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Status {
+    Starting,
+    Running,
+    #[default]
+    Stopped,
+    /// Couldn't be started or crashed too often; not restarted
+    Failed(String),
+}
+
+/// How the server wants document changes sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum SyncKind {
+    #[default]
+    None,
+    Full,
+    Incremental,
+}
+
+/// A document's connection to the server for its language, kept by the `Editor` showing it.
+#[derive(Clone)]
+pub struct Attachment {
+    pub client: Rc<LspClient>,
+    pub uri: String,
+}
+
+/// A running server process. Messages to it are written by a thread of their own, so a
+/// server that's slow to read never blocks the UI.
+struct Connection {
+    id: u64,
+    child: Option<Child>,
+    outgoing: mpsc::Sender<Value>,
+}
+
+impl Connection {
+    fn spawn(config: &ServerConfig, root: &Path, id: u64, sink: Sink) -> io::Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("no pipes to the server"));
+        };
+
+        let (outgoing, messages) = mpsc::channel::<Value>();
+        thread::spawn(move || {
+            let mut stdin = BufWriter::new(stdin);
+            for message in messages {
+                if transport::write_message(&mut stdin, &message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let command = config.command.clone();
+        thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            loop {
+                match transport::read_message(&mut stdout) {
+                    Ok(Some(message)) => sink(Event { connection: id, message: Some(message) }),
+                    Ok(None) => break,
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        eprintln!("Warning: ignoring malformed message from {}: {}", command, e);
+                    }
+                    Err(_) => break,
+                }
+            }
+            sink(Event { connection: id, message: None });
+        });
+
+        Ok(Self { id, child: Some(child), outgoing })
+    }
+
+    fn send(&self, message: Value) {
+        // A closed channel means the server is gone; its exit event is on the way
+        let _ = self.outgoing.send(message);
+    }
+}
+
+impl Drop for Connection {
+    /// Dropping the sender closes the server's stdin, which servers take as a cue to exit;
+    /// one that doesn't is killed after `EXIT_GRACE`.
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+        thread::spawn(move || {
+            let deadline = Instant::now() + EXIT_GRACE;
+            while Instant::now() < deadline {
+                if !matches!(child.try_wait(), Ok(None)) {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+    }
+}
+
+/// A document as the server knows it.
+struct OpenDocument {
+    language_id: String,
+    version: i64,
+    text: Rope,
+}
+
+#[derive(Default)]
+struct State {
+    status: Status,
+    connection: Option<Connection>,
+    /// Connections started so far, numbering them
+    connections: u64,
+    /// Set once the server answered `initialize`; until then requests wait in `queued`
+    initialized: bool,
+    queued: Vec<Value>,
+    next_id: i64,
+    pending: HashMap<i64, Callback>,
+    capabilities: Value,
+    sync: SyncKind,
+    encoding: PositionEncoding,
+    documents: BTreeMap<String, OpenDocument>,
+    /// Times of recent crashes, for `MAX_RESTARTS`
+    crashes: VecDeque<Instant>,
+    shutting_down: bool,
+}
+
+/// Client of one language server process, living on the main thread.
+///
+/// Documents are opened with `open_document` and kept in sync by calling `did_insert` and
+/// `did_delete` for every edit, before the edit is applied to the caller's text. Messages
+/// from the server must be fed back through `handle`.
+pub struct LspClient {
+    pub language_id: String,
+    pub root: PathBuf,
+    config: ServerConfig,
+    make_sink: Box<dyn Fn() -> Sink>,
+    state: RefCell<State>,
+    notification_handlers: RefCell<Vec<NotificationHandler>>,
+    request_handlers: RefCell<Vec<RequestHandler>>,
+    status_handlers: RefCell<Vec<StatusHandler>>,
+}
+
+impl LspClient {
+    /// A client for `config` run in `root`; `make_sink` is called for each process started.
+    /// Nothing runs until `start`.
+    pub fn new(language_id: &str, config: ServerConfig, root: PathBuf, make_sink: impl Fn() -> Sink + 'static) -> Rc<Self> {
+        Rc::new(Self {
+            language_id: language_id.to_string(),
+            root,
+            config,
+            make_sink: Box::new(make_sink),
+            state: RefCell::new(State::default()),
+            notification_handlers: RefCell::new(Vec::new()),
+            request_handlers: RefCell::new(Vec::new()),
+            status_handlers: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Start the server process and initialize it. Open documents are sent to it once it's
+    /// initialized.
+    pub fn start(self: &Rc<Self>) {
+        let spawned = {
+            let mut state = self.state.borrow_mut();
+            state.connections += 1;
+            state.initialized = false;
+            state.shutting_down = false;
+            let spawned = Connection::spawn(&self.config, &self.root, state.connections, (self.make_sink)());
+            spawned.map(|connection| state.connection = Some(connection))
+        };
+        if let Err(e) = spawned {
+            eprintln!("Warning: could not start language server {}: {}", self.config.command, e);
+            self.set_status(Status::Failed(format!("Could not start {}: {}", self.config.command, e)));
+            return;
+        }
+        self.set_status(Status::Starting);
+
+        let client = Rc::downgrade(self);
+        self.send_request("initialize", initialize_params(&self.root), Box::new(move |result| {
+            if let Some(client) = client.upgrade() {
+                client.initialized(result);
+            }
+        }));
+    }
+
+    #[cfg(test)]
+    pub fn status(&self) -> Status {
+        self.state.borrow().status.clone()
+    }
+
+    /// What the server said it supports in its `initialize` response.
+    pub fn capabilities(&self) -> Value {
+        self.state.borrow().capabilities.clone()
+    }
+
+    /// Unit of the columns in positions exchanged with the server.
+    pub fn encoding(&self) -> PositionEncoding {
+        self.state.borrow().encoding
+    }
+
+    /// The text of `uri` as last sent to the server, if it's open.
+    pub fn document_text(&self, uri: &str) -> Option<Rope> {
+        self.state.borrow().documents.get(uri).map(|document| document.text.clone())
+    }
+
+    /// The version of `uri` last sent to the server, if it's open.
+    pub fn document_version(&self, uri: &str) -> Option<i64> {
+        self.state.borrow().documents.get(uri).map(|document| document.version)
+    }
+
+    /// Call `handler` with the method and params of every notification from the server.
+    pub fn on_notification(&self, handler: impl Fn(&str, &Value) + 'static) {
+        self.notification_handlers.borrow_mut().push(Rc::new(handler));
+    }
+
+    /// Let `handler` answer requests from the server, such as `workspace/applyEdit`, before
+    /// the client's own answers: it gets the method and params and returns the result, or
+    /// `None` to leave the request to others.
+    pub fn on_request(&self, handler: impl Fn(&str, &Value) -> Option<Value> + 'static) {
+        self.request_handlers.borrow_mut().push(Rc::new(handler));
+    }
+
+    pub fn on_status(&self, handler: impl Fn(&Status) + 'static) {
+        self.status_handlers.borrow_mut().push(Rc::new(handler));
+    }
+
+    /// Tell the server about a document now open with `text`.
+    pub fn open_document(&self, uri: &str, text: Rope) {
+        let mut state = self.state.borrow_mut();
+        let document = OpenDocument { language_id: self.language_id.clone(), version: 0, text };
+        if state.initialized {
+            let message = did_open(uri, &document);
+            send(&state, message);
+        }
+        state.documents.insert(uri.to_string(), document);
+    }
+
+    pub fn close_document(&self, uri: &str) {
+        let mut state = self.state.borrow_mut();
+        if state.documents.remove(uri).is_some() && state.initialized {
+            send(&state, notification("textDocument/didClose", json!({ "textDocument": { "uri": uri } })));
+        }
+    }
+
+    /// `text` is about to be inserted at char `offset` of the document.
+    pub fn did_insert(&self, uri: &str, offset: usize, text: &str) {
+        self.did_change(uri, |document, encoding| {
+            let offset = offset.min(document.len_chars());
+            let change = protocol::insert_change(document, offset, text, encoding);
+            document.insert(offset, text);
+            change
+        });
+    }
+
+    /// Chars `start..end` of the document are about to be deleted.
+    pub fn did_delete(&self, uri: &str, start: usize, end: usize) {
+        self.did_change(uri, |document, encoding| {
+            let end = end.min(document.len_chars());
+            let start = start.min(end);
+            let change = protocol::delete_change(document, start, end, encoding);
+            document.remove(start..end);
+            change
+        });
+    }
+
+    fn did_change(&self, uri: &str, edit: impl FnOnce(&mut Rope, PositionEncoding) -> Value) {
+        let mut guard = self.state.borrow_mut();
+        let state = &mut *guard;
+        let Some(document) = state.documents.get_mut(uri) else {
+            return;
+        };
+        let change = edit(&mut document.text, state.encoding);
+        document.version += 1;
+        // Before initialization there's nothing to send; the document goes out whole once
+        // the server is ready
+        if !state.initialized {
+            return;
+        }
+        let change = match state.sync {
+            SyncKind::None => return,
+            SyncKind::Full => json!({ "text": document.text.to_string() }),
+            SyncKind::Incremental => change,
+        };
+        let params = json!({
+            "textDocument": { "uri": uri, "version": document.version },
+            "contentChanges": [change],
+        });
+        send(state, notification("textDocument/didChange", params));
+    }
+
+    /// The document was saved to disk.
+    pub fn save_document(&self, uri: &str) {
+        let state = self.state.borrow();
+        let Some(document) = state.documents.get(uri) else {
+            return;
+        };
+        if !state.initialized {
+            return;
+        }
+        let save = &state.capabilities["textDocumentSync"]["save"];
+        if save.is_null() || *save == Value::Bool(false) {
+            return;
+        }
+        let mut params = json!({ "textDocument": { "uri": uri } });
+        if save["includeText"] == Value::Bool(true) {
+            params["text"] = Value::String(document.text.to_string());
+        }
+        send(&state, notification("textDocument/didSave", params));
+    }
+
+    /// Send a request; `callback` gets the result, or an error if the server fails it or
+    /// exits first. Requests made while the server starts are sent once it's ready.
+    pub fn request(&self, method: &str, params: Value, callback: impl FnOnce(Result<Value, ResponseError>) + 'static) {
+        let stopped = {
+            let state = self.state.borrow();
+            state.connection.is_none() || state.shutting_down
+        };
+        if stopped {
+            callback(Err(ResponseError::new(ResponseError::SERVER_EXITED, "language server is not running")));
+            return;
+        }
+        self.send_request(method, params, Box::new(callback));
+    }
+
+    fn send_request(&self, method: &str, params: Value, callback: Callback) {
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.pending.insert(id, callback);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        // `initialize` itself can't wait for initialization
+        if state.initialized || method == "initialize" {
+            send(&state, message);
+        } else {
+            state.queued.push(message);
+        }
+    }
+
+    /// Ask the server to shut down, then to exit.
+    pub fn shutdown(self: &Rc<Self>) {
+        let initialized = {
+            let mut state = self.state.borrow_mut();
+            state.shutting_down = true;
+            state.initialized
+        };
+        if !initialized {
+            self.state.borrow_mut().connection = None;
+            self.set_status(Status::Stopped);
+            return;
+        }
+        let client = Rc::downgrade(self);
+        self.send_request("shutdown", Value::Null, Box::new(move |_| {
+            if let Some(client) = client.upgrade() {
+                send(&client.state.borrow(), notification("exit", Value::Null));
+            }
+        }));
+    }
+
+    /// Process an event from a connection's reader thread.
+    pub fn handle(self: &Rc<Self>, event: Event) {
+        let current = self.state.borrow().connection.as_ref().map(|connection| connection.id);
+        if current != Some(event.connection) {
+            return;
+        }
+        let Some(message) = event.message else {
+            self.exited();
+            return;
+        };
+        match transport::classify(message) {
+            Some(Incoming::Response { id, result }) => {
+                let callback = self.state.borrow_mut().pending.remove(&id);
+                if let Some(callback) = callback {
+                    callback(result);
+                }
+            }
+            Some(Incoming::Request { id, method, params }) => {
+                let handlers = self.request_handlers.borrow().clone();
+                let handled = handlers.iter().find_map(|handler| handler(&method, &params));
+                let response = match handled.map_or_else(|| self.answer(&method, &params), Ok) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                };
+                send(&self.state.borrow(), response);
+            }
+            Some(Incoming::Notification { method, params }) => {
+                let handlers = self.notification_handlers.borrow().clone();
+                for handler in handlers {
+                    handler(&method, &params);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// The answer to a request from the server.
+    fn answer(&self, method: &str, params: &Value) -> Result<Value, ResponseError> {
+        match method {
+            // No settings of our own to give; servers use their defaults
+            "workspace/configuration" => {
+                let items = params["items"].as_array().map_or(0, Vec::len);
+                Ok(Value::Array(vec![Value::Null; items]))
+            }
+            "workspace/workspaceFolders" => Ok(json!([workspace_folder(&self.root)])),
+            "window/workDoneProgress/create"
+            | "client/registerCapability"
+            | "client/unregisterCapability"
+            | "window/showMessageRequest" => Ok(Value::Null),
+            _ => Err(ResponseError::new(ResponseError::METHOD_NOT_FOUND, format!("unsupported method {}", method))),
+        }
+    }
+
+    fn initialized(self: &Rc<Self>, result: Result<Value, ResponseError>) {
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Warning: language server {} failed to initialize: {}", self.config.command, e.message);
+                self.state.borrow_mut().connection = None;
+                self.fail_pending();
+                self.set_status(Status::Failed(e.message));
+                return;
+            }
+        };
+        {
+            let mut state = self.state.borrow_mut();
+            let capabilities = result["capabilities"].clone();
+            state.sync = sync_kind(&capabilities["textDocumentSync"]);
+            state.encoding = PositionEncoding::from_name(capabilities["positionEncoding"].as_str());
+            state.capabilities = capabilities;
+            state.initialized = true;
+
+            send(&state, notification("initialized", json!({})));
+            for (uri, document) in &state.documents {
+                send(&state, did_open(uri, document));
+            }
+            for message in std::mem::take(&mut state.queued) {
+                send(&state, message);
+            }
+        }
+        self.set_status(Status::Running);
+    }
+
+    /// The server's output ended: it exited or crashed. Unless it was asked to, it's
+    /// restarted and the open documents are sent to the new process.
+    fn exited(self: &Rc<Self>) {
+        let (shutting_down, crashes) = {
+            let mut state = self.state.borrow_mut();
+            state.connection = None;
+            state.initialized = false;
+            state.queued.clear();
+            if !state.shutting_down {
+                let now = Instant::now();
+                state.crashes.push_back(now);
+                while state.crashes.front().is_some_and(|crash| now.duration_since(*crash) > RESTART_WINDOW) {
+                    state.crashes.pop_front();
+                }
+            }
+            (state.shutting_down, state.crashes.len())
+        };
+        self.fail_pending();
+
+        if shutting_down {
+            self.set_status(Status::Stopped);
+        } else if crashes > MAX_RESTARTS {
+            eprintln!("Warning: language server {} keeps crashing, not restarting it", self.config.command);
+            self.set_status(Status::Failed(format!(
+                "{} crashed {} times in {} minutes",
+                self.config.command,
+                crashes,
+                RESTART_WINDOW.as_secs() / 60
+            )));
+        } else {
+            eprintln!("Warning: language server {} exited, restarting it", self.config.command);
+            self.start();
+        }
+    }
+
+    fn fail_pending(&self) {
+        let pending = std::mem::take(&mut self.state.borrow_mut().pending);
+        for callback in pending.into_values() {
+            callback(Err(ResponseError::new(ResponseError::SERVER_EXITED, "language server exited")));
+        }
+    }
+
+    fn set_status(&self, status: Status) {
+        self.state.borrow_mut().status = status.clone();
+        let handlers = self.status_handlers.borrow().clone();
+        for handler in handlers {
+            handler(&status);
+        }
+    }
+}
+
+fn send(state: &State, message: Value) {
+    if let Some(connection) = &state.connection {
+        connection.send(message);
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn did_open(uri: &str, document: &OpenDocument) -> Value {
+    notification(
+        "textDocument/didOpen",
+        json!({
+            "textDocument": {
+                "uri": uri,
+                "languageId": document.language_id,
+                "version": document.version,
+                "text": document.text.to_string(),
+            }
+        }),
+    )
+}
+
+/// `textDocumentSync` is either the kind itself or an object holding it in `change`; without
+/// it, changes aren't sent at all.
+fn sync_kind(sync: &Value) -> SyncKind {
+    let kind = if sync.is_object() { &sync["change"] } else { sync };
+    match kind.as_u64() {
+        Some(1) => SyncKind::Full,
+        Some(2) => SyncKind::Incremental,
+        _ => SyncKind::None,
+    }
+}
+
+fn workspace_folder(root: &Path) -> Value {
+    let name = root.file_name().map_or_else(|| root.to_string_lossy(), |name| name.to_string_lossy());
+    json!({ "uri": protocol::file_uri(root), "name": name })
+}
+
+fn initialize_params(root: &Path) -> Value {
+    json!({
+        "processId": std::process::id(),
+        "clientInfo": { "name": "Fikby", "version": env!("CARGO_PKG_VERSION") },
+        "rootUri": protocol::file_uri(root),
+        "rootPath": root.to_string_lossy(),
+        "workspaceFolders": [workspace_folder(root)],
+        "capabilities": client_capabilities(),
+    })
+}
+
+/// What this client supports, sent with `initialize`.
+fn client_capabilities() -> Value {
+    json!({
+        "general": { "positionEncodings": ["utf-32", "utf-8", "utf-16"] },
+        "workspace": {
+            "configuration": true,
+            "workspaceFolders": true,
+            "applyEdit": true,
+            "workspaceEdit": { "documentChanges": true },
+        },
+        "textDocument": {
+            "synchronization": { "didSave": true, "dynamicRegistration": false },
+            "completion": {
+                "completionItem": {
+                    "snippetSupport": true,
+                    "insertReplaceSupport": true,
+                    "documentationFormat": ["plaintext", "markdown"],
+                },
+                "completionItemKind": { "valueSet": (1..=25).collect::<Vec<u32>>() },
+                "contextSupport": true,
+            },
+            "definition": { "linkSupport": true },
+            "references": {},
+            "hover": { "contentFormat": ["markdown", "plaintext"] },
+            "rename": {},
+            "codeAction": {
+                "codeActionLiteralSupport": {
+                    "codeActionKind": {
+                        "valueSet": [
+                            "", "quickfix", "refactor", "refactor.extract", "refactor.inline", "refactor.rewrite", "source",
+                        ],
+                    },
+                },
+                "isPreferredSupport": true,
+                "disabledSupport": true,
+                "dataSupport": true,
+                "resolveSupport": { "properties": ["edit"] },
+            },
+        },
+        "window": { "workDoneProgress": true },
+    })
+}
@@ -0,0 +1,63 @@
+//! Language server client: starts the server configured for a file's language and keeps it
+//! in sync with the open documents over JSON-RPC on stdio.
+
+pub mod client;
+pub mod protocol;
+pub mod transport;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// How to run the language server for one language, from `[language_servers.<language id>]`
+/// in the settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Extensions of the files this server handles, without the dot
+    pub extensions: Vec<String>,
+    /// Files marking the project root, searched for upwards from the opened file
+    pub root_markers: Vec<String>,
+    /// Extra environment variables for the server process
+    pub env: BTreeMap<String, String>,
+}
+
+/// The servers configured out of the box, keyed by LSP language id.
+pub fn default_servers() -> BTreeMap<String, ServerConfig> {
+    BTreeMap::from([(
+        "rust".to_string(),
+        ServerConfig {
+            command: "rust-analyzer".to_string(),
+            extensions: vec!["rs".to_string()],
+            root_markers: vec!["Cargo.toml".to_string()],
+            ..ServerConfig::default()
+        },
+    )])
+}
+
+/// The language id and server for `path`, by its extension: from `configured` (the settings),
+/// else from `default_servers`. A configured entry without a command turns a server off.
+pub fn server_for(configured: &BTreeMap<String, ServerConfig>, path: &Path) -> Option<(String, ServerConfig)> {
+    let extension = path.extension()?.to_str()?;
+    let mut servers = default_servers();
+    servers.extend(configured.clone());
+    servers
+        .into_iter()
+        .find(|(_, config)| !config.command.is_empty() && config.extensions.iter().any(|e| e == extension))
+}
+
+/// The project root for `path`: the outermost directory holding one of `markers`, so a crate
+/// inside a Cargo workspace shares the workspace's server. Without markers, the nearest
+/// directory holding `.git`, else the file's own directory.
+pub fn find_root(path: &Path, markers: &[String]) -> PathBuf {
+    let dir = path.parent().unwrap_or(path);
+    let has = |dir: &Path, name: &str| dir.join(name).exists();
+    let marked = dir.ancestors().filter(|ancestor| markers.iter().any(|marker| has(ancestor, marker))).last();
+    marked
+        .or_else(|| dir.ancestors().find(|ancestor| has(ancestor, ".git")))
+        .unwrap_or(dir)
+        .to_path_buf()
+}
@@ -0,0 +1,286 @@
+//! The pieces of the LSP data model the client works with: positions in a chosen encoding,
+//! file URIs, incremental content changes, and the responses of the requests the editor
+//! makes.
+
+use std::path::{Path, PathBuf};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Unit columns are counted in, negotiated with the server. LSP defaults to UTF-16.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// From the `positionEncoding` the server picked; unknown names mean the default.
+    pub fn from_name(name: Option<&str>) -> Self {
+        match name {
+            Some("utf-8") => Self::Utf8,
+            Some("utf-32") => Self::Utf32,
+            _ => Self::Utf16,
+        }
+    }
+
+    fn width(self, c: char) -> u32 {
+        match self {
+            Self::Utf8 => c.len_utf8() as u32,
+            Self::Utf16 => c.len_utf16() as u32,
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Zero-based line and column, the column in the connection's encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// The position of the char offset `offset` in `rope`.
+pub fn position_at(rope: &Rope, offset: usize, encoding: PositionEncoding) -> Position {
+    let offset = offset.min(rope.len_chars());
+    let line = rope.char_to_line(offset);
+    let line_start = rope.line_to_char(line);
+    let character = rope.slice(line_start..offset).chars().map(|c| encoding.width(c)).sum();
+    Position { line: line as u32, character }
+}
+
+/// The char offset of `position` in `rope`. Lines past the end clamp to the end of the text,
+/// columns past the end of a line to the end of the line, and columns inside a character to
+/// its start.
+pub fn char_at(rope: &Rope, position: Position, encoding: PositionEncoding) -> usize {
+    let line = position.line as usize;
+    if line >= rope.len_lines() {
+        return rope.len_chars();
+    }
+    let line_start = rope.line_to_char(line);
+    let mut column = 0;
+    let mut offset = line_start;
+    for c in rope.line(line).chars() {
+        let width = encoding.width(c);
+        if c == '\n' || c == '\r' || column + width > position.character {
+            break;
+        }
+        column += width;
+        offset += 1;
+    }
+    offset
+}
+
+/// `file://` URI of an absolute path, percent-encoding everything but unreserved characters
+/// and `/`.
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    let drive = !path.starts_with('/');
+    if drive {
+        // Windows drive paths become file:///C:/...
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            b':' if drive && uri.len() == "file:///".len() + 1 => uri.push(':'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The path a `file://` URI names, or `None` for other schemes.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // Skip an authority such as `localhost`
+    let path = &path[path.find('/')?..];
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%' && tail.len() >= 2)
+            .then(|| std::str::from_utf8(&tail[..2]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .flatten();
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // file:///C:/x is C:/x, not /C:/x
+    let is_drive = path.len() >= 3 && path.as_bytes()[2] == b':' && path.as_bytes()[1].is_ascii_alphabetic();
+    Some(PathBuf::from(if is_drive { &path[1..] } else { &path[..] }))
+}
+
+/// `didChange` content change for inserting `text` at char `offset`, computed against `rope`
+/// before the insertion.
+pub fn insert_change(rope: &Rope, offset: usize, text: &str, encoding: PositionEncoding) -> Value {
+    let at = position_at(rope, offset, encoding);
+    json!({ "range": Range { start: at, end: at }, "text": text })
+}
+
+/// `didChange` content change for deleting chars `start..end`, computed against `rope` before
+/// the deletion.
+pub fn delete_change(rope: &Rope, start: usize, end: usize, encoding: PositionEncoding) -> Value {
+    let range = Range { start: position_at(rope, start, encoding), end: position_at(rope, end, encoding) };
+    json!({ "range": range, "text": "" })
+}
+
+/// The targets of a definition or references response: a `Location`, or an array of
+/// `Location`s or `LocationLink`s (pointing at the name in `targetSelectionRange`). `null`
+/// and URIs of other schemes than `file` give none.
+pub fn locations(result: &Value) -> Vec<(PathBuf, Range)> {
+    let location = |value: &Value| -> Option<(PathBuf, Range)> {
+        let (uri, range) = match value.get("targetUri") {
+            Some(uri) => (uri, value.get("targetSelectionRange").or_else(|| value.get("targetRange"))?),
+            None => (value.get("uri")?, value.get("range")?),
+        };
+        Some((uri_to_path(uri.as_str()?)?, Range::deserialize(range).ok()?))
+    };
+    match result {
+        Value::Array(values) => values.iter().filter_map(location).collect(),
+        _ => location(result).into_iter().collect(),
+    }
+}
+
+/// The markdown of a hover response's `contents`: markup content, or marked strings, with a
+/// language making a code block. Plain text is shown as a code block too, so it isn't taken
+/// for markdown. `None` when there's nothing to show.
+pub fn hover_markdown(hover: &Value) -> Option<String> {
+    let part = |value: &Value| -> Option<String> {
+        let text = match value {
+            Value::String(text) => text.clone(),
+            Value::Object(object) => {
+                let text = object.get("value")?.as_str()?;
+                match (object.get("kind").and_then(Value::as_str), object.get("language").and_then(Value::as_str)) {
+                    (_, Some(language)) => format!("```{}\n{}\n```", language, text),
+                    (Some("plaintext"), _) => format!("```\n{}\n```", text),
+                    _ => text.to_string(),
+                }
+            }
+            _ => return None,
+        };
+        (!text.trim().is_empty()).then_some(text)
+    };
+    let parts: Vec<String> = match &hover["contents"] {
+        Value::Array(values) => values.iter().filter_map(part).collect(),
+        contents => part(contents).into_iter().collect(),
+    };
+    (!parts.is_empty()).then(|| parts.join("\n\n"))
+}
+
+/// A replacement of `range` with `new_text`, positioned in the text before any edit of the
+/// same batch is made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+/// The `TextEdit`s of an array, skipping malformed ones.
+pub fn text_edits(edits: &Value) -> Vec<TextEdit> {
+    let edit = |value: &Value| {
+        Some(TextEdit {
+            range: Range::deserialize(value.get("range")?).ok()?,
+            new_text: value.get("newText")?.as_str()?.to_string(),
+        })
+    };
+    edits.as_array().into_iter().flatten().filter_map(edit).collect()
+}
+
+/// The edits of a `WorkspaceEdit` by file, in the order the files first appear, from its
+/// `documentChanges` if it has them, else its `changes`. File operations (creating,
+/// renaming and deleting files) aren't supported and are left out.
+pub fn workspace_edit(edit: &Value) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let mut files: Vec<(PathBuf, Vec<TextEdit>)> = Vec::new();
+    let mut add = |uri: &Value, edits: &Value| {
+        let Some(path) = uri.as_str().and_then(uri_to_path) else {
+            return;
+        };
+        let edits = text_edits(edits);
+        match files.iter_mut().find(|(file, _)| *file == path) {
+            Some((_, existing)) => existing.extend(edits),
+            None => files.push((path, edits)),
+        }
+    };
+    match (edit["documentChanges"].as_array(), edit["changes"].as_object()) {
+        (Some(changes), _) => {
+            for change in changes.iter().filter(|change| change.get("kind").is_none()) {
+                add(&change["textDocument"]["uri"], &change["edits"]);
+            }
+        }
+        (None, Some(changes)) => {
+            for (uri, edits) in changes {
+                add(&Value::String(uri.clone()), edits);
+            }
+        }
+        (None, None) => {}
+    }
+    files.retain(|(_, edits)| !edits.is_empty());
+    files
+}
+
+/// An entry of a code action response: a `CodeAction`, or a bare `Command` to run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeAction {
+    pub title: String,
+    /// E.g. `quickfix` or `refactor.extract`
+    pub kind: Option<String>,
+    pub preferred: bool,
+    /// The workspace edit to make, unless it's still to be resolved
+    pub edit: Option<Value>,
+    /// Run after the edit is made
+    pub command: Option<Value>,
+    /// The action as the server sent it, for `codeAction/resolve`
+    pub raw: Value,
+}
+
+/// The code actions of a response, quick fixes first and preferred ones first among them.
+/// Disabled actions are left out.
+pub fn code_actions(result: &Value) -> Vec<CodeAction> {
+    let action = |value: &Value| -> Option<CodeAction> {
+        if value.get("disabled").is_some() {
+            return None;
+        }
+        let title = value.get("title")?.as_str()?.to_string();
+        // A bare command has its name in `command`, an action a `Command` object there
+        if value["command"].is_string() {
+            return Some(CodeAction {
+                title,
+                kind: None,
+                preferred: false,
+                edit: None,
+                command: Some(value.clone()),
+                raw: value.clone(),
+            });
+        }
+        Some(CodeAction {
+            title,
+            kind: value["kind"].as_str().map(str::to_string),
+            preferred: value["isPreferred"] == Value::Bool(true),
+            edit: value.get("edit").filter(|edit| !edit.is_null()).cloned(),
+            command: value.get("command").filter(|command| command.is_object()).cloned(),
+            raw: value.clone(),
+        })
+    };
+    let mut actions: Vec<CodeAction> = result.as_array().into_iter().flatten().filter_map(action).collect();
+    let quick_fix = |action: &CodeAction| action.kind.as_deref().is_some_and(|kind| kind.starts_with("quickfix"));
+    actions.sort_by_key(|action| (!quick_fix(action), !action.preferred));
+    actions
+}
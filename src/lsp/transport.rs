@@ -0,0 +1,96 @@
+//! JSON-RPC messages framed with `Content-Length` headers, as language servers speak them
+//! over stdio.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// A message from the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming {
+    /// Answer to one of our requests
+    Response { id: i64, result: Result<Value, ResponseError> },
+    /// A request the server expects an answer to, e.g. `workspace/configuration`
+    Request { id: Value, method: String, params: Value },
+    Notification { method: String, params: Value },
+}
+
+/// The `error` of a failed response.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl ResponseError {
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    /// Used for requests still pending when the server went away
+    pub const SERVER_EXITED: i64 = -32099;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+/// Write `message` with its header and flush it.
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Read the next message, or `None` once the stream ends.
+///
+/// Lines before a header that aren't headers, such as a banner some servers print on start,
+/// are skipped. A body that isn't valid JSON is an `InvalidData` error, after which reading
+/// can go on with the next message.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Tell responses, requests and notifications apart. `None` for anything else, such as a
+/// response to an id we never sent.
+pub fn classify(message: Value) -> Option<Incoming> {
+    let Value::Object(mut fields) = message else {
+        return None;
+    };
+    let method = fields.get("method").and_then(Value::as_str).map(str::to_string);
+    let params = fields.remove("params").unwrap_or(Value::Null);
+    match (fields.remove("id"), method) {
+        (Some(id), Some(method)) => Some(Incoming::Request { id, method, params }),
+        (None, Some(method)) => Some(Incoming::Notification { method, params }),
+        (Some(id), None) => {
+            let id = id.as_i64()?;
+            let result = match fields.remove("error") {
+                Some(error) => Err(serde_json::from_value(error)
+                    .unwrap_or_else(|_| ResponseError::new(0, "malformed error response"))),
+                None => Ok(fields.remove("result").unwrap_or(Value::Null)),
+            };
+            Some(Incoming::Response { id, result })
+        }
+        (None, None) => None,
+    }
+}
@@ -1,29 +1,62 @@
+mod brackets;
+mod commands;
+mod completion;
 mod config;
+mod diagnostics;
+mod documents;
 mod editor;
+mod external_change;
 mod file_explorer;
+mod file_format;
+mod find_in_files;
+mod folding;
 mod highlight;
+mod indent;
+mod language;
+mod lsp;
+mod markdown;
+mod multi_cursor;
+mod navigation;
+mod quick_open;
+mod search;
+mod session;
+mod settings;
+mod symbols;
 mod ui;
-mod find_replace;
+mod workspace_edit;
 
 #[cfg(test)]
 mod tests;
 
 use gtk4::prelude::*;
 use gtk4::{gdk, Application, CssProvider};
+use std::cell::RefCell;
 use config::ThemeMode;
+use settings::Settings;
+
+thread_local! {
+    // Provider holding the font CSS from the settings, replaced whenever they change
+    static FONT_CSS: RefCell<Option<CssProvider>> = const { RefCell::new(None) };
+}
 
 fn main() {
+    // Read once: the window starts from the same settings as the CSS
+    let settings = Settings::load();
     let app = Application::builder()
         .application_id(config::APP_ID)
         .build();
 
-    app.connect_startup(|_| {
-        // Load default CSS on startup (dark theme by default)
-        load_css(ThemeMode::Dark);
-    });
+    {
+        let settings = settings.clone();
+        app.connect_startup(move |_| {
+            // Load the theme CSS from the user's settings (dark theme by default)
+            load_css(settings.theme);
+            load_font_css(&settings);
+        });
+    }
 
-    app.connect_activate(|app| {
-        ui::build_ui(app);
+    app.connect_activate(move |app| {
+        ui::build_ui(app, settings.clone());
     });
 
     app.run();
@@ -39,4 +72,24 @@ pub fn load_css(theme: ThemeMode) {
         &provider,
         gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
     );
-}
\ No newline at end of file
+}
+
+/// Apply the font from `settings` to the editor views and gutters, replacing the previous font CSS.
+pub fn load_font_css(settings: &Settings) {
+    let display = gdk::Display::default().expect("Could not connect to a display.");
+    let provider = CssProvider::new();
+    provider.load_from_data(&settings.font_css());
+
+    FONT_CSS.with(|current| {
+        if let Some(old) = current.borrow_mut().take() {
+            gtk4::style_context_remove_provider_for_display(&display, &old);
+        }
+        // One step above the theme CSS so toggling the theme doesn't reset the font
+        gtk4::style_context_add_provider_for_display(
+            &display,
+            &provider,
+            gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+        *current.borrow_mut() = Some(provider);
+    });
+}
@@ -0,0 +1,185 @@
+// Characters of the line a horizontal rule is drawn as
+const RULE_WIDTH: usize = 24;
+
+/// Pango markup for `markdown`, the way language servers write hover text: headings,
+/// paragraphs, lists, rules, fenced code blocks, inline code, emphasis and links. Anything
+/// else shows as plain text.
+pub fn to_pango(markdown: &str) -> String {
+    let mut blocks: Vec<String> = Vec::new();
+    // Lines of the paragraph or list being read; text lines continue the last one
+    let mut lines: Vec<String> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+
+    let flush = |lines: &mut Vec<String>, blocks: &mut Vec<String>| {
+        if !lines.is_empty() {
+            blocks.push(lines.iter().map(|line| inline(line)).collect::<Vec<_>>().join("\n"));
+            lines.clear();
+        }
+    };
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if let Some(code_lines) = &mut code {
+            if trimmed.starts_with("```") {
+                blocks.push(format!("<tt>{}</tt>", escape(&code_lines.join("\n"))));
+                code = None;
+            } else {
+                code_lines.push(line);
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            flush(&mut lines, &mut blocks);
+            code = Some(Vec::new());
+        } else if trimmed.is_empty() {
+            flush(&mut lines, &mut blocks);
+        } else if let Some(heading) = heading(trimmed) {
+            flush(&mut lines, &mut blocks);
+            blocks.push(format!("<b>{}</b>", inline(heading)));
+        } else if is_rule(trimmed) {
+            flush(&mut lines, &mut blocks);
+            blocks.push("─".repeat(RULE_WIDTH));
+        } else if let Some(item) = list_item(trimmed) {
+            let depth = (line.len() - line.trim_start().len()) / 2;
+            lines.push(format!("{}• {}", "  ".repeat(depth), item));
+        } else {
+            match lines.last_mut() {
+                Some(last) => {
+                    last.push(' ');
+                    last.push_str(trimmed);
+                }
+                None => lines.push(trimmed.to_string()),
+            }
+        }
+    }
+    flush(&mut lines, &mut blocks);
+    // An unclosed fence runs to the end
+    if let Some(code_lines) = code.filter(|lines| !lines.is_empty()) {
+        blocks.push(format!("<tt>{}</tt>", escape(&code_lines.join("\n"))));
+    }
+    blocks.join("\n\n")
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then(|| text.trim_end_matches('#').trim())
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && ['-', '*', '_'].iter().any(|&mark| marks.chars().all(|c| c == mark))
+}
+
+/// The text of a `- `, `* `, `+ ` or `1. ` list item.
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(text) = ["- ", "* ", "+ "].iter().find_map(|marker| line.strip_prefix(marker)) {
+        return Some(text);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..].strip_prefix(". ").or_else(|| line[digits..].strip_prefix(") "))
+}
+
+/// Markup of one line's inline elements. Emphasis left open is closed at the end, so the
+/// markup is always valid.
+fn inline(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut markup = String::new();
+    // Open emphasis, as the markdown marker and the Pango tag
+    let mut open: Vec<(String, &str)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                markup.push_str(&escape(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let fence: String = "`".repeat(run);
+                let after: String = chars[i + run..].iter().collect();
+                match after.find(&fence) {
+                    Some(end) => {
+                        markup.push_str(&format!("<tt>{}</tt>", escape(after[..end].trim())));
+                        i += run + after[..end].chars().count() + run;
+                    }
+                    None => {
+                        markup.push_str(&fence);
+                        i += run;
+                    }
+                }
+            }
+            '[' => match link(&chars[i..]) {
+                Some((label, length)) => {
+                    markup.push_str(&inline(&label));
+                    i += length;
+                }
+                None => {
+                    markup.push('[');
+                    i += 1;
+                }
+            },
+            '*' | '_' => {
+                let bold = chars.get(i + 1) == Some(&c);
+                let (marker, tag) = if bold { (format!("{}{}", c, c), "b") } else { (c.to_string(), "i") };
+                let length = marker.len();
+                let after = chars.get(i + length);
+                // Underscores inside words, as in snake_case, aren't emphasis
+                let inside_word =
+                    c == '_' && i > 0 && chars[i - 1].is_alphanumeric() && after.is_some_and(|c| c.is_alphanumeric());
+                let can_open = after.is_some_and(|c| !c.is_whitespace());
+                let can_close = i > 0 && !chars[i - 1].is_whitespace();
+                if inside_word {
+                    markup.push_str(&marker);
+                } else if can_close && open.last().is_some_and(|(open_marker, _)| *open_marker == marker) {
+                    open.pop();
+                    markup.push_str(&format!("</{}>", tag));
+                } else if can_open && !open.iter().any(|(open_marker, _)| *open_marker == marker) {
+                    open.push((marker, tag));
+                    markup.push_str(&format!("<{}>", tag));
+                } else {
+                    markup.push_str(&marker);
+                }
+                i += length;
+            }
+            _ => {
+                markup.push_str(&escape(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+    for (_, tag) in open.iter().rev() {
+        markup.push_str(&format!("</{}>", tag));
+    }
+    markup
+}
+
+/// The label and length in chars of the `[label](target)` link starting `chars`.
+fn link(chars: &[char]) -> Option<(String, usize)> {
+    let close = chars.iter().position(|&c| c == ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = close + 1 + chars[close + 1..].iter().position(|&c| c == ')')?;
+    Some((chars[1..close].iter().collect(), end + 1))
+}
+
+/// `text` with the characters that are special in Pango markup escaped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,74 @@
+use crate::completion::is_word_char;
+
+/// A caret and the selection it extends, as character offsets into the buffer. The caret is
+/// at `head`; `anchor == head` for a bare caret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor, head }
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    fn overlaps(&self, other: &Selection) -> bool {
+        self.start() < other.end() && other.start() < self.end()
+    }
+}
+
+/// Sort `selections` and merge the ones that overlap or share a caret position, e.g. after
+/// carets ran into each other. A merged selection keeps the direction of the first one.
+pub fn merge(mut selections: Vec<Selection>) -> Vec<Selection> {
+    selections.sort_by_key(|s| (s.start(), s.end()));
+    let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+    for selection in selections {
+        match merged.last_mut() {
+            Some(last) if selection.start() < last.end() || selection.start() == last.start() => {
+                let (start, end) = (last.start(), last.end().max(selection.end()));
+                *last = if last.anchor <= last.head { Selection::new(start, end) } else { Selection::new(end, start) };
+            }
+            _ => merged.push(selection),
+        }
+    }
+    merged
+}
+
+/// Every non-overlapping occurrence of `needle` in `text`, in order, selected with the caret
+/// at its end. With `whole_word`, occurrences inside a longer word are left out.
+pub fn all_occurrences(text: &str, needle: &str, whole_word: bool) -> Vec<Selection> {
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    let needle_chars = needle.chars().count();
+    let (mut byte_pos, mut char_pos) = (0, 0);
+    text.match_indices(needle)
+        .filter_map(|(byte, _)| {
+            char_pos += text[byte_pos..byte].chars().count();
+            byte_pos = byte;
+            let bounded = !whole_word
+                || !(text[..byte].chars().next_back().is_some_and(is_word_char)
+                    || text[byte + needle.len()..].chars().next().is_some_and(is_word_char));
+            bounded.then(|| Selection::new(char_pos, char_pos + needle_chars))
+        })
+        .collect()
+}
+
+/// The first occurrence of `needle` at or after `after` that isn't selected yet, wrapping
+/// around to the start of `text`.
+pub fn next_occurrence(text: &str, needle: &str, after: usize, taken: &[Selection]) -> Option<Selection> {
+    let free: Vec<Selection> = all_occurrences(text, needle, false)
+        .into_iter()
+        .filter(|occurrence| !taken.iter().any(|t| t.overlaps(occurrence)))
+        .collect();
+    free.iter().find(|occurrence| occurrence.start() >= after).or(free.first()).copied()
+}
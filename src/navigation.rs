@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+// Jumps remembered for going back; older ones are dropped
+pub const MAX_HISTORY: usize = 50;
+
+/// A cursor position in a file, zero-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Back and forward stacks of the places jumps left from, for Alt+Left and Alt+Right.
+#[derive(Debug, Default)]
+pub struct NavigationHistory {
+    back: Vec<Location>,
+    forward: Vec<Location>,
+}
+
+impl NavigationHistory {
+    /// Remember `from`, the place a jump is leaving. Like in a browser, a new jump drops the
+    /// way forward.
+    pub fn record(&mut self, from: Location) {
+        if self.back.last() != Some(&from) {
+            self.back.push(from);
+            if self.back.len() > MAX_HISTORY {
+                self.back.remove(0);
+            }
+        }
+        self.forward.clear();
+    }
+
+    /// Where going back from `current` leads; `current` becomes the way forward.
+    pub fn back(&mut self, current: Option<Location>) -> Option<Location> {
+        step(&mut self.back, &mut self.forward, current)
+    }
+
+    /// Where going forward from `current` leads; `current` becomes the way back.
+    pub fn forward(&mut self, current: Option<Location>) -> Option<Location> {
+        step(&mut self.forward, &mut self.back, current)
+    }
+
+    pub fn clear(&mut self) {
+        self.back.clear();
+        self.forward.clear();
+    }
+}
+
+fn step(from: &mut Vec<Location>, to: &mut Vec<Location>, current: Option<Location>) -> Option<Location> {
+    // Entries equal to where the cursor already is would make the step do nothing
+    while let Some(location) = from.pop() {
+        if Some(&location) == current.as_ref() {
+            continue;
+        }
+        if let Some(current) = current {
+            if to.last() != Some(&current) {
+                to.push(current);
+            }
+        }
+        return Some(location);
+    }
+    None
+}
+
+/// A position typed into Go to Line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoToTarget {
+    /// File to open first; `None` stays in the current one
+    pub path: Option<PathBuf>,
+    /// Zero-based, though typed one-based
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Parse `line`, `line:column` or `file:line:column` (one-based, as compilers print them).
+/// Surrounding whitespace, a trailing `:` and the `-->` rustc puts before locations are
+/// ignored, so a copied location works as is.
+pub fn parse_go_to(text: &str) -> Option<GoToTarget> {
+    let text = text.trim();
+    let text = text.strip_prefix("-->").unwrap_or(text).trim().trim_end_matches(':');
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+
+    // Up to two numbers from the end; what's left is the file, unless it's the line itself
+    let mut numbers = Vec::new();
+    let mut rest = text;
+    while numbers.len() < 2 {
+        match rest.rsplit_once(':') {
+            Some((head, tail)) if is_number(tail) => {
+                numbers.push(tail);
+                rest = head;
+            }
+            _ => break,
+        }
+    }
+    if is_number(rest) && numbers.len() < 2 {
+        numbers.push(rest);
+        rest = "";
+    }
+
+    let mut numbers = numbers.into_iter().rev().map(|n| n.parse::<usize>().ok());
+    let line = numbers.next()??;
+    let column = numbers.next().flatten().unwrap_or(1);
+    Some(GoToTarget {
+        path: (!rest.is_empty()).then(|| PathBuf::from(rest)),
+        line: line.saturating_sub(1),
+        column: column.saturating_sub(1),
+    })
+}
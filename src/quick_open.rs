@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+use crate::settings::{wildcard_match, Settings};
+
+// Stop indexing huge trees (e.g. a home directory) rather than walking them for minutes
+pub const MAX_INDEXED_FILES: usize = 50_000;
+// Rows shown in the picker; typing narrows the list further
+pub const MAX_RESULTS: usize = 200;
+
+/// One line of a `.gitignore` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IgnoreRule {
+    /// Directory holding the `.gitignore`, relative to the indexed root
+    base: PathBuf,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    /// Patterns containing a `/` match the path relative to `base`, others only the name
+    anchored: bool,
+}
+
+/// `.gitignore` rules collected from the root down to the directory being indexed.
+#[derive(Debug, Clone, Default)]
+pub struct GitIgnore {
+    rules: Vec<IgnoreRule>,
+}
+
+impl GitIgnore {
+    /// Add the rules of a `.gitignore` found in `base` (relative to the indexed root).
+    pub fn add_file(&mut self, base: &Path, text: &str) {
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let pattern = line.trim_start_matches('/').to_string();
+            if pattern.is_empty() {
+                continue;
+            }
+            self.rules.push(IgnoreRule { base: base.to_path_buf(), pattern, negated, dir_only, anchored });
+        }
+    }
+
+    /// Whether `path` (relative to the indexed root) is ignored. Later rules override earlier
+    /// ones, so a `!pattern` can re-include something.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            let matches = if rule.anchored {
+                path_match(&rule.pattern, &slash_path(relative))
+            } else {
+                relative
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|name| wildcard_match(&rule.pattern, name))
+            };
+            if matches {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Match a `/`-separated `path` against an anchored `.gitignore` pattern. Wildcards stay within
+/// one component, and a `**` component matches any number of them: `**/foo` matches `foo` and
+/// `a/b/foo` but not `xfoo`, and `a/**` everything inside `a`.
+fn path_match(pattern: &str, path: &str) -> bool {
+    fn components_match(pattern: &[&str], path: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => path.is_empty(),
+            // A trailing `**` needs something inside
+            Some((&"**", [])) => !path.is_empty(),
+            Some((&"**", rest)) => (0..=path.len()).any(|skip| components_match(rest, &path[skip..])),
+            Some((first, rest)) => path
+                .split_first()
+                .is_some_and(|(name, path)| wildcard_match(first, name) && components_match(rest, path)),
+        }
+    }
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    components_match(&pattern, &path)
+}
+
+/// Files under `root` for Quick Open, relative to `root` and sorted, up to
+/// `MAX_INDEXED_FILES`. Skips what `walk_files` skips.
+pub fn index_files(root: &Path, settings: &Settings) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk_files(root, settings, |path| {
+        files.push(path);
+        files.len() < MAX_INDEXED_FILES
+    });
+    files.sort();
+    files
+}
+
+/// Pass each file under `root`, relative to `root`, to `visit` until it returns `false`. Skips
+/// what the file explorer hides, `.git` and everything matched by `.gitignore` files along
+/// the way. Files come in no particular order.
+pub fn walk_files(root: &Path, settings: &Settings, mut visit: impl FnMut(PathBuf) -> bool) {
+    let mut pending = vec![(PathBuf::new(), GitIgnore::default())];
+    // Directories already walked, resolved, so symlinks looping back up the tree end there
+    let mut visited = HashSet::new();
+
+    while let Some((dir, mut ignore)) = pending.pop() {
+        let full_dir = root.join(&dir);
+        let Ok(canonical) = std::fs::canonicalize(&full_dir) else {
+            continue;
+        };
+        if !visited.insert(canonical) {
+            continue;
+        }
+        if let Ok(text) = std::fs::read_to_string(full_dir.join(".gitignore")) {
+            ignore.add_file(&dir, &text);
+        }
+        let Ok(entries) = std::fs::read_dir(&full_dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" || settings.is_hidden(&name) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            // Symlinks are followed; broken ones are skipped
+            let is_dir = if file_type.is_symlink() {
+                match std::fs::metadata(entry.path()) {
+                    Ok(meta) => meta.is_dir(),
+                    Err(_) => continue,
+                }
+            } else {
+                file_type.is_dir()
+            };
+            let path = dir.join(&name);
+            if ignore.is_ignored(&path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                pending.push((path, ignore.clone()));
+            } else if !visit(path) {
+                return;
+            }
+        }
+    }
+}
+
+/// An indexed file matching the Quick Open query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickOpenMatch {
+    pub path: PathBuf,
+    /// Path as shown, with `/` separators
+    pub display: String,
+    pub score: i64,
+    /// Character indices in `display` matched by the query
+    pub indices: Vec<usize>,
+}
+
+/// Fuzzy-rank `files` against `query`, best first; ties go to the shorter path. An empty
+/// query lists the files in index order.
+pub fn rank(matcher: &SkimMatcherV2, query: &str, files: &[PathBuf]) -> Vec<QuickOpenMatch> {
+    let query = query.trim();
+    if query.is_empty() {
+        return files
+            .iter()
+            .take(MAX_RESULTS)
+            .map(|path| QuickOpenMatch { path: path.clone(), display: slash_path(path), score: 0, indices: Vec::new() })
+            .collect();
+    }
+
+    let mut matches: Vec<QuickOpenMatch> = files
+        .iter()
+        .filter_map(|path| {
+            let display = slash_path(path);
+            let (score, indices) = matcher.fuzzy_indices(&display, query)?;
+            Some(QuickOpenMatch { path: path.clone(), display, score, indices })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.display.len().cmp(&b.display.len()))
+            .then_with(|| a.display.cmp(&b.display))
+    });
+    matches.truncate(MAX_RESULTS);
+    matches
+}
+
+/// Pango markup for `text` with the characters at `indices` in bold.
+pub fn highlight_markup(text: &str, indices: &[usize]) -> String {
+    let mut markup = String::new();
+    for (i, c) in text.chars().enumerate() {
+        let escaped = match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            _ => c.to_string(),
+        };
+        if indices.contains(&i) {
+            markup.push_str("<b>");
+            markup.push_str(&escaped);
+            markup.push_str("</b>");
+        } else {
+            markup.push_str(&escaped);
+        }
+    }
+    markup
+}
+
+/// `path` with `/` separators whatever the platform, as shown in lists.
+pub fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,139 @@
+use regex::{Regex, RegexBuilder};
+use thiserror::Error;
+
+/// Toggles of the find bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Treat the query as a regular expression; replacements may then use `$1` or `${name}`
+    pub regex: bool,
+    /// Match the case of each replaced text: `foo`/`Foo`/`FOO` become `bar`/`Bar`/`BAR`
+    pub preserve_case: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum SearchError {
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(#[from] regex::Error),
+}
+
+/// A match as character offsets into the searched text, to line up with GTK's text iters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A compiled query of the find bar or Find in Files.
+#[derive(Debug, Clone)]
+pub struct Search {
+    regex: Regex,
+    options: SearchOptions,
+}
+
+impl Search {
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, SearchError> {
+        let pattern = if options.regex { query.to_string() } else { regex::escape(query) };
+        let pattern = if options.whole_word { format!(r"\b(?:{})\b", pattern) } else { pattern };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .multi_line(true)
+            .build()?;
+        Ok(Self { regex, options })
+    }
+
+    /// Every match in `text`, in order. Empty matches (e.g. of `^`) are left out, since there
+    /// is nothing to highlight or select.
+    pub fn find_all(&self, text: &str) -> Vec<SearchMatch> {
+        let (mut byte_pos, mut char_pos) = (0, 0);
+        self.regex
+            .find_iter(text)
+            .filter(|m| !m.as_str().is_empty())
+            .map(|m| {
+                char_pos += text[byte_pos..m.start()].chars().count();
+                byte_pos = m.start();
+                SearchMatch { start: char_pos, end: char_pos + m.as_str().chars().count() }
+            })
+            .collect()
+    }
+
+    /// The texts to put in place of `matches`, found in `text` by `find_all`, for the replace
+    /// field `replace`. Captures are taken from each match within `text`, so `\b` and anchors
+    /// see the text around it.
+    pub fn replacements(&self, text: &str, matches: &[SearchMatch], replace: &str) -> Vec<String> {
+        // Matches are in order, so char offsets convert to bytes in one pass
+        let mut offsets =
+            text.char_indices().map(|(byte, _)| byte).chain(std::iter::once(text.len())).enumerate().peekable();
+        let mut byte_at = |char_offset: usize| {
+            while offsets.next_if(|&(i, _)| i < char_offset).is_some() {}
+            offsets.peek().map_or(text.len(), |&(_, byte)| byte)
+        };
+
+        matches
+            .iter()
+            .map(|m| {
+                let (start, end) = (byte_at(m.start), byte_at(m.end));
+                let replaced = match self.regex.captures_at(text, start) {
+                    Some(captures) if self.options.regex && captures.get(0).is_some_and(|c| c.start() == start) => {
+                        let mut expanded = String::new();
+                        captures.expand(replace, &mut expanded);
+                        expanded
+                    }
+                    _ => replace.to_string(),
+                };
+                if self.options.preserve_case {
+                    preserve_case(&text[start..end], &replaced)
+                } else {
+                    replaced
+                }
+            })
+            .collect()
+    }
+
+    /// The text to put in place of one match `m` of `text`, as in `replacements`.
+    pub fn replacement(&self, text: &str, m: SearchMatch, replace: &str) -> String {
+        self.replacements(text, &[m], replace).remove(0)
+    }
+
+    /// Turn preserve case on or off without compiling the query again.
+    pub fn set_preserve_case(&mut self, preserve_case: bool) {
+        self.options.preserve_case = preserve_case;
+    }
+}
+
+/// Index of the match to go to from offset `from`: the first one starting at or after it, or
+/// going `backwards`, the last one starting before it. Wraps around at either end.
+pub fn next_match(matches: &[SearchMatch], from: usize, backwards: bool) -> Option<usize> {
+    if matches.is_empty() {
+        return None;
+    }
+    if backwards {
+        Some(matches.iter().rposition(|m| m.start < from).unwrap_or(matches.len() - 1))
+    } else {
+        Some(matches.iter().position(|m| m.start >= from).unwrap_or(0))
+    }
+}
+
+/// `replacement` in the case of `matched`: all upper case, capitalized, or all lower case.
+/// Mixed case leaves it as typed.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let letters: Vec<char> = matched.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.is_empty() {
+        return replacement.to_string();
+    }
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        return replacement.to_uppercase();
+    }
+    if letters.iter().all(|c| c.is_lowercase()) {
+        return replacement.to_lowercase();
+    }
+    if letters[0].is_uppercase() && letters[1..].iter().all(|c| c.is_lowercase()) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::settings::xdg_dir_from;
+
+const SESSION_FILE_NAME: &str = "session.toml";
+const SNAPSHOT_DIR_NAME: &str = "snapshots";
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("could not access session file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid session file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("could not serialize session: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("no state directory found ($XDG_STATE_HOME and $HOME are unset)")]
+    NoStateDir,
+}
+
+/// Window layout and open tabs, saved on quit and restored on the next launch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub window_width: i32,
+    pub window_height: i32,
+    pub maximized: bool,
+    pub paned_position: Option<i32>,
+    pub explorer_root: Option<PathBuf>,
+    pub expanded_dirs: Vec<PathBuf>,
+    /// Index into `tabs` of the tab that was focused
+    pub active_tab: usize,
+    pub tabs: Vec<TabSession>,
+    /// Command palette entries, most recently used first
+    pub recent_commands: Vec<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            window_width: 1000,
+            window_height: 700,
+            maximized: false,
+            paned_position: None,
+            explorer_root: None,
+            expanded_dirs: Vec::new(),
+            active_tab: 0,
+            tabs: Vec::new(),
+            recent_commands: Vec::new(),
+        }
+    }
+}
+
+/// One open tab: a file on disk, or an untitled buffer kept as a snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TabSession {
+    pub path: Option<PathBuf>,
+    /// Snapshot file in the snapshots directory holding an unsaved untitled buffer
+    pub snapshot: Option<String>,
+    /// Contents of `snapshot`; read and written alongside the session file
+    #[serde(skip)]
+    pub snapshot_text: Option<String>,
+    /// Cursor position as a character offset
+    pub cursor_offset: i32,
+    /// First visible line
+    pub scroll_line: i32,
+    pub syntax_override: Option<String>,
+}
+
+impl Session {
+    /// `$XDG_STATE_HOME/fikby`, falling back to `$HOME/.local/state/fikby`.
+    pub fn state_dir() -> Option<PathBuf> {
+        xdg_dir_from(std::env::var_os("XDG_STATE_HOME"), std::env::var_os("HOME"), ".local/state")
+    }
+
+    /// Load the previous session; a missing or invalid session starts fresh.
+    pub fn load() -> Self {
+        let Some(dir) = Self::state_dir() else {
+            return Self::default();
+        };
+        match Self::load_from(&dir) {
+            Ok(session) => session,
+            Err(SessionError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                eprintln!("Warning: {} ({}), starting a new session", e, dir.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), SessionError> {
+        let dir = Self::state_dir().ok_or(SessionError::NoStateDir)?;
+        self.save_to(&dir)
+    }
+
+    /// Read `session.toml` and the snapshots it references from `dir`. Untitled tabs whose
+    /// snapshot has gone missing are dropped.
+    pub fn load_from(dir: &Path) -> Result<Self, SessionError> {
+        let text = std::fs::read_to_string(dir.join(SESSION_FILE_NAME))?;
+        let mut session: Session = toml::from_str(&text)?;
+
+        let snapshot_dir = dir.join(SNAPSHOT_DIR_NAME);
+        for tab in &mut session.tabs {
+            if let Some(name) = &tab.snapshot {
+                match std::fs::read_to_string(snapshot_dir.join(name)) {
+                    Ok(text) => tab.snapshot_text = Some(text),
+                    Err(e) => eprintln!("Failed to read snapshot {}: {}", name, e),
+                }
+            }
+        }
+        session.tabs.retain(|tab| tab.path.is_some() || tab.snapshot_text.is_some());
+        session.active_tab = session.active_tab.min(session.tabs.len().saturating_sub(1));
+
+        Ok(session)
+    }
+
+    /// Write `session.toml` and one snapshot file per untitled tab with unsaved text to `dir`,
+    /// then delete snapshots from earlier sessions.
+    pub fn save_to(&self, dir: &Path) -> Result<(), SessionError> {
+        let snapshot_dir = dir.join(SNAPSHOT_DIR_NAME);
+        std::fs::create_dir_all(&snapshot_dir)?;
+
+        let mut session = self.clone();
+        let mut written = HashSet::new();
+        for (index, tab) in session.tabs.iter_mut().enumerate() {
+            tab.snapshot = None;
+            if let Some(text) = &tab.snapshot_text {
+                let name = format!("untitled-{}.txt", index);
+                std::fs::write(snapshot_dir.join(&name), text)?;
+                written.insert(name.clone());
+                tab.snapshot = Some(name);
+            }
+        }
+
+        std::fs::write(dir.join(SESSION_FILE_NAME), toml::to_string_pretty(&session)?)?;
+
+        // Only remove old snapshots once the session pointing at the new ones is on disk
+        for entry in std::fs::read_dir(&snapshot_dir)?.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !written.contains(&name) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+        Ok(())
+    }
+}
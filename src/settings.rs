@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::{self, ThemeMode};
+use crate::indent::IndentStyle;
+use crate::lsp::ServerConfig;
+
+const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("could not access settings file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid settings file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("could not serialize settings: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("no configuration directory found ($XDG_CONFIG_HOME and $HOME are unset)")]
+    NoConfigDir,
+}
+
+/// User settings persisted as TOML in `$XDG_CONFIG_HOME/fikby/settings.toml`.
+///
+/// Every field has a default, so partial files (or files from older versions) load fine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub font_family: String,
+    pub font_size: u32,
+    pub tab_width: usize,
+    pub insert_spaces: bool,
+    /// Per-language indentation keyed by syntax name (e.g. `[language_indent.Python]`),
+    /// overriding the built-in rules in `indent::language_default`
+    pub language_indent: BTreeMap<String, IndentStyle>,
+    /// Language servers keyed by LSP language id (e.g. `[language_servers.rust]`), overriding
+    /// the built-in ones in `lsp::default_servers`
+    pub language_servers: BTreeMap<String, ServerConfig>,
+    pub theme: ThemeMode,
+    pub wrap_by_default: bool,
+    /// Type closing brackets and quotes along with opening ones
+    pub auto_close_brackets: bool,
+    /// Files at least this large open in large-file mode
+    pub large_file_threshold_bytes: u64,
+    /// Opening files at least this large asks for confirmation
+    pub large_file_warn_bytes: u64,
+    /// Show dotfiles in the file explorer
+    pub show_hidden_files: bool,
+    /// Extra names hidden from the file explorer; `*` and `?` wildcards are supported
+    pub hidden_patterns: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            font_family: "monospace".to_string(),
+            font_size: 10,
+            tab_width: 4,
+            insert_spaces: true,
+            language_indent: BTreeMap::new(),
+            language_servers: BTreeMap::new(),
+            theme: ThemeMode::Dark,
+            wrap_by_default: false,
+            auto_close_brackets: true,
+            large_file_threshold_bytes: config::LARGE_FILE_THRESHOLD_BYTES,
+            large_file_warn_bytes: config::LARGE_FILE_WARN_BYTES,
+            show_hidden_files: false,
+            hidden_patterns: vec!["target".to_string(), "node_modules".to_string()],
+        }
+    }
+}
+
+impl Settings {
+    /// `$XDG_CONFIG_HOME/fikby`, falling back to `$HOME/.config/fikby`.
+    pub fn config_dir() -> Option<PathBuf> {
+        xdg_dir_from(std::env::var_os("XDG_CONFIG_HOME"), std::env::var_os("HOME"), ".config")
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Self::config_dir().map(|dir| dir.join(SETTINGS_FILE_NAME))
+    }
+
+    /// Load the user's settings, falling back to defaults if the file is missing or invalid.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match Self::load_from(&path) {
+            Ok(settings) => settings,
+            Err(SettingsError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                eprintln!("Warning: {} ({}), using defaults", e, path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, SettingsError> {
+        let text = std::fs::read_to_string(path)?;
+        Self::from_toml(&text)
+    }
+
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = Self::path().ok_or(SettingsError::NoConfigDir)?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), SettingsError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, SettingsError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, SettingsError> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Whether the file explorer should hide an entry called `name`.
+    pub fn is_hidden(&self, name: &str) -> bool {
+        if !self.show_hidden_files && name.starts_with('.') {
+            return true;
+        }
+        self.hidden_patterns.iter().any(|pattern| wildcard_match(pattern, name))
+    }
+
+    /// CSS applying the configured editor font to the text view and the gutter. Each family
+    /// of a comma-separated list is quoted, so names with spaces work.
+    pub fn font_css(&self) -> String {
+        let families: Vec<String> =
+            self.font_family.split(',').map(str::trim).filter(|f| !f.is_empty()).map(css_font_family).collect();
+        format!(".editor-view, .gutter {{ font-family: {}; font-size: {}pt; }}", families.join(", "), self.font_size)
+    }
+}
+
+/// `name` as a CSS font family: a quoted string, unless it's a generic family like `monospace`,
+/// which only works as a keyword.
+fn css_font_family(name: &str) -> String {
+    const GENERIC: [&str; 5] = ["serif", "sans-serif", "monospace", "cursive", "fantasy"];
+    if GENERIC.contains(&name) {
+        return name.to_string();
+    }
+    let name = name.trim_matches(|c| c == '"' || c == '\'');
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `<xdg>/fikby` for an XDG base directory variable, or `$HOME/<home_fallback>/fikby` when it is
+/// unset or empty.
+pub(crate) fn xdg_dir_from(xdg: Option<OsString>, home: Option<OsString>, home_fallback: &str) -> Option<PathBuf> {
+    let base = xdg
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| PathBuf::from(home).join(home_fallback)))?;
+    Some(base.join("fikby"))
+}
+
+/// Match `name` against a pattern where `*` matches any run of characters and `?` any one.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use regex::Regex;
+use ropey::Rope;
+
+use crate::completion::is_word_char;
+use crate::file_format;
+use crate::quick_open;
+use crate::settings::Settings;
+
+// Files larger than this aren't scanned: they're almost always generated
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+// Longer lines are skipped, so minified code doesn't fill the index with noise
+const MAX_LINE_BYTES: usize = 1000;
+// Optional visibility of Rust items, substituted for `VIS` in the Rust patterns
+const RUST_VISIBILITY: &str = r"(?:pub(?:\([^)]*\))?\s+)?";
+
+/// A definition found by scanning source text the way ctags does: by recognizing lines that
+/// look like one rather than parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// What was defined: "function", "struct", "class"...
+    pub kind: &'static str,
+    pub path: PathBuf,
+    /// Zero-based line, and character column of the name on it
+    pub line: usize,
+    pub column: usize,
+    /// The defining line, trimmed
+    pub signature: String,
+    /// The comment right above the definition (or a Python docstring below it), without
+    /// comment markers
+    pub doc: Option<String>,
+}
+
+struct Pattern {
+    kind: &'static str,
+    /// Matches a defining line, capturing the defined name as `name`
+    regex: Regex,
+}
+
+/// How to find definitions and their comments in the files with one of `extensions`.
+struct Language {
+    extensions: &'static [&'static str],
+    patterns: Vec<Pattern>,
+    /// Markers of the comment lines documenting a definition, longest first
+    line_comments: &'static [&'static str],
+    /// Whether `/* ... */` comments document definitions
+    block_comments: bool,
+    /// Starts of the attribute or decorator lines between a comment and its definition
+    attributes: &'static [&'static str],
+    /// Whether docstrings follow the defining line, as in Python
+    docstrings: bool,
+}
+
+fn languages() -> &'static [Language] {
+    static LANGUAGES: OnceLock<Vec<Language>> = OnceLock::new();
+    LANGUAGES.get_or_init(|| {
+        let patterns = |patterns: &[(&'static str, &str)]| {
+            patterns
+                .iter()
+                .map(|&(kind, pattern)| Pattern {
+                    kind,
+                    regex: Regex::new(&pattern.replace("VIS", RUST_VISIBILITY)).expect("invalid symbol pattern"),
+                })
+                .collect()
+        };
+        let c_like = |extensions, patterns| Language {
+            extensions,
+            patterns,
+            line_comments: &["///", "//"],
+            block_comments: true,
+            attributes: &["@"],
+            docstrings: false,
+        };
+        vec![
+            Language {
+                extensions: &["rs"],
+                patterns: patterns(&[
+                    ("function", r#"^\s*VIS(?:(?:default|const|async|unsafe|extern(?:\s+"[^"]*")?)\s+)*fn\s+(?P<name>\w+)"#),
+                    ("struct", r"^\s*VISstruct\s+(?P<name>\w+)"),
+                    ("enum", r"^\s*VISenum\s+(?P<name>\w+)"),
+                    ("union", r"^\s*VISunion\s+(?P<name>\w+)\s*[<{]"),
+                    ("trait", r"^\s*VIS(?:unsafe\s+)?(?:auto\s+)?trait\s+(?P<name>\w+)"),
+                    ("type", r"^\s*VIStype\s+(?P<name>\w+)"),
+                    ("constant", r"^\s*VIS(?:const|static(?:\s+mut)?)\s+(?P<name>\w+)\s*:"),
+                    ("module", r"^\s*VISmod\s+(?P<name>\w+)"),
+                    ("macro", r"^\s*macro_rules!\s*(?P<name>\w+)"),
+                ]),
+                line_comments: &["///"],
+                block_comments: false,
+                attributes: &["#["],
+                docstrings: false,
+            },
+            Language {
+                extensions: &["py", "pyi"],
+                patterns: patterns(&[
+                    ("function", r"^\s*(?:async\s+)?def\s+(?P<name>\w+)"),
+                    ("class", r"^\s*class\s+(?P<name>\w+)"),
+                ]),
+                line_comments: &["#"],
+                block_comments: false,
+                attributes: &["@"],
+                docstrings: true,
+            },
+            c_like(
+                &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+                patterns(&[
+                    ("function", r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>[\w$]+)"),
+                    ("class", r"^\s*(?:export\s+)?(?:default\s+)?(?:abstract\s+)?class\s+(?P<name>[\w$]+)"),
+                    ("interface", r"^\s*(?:export\s+)?interface\s+(?P<name>[\w$]+)"),
+                    ("type", r"^\s*(?:export\s+)?type\s+(?P<name>[\w$]+)\s*(?:<[^=]*>)?\s*="),
+                    ("enum", r"^\s*(?:export\s+)?(?:const\s+)?enum\s+(?P<name>[\w$]+)"),
+                    // Only at the top level, to leave out locals
+                    ("variable", r"^(?:export\s+)?(?:const|let|var)\s+(?P<name>[\w$]+)\s*(?::[^=]*)?="),
+                ]),
+            ),
+            c_like(
+                &["go"],
+                patterns(&[
+                    ("function", r"^func\s+(?:\([^)]*\)\s*)?(?P<name>\w+)"),
+                    ("type", r"^type\s+(?P<name>\w+)"),
+                    ("constant", r"^(?:const|var)\s+(?P<name>\w+)"),
+                ]),
+            ),
+            c_like(
+                &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx"],
+                patterns(&[
+                    ("macro", r"^\s*#\s*define\s+(?P<name>\w+)"),
+                    ("struct", r"^\s*(?:typedef\s+)?(?:struct|union|enum|class)\s+(?P<name>\w+)\s*(?:[:{]|$)"),
+                    // A top-level line with a return type and no `;`: a definition, not a call
+                    // or a declaration
+                    ("function", r"^(?:[A-Za-z_][\w:<>,*&]*[\s*&]+)+(?:\w+::)*(?P<name>~?[A-Za-z_]\w*)\s*\([^;]*$"),
+                ]),
+            ),
+            Language {
+                extensions: &["sh", "bash", "zsh"],
+                patterns: patterns(&[
+                    ("function", r"^\s*function\s+(?P<name>[\w-]+)"),
+                    ("function", r"^\s*(?P<name>[\w-]+)\s*\(\)"),
+                ]),
+                line_comments: &["#"],
+                block_comments: false,
+                attributes: &[],
+                docstrings: false,
+            },
+        ]
+    })
+}
+
+fn language_of(path: &Path) -> Option<&'static Language> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    languages().iter().find(|language| language.extensions.contains(&extension.as_str()))
+}
+
+/// Whether definitions are looked for in files like `path`.
+pub fn is_indexed(path: &Path) -> bool {
+    language_of(path).is_some()
+}
+
+/// The definitions in `text`, the contents of the file at `path`, in order. Files in
+/// languages without patterns have none.
+pub fn extract_symbols(path: &Path, text: &str) -> Vec<Symbol> {
+    let Some(language) = language_of(path) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let mut symbols = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if line.len() > MAX_LINE_BYTES {
+            continue;
+        }
+        let name = language
+            .patterns
+            .iter()
+            .find_map(|pattern| Some((pattern.kind, pattern.regex.captures(line)?.name("name")?)));
+        let Some((kind, name)) = name else {
+            continue;
+        };
+        let doc = doc_above(&lines, index, language)
+            .or_else(|| language.docstrings.then(|| docstring_below(&lines, index)).flatten());
+        symbols.push(Symbol {
+            name: name.as_str().to_string(),
+            kind,
+            path: path.to_path_buf(),
+            line: index,
+            column: line[..name.start()].chars().count(),
+            signature: line.trim().trim_end_matches('{').trim_end().to_string(),
+            doc,
+        });
+    }
+    symbols
+}
+
+/// The comment lines right above line `index`, skipping attributes, without their markers.
+fn doc_above(lines: &[&str], index: usize, language: &Language) -> Option<String> {
+    let mut doc = Vec::new();
+    let mut in_block = false;
+    for line in lines[..index].iter().rev() {
+        let trimmed = line.trim();
+        if in_block {
+            doc.push(strip_block_comment(trimmed));
+            if trimmed.starts_with("/*") {
+                break;
+            }
+            continue;
+        }
+        if language.block_comments && trimmed.ends_with("*/") {
+            doc.push(strip_block_comment(trimmed));
+            if trimmed.starts_with("/*") {
+                break;
+            }
+            in_block = true;
+            continue;
+        }
+        if let Some(text) = language.line_comments.iter().find_map(|marker| trimmed.strip_prefix(marker)) {
+            doc.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+            continue;
+        }
+        if language.attributes.iter().any(|attribute| trimmed.starts_with(attribute)) {
+            continue;
+        }
+        break;
+    }
+    doc.reverse();
+    join_doc(&doc)
+}
+
+fn strip_block_comment(line: &str) -> &str {
+    let line = line.strip_prefix("/**").or_else(|| line.strip_prefix("/*")).unwrap_or(line);
+    let line = line.strip_suffix("*/").unwrap_or(line);
+    let line = line.strip_prefix('*').unwrap_or(line);
+    line.strip_prefix(' ').unwrap_or(line).trim_end()
+}
+
+/// The docstring starting on the line after `index`, without its quotes and indentation.
+fn docstring_below(lines: &[&str], index: usize) -> Option<String> {
+    let first = lines.get(index + 1)?.trim();
+    let quotes = ["\"\"\"", "'''"].into_iter().find(|quotes| first.starts_with(quotes))?;
+    let first = &first[quotes.len()..];
+    if let Some(end) = first.find(quotes) {
+        return join_doc(&[&first[..end]]);
+    }
+    let mut doc = vec![first];
+    for line in &lines[index + 2..] {
+        let line = line.trim();
+        if let Some(end) = line.find(quotes) {
+            doc.push(&line[..end]);
+            break;
+        }
+        doc.push(line);
+    }
+    join_doc(&doc)
+}
+
+/// `lines` joined, without leading and trailing blank lines; `None` if nothing's left.
+fn join_doc(lines: &[&str]) -> Option<String> {
+    let first = lines.iter().position(|line| !line.trim().is_empty())?;
+    let last = lines.iter().rposition(|line| !line.trim().is_empty())?;
+    Some(lines[first..=last].join("\n"))
+}
+
+/// Char range of the identifier at `offset` in `text`: the one it's in, else the one it ends.
+pub fn identifier_at(text: &Rope, offset: usize) -> Option<(usize, usize)> {
+    let is_word_at = |offset: usize| offset < text.len_chars() && is_word_char(text.char(offset));
+    let inside = if is_word_at(offset) { offset } else { offset.checked_sub(1).filter(|&before| is_word_at(before))? };
+    let start = (0..inside).rev().take_while(|&i| is_word_at(i)).last().unwrap_or(inside);
+    let end = (inside..text.len_chars()).take_while(|&i| is_word_at(i)).last().map_or(inside, |last| last + 1);
+    // Numbers aren't identifiers
+    (!text.char(start).is_ascii_digit()).then_some((start, end))
+}
+
+/// Definitions by name, from every file under a folder; used for navigating and hovering in
+/// files no language server handles.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    by_name: HashMap<String, Vec<Symbol>>,
+}
+
+impl SymbolIndex {
+    /// Index the files under `root` that Quick Open lists. Meant for a background thread.
+    pub fn build(root: &Path, settings: &Settings) -> Self {
+        let mut index = Self::default();
+        for path in quick_open::index_files(root, settings) {
+            if !is_indexed(&path) {
+                continue;
+            }
+            let path = root.join(path);
+            if let Some(text) = read_source(&path) {
+                index.insert(&path, &text);
+            }
+        }
+        index
+    }
+
+    /// Replace the definitions indexed for `path` with those in `text`, after it was saved.
+    pub fn update_file(&mut self, path: &Path, text: &str) {
+        self.by_name.retain(|_, symbols| {
+            symbols.retain(|symbol| symbol.path != path);
+            !symbols.is_empty()
+        });
+        self.insert(path, text);
+    }
+
+    /// Definitions of `name`, in the order they were indexed.
+    pub fn definitions(&self, name: &str) -> &[Symbol] {
+        self.by_name.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    fn insert(&mut self, path: &Path, text: &str) {
+        for symbol in extract_symbols(path, text) {
+            self.by_name.entry(symbol.name.clone()).or_default().push(symbol);
+        }
+    }
+}
+
+/// The text of the file at `path`, or `None` if it can't be read, is too large or looks
+/// binary.
+fn read_source(path: &Path) -> Option<String> {
+    if std::fs::metadata(path).ok()?.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let (text, _) = file_format::decode(&std::fs::read(path).ok()?);
+    (!text.contains('\0')).then_some(text)
+}
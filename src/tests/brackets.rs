@@ -0,0 +1,75 @@
+use ropey::Rope;
+use syntect::parsing::SyntaxSet;
+
+use crate::brackets::{
+    in_string_or_comment, is_empty_pair, jump_target, matching_bracket, matching_bracket_where, should_auto_close,
+    string_and_comment_ranges,
+};
+
+/// The bracket after the cursor wins over the one before; nesting is respected both ways
+#[test]
+fn matches_brackets_around_cursor() {
+    let rope = Rope::from_str("fn f(a: [u8; 2]) { g(); }");
+    assert_eq!(matching_bracket(&rope, 4), Some((4, 15)));
+    assert_eq!(matching_bracket(&rope, 16), Some((15, 4)));
+    assert_eq!(matching_bracket(&rope, 17), Some((17, 24)));
+    assert_eq!(matching_bracket(&rope, 25), Some((24, 17)));
+    assert_eq!(matching_bracket(&rope, 2), None);
+    // Unbalanced
+    assert_eq!(matching_bracket(&Rope::from_str("(()"), 0), None);
+
+    // Jumping keeps the cursor on the same side of the bracket, so it goes back and forth
+    assert_eq!(jump_target(&rope, 4), Some(15));
+    assert_eq!(jump_target(&rope, 15), Some(4));
+    assert_eq!(jump_target(&rope, 25), Some(18));
+}
+
+/// Closers are only added where they can't swallow following text
+#[test]
+fn auto_close_rules() {
+    assert!(should_auto_close('(', Some('f'), None));
+    assert!(should_auto_close('{', Some(' '), Some(')')));
+    assert!(!should_auto_close('(', None, Some('x')));
+    assert!(should_auto_close('"', Some('('), Some(')')));
+    // "don't", escaped quotes and a second quote right after one
+    assert!(!should_auto_close('\'', Some('n'), None));
+    assert!(!should_auto_close('"', Some('\\'), None));
+    assert!(!should_auto_close('"', Some('"'), None));
+    // Lifetimes
+    assert!(!should_auto_close('\'', Some('&'), Some(' ')));
+    assert!(!should_auto_close('\'', Some('<'), None));
+    assert!(should_auto_close('"', Some('&'), None));
+
+    assert!(is_empty_pair(Some('('), Some(')')));
+    assert!(is_empty_pair(Some('\''), Some('\'')));
+    assert!(!is_empty_pair(Some('('), Some(']')));
+    assert!(!is_empty_pair(None, Some(')')));
+}
+
+/// Syntax scopes tell strings and comments apart from code
+#[test]
+fn detects_strings_and_comments() {
+    let ss = SyntaxSet::load_defaults_newlines();
+    let rust = ss.find_syntax_by_extension("rs").unwrap();
+    assert!(!in_string_or_comment(&ss, rust, "fn main() {\n    let s = "));
+    assert!(in_string_or_comment(&ss, rust, "fn main() {\n    let s = \"abc"));
+    assert!(!in_string_or_comment(&ss, rust, "    let s = \"abc\";"));
+    assert!(in_string_or_comment(&ss, rust, "let x = 1; // note"));
+    assert!(in_string_or_comment(&ss, rust, "/* start\n still"));
+}
+
+/// Brackets in strings and comments are skipped when matching
+#[test]
+fn matches_brackets_outside_strings_and_comments() {
+    let ss = SyntaxSet::load_defaults_newlines();
+    let rust = ss.find_syntax_by_extension("rs").unwrap();
+    let text = "f(\")\", x) // (\n";
+    let ranges = string_and_comment_ranges(&ss, rust, text);
+    assert_eq!(ranges, vec![2..5, 10..15]);
+
+    let rope = Rope::from_str(text);
+    let in_code = |at: usize| !ranges.iter().any(|range| range.contains(&at));
+    assert_eq!(matching_bracket(&rope, 1), Some((1, 3)));
+    assert_eq!(matching_bracket_where(&rope, 1, in_code), Some((1, 8)));
+    assert_eq!(matching_bracket_where(&rope, 13, in_code), None);
+}
@@ -0,0 +1,57 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use crate::commands::{title_from_action_name, CommandRegistry, MAX_RECENT_COMMANDS};
+
+fn registry() -> CommandRegistry {
+    let mut registry = CommandRegistry::default();
+    registry.register("save", "File: Save");
+    registry.register("save-all", "File: Save All");
+    registry.register("toggle-theme", "View: Toggle Theme");
+    registry.register_with_target("convert-indentation", "tabs", "Edit: Convert Indentation to Tabs");
+    registry
+}
+
+/// Actions without a registered title still show up, named after the action
+#[test]
+fn unregistered_actions_get_derived_titles() {
+    let commands = registry().commands_for(vec!["save".to_string(), "explorer-new-file".to_string()]);
+    assert_eq!(commands.len(), 5);
+    assert_eq!(commands.last().unwrap().title, "Explorer New File");
+    assert_eq!(title_from_action_name("toggle-wrap"), "Toggle Wrap");
+}
+
+/// Recent commands lead the empty query and break ties; the rest are alphabetical
+#[test]
+fn recent_commands_rank_first() {
+    let mut registry = registry();
+    let commands = registry.commands_for(Vec::new());
+    registry.record_use("toggle-theme");
+    registry.record_use("convert-indentation::tabs");
+
+    let titles: Vec<String> = registry
+        .rank(&SkimMatcherV2::default(), "", &commands)
+        .into_iter()
+        .map(|m| m.command.title)
+        .collect();
+    assert_eq!(
+        titles,
+        ["Edit: Convert Indentation to Tabs", "View: Toggle Theme", "File: Save", "File: Save All"]
+    );
+
+    let matches = registry.rank(&SkimMatcherV2::default(), "save all", &commands);
+    assert_eq!(matches[0].command.action, "save-all");
+    assert!(!matches[0].indices.is_empty());
+}
+
+/// Using a command again moves it to the front, and the list is capped
+#[test]
+fn recent_list_is_bounded() {
+    let mut registry = CommandRegistry::default();
+    for i in 0..MAX_RECENT_COMMANDS + 3 {
+        registry.record_use(&format!("command-{}", i));
+    }
+    registry.record_use("command-5");
+    assert_eq!(registry.recent().len(), MAX_RECENT_COMMANDS);
+    assert_eq!(registry.recent()[0], "command-5");
+    assert_eq!(registry.recent().iter().filter(|r| *r == "command-5").count(), 1);
+}
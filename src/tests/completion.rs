@@ -0,0 +1,113 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use ropey::Rope;
+use serde_json::json;
+
+use crate::completion::{
+    adjust_indentation, buffer_words, filter, from_lsp, from_lsp_response, parse_snippet, CompletionItem, CompletionKind,
+    TextEdit,
+};
+use crate::lsp::protocol::PositionEncoding;
+
+/// Edits are converted to char offsets through the text the server knows; insert ranges are
+/// preferred over replace ones, and documentation may be markup
+#[test]
+fn converts_lsp_items() {
+    let text = Rope::from_str("use std;\n😀 ve\n");
+    let item = json!({
+        "label": "vec!",
+        "kind": 3,
+        "detail": "macro_rules! vec",
+        "documentation": { "kind": "markdown", "value": "Creates a `Vec`." },
+        "filterText": "vec",
+        "insertTextFormat": 2,
+        "textEdit": {
+            "newText": "vec![$0]",
+            "insert": { "start": { "line": 1, "character": 3 }, "end": { "line": 1, "character": 5 } },
+            "replace": { "start": { "line": 1, "character": 3 }, "end": { "line": 1, "character": 6 } },
+        },
+        "additionalTextEdits": [
+            { "range": { "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 7 } }, "newText": "std::vec" },
+        ],
+    });
+    let converted = from_lsp(&item, Some(&text), PositionEncoding::Utf16).unwrap();
+    assert_eq!(converted.kind, CompletionKind::Function);
+    assert_eq!(converted.insert_text, "vec![$0]");
+    assert!(converted.is_snippet);
+    // 😀 is two UTF-16 units but one char
+    assert_eq!(converted.replace_start, Some(11));
+    assert_eq!(converted.additional_edits, vec![TextEdit { start: 4, end: 7, text: "std::vec".to_string() }]);
+    assert_eq!(converted.documentation.as_deref(), Some("Creates a `Vec`."));
+    assert_eq!((converted.filter_text.as_str(), converted.sort_text.as_str()), ("vec", "vec!"));
+
+    let plain = from_lsp(&json!({ "label": "len", "detail": "" }), None, PositionEncoding::Utf16).unwrap();
+    assert_eq!((plain.insert_text.as_str(), plain.kind, plain.detail), ("len", CompletionKind::Text, None));
+    assert!(!plain.is_snippet && plain.replace_start.is_none());
+    assert_eq!(CompletionKind::from_lsp(Some(99)), CompletionKind::Text);
+
+    let list = json!({ "isIncomplete": true, "items": [{ "label": "a" }, { "kind": 1 }] });
+    let (items, incomplete) = from_lsp_response(&list, None, PositionEncoding::Utf16);
+    assert_eq!((items.len(), incomplete), (1, true));
+    let (items, incomplete) = from_lsp_response(&json!([{ "label": "a" }, { "label": "b" }]), None, PositionEncoding::Utf16);
+    assert_eq!((items.len(), incomplete), (2, false));
+}
+
+/// Words come from every text, once each, without numbers, single chars or the typed word
+#[test]
+fn collects_buffer_words() {
+    let texts = [Rope::from_str("let count = 42;\nx += count_total"), Rope::from_str("count; 3d coun")];
+    assert_eq!(buffer_words(&texts, "coun"), vec!["count", "count_total", "let"]);
+}
+
+/// Better matches first; on ties the server's items beat buffer words, then sort text
+/// decides. Labels are bolded only where they were matched
+#[test]
+fn filters_fuzzily() {
+    let matcher = SkimMatcherV2::default();
+    let server_item = |label: &str, sort_text: &str| CompletionItem {
+        sort_text: sort_text.to_string(),
+        kind: CompletionKind::Method,
+        ..CompletionItem::word(label)
+    };
+    let items = vec![
+        CompletionItem::word("push_str"),
+        server_item("push_str", "2"),
+        server_item("push", "1"),
+        server_item("pop", "0"),
+        CompletionItem { filter_text: "is_empty".to_string(), ..server_item("is_empty()", "3") },
+    ];
+
+    let labels = |typed: &str| -> Vec<(String, CompletionKind)> {
+        filter(&matcher, &items, typed).iter().map(|m| (items[m.index].label.clone(), items[m.index].kind)).collect()
+    };
+    let ranked = labels("psh");
+    assert_eq!(ranked.len(), 3);
+    assert_eq!(ranked[1..], [("push_str".to_string(), CompletionKind::Method), ("push_str".to_string(), CompletionKind::Word)]);
+    assert_eq!(labels("").first().map(|(label, _)| label.as_str()), Some("pop"));
+    assert!(labels("xyz").is_empty());
+
+    let matches = filter(&matcher, &items, "pu");
+    assert_eq!(matches[0].indices, vec![0, 1]);
+    let matches = filter(&matcher, &items, "empty");
+    assert_eq!((items[matches[0].index].label.as_str(), matches[0].indices.len()), ("is_empty()", 0));
+}
+
+/// Tab stops in order with `$0` last, placeholders (nested too), choices, variables and
+/// escapes; multi-line snippets follow the line's indentation
+#[test]
+fn expands_snippets() {
+    let snippet = parse_snippet("fn ${1:name}(${2:arg: ${3:T}}) {\n\t$0\n}");
+    assert_eq!(snippet.text, "fn name(arg: T) {\n\t\n}");
+    assert_eq!(snippet.tab_stops, vec![(3, 7), (8, 14), (13, 14), (19, 19)]);
+
+    let snippet = parse_snippet("${2:b} ${1|x,y|} $1 \\$HOME ${TM_FILENAME:file} $VAR \\}");
+    assert_eq!(snippet.text, "b x  $HOME file  }");
+    // Without `$0`, the cursor ends at the end
+    assert_eq!(snippet.tab_stops, vec![(2, 3), (0, 1), (18, 18)]);
+
+    let snippet = parse_snippet("cost: $ ${ 5 }");
+    assert_eq!(snippet.text, "cost: $ ${ 5 }");
+    assert_eq!(snippet.tab_stops, vec![(14, 14)]);
+
+    let adjusted = adjust_indentation("match $1 {\n\t$0\n}", "    ", "    ");
+    assert_eq!(adjusted, "match $1 {\n        $0\n    }");
+}
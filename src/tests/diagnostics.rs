@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use ropey::Rope;
+use serde_json::json;
+
+use crate::diagnostics::{from_lsp, parse_cargo_messages, Diagnostic, DiagnosticStore, Severity};
+use crate::lsp::find_root;
+use crate::lsp::protocol::PositionEncoding;
+
+fn diagnostic(severity: Severity, line: usize, column: usize) -> Diagnostic {
+    Diagnostic {
+        severity,
+        message: format!("{:?} at {}:{}", severity, line, column),
+        source: None,
+        code: None,
+        line,
+        column,
+        end_line: line,
+        end_column: column + 1,
+    }
+}
+
+/// LSP ranges are converted to char columns through the text the server knows
+#[test]
+fn converts_lsp_diagnostics() {
+    let value = json!({
+        "range": { "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 5 } },
+        "severity": 2,
+        "code": 401,
+        "source": "rust-analyzer",
+        "message": "unused",
+    });
+    let text = Rope::from_str("😀é x\n");
+    let converted = from_lsp(&value, Some(&text), PositionEncoding::Utf16).unwrap();
+    assert_eq!((converted.line, converted.column, converted.end_column), (0, 3, 4));
+    assert_eq!(converted.severity, Severity::Warning);
+    assert_eq!(converted.code.as_deref(), Some("401"));
+    assert_eq!(converted.source.as_deref(), Some("rust-analyzer"));
+
+    let without_text = from_lsp(&value, None, PositionEncoding::Utf16).unwrap();
+    assert_eq!((without_text.column, without_text.end_column), (4, 5));
+    assert_eq!(from_lsp(&json!({ "message": "no range" }), None, PositionEncoding::Utf16), None);
+    assert_eq!(Severity::from_lsp(None), Severity::Error);
+
+    assert!(converted.contains(0, 3));
+    assert!(!converted.contains(0, 4));
+    let empty = Diagnostic { end_column: 3, ..converted };
+    assert!(empty.contains(0, 3));
+}
+
+/// Primary spans of compiler messages, one-based in the output, with help folded into the
+/// message; other records, summaries and duplicates are skipped
+#[test]
+fn parses_cargo_check_output() {
+    let message = json!({
+        "reason": "compiler-message",
+        "message": {
+            "message": "unused variable: `x`",
+            "level": "warning",
+            "code": { "code": "unused_variables" },
+            "spans": [
+                { "file_name": "src/other.rs", "is_primary": false, "line_start": 1, "line_end": 1, "column_start": 1, "column_end": 2 },
+                { "file_name": "src/main.rs", "is_primary": true, "line_start": 3, "line_end": 3, "column_start": 9, "column_end": 10 },
+            ],
+            "children": [{ "message": "prefix it with an underscore: `_x`", "level": "help", "spans": [] }],
+        },
+    });
+    let summary = json!({
+        "reason": "compiler-message",
+        "message": { "message": "aborting due to 1 previous error", "level": "error", "spans": [], "children": [] },
+    });
+    let output = [
+        json!({ "reason": "compiler-artifact" }).to_string(),
+        message.to_string(),
+        message.to_string(),
+        summary.to_string(),
+        "   Compiling fikby v0.1.0".to_string(),
+        json!({ "reason": "build-finished", "success": true }).to_string(),
+    ]
+    .join("\n");
+
+    let files = parse_cargo_messages(&output, Path::new("/work"));
+    assert_eq!(files.keys().collect::<Vec<_>>(), [Path::new("/work/src/main.rs")]);
+    let diagnostics = &files[Path::new("/work/src/main.rs")];
+    assert_eq!(diagnostics.len(), 1);
+    let d = &diagnostics[0];
+    assert_eq!((d.severity, d.line, d.column, d.end_line, d.end_column), (Severity::Warning, 2, 8, 2, 9));
+    assert_eq!(d.message, "unused variable: `x`\nhelp: prefix it with an underscore: `_x`");
+    assert_eq!(d.code.as_deref(), Some("unused_variables"));
+    assert_eq!(d.source.as_deref(), Some("rustc"));
+}
+
+/// A member crate is checked from its workspace, whose root cargo's file names are relative to
+#[test]
+fn checks_member_crates_from_the_workspace() {
+    let dir = std::env::temp_dir().join(format!("fikby-cargo-member-{}", std::process::id()));
+    let member = dir.join("crates/foo");
+    std::fs::create_dir_all(member.join("src")).unwrap();
+    std::fs::write(dir.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/foo\"]\n").unwrap();
+    std::fs::write(member.join("Cargo.toml"), "").unwrap();
+    std::fs::write(member.join("src/lib.rs"), "").unwrap();
+
+    let root = find_root(&member.join("src/lib.rs"), &["Cargo.toml".to_string()]);
+    assert_eq!(root, dir);
+    let span = json!({ "file_name": "crates/foo/src/lib.rs", "is_primary": true, "line_start": 1, "line_end": 1, "column_start": 1, "column_end": 1 });
+    let message = json!({
+        "reason": "compiler-message",
+        "message": { "message": "unused import", "level": "warning", "spans": [span], "children": [] },
+    });
+    let files = parse_cargo_messages(&message.to_string(), &root);
+    assert_eq!(files.keys().collect::<Vec<_>>(), [&member.join("src/lib.rs")]);
+    assert!(files.keys().all(|path| path.is_file()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Each origin's diagnostics are replaced on their own; files list them merged and sorted
+#[test]
+fn store_keeps_origins_apart() {
+    let (a, b) = (Path::new("/a.rs"), Path::new("/b.rs"));
+    let mut store = DiagnosticStore::default();
+    store.set(a, "rust", vec![diagnostic(Severity::Warning, 5, 0)]);
+    let cargo = BTreeMap::from([
+        (a.to_path_buf(), vec![diagnostic(Severity::Error, 1, 0)]),
+        (b.to_path_buf(), vec![diagnostic(Severity::Error, 2, 0)]),
+    ]);
+    store.replace_origin("cargo", cargo);
+    assert_eq!(store.counts(), (2, 1));
+    assert_eq!(store.for_file(a), vec![diagnostic(Severity::Error, 1, 0), diagnostic(Severity::Warning, 5, 0)]);
+
+    // A new run without problems in b.rs clears it, and leaves the server's ones alone
+    let changed = store.replace_origin("cargo", BTreeMap::from([(a.to_path_buf(), vec![diagnostic(Severity::Hint, 0, 0)])]));
+    assert_eq!(changed, vec![PathBuf::from(b), PathBuf::from(a)]);
+    assert_eq!(store.files().into_iter().map(|(path, d)| (path, d.len())).collect::<Vec<_>>(), vec![(a.to_path_buf(), 2)]);
+    store.set(a, "rust", Vec::new());
+    store.replace_origin("cargo", BTreeMap::new());
+    assert!(store.files().is_empty());
+    assert_eq!(store.counts(), (0, 0));
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use crate::documents::{canonical_key, DocumentRegistry};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fikby-documents-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::canonicalize(dir).unwrap()
+}
+
+/// The same file reached through `..` or a symlink finds the same document
+#[cfg(unix)]
+#[test]
+fn lookup_by_canonical_path() {
+    let dir = temp_dir("lookup");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/main.rs"), "").unwrap();
+    std::os::unix::fs::symlink(dir.join("src"), dir.join("link")).unwrap();
+
+    let mut registry = DocumentRegistry::default();
+    registry.insert(&dir.join("src/main.rs"), 1);
+    assert_eq!(registry.get(&dir.join("src/../src/main.rs")), Some(1));
+    assert_eq!(registry.get(&dir.join("link/main.rs")), Some(1));
+    assert_eq!(registry.get(&dir.join("src/lib.rs")), None);
+
+    // Files that don't exist resolve through their parent
+    assert_eq!(canonical_key(&dir.join("link/new.rs")), dir.join("src/new.rs"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Renaming a folder moves every document inside it; files next to it stay put
+#[test]
+fn rename_moves_files_and_folders() {
+    let dir = temp_dir("rename");
+    let mut registry = DocumentRegistry::default();
+    registry.insert(&dir.join("old/a.rs"), "a");
+    registry.insert(&dir.join("old/sub/b.rs"), "b");
+    registry.insert(&dir.join("older.rs"), "c");
+
+    let mut moved = registry.rename(&dir.join("old"), &dir.join("new"));
+    moved.sort();
+    assert_eq!(moved, vec![("a", dir.join("new/a.rs")), ("b", dir.join("new/sub/b.rs"))]);
+    assert_eq!(registry.get(&dir.join("new/sub/b.rs")), Some("b"));
+    assert_eq!(registry.get(&dir.join("old/a.rs")), None);
+    assert_eq!(registry.get(&dir.join("older.rs")), Some("c"));
+
+    assert_eq!(registry.rename(&dir.join("older.rs"), &dir.join("renamed.rs")), vec![("c", dir.join("renamed.rs"))]);
+
+    let mut deleted = registry.under(&dir.join("new"));
+    deleted.sort();
+    assert_eq!(deleted, vec!["a", "b"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
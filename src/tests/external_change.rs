@@ -0,0 +1,36 @@
+use std::time::{Duration, SystemTime};
+
+use crate::external_change::{check, modified_time, unified_diff, DiskChange};
+
+/// Rewrites are noticed through the modification time, deletions through the missing file
+#[test]
+fn detects_modified_and_deleted_files() {
+    let path = std::env::temp_dir().join(format!("fikby-external-{}.txt", std::process::id()));
+    std::fs::write(&path, "one\n").unwrap();
+    let recorded = modified_time(&path);
+    assert_eq!(check(recorded, &path), DiskChange::Unchanged);
+
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+    drop(file);
+    assert_eq!(check(recorded, &path), DiskChange::Modified);
+
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(check(recorded, &path), DiskChange::Deleted);
+
+    // A file created after the tab was opened counts as a change
+    std::fs::write(&path, "two\n").unwrap();
+    assert_eq!(check(None, &path), DiskChange::Modified);
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// The diff goes from the disk version to the unsaved buffer
+#[test]
+fn diff_shows_disk_to_buffer() {
+    let diff = unified_diff("main.rs", "a\nb\nc\n", "a\nB\nc\n");
+    assert!(diff.contains("--- main.rs (on disk)"));
+    assert!(diff.contains("+++ main.rs (unsaved)"));
+    assert!(diff.contains("-b\n"));
+    assert!(diff.contains("+B\n"));
+    assert!(diff.contains(" a\n"));
+}
@@ -0,0 +1,77 @@
+use crate::file_format::{decode, encode, write_atomic, Encoding, FileFormat, LineEnding};
+
+fn format(encoding: Encoding, line_ending: LineEnding) -> FileFormat {
+    FileFormat { encoding, line_ending, mixed_line_endings: false }
+}
+
+/// Every supported encoding survives a decode/encode round trip byte for byte
+#[test]
+fn round_trips_each_encoding() {
+    for encoding in [Encoding::Utf8, Encoding::Utf8Bom, Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Latin1] {
+        let original = encode("fn main() {\n    café();\n}\n", format(encoding, LineEnding::Lf)).unwrap();
+        let (text, detected) = decode(&original);
+        assert_eq!(text, "fn main() {\n    café();\n}\n");
+        assert_eq!(detected, format(encoding, LineEnding::Lf), "{:?}", encoding);
+        assert_eq!(encode(&text, detected).unwrap(), original);
+    }
+}
+
+/// CRLF files are edited with `\n` and written back with `\r\n`
+#[test]
+fn detects_and_restores_crlf() {
+    let (text, detected) = decode(b"one\r\ntwo\r\nthree\n");
+    assert_eq!(text, "one\ntwo\nthree\n");
+    assert_eq!(detected.line_ending, LineEnding::CrLf);
+    assert_eq!(encode(&text, detected).unwrap(), b"one\r\ntwo\r\nthree\r\n");
+
+    // Mixed files are flagged, since saving normalizes them
+    assert!(detected.mixed_line_endings);
+    let (_, detected) = decode(b"one\ntwo\r\nthree\n");
+    assert_eq!(detected.line_ending, LineEnding::Lf);
+    assert!(detected.mixed_line_endings);
+    assert!(!decode(b"one\r\ntwo\r\n").1.mixed_line_endings);
+}
+
+/// Invalid UTF-8 falls back to Latin-1, which can't hold characters above U+00FF
+#[test]
+fn latin1_fallback_and_unencodable_chars() {
+    let (text, detected) = decode(b"caf\xe9\n");
+    assert_eq!(text, "café\n");
+    assert_eq!(detected.encoding, Encoding::Latin1);
+
+    let err = encode("ok\nsnow ☃\n", detected).unwrap_err();
+    assert_eq!(err.ch, '☃');
+    assert_eq!(err.line, 2);
+}
+
+/// UTF-16 without a byte order mark is recognised from its zero bytes
+#[test]
+fn guesses_utf16_without_bom() {
+    let le: Vec<u8> = "hello\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let (text, detected) = decode(&le);
+    assert_eq!(text, "hello\n");
+    assert_eq!(detected.encoding, Encoding::Utf16Le);
+
+    let be: Vec<u8> = "hello\n".encode_utf16().flat_map(u16::to_be_bytes).collect();
+    assert_eq!(decode(&be).1.encoding, Encoding::Utf16Be);
+}
+
+/// Saving replaces the file's contents but keeps its permissions and leaves no temp file
+#[cfg(unix)]
+#[test]
+fn atomic_write_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("fikby-format-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("script.sh");
+    std::fs::write(&path, "old\n").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o754)).unwrap();
+
+    write_atomic(&path, b"new\n").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"new\n");
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o754);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use crate::find_in_files::{
+    apply_replacements, replace_in_file, search_files, search_text, FileFilter, ReplaceError, Replacement, MAX_MATCHES,
+};
+use crate::search::{Search, SearchOptions};
+use crate::settings::Settings;
+
+/// Include narrows, exclude wins; bare names match any folder or the file name
+#[test]
+fn include_and_exclude_patterns() {
+    let filter = FileFilter::new("*.rs, docs/**", "tests, ./src/gen/");
+    assert!(filter.matches(Path::new("src/main.rs")));
+    assert!(filter.matches(Path::new("docs/guide/intro.md")));
+    assert!(!filter.matches(Path::new("README.md")));
+    assert!(!filter.matches(Path::new("src/tests/mod.rs")));
+    assert!(!filter.matches(Path::new("src/gen/parser.rs")));
+
+    let everything = FileFilter::new("", " , ");
+    assert!(everything.matches(Path::new("any/file.txt")));
+}
+
+/// Matches carry zero-based lines and character columns, with a trimmed preview
+#[test]
+fn line_matches_and_previews() {
+    let search = Search::new("needle", SearchOptions::default()).unwrap();
+    let text = format!("first\n    let é = needle;  \n{}needle{}", "x".repeat(100), "y".repeat(100));
+    let matches = search_text(&search, &text);
+    assert_eq!(matches.len(), 2);
+
+    assert_eq!((matches[0].line, matches[0].start_column, matches[0].end_column), (1, 12, 18));
+    assert_eq!(matches[0].preview, "let é = needle;");
+    assert_eq!(matches[0].preview_match, (8, 14));
+
+    // Long lines are cut around the match
+    let long = &matches[1];
+    assert_eq!((long.line, long.start_column), (2, 100));
+    assert!(long.preview.starts_with('…') && long.preview.ends_with('…'));
+    let (start, end) = long.preview_match;
+    assert_eq!(long.preview.chars().skip(start).take(end - start).collect::<String>(), "needle");
+}
+
+/// Searching a folder skips gitignored and binary files and reports paths relative to it
+#[test]
+fn searches_folder_respecting_gitignore() {
+    let root = std::env::temp_dir().join(format!("fikby-find-in-files-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+    std::fs::write(root.join("src/lib.rs"), "fn todo() {}\n// TODO: more\n").unwrap();
+    std::fs::write(root.join("notes.md"), "todo list").unwrap();
+    std::fs::write(root.join("debug.log"), "todo").unwrap();
+    std::fs::write(root.join("image.bin"), b"todo\0\x01\x02").unwrap();
+
+    let search = Search::new("todo", SearchOptions::default()).unwrap();
+    let mut found = Vec::new();
+    let truncated = search_files(&root, &Settings::default(), &FileFilter::default(), &search, &AtomicBool::new(false), |file| {
+        found.push((file.path, file.matches.len()))
+    });
+    found.sort();
+    assert!(!truncated);
+    assert_eq!(found, vec![(PathBuf::from("notes.md"), 1), (PathBuf::from("src/lib.rs"), 2)]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// The search stops once it has `MAX_MATCHES`, without passing on files it has none left for
+#[test]
+fn stops_at_max_matches() {
+    let root = std::env::temp_dir().join(format!("fikby-find-in-files-max-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("a.txt"), "hit\n".repeat(MAX_MATCHES)).unwrap();
+    std::fs::write(root.join("b.txt"), "hit\n".repeat(MAX_MATCHES)).unwrap();
+
+    let search = Search::new("hit", SearchOptions::default()).unwrap();
+    let mut found = Vec::new();
+    let truncated = search_files(&root, &Settings::default(), &FileFilter::default(), &search, &AtomicBool::new(false), |file| {
+        found.push(file.matches.len())
+    });
+    assert!(truncated);
+    assert_eq!(found, vec![MAX_MATCHES]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// Replacements are made by line and column, and refused if the text moved underneath
+#[test]
+fn applies_replacements_by_position() {
+    let search = Search::new("old", SearchOptions::default()).unwrap();
+    let text = "old one\n  é old, OLD\nnothing\n";
+    let matches = search_text(&search, text);
+    let replacements: Vec<Replacement> = matches.iter().map(|m| Replacement::new(m, "new".to_string())).collect();
+
+    assert_eq!(apply_replacements(text, &replacements).unwrap(), "new one\n  é new, new\nnothing\n");
+    // Only the chosen ones
+    assert_eq!(apply_replacements(text, &replacements[1..2]).unwrap(), "old one\n  é new, OLD\nnothing\n");
+    // The text changed since the search
+    assert_eq!(apply_replacements("older one\n", &replacements[1..2]), None);
+    assert_eq!(apply_replacements("", &replacements[..1]), None);
+}
+
+/// Files on disk keep their line endings, and changed files are left alone
+#[test]
+fn replaces_in_files_on_disk() {
+    let dir = std::env::temp_dir().join(format!("fikby-replace-in-files-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("crlf.txt");
+    std::fs::write(&path, "let x = 1;\r\nx += x;\r\n").unwrap();
+
+    let search = Search::new("x", SearchOptions { whole_word: true, ..SearchOptions::default() }).unwrap();
+    let (text, _) = crate::file_format::read_file(&path).unwrap();
+    let replacements: Vec<Replacement> =
+        search_text(&search, &text).iter().map(|m| Replacement::new(m, "count".to_string())).collect();
+    replace_in_file(&path, &replacements).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "let count = 1;\r\ncount += count;\r\n");
+
+    // Replaying the same replacements finds "count", not "x"
+    assert!(matches!(replace_in_file(&path, &replacements), Err(ReplaceError::Changed)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
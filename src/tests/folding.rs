@@ -0,0 +1,68 @@
+use syntect::parsing::SyntaxSet;
+
+use crate::folding::{analyze, fold_regions, FoldRegion, LineKind};
+
+fn regions(extension: &str, text: &str) -> Vec<(usize, usize, usize)> {
+    let ss = SyntaxSet::load_defaults_newlines();
+    let syntax = ss.find_syntax_by_extension(extension).unwrap();
+    fold_regions(&analyze(&ss, syntax, text, 4))
+        .into_iter()
+        .map(|FoldRegion { start_line, end_line, level }| (start_line, end_line, level))
+        .collect()
+}
+
+/// Bracket pairs fold up to the line of the closer when it leads its line, and brackets in
+/// strings and comments don't count
+#[test]
+fn folds_bracket_pairs() {
+    let text = "\
+fn main() {
+    let v = vec![
+        1, 2];
+    let s = \"{\";
+    // {
+    if s.is_empty() {
+        println!();
+    }
+}
+";
+    assert_eq!(regions("rs", text), vec![(0, 7, 1), (1, 2, 2), (5, 6, 2)]);
+}
+
+/// Indentation folds languages without brackets; trailing blank lines stay outside
+#[test]
+fn folds_indentation() {
+    let text = "\
+class A:
+    def f(self):
+        return 1
+
+    def g(self):
+        pass
+
+
+x = A()
+";
+    assert_eq!(regions("py", text), vec![(0, 5, 1), (1, 2, 2), (4, 5, 2)]);
+}
+
+/// Runs of comment lines and of imports fold on their own
+#[test]
+fn folds_comments_and_imports() {
+    let text = "\
+use std::fs;
+use std::io;
+
+// One
+// Two
+fn f() {}
+";
+    let ss = SyntaxSet::load_defaults_newlines();
+    let rust = ss.find_syntax_by_extension("rs").unwrap();
+    let kinds: Vec<LineKind> = analyze(&ss, rust, text, 4).iter().map(|line| line.kind).collect();
+    assert_eq!(kinds[..5], [LineKind::Import, LineKind::Import, LineKind::Blank, LineKind::Comment, LineKind::Comment]);
+    assert_eq!(regions("rs", text), vec![(0, 1, 1), (3, 4, 1)]);
+
+    let python = "import os\n\nimport sys\nprint(os, sys)\n";
+    assert_eq!(regions("py", python), vec![(0, 2, 1)]);
+}
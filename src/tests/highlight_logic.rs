@@ -7,6 +7,7 @@ use syntect::util::LinesWithEndings;
 /// and that ranges are valid and non-overlapping for a sample Rust snippet containing
 /// multi-byte characters.
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn compute_syntect_ranges_are_character_correct() {
     // Load syntect resources
     let ss = SyntaxSet::load_defaults_newlines();
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use ropey::Rope;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+use crate::highlight::{LineEdit, LineHighlighter, LineSpans};
+
+fn rust_highlighter(rope: &Rope) -> LineHighlighter {
+    let ss = Arc::new(SyntaxSet::load_defaults_newlines());
+    let ts = ThemeSet::load_defaults();
+    let theme = Arc::new(ts.themes["base16-ocean.dark"].clone());
+    LineHighlighter::with_mode(ss, "Rust", theme, rope.len_lines(), false)
+}
+
+fn sample_source(functions: usize) -> String {
+    (0..functions)
+        .map(|i| format!("fn f{}() -> u32 {{\n    let x = {};\n    x + 1\n}}\n", i, i))
+        .collect()
+}
+
+/// Insert `text` at `char_idx` and describe the edit the way the editor does
+fn insert(rope: &mut Rope, char_idx: usize, text: &str) -> LineEdit {
+    let start_line = rope.char_to_line(char_idx);
+    rope.insert(char_idx, text);
+    let end_line = rope.char_to_line(char_idx + text.chars().count());
+    LineEdit { start_line, removed_lines: 0, added_lines: end_line - start_line }
+}
+
+fn spans_for(lines: &[LineSpans], line: usize) -> Option<&LineSpans> {
+    lines.iter().find(|l| l.line == line)
+}
+
+/// A full pass covers every line exactly once
+#[test]
+fn full_pass_highlights_every_line() {
+    let rope = Rope::from_str(&sample_source(50));
+    let mut hl = rust_highlighter(&rope);
+
+    let mut seen = Vec::new();
+    while hl.is_dirty() {
+        seen.extend(hl.highlight_dirty(&rope, 64).into_iter().map(|l| l.line));
+    }
+    assert_eq!(seen, (0..rope.len_lines()).collect::<Vec<_>>());
+}
+
+/// Editing inside one line only re-highlights until the parse state converges
+#[test]
+fn local_edit_rehighlights_only_nearby_lines() {
+    let mut rope = Rope::from_str(&sample_source(200));
+    let mut hl = rust_highlighter(&rope);
+    while hl.is_dirty() {
+        hl.highlight_dirty(&rope, usize::MAX);
+    }
+
+    let line = 401;
+    let at = rope.line_to_char(line) + 4;
+    let edit = insert(&mut rope, at, "let y = 2; ");
+    hl.apply_edit(edit);
+    let redone = hl.highlight_dirty(&rope, usize::MAX);
+
+    assert!(!hl.is_dirty());
+    assert!(spans_for(&redone, line).is_some(), "edited line must be re-highlighted");
+    assert!(redone.len() <= 3, "expected convergence right after the edit, got {} lines", redone.len());
+    assert!(redone.iter().all(|l| l.line >= line));
+}
+
+/// Opening a block comment changes the state of every following line, so nothing converges
+#[test]
+fn unterminated_comment_propagates_to_end() {
+    let mut rope = Rope::from_str(&sample_source(20));
+    let mut hl = rust_highlighter(&rope);
+    while hl.is_dirty() {
+        hl.highlight_dirty(&rope, usize::MAX);
+    }
+    let before_last = rope.len_lines() - 2;
+
+    let at = rope.line_to_char(10);
+    let edit = insert(&mut rope, at, "/*\n");
+    hl.apply_edit(edit);
+    let redone = hl.highlight_dirty(&rope, usize::MAX);
+
+    assert_eq!(redone.first().map(|l| l.line), Some(10));
+    assert!(spans_for(&redone, before_last + 1).is_some(), "comment should reach the last line");
+}
+
+/// Inserting lines shifts later cached states instead of invalidating them
+#[test]
+fn inserted_lines_keep_later_cache() {
+    let mut rope = Rope::from_str(&sample_source(100));
+    let mut hl = rust_highlighter(&rope);
+    while hl.is_dirty() {
+        hl.highlight_dirty(&rope, usize::MAX);
+    }
+
+    let at = rope.line_to_char(4);
+    let edit = insert(&mut rope, at, "fn g() {}\nfn h() {}\n");
+    assert_eq!(edit, LineEdit { start_line: 4, removed_lines: 0, added_lines: 2 });
+    hl.apply_edit(edit);
+    let redone = hl.highlight_dirty(&rope, usize::MAX);
+
+    assert!(redone.len() <= 4, "got {} lines", redone.len());
+}
+
+/// Stale line numbers are mapped through later edits, and touched lines are dropped
+#[test]
+fn line_edit_maps_lines() {
+    let edit = LineEdit { start_line: 10, removed_lines: 2, added_lines: 5 };
+    assert_eq!(edit.map_line(3), Some(3));
+    assert_eq!(edit.map_line(10), None);
+    assert_eq!(edit.map_line(12), None);
+    assert_eq!(edit.map_line(13), Some(16));
+
+    let deletion = LineEdit { start_line: 0, removed_lines: 3, added_lines: 0 };
+    assert_eq!(deletion.map_line(7), Some(4));
+}
+
+/// The viewport pass returns exactly the requested lines without consuming dirty work
+#[test]
+fn viewport_range_is_highlighted_out_of_order() {
+    let rope = Rope::from_str(&sample_source(500));
+    let hl = rust_highlighter(&rope);
+
+    let lines = hl.highlight_range(&rope, 1200, 1240);
+    assert_eq!(lines.first().map(|l| l.line), Some(1200));
+    assert_eq!(lines.last().map(|l| l.line), Some(1240));
+    assert!(lines.iter().any(|l| !l.spans.is_empty()));
+    assert_eq!(hl.dirty_start(), Some(0));
+}
+
+/// Large-file mode keeps no per-line cache and only highlights requested ranges
+#[test]
+fn visible_only_mode_keeps_no_cache() {
+    let ss = Arc::new(SyntaxSet::load_defaults_newlines());
+    let ts = ThemeSet::load_defaults();
+    let theme = Arc::new(ts.themes["base16-ocean.dark"].clone());
+    let mut rope = Rope::from_str(&sample_source(1000));
+    let mut hl = LineHighlighter::with_mode(ss, "Rust", theme, rope.len_lines(), true);
+
+    assert!(hl.is_visible_only());
+    assert!(!hl.is_dirty());
+
+    let at = rope.line_to_char(3000);
+    hl.apply_edit(insert(&mut rope, at, "// note\n"));
+    assert!(!hl.is_dirty());
+    assert!(hl.highlight_dirty(&rope, usize::MAX).is_empty());
+
+    let lines = hl.highlight_range(&rope, 3000, 3010);
+    assert_eq!(lines.len(), 11);
+    assert!(lines[0].spans.iter().any(|s| s.start == 0), "comment line should be coloured");
+}
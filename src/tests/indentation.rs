@@ -0,0 +1,80 @@
+use crate::indent::{detect, resolve, IndentStyle};
+use crate::settings::Settings;
+
+/// Files indented with tabs or with 2/4 spaces are recognised
+#[test]
+fn detects_existing_indentation() {
+    let tabs = "fn main() {\n\tlet x = 1;\n\tif x {\n\t\tx;\n\t}\n}\n";
+    assert_eq!(detect(tabs).map(|s| s.use_tabs), Some(true));
+
+    let two = "a:\n  b:\n    c: 1\n  d: 2\n";
+    assert_eq!(detect(two), Some(IndentStyle::spaces(2)));
+
+    let four = "def f():\n    if x:\n        return 1\n    return 2\n";
+    assert_eq!(detect(four), Some(IndentStyle::spaces(4)));
+
+    // Doc comment continuation lines and blank lines don't count
+    let doc = "/**\n * Docs\n */\nfn f() {\n    g();\n}\n";
+    assert_eq!(detect(doc), Some(IndentStyle::spaces(4)));
+
+    assert_eq!(detect("no\nindentation\nhere\n"), None);
+}
+
+/// Detection beats language rules, which beat the global settings
+#[test]
+fn resolves_language_rules_and_settings() {
+    let mut settings = Settings { tab_width: 8, ..Settings::default() };
+
+    assert_eq!(resolve(&settings, "Rust", ""), IndentStyle::spaces(8));
+    assert_eq!(resolve(&settings, "YAML", ""), IndentStyle::spaces(2));
+    assert_eq!(resolve(&settings, "JavaScript", ""), IndentStyle::spaces(2));
+    assert_eq!(resolve(&settings, "Makefile", ""), IndentStyle::tabs(8));
+    assert_eq!(resolve(&settings, "Go", ""), IndentStyle::tabs(8));
+
+    // A file indented with tabs keeps them, with the configured tab width
+    assert_eq!(resolve(&settings, "Rust", "fn f() {\n\tg();\n}\n"), IndentStyle::tabs(8));
+    assert_eq!(resolve(&settings, "Go", "func f() {\n    g()\n}\n"), IndentStyle::spaces(4));
+
+    settings.language_indent.insert("YAML".to_string(), IndentStyle::spaces(4));
+    settings.insert_spaces = false;
+    assert_eq!(resolve(&settings, "YAML", ""), IndentStyle::spaces(4));
+    assert_eq!(resolve(&settings, "Rust", ""), IndentStyle::tabs(8));
+}
+
+/// Dedenting goes back to the previous indentation stop in either mode
+#[test]
+fn dedent_respects_mode() {
+    let spaces = IndentStyle::spaces(4);
+    assert_eq!(spaces.dedent("        "), "    ");
+    assert_eq!(spaces.dedent("      "), "    ");
+    assert_eq!(spaces.dedent("  "), "");
+    assert_eq!(spaces.dedent("\t"), "");
+
+    let tabs = IndentStyle::tabs(4);
+    assert_eq!(tabs.dedent("\t\t"), "\t");
+    assert_eq!(tabs.dedent("        "), "\t");
+    assert_eq!(tabs.unit(), "\t");
+    assert_eq!(spaces.unit(), "    ");
+}
+
+/// Converting rewrites only leading whitespace, measured with the tab width
+#[test]
+fn converts_indentation() {
+    let text = "fn f() {\n    if x {\n\t\ty(\"\\t  \");\n      }\n}";
+    assert_eq!(
+        IndentStyle::tabs(4).convert_text(text),
+        "fn f() {\n\tif x {\n\t\ty(\"\\t  \");\n\t  }\n}"
+    );
+    assert_eq!(
+        IndentStyle::spaces(4).convert_text("\ta\n\t\tb\r\n  \tc\n"),
+        "    a\n        b\r\n    c\n"
+    );
+}
+
+/// Per-language rules round-trip through the settings file
+#[test]
+fn language_indent_in_settings_file() {
+    let settings = Settings::from_toml("[language_indent.Python]\nuse_tabs = false\nwidth = 4\n").unwrap();
+    assert_eq!(settings.language_indent.get("Python"), Some(&IndentStyle::spaces(4)));
+    assert_eq!(Settings::from_toml(&settings.to_toml().unwrap()).unwrap(), settings);
+}
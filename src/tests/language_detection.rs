@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use syntect::parsing::SyntaxSet;
+
+use crate::language::{detect_syntax, language_names, modeline_language};
+
+/// Files are highlighted according to their extension instead of always as Rust
+#[test]
+fn detects_syntax_from_extension() {
+    let ss = SyntaxSet::load_defaults_newlines();
+
+    let cases = [
+        ("main.rs", "Rust"),
+        ("script.py", "Python"),
+        ("README.md", "Markdown"),
+        ("package.json", "JSON"),
+        ("Makefile", "Makefile"),
+    ];
+    for (file, expected) in cases {
+        let syntax = detect_syntax(&ss, None, Some(Path::new(file)), "");
+        assert_eq!(syntax.name, expected, "wrong syntax for {}", file);
+    }
+}
+
+/// Extension-less files fall back to the shebang on the first line
+#[test]
+fn detects_syntax_from_shebang() {
+    let ss = SyntaxSet::load_defaults_newlines();
+    let text = "#!/usr/bin/env python3\nprint('hi')\n";
+
+    let syntax = detect_syntax(&ss, None, Some(Path::new("tool")), text);
+    assert_eq!(syntax.name, "Python");
+}
+
+/// Vim and emacs modelines are recognised and win over the extension
+#[test]
+fn detects_syntax_from_modeline() {
+    let ss = SyntaxSet::load_defaults_newlines();
+
+    assert_eq!(modeline_language("# vim: set ft=ruby ts=2:\n").as_deref(), Some("ruby"));
+    assert_eq!(modeline_language("// -*- mode: python; coding: utf-8 -*-\n").as_deref(), Some("python"));
+    assert_eq!(modeline_language("/* -*- c -*- */\n").as_deref(), Some("c"));
+    assert_eq!(modeline_language("fn main() {}\n"), None);
+    // Markers inside words don't count
+    assert_eq!(modeline_language("let regex: ft=ruby\n"), None);
+    assert_eq!(modeline_language("vi:ft=ruby\n").as_deref(), Some("ruby"));
+
+    let text = "line 1\nline 2\n# vim: ft=python\n";
+    let syntax = detect_syntax(&ss, None, Some(Path::new("notes.txt")), text);
+    assert_eq!(syntax.name, "Python");
+}
+
+/// The per-tab override wins, and unknown content ends up as plain text
+#[test]
+fn override_and_plain_text_fallback() {
+    let ss = SyntaxSet::load_defaults_newlines();
+
+    let syntax = detect_syntax(&ss, Some("YAML"), Some(Path::new("main.rs")), "");
+    assert_eq!(syntax.name, "YAML");
+
+    let syntax = detect_syntax(&ss, None, None, "just some words\n");
+    assert_eq!(syntax.name, "Plain Text");
+
+    let names = language_names(&ss);
+    assert!(names.iter().any(|n| n == "Rust"));
+    assert!(names.windows(2).all(|w| w[0].to_lowercase() <= w[1].to_lowercase()), "names should be sorted");
+}
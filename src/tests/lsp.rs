@@ -0,0 +1,351 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use ropey::Rope;
+use serde_json::{json, Value};
+
+use crate::lsp::client::{Event, LspClient, Status, MAX_RESTARTS};
+use crate::lsp::protocol::{self, char_at, file_uri, position_at, uri_to_path, Position, PositionEncoding, Range};
+use crate::lsp::transport::{classify, read_message, write_message, Incoming, ResponseError};
+use crate::lsp::{find_root, server_for, ServerConfig};
+
+/// Set in the environment of this test binary when it runs as the mock server
+const MOCK_ENV: &str = "FIKBY_MOCK_LSP";
+
+/// Messages survive a round trip, and noise before a header (like the banner this test binary
+/// prints when it runs as the mock server) is skipped
+#[test]
+fn transport_frames_messages() {
+    let messages = [json!({ "jsonrpc": "2.0", "id": 1, "result": "é" }), json!({ "method": "exit" })];
+    let mut wire = b"\nrunning 1 test\n".to_vec();
+    for message in &messages {
+        write_message(&mut wire, message).unwrap();
+    }
+
+    let mut reader = BufReader::new(Cursor::new(wire));
+    assert_eq!(read_message(&mut reader).unwrap().as_ref(), Some(&messages[0]));
+    assert_eq!(read_message(&mut reader).unwrap().as_ref(), Some(&messages[1]));
+    assert_eq!(read_message(&mut reader).unwrap(), None);
+
+    let mut reader = BufReader::new(Cursor::new(b"Content-Length: 3\r\n\r\n{]}Content-Length: 2\r\n\r\n{}".to_vec()));
+    assert_eq!(read_message(&mut reader).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+
+    assert_eq!(
+        classify(json!({ "id": 3, "error": { "code": -32601, "message": "no" } })),
+        Some(Incoming::Response { id: 3, result: Err(ResponseError::new(-32601, "no")) })
+    );
+    assert_eq!(
+        classify(json!({ "id": "a", "method": "m" })),
+        Some(Incoming::Request { id: json!("a"), method: "m".to_string(), params: Value::Null })
+    );
+}
+
+/// Columns count UTF-16 code units by default, and content changes use them
+#[test]
+fn positions_follow_the_encoding() {
+    let rope = Rope::from_str("a\néx😀z\n");
+    let at = |line, character| Position { line, character };
+    assert_eq!(position_at(&rope, 5, PositionEncoding::Utf16), at(1, 4));
+    assert_eq!(position_at(&rope, 5, PositionEncoding::Utf8), at(1, 7));
+    assert_eq!(position_at(&rope, 5, PositionEncoding::Utf32), at(1, 3));
+    assert_eq!(char_at(&rope, at(1, 4), PositionEncoding::Utf16), 5);
+    // Inside the emoji, past the end of the line and past the last line
+    assert_eq!(char_at(&rope, at(1, 3), PositionEncoding::Utf16), 4);
+    assert_eq!(char_at(&rope, at(1, 40), PositionEncoding::Utf16), 6);
+    assert_eq!(char_at(&rope, at(9, 0), PositionEncoding::Utf16), rope.len_chars());
+
+    let change = protocol::delete_change(&rope, 4, 6, PositionEncoding::Utf16);
+    assert_eq!(change["range"], json!(Range { start: at(1, 2), end: at(1, 5) }));
+    assert_eq!(change["text"], "");
+}
+
+#[test]
+fn file_uris_round_trip() {
+    let path = Path::new("/home/me/my project/é#1.rs");
+    assert_eq!(file_uri(path), "file:///home/me/my%20project/%C3%A9%231.rs");
+    assert_eq!(uri_to_path(&file_uri(path)).as_deref(), Some(path));
+    assert_eq!(file_uri(Path::new("C:\\src\\main.rs")), "file:///C:/src/main.rs");
+    assert_eq!(uri_to_path("file:///C:/src/main.rs"), Some(PathBuf::from("C:/src/main.rs")));
+    assert_eq!(uri_to_path("file://localhost/tmp/a.rs"), Some(PathBuf::from("/tmp/a.rs")));
+    assert_eq!(uri_to_path("untitled:1"), None);
+}
+
+/// Definition results come as one location, a list, or links pointing at the target's name;
+/// hover contents as markup, marked strings or a list of them
+#[test]
+fn reads_locations_and_hover_contents() {
+    let range = json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 9 } });
+    let at = |line, start, end| Range {
+        start: Position { line, character: start },
+        end: Position { line, character: end },
+    };
+    let location = json!({ "uri": "file:///src/a.rs", "range": range });
+    assert_eq!(protocol::locations(&location), vec![(PathBuf::from("/src/a.rs"), at(1, 4, 9))]);
+    let links = json!([
+        { "targetUri": "file:///src/b.rs", "targetRange": range, "targetSelectionRange": { "start": { "line": 2, "character": 0 }, "end": { "line": 2, "character": 3 } } },
+        { "uri": "untitled:1", "range": range },
+    ]);
+    assert_eq!(protocol::locations(&links), vec![(PathBuf::from("/src/b.rs"), at(2, 0, 3))]);
+    assert!(protocol::locations(&Value::Null).is_empty());
+
+    let markup = json!({ "contents": { "kind": "markdown", "value": "```rust\nfn f()\n```" } });
+    assert_eq!(protocol::hover_markdown(&markup).as_deref(), Some("```rust\nfn f()\n```"));
+    let marked = json!({ "contents": [{ "language": "python", "value": "def f()" }, "Does *f*.", ""] });
+    assert_eq!(protocol::hover_markdown(&marked).as_deref(), Some("```python\ndef f()\n```\n\nDoes *f*."));
+    let plain = json!({ "contents": { "kind": "plaintext", "value": "int x" } });
+    assert_eq!(protocol::hover_markdown(&plain).as_deref(), Some("```\nint x\n```"));
+    assert_eq!(protocol::hover_markdown(&json!({ "contents": "  " })), None);
+}
+
+/// Workspace edits come as `documentChanges` or a `changes` map, merged by file; code actions
+/// as actions or bare commands, quick fixes first and disabled ones left out
+#[test]
+fn reads_workspace_edits_and_code_actions() {
+    let edit = |line, text: &str| {
+        let range = Range { start: Position { line, character: 0 }, end: Position { line, character: 1 } };
+        json!({ "range": range, "newText": text })
+    };
+    let document = |uri: &str, edits: Value| json!({ "textDocument": { "uri": uri, "version": 1 }, "edits": edits });
+    let changes = json!({ "documentChanges": [
+        document("file:///src/b.rs", json!([edit(2, "x")])),
+        { "kind": "create", "uri": "file:///src/new.rs" },
+        document("file:///src/a.rs", json!([edit(0, "y"), { "range": "malformed" }])),
+        document("file:///src/b.rs", json!([edit(4, "z")])),
+        document("file:///src/c.rs", json!([])),
+    ] });
+    let files = protocol::workspace_edit(&changes);
+    let paths: Vec<&Path> = files.iter().map(|(path, _)| path.as_path()).collect();
+    assert_eq!(paths, [Path::new("/src/b.rs"), Path::new("/src/a.rs")]);
+    let texts: Vec<&str> = files[0].1.iter().map(|edit| edit.new_text.as_str()).collect();
+    assert_eq!(texts, ["x", "z"]);
+    assert_eq!(files[1].1[0].range.start, Position { line: 0, character: 0 });
+    let map = json!({ "changes": { "file:///src/a.rs": [edit(1, "w")] } });
+    assert_eq!(protocol::workspace_edit(&map).len(), 1);
+    assert!(protocol::workspace_edit(&Value::Null).is_empty());
+
+    let actions = protocol::code_actions(&json!([
+        { "title": "Extract function", "kind": "refactor.extract", "data": 7 },
+        { "title": "Run tests", "command": "runTests", "arguments": [1] },
+        { "title": "Disabled", "kind": "quickfix", "disabled": { "reason": "no" } },
+        { "title": "Import Foo", "kind": "quickfix", "edit": changes },
+        { "title": "Import crate::Foo", "kind": "quickfix", "isPreferred": true, "command": { "title": "t", "command": "c" } },
+    ]));
+    let titles: Vec<&str> = actions.iter().map(|action| action.title.as_str()).collect();
+    assert_eq!(titles, ["Import crate::Foo", "Import Foo", "Extract function", "Run tests"]);
+    assert!(actions[0].preferred && actions[0].edit.is_none() && actions[0].command.is_some());
+    assert_eq!(actions[1].edit.as_ref(), Some(&changes));
+    assert_eq!(actions[2].raw["data"], 7);
+    assert_eq!(actions[3].command.as_ref().map(|command| &command["arguments"]), Some(&json!([1])));
+}
+
+/// Servers are picked by extension, the settings overriding the built-in ones, and the root
+/// is the outermost marked folder
+#[test]
+fn finds_server_and_root() {
+    let mut configured = BTreeMap::new();
+    let rust = server_for(&configured, Path::new("/src/main.rs"));
+    assert_eq!(rust.map(|(id, config)| (id, config.command)), Some(("rust".to_string(), "rust-analyzer".to_string())));
+    assert!(server_for(&configured, Path::new("/src/main.py")).is_none());
+
+    let pylsp = ServerConfig { command: "pylsp".to_string(), extensions: vec!["py".to_string()], ..ServerConfig::default() };
+    configured.insert("python".to_string(), pylsp);
+    configured.insert("rust".to_string(), ServerConfig::default());
+    assert_eq!(server_for(&configured, Path::new("/src/main.py")).map(|(id, _)| id).as_deref(), Some("python"));
+    assert!(server_for(&configured, Path::new("/src/main.rs")).is_none());
+
+    let dir = std::env::temp_dir().join(format!("fikby-lsp-root-{}", std::process::id()));
+    let crate_dir = dir.join("workspace/member");
+    std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+    std::fs::write(dir.join("workspace/Cargo.toml"), "").unwrap();
+    std::fs::write(crate_dir.join("Cargo.toml"), "").unwrap();
+    let markers = ["Cargo.toml".to_string()];
+    assert_eq!(find_root(&crate_dir.join("src/lib.rs"), &markers), dir.join("workspace"));
+    assert_eq!(find_root(&dir.join("loose.rs"), &markers), dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A client talking to the mock server below, with the events of its connections pumped
+/// by hand instead of by the main loop.
+struct Harness {
+    client: Rc<LspClient>,
+    events: mpsc::Receiver<Event>,
+    notifications: Rc<RefCell<Vec<(String, Value)>>>,
+}
+
+impl Harness {
+    fn start() -> Self {
+        let config = ServerConfig {
+            command: std::env::current_exe().unwrap().to_string_lossy().into_owned(),
+            args: ["--exact", "tests::lsp::mock_server", "--nocapture", "--quiet", "--test-threads=1"]
+                .map(String::from)
+                .to_vec(),
+            env: BTreeMap::from([(MOCK_ENV.to_string(), "1".to_string())]),
+            ..ServerConfig::default()
+        };
+        let (tx, events) = mpsc::channel();
+        let client = LspClient::new("rust", config, std::env::temp_dir(), move || {
+            let tx = tx.clone();
+            Box::new(move |event| {
+                let _ = tx.send(event);
+            })
+        });
+        let notifications = Rc::new(RefCell::new(Vec::new()));
+        let notifications_cl = notifications.clone();
+        client.on_notification(move |method, params| {
+            notifications_cl.borrow_mut().push((method.to_string(), params.clone()));
+        });
+        client.start();
+        Self { client, events, notifications }
+    }
+
+    /// Handle events until `done` holds.
+    fn pump_until(&self, mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !done() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let event = self.events.recv_timeout(left).expect("timed out waiting for the mock server");
+            self.client.handle(event);
+        }
+    }
+
+    /// Wait for the mock server to report the text it has for `uri` at `version`.
+    fn expect_text(&self, uri: &str, version: i64, text: &str) {
+        let expected = (String::from("$/mock/text"), json!({ "uri": uri, "version": version, "text": text }));
+        self.pump_until(|| self.notifications.borrow().contains(&expected));
+    }
+}
+
+/// Documents are opened after initialization and edits reach the server as incremental
+/// changes in its encoding; requests in both directions get answers
+#[test]
+fn client_syncs_documents() {
+    let harness = Harness::start();
+    let client = &harness.client;
+    let uri = "file:///tmp/main.rs";
+    let mut rope = Rope::from_str("a\néx😀z\n");
+
+    client.open_document(uri, rope.clone());
+    let echoed = Rc::new(RefCell::new(None));
+    let echoed_cl = echoed.clone();
+    client.request("mock/echo", json!([1, 2]), move |result| *echoed_cl.borrow_mut() = Some(result));
+    harness.expect_text(uri, 0, "a\néx😀z\n");
+    assert_eq!(client.status(), Status::Running);
+
+    client.did_insert(uri, 5, "y\n");
+    rope.insert(5, "y\n");
+    client.did_delete(uri, 2, 4);
+    rope.remove(2..4);
+    harness.expect_text(uri, 2, &rope.to_string());
+    assert_eq!(rope.to_string(), "a\n😀y\nz\n");
+
+    harness.pump_until(|| echoed.borrow().is_some());
+    assert_eq!(*echoed.borrow(), Some(Ok(json!([1, 2]))));
+    // The mock asked for its configuration on initialization and reports the answer
+    harness.pump_until(|| harness.notifications.borrow().iter().any(|(method, _)| method == "$/mock/configured"));
+    let configured = harness.notifications.borrow().iter().find(|(method, _)| method == "$/mock/configured").cloned();
+    assert_eq!(configured.map(|(_, params)| params), Some(json!([null])));
+
+    let failed = Rc::new(RefCell::new(None));
+    let failed_cl = failed.clone();
+    client.request("mock/unknown", Value::Null, move |result| *failed_cl.borrow_mut() = Some(result));
+    harness.pump_until(|| failed.borrow().is_some());
+    assert!(matches!(&*failed.borrow(), Some(Err(e)) if e.code == ResponseError::METHOD_NOT_FOUND));
+
+    client.shutdown();
+    harness.pump_until(|| client.status() == Status::Stopped);
+}
+
+/// A crashed server fails the requests it left unanswered, and is restarted with the open
+/// documents as they are now, until it crashes too often
+#[test]
+fn client_restarts_crashed_server() {
+    let harness = Harness::start();
+    let client = &harness.client;
+    let uri = "file:///tmp/lib.rs";
+    client.open_document(uri, Rope::from_str("fn f() {}\n"));
+    harness.expect_text(uri, 0, "fn f() {}\n");
+
+    let crashed = Rc::new(RefCell::new(None));
+    let crashed_cl = crashed.clone();
+    client.request("mock/crash", Value::Null, move |result| *crashed_cl.borrow_mut() = Some(result));
+    client.did_insert(uri, 0, "pub ");
+    harness.pump_until(|| crashed.borrow().is_some());
+    assert!(matches!(&*crashed.borrow(), Some(Err(e)) if e.code == ResponseError::SERVER_EXITED));
+    // The edit made while the server was going down arrives with the reopened document
+    harness.expect_text(uri, 1, "pub fn f() {}\n");
+
+    for _ in 0..MAX_RESTARTS {
+        client.request("mock/crash", Value::Null, |_| {});
+        harness.pump_until(|| matches!(client.status(), Status::Starting | Status::Failed(_)));
+        harness.pump_until(|| client.status() != Status::Starting);
+    }
+    assert!(matches!(client.status(), Status::Failed(_)));
+}
+
+/// Not a test of its own: run with `MOCK_ENV` set, this test binary acts as a minimal language
+/// server for the tests above, which start it as their server process. It keeps the text of
+/// open documents, applying changes in UTF-16 positions, and reports it as `$/mock/text`.
+#[test]
+fn mock_server() {
+    if std::env::var_os(MOCK_ENV).is_none() {
+        return;
+    }
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
+    let mut reply = |message: Value| write_message(&mut output, &message).unwrap();
+    let mut documents: HashMap<String, (i64, Rope)> = HashMap::new();
+
+    while let Ok(Some(message)) = read_message(&mut input) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = &message["id"];
+        match method {
+            "initialize" => reply(json!({
+                "id": id,
+                "result": { "capabilities": { "textDocumentSync": { "openClose": true, "change": 2 }, "positionEncoding": "utf-16" } },
+            })),
+            "initialized" => reply(json!({ "id": "configuration", "method": "workspace/configuration", "params": { "items": [{ "section": "mock" }] } })),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let uri = document["uri"].as_str().unwrap();
+                let text = Rope::from_str(document["text"].as_str().unwrap());
+                documents.insert(uri.to_string(), (document["version"].as_i64().unwrap(), text));
+                reply(mock_text(uri, &documents));
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap();
+                let (version, text) = documents.get_mut(uri).unwrap();
+                *version = params["textDocument"]["version"].as_i64().unwrap();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let range: Range = serde_json::from_value(change["range"].clone()).unwrap();
+                    let start = char_at(text, range.start, PositionEncoding::Utf16);
+                    let end = char_at(text, range.end, PositionEncoding::Utf16);
+                    text.remove(start..end);
+                    text.insert(start, change["text"].as_str().unwrap());
+                }
+                reply(mock_text(uri, &documents));
+            }
+            "mock/echo" => reply(json!({ "id": id, "result": params })),
+            "mock/crash" => std::process::exit(1),
+            "shutdown" => reply(json!({ "id": id, "result": null })),
+            "exit" => std::process::exit(0),
+            // The client's answer to the configuration request
+            "" if *id == json!("configuration") => reply(json!({ "method": "$/mock/configured", "params": message["result"] })),
+            _ if !id.is_null() => reply(json!({ "id": id, "error": { "code": ResponseError::METHOD_NOT_FOUND, "message": method } })),
+            _ => {}
+        }
+    }
+    std::process::exit(0);
+}
+
+fn mock_text(uri: &str, documents: &HashMap<String, (i64, Rope)>) -> Value {
+    let (version, text) = &documents[uri];
+    json!({ "method": "$/mock/text", "params": { "uri": uri, "version": version, "text": text.to_string() } })
+}
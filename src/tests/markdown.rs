@@ -0,0 +1,22 @@
+use crate::markdown::to_pango;
+
+/// Paragraphs are rewrapped, code blocks kept as they are, and everything is escaped
+#[test]
+fn renders_blocks() {
+    let markdown = "# Vec<T>\n\nA contiguous\ngrowable array.\n\n```rust\nlet v = vec![1, 2];\nif a < b {}\n```\n---\n- one\n- two\n  continued\n  * nested";
+    assert_eq!(
+        to_pango(markdown),
+        "<b>Vec&lt;T&gt;</b>\n\nA contiguous growable array.\n\n<tt>let v = vec![1, 2];\nif a &lt; b {}</tt>\n\n\
+         ────────────────────────\n\n• one\n• two continued\n  • nested"
+    );
+}
+
+/// Inline code, emphasis and links; snake_case and stray markers stay text, and emphasis
+/// left open is closed
+#[test]
+fn renders_inline_elements() {
+    assert_eq!(to_pango("Use `a<b>` or **bold** and *it*"), "Use <tt>a&lt;b&gt;</tt> or <b>bold</b> and <i>it</i>");
+    assert_eq!(to_pango("see [`Vec`](std::vec::Vec) now"), "see <tt>Vec</tt> now");
+    assert_eq!(to_pango("snake_case_name and 2 * 3 \\*x"), "snake_case_name and 2 * 3 *x");
+    assert_eq!(to_pango("_open **never closed"), "<i>open <b>never closed</b></i>");
+}
@@ -1,2 +1,24 @@
+mod brackets;
+mod commands;
+mod completion;
+mod diagnostics;
+mod documents;
+mod external_change;
+mod file_format;
+mod find_in_files;
+mod folding;
 mod highlight_logic;
+mod incremental_highlight;
+mod indentation;
+mod language_detection;
+mod lsp;
+mod markdown;
+mod multi_cursor;
+mod navigation;
+mod quick_open;
+mod search;
+mod session;
+mod settings;
+mod symbols;
 mod theme_mode;
+mod workspace_edit;
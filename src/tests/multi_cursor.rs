@@ -0,0 +1,52 @@
+use crate::multi_cursor::{all_occurrences, merge, next_occurrence, Selection};
+
+/// Offsets count characters, not bytes, to line up with GTK's text iters
+#[test]
+fn occurrences_use_char_offsets() {
+    let text = "é foo, ö foo\nfoofoo";
+    assert_eq!(
+        all_occurrences(text, "foo", false),
+        vec![Selection::new(2, 5), Selection::new(9, 12), Selection::new(13, 16), Selection::new(16, 19)]
+    );
+    assert!(all_occurrences(text, "", false).is_empty());
+    assert!(all_occurrences(text, "bar", false).is_empty());
+}
+
+/// The word under the cursor only selects other whole words, not parts of longer ones
+#[test]
+fn whole_word_occurrences() {
+    let text = "id = user_id + id2; id";
+    assert_eq!(all_occurrences(text, "id", true), vec![Selection::new(0, 2), Selection::new(20, 22)]);
+    assert_eq!(all_occurrences(text, "id", false).len(), 4);
+}
+
+/// Ctrl+D picks the next unselected occurrence and wraps around at the end
+#[test]
+fn next_occurrence_skips_taken_and_wraps() {
+    let text = "let a = a + a;";
+    let first = Selection::new(4, 5);
+    let second = next_occurrence(text, "a", first.end(), &[first]).unwrap();
+    assert_eq!(second, Selection::new(8, 9));
+
+    let third = next_occurrence(text, "a", 13, &[first, second]).unwrap();
+    assert_eq!(third, Selection::new(12, 13));
+
+    // Only "a"s left are taken; wrapping finds nothing new
+    assert_eq!(next_occurrence(text, "a", 13, &[first, second, third]), None);
+    // Wraps to the start when nothing follows
+    assert_eq!(next_occurrence(text, "let", 5, &[]), Some(Selection::new(0, 3)));
+}
+
+/// Carets that end up on top of each other become one
+#[test]
+fn merge_overlapping_selections() {
+    let merged = merge(vec![
+        Selection::new(10, 10),
+        Selection::new(3, 1),
+        Selection::new(2, 6),
+        Selection::new(10, 10),
+        Selection::new(6, 8),
+    ]);
+    // 1..3 and 2..6 overlap (keeping the backward direction); 6..8 only touches 2..6
+    assert_eq!(merged, vec![Selection::new(6, 1), Selection::new(6, 8), Selection::new(10, 10)]);
+}
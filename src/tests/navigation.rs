@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use crate::navigation::{parse_go_to, GoToTarget, Location, NavigationHistory, MAX_HISTORY};
+
+fn at(path: &str, line: usize) -> Location {
+    Location { path: PathBuf::from(path), line, column: 0 }
+}
+
+fn target(path: Option<&str>, line: usize, column: usize) -> Option<GoToTarget> {
+    Some(GoToTarget { path: path.map(PathBuf::from), line, column })
+}
+
+/// Lines and columns are typed one-based; files may carry drive letters or rustc's arrow
+#[test]
+fn parses_go_to_input() {
+    assert_eq!(parse_go_to("42"), target(None, 41, 0));
+    assert_eq!(parse_go_to(" 42:7 "), target(None, 41, 6));
+    assert_eq!(parse_go_to("src/main.rs:42"), target(Some("src/main.rs"), 41, 0));
+    assert_eq!(parse_go_to("src/main.rs:42:7:"), target(Some("src/main.rs"), 41, 6));
+    assert_eq!(parse_go_to("  --> src/ui/mod.rs:3:14"), target(Some("src/ui/mod.rs"), 2, 13));
+    assert_eq!(parse_go_to(r"C:\src\main.rs:42"), target(Some(r"C:\src\main.rs"), 41, 0));
+    assert_eq!(parse_go_to("0"), target(None, 0, 0));
+
+    assert_eq!(parse_go_to(""), None);
+    assert_eq!(parse_go_to("main.rs"), None);
+    assert_eq!(parse_go_to("x:y"), None);
+}
+
+/// Back and forward walk the recorded jumps like a browser's history
+#[test]
+fn history_goes_back_and_forward() {
+    let mut history = NavigationHistory::default();
+    history.record(at("a.rs", 1));
+    history.record(at("b.rs", 5));
+
+    assert_eq!(history.back(Some(at("c.rs", 9))), Some(at("b.rs", 5)));
+    assert_eq!(history.back(Some(at("b.rs", 5))), Some(at("a.rs", 1)));
+    assert_eq!(history.back(Some(at("a.rs", 1))), None);
+    assert_eq!(history.forward(Some(at("a.rs", 1))), Some(at("b.rs", 5)));
+    assert_eq!(history.forward(Some(at("b.rs", 5))), Some(at("c.rs", 9)));
+    assert_eq!(history.forward(Some(at("c.rs", 9))), None);
+
+    // A new jump drops the way forward
+    history.back(Some(at("c.rs", 9)));
+    history.record(at("b.rs", 5));
+    assert_eq!(history.forward(Some(at("d.rs", 0))), None);
+}
+
+/// Going back skips entries where the cursor already is, and old entries fall off
+#[test]
+fn history_skips_current_location_and_is_bounded() {
+    let mut history = NavigationHistory::default();
+    history.record(at("a.rs", 1));
+    history.record(at("a.rs", 1));
+    history.record(at("b.rs", 2));
+    assert_eq!(history.back(Some(at("b.rs", 2))), Some(at("a.rs", 1)));
+    assert_eq!(history.back(None), None);
+
+    let mut history = NavigationHistory::default();
+    for line in 0..MAX_HISTORY + 10 {
+        history.record(at("a.rs", line));
+    }
+    let mut steps = 0;
+    while history.back(None).is_some() {
+        steps += 1;
+    }
+    assert_eq!(steps, MAX_HISTORY);
+}
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use crate::quick_open::{highlight_markup, index_files, rank, GitIgnore};
+use crate::settings::Settings;
+
+/// Name patterns match at any depth, `/` anchors to the `.gitignore`'s directory, and `!`
+/// re-includes
+#[test]
+fn gitignore_rules() {
+    let mut ignore = GitIgnore::default();
+    ignore.add_file(Path::new(""), "# build output\n*.log\n/dist\nbuild/\n!keep.log\n");
+    ignore.add_file(Path::new("web"), "cache\n");
+
+    assert!(ignore.is_ignored(Path::new("src/debug.log"), false));
+    assert!(!ignore.is_ignored(Path::new("src/keep.log"), false));
+    assert!(ignore.is_ignored(Path::new("dist"), true));
+    assert!(!ignore.is_ignored(Path::new("src/dist"), true));
+    assert!(ignore.is_ignored(Path::new("src/build"), true));
+    assert!(!ignore.is_ignored(Path::new("src/build"), false));
+    assert!(ignore.is_ignored(Path::new("web/cache"), false));
+    assert!(!ignore.is_ignored(Path::new("cache"), false));
+}
+
+/// `**` stands for whole path components, any number of them
+#[test]
+fn gitignore_double_star() {
+    let mut ignore = GitIgnore::default();
+    ignore.add_file(Path::new(""), "**/foo\nlogs/**\nsrc/*.rs\n");
+
+    assert!(ignore.is_ignored(Path::new("foo"), false));
+    assert!(ignore.is_ignored(Path::new("a/b/foo"), false));
+    assert!(!ignore.is_ignored(Path::new("xfoo"), false));
+    assert!(!ignore.is_ignored(Path::new("a/xfoo"), false));
+    assert!(ignore.is_ignored(Path::new("logs/a/today.txt"), false));
+    assert!(!ignore.is_ignored(Path::new("logs"), true));
+    assert!(ignore.is_ignored(Path::new("src/main.rs"), false));
+    assert!(!ignore.is_ignored(Path::new("src/bin/main.rs"), false));
+}
+
+/// Indexing skips `.git`, gitignored files and what the explorer hides
+#[test]
+fn indexes_files_respecting_gitignore() {
+    let root = std::env::temp_dir().join(format!("fikby-quick-open-{}", std::process::id()));
+    for dir in ["src", ".git", "target", "logs"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for file in ["src/main.rs", "src/out.tmp", ".git/HEAD", "target/app", "logs/today.txt", "README.md"] {
+        std::fs::write(root.join(file), "").unwrap();
+    }
+    std::fs::write(root.join(".gitignore"), "*.tmp\nlogs/\n").unwrap();
+
+    let files = index_files(&root, &Settings::default());
+    assert_eq!(files, vec![PathBuf::from("README.md"), PathBuf::from("src/main.rs")]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// Symlinked files and directories are indexed, without looping on links back up the tree
+#[cfg(unix)]
+#[test]
+fn follows_symlinks_without_looping() {
+    let root = std::env::temp_dir().join(format!("fikby-quick-open-links-{}", std::process::id()));
+    let shared = std::env::temp_dir().join(format!("fikby-quick-open-shared-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::create_dir_all(&shared).unwrap();
+    std::fs::write(root.join("src/main.rs"), "").unwrap();
+    std::fs::write(shared.join("lib.rs"), "").unwrap();
+    std::os::unix::fs::symlink(&shared, root.join("shared")).unwrap();
+    std::os::unix::fs::symlink(root.join("src/main.rs"), root.join("main.rs")).unwrap();
+    std::os::unix::fs::symlink(&root, root.join("src/loop")).unwrap();
+    std::os::unix::fs::symlink(root.join("missing"), root.join("broken")).unwrap();
+
+    let files = index_files(&root, &Settings::default());
+    assert_eq!(files, vec![PathBuf::from("main.rs"), PathBuf::from("shared/lib.rs"), PathBuf::from("src/main.rs")]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_dir_all(&shared).unwrap();
+}
+
+/// Better matches come first and the matched characters are reported for highlighting
+#[test]
+fn ranks_fuzzy_matches() {
+    let files: Vec<PathBuf> = ["src/editor.rs", "src/ui/workspace.rs", "docs/editing.md"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    let matcher = SkimMatcherV2::default();
+
+    let matches = rank(&matcher, "editrs", &files);
+    assert_eq!(matches[0].display, "src/editor.rs");
+    assert!(matches.iter().all(|m| m.display != "docs/editing.md"));
+    assert_eq!(rank(&matcher, "", &files).len(), 3);
+
+    assert_eq!(highlight_markup("a<b", &[0, 2]), "<b>a</b>&lt;<b>b</b>");
+}
@@ -0,0 +1,80 @@
+use crate::search::{next_match, preserve_case, Search, SearchMatch, SearchOptions};
+
+fn spans(search: &Search, text: &str) -> Vec<(usize, usize)> {
+    search.find_all(text).iter().map(|m| (m.start, m.end)).collect()
+}
+
+/// Literal queries escape regex syntax; case and whole-word toggles narrow the matches
+#[test]
+fn literal_case_and_whole_word() {
+    let text = "Foo foo.bar food (foo)";
+    let options = SearchOptions::default();
+    assert_eq!(spans(&Search::new("foo", options).unwrap(), text), vec![(0, 3), (4, 7), (12, 15), (18, 21)]);
+
+    let case_sensitive = SearchOptions { case_sensitive: true, ..options };
+    assert_eq!(spans(&Search::new("Foo", case_sensitive).unwrap(), text), vec![(0, 3)]);
+
+    let whole_word = SearchOptions { whole_word: true, ..options };
+    assert_eq!(spans(&Search::new("foo", whole_word).unwrap(), text), vec![(0, 3), (4, 7), (18, 21)]);
+
+    // "." is literal unless regex is on
+    assert_eq!(spans(&Search::new("o.b", options).unwrap(), text), vec![(6, 9)]);
+    assert_eq!(spans(&Search::new("(foo)", options).unwrap(), text), vec![(17, 22)]);
+
+    // Offsets are in characters
+    assert_eq!(spans(&Search::new("b", options).unwrap(), "äöü b"), vec![(4, 5)]);
+}
+
+/// Regex mode: anchors work per line, empty matches are skipped, captures feed replacements
+#[test]
+fn regex_matches_and_capture_replacements() {
+    let regex = SearchOptions { regex: true, case_sensitive: true, ..SearchOptions::default() };
+    let search = Search::new(r"^(\w+) = (\d+)$", regex).unwrap();
+    let text = "a = 1\nbad = x\nlimit = 20";
+    assert_eq!(spans(&search, text), vec![(0, 5), (14, 24)]);
+    let matches = search.find_all(text);
+    assert_eq!(search.replacements(text, &matches, "$2 => ${1}"), ["1 => a", "20 => limit"]);
+
+    // Captures come from the match in context: alone, "bc" wouldn't match `\B`
+    let inside_word = Search::new(r"\Bb(\w)", regex).unwrap();
+    let m = inside_word.find_all("abc")[0];
+    assert_eq!(inside_word.replacement("abc", m, "[$1]"), "[c]");
+    let letters = Search::new(r"(\w)", regex).unwrap();
+    assert_eq!(letters.replacements("éx", &letters.find_all("éx"), "$1$1"), ["éé", "xx"]);
+
+    assert!(Search::new("^", regex).unwrap().find_all("one\ntwo").is_empty());
+    assert!(Search::new("(unclosed", regex).is_err());
+
+    // Without regex, "$1" is literal
+    let literal = Search::new("a", SearchOptions::default()).unwrap();
+    assert_eq!(literal.replacement("a", SearchMatch { start: 0, end: 1 }, "$1"), "$1");
+}
+
+/// Preserve case follows the replaced text's case
+#[test]
+fn preserve_case_replacements() {
+    assert_eq!(preserve_case("FOO", "bar"), "BAR");
+    assert_eq!(preserve_case("Foo", "bar"), "Bar");
+    assert_eq!(preserve_case("foo", "Bar"), "bar");
+    assert_eq!(preserve_case("fooBar", "bazQux"), "bazQux");
+    assert_eq!(preserve_case("42", "x"), "x");
+
+    let options = SearchOptions { preserve_case: true, ..SearchOptions::default() };
+    let mut search = Search::new("name", options).unwrap();
+    let m = SearchMatch { start: 4, end: 8 };
+    assert_eq!(search.replacement("the NAME", m, "title"), "TITLE");
+    search.set_preserve_case(false);
+    assert_eq!(search.replacement("the NAME", m, "title"), "title");
+}
+
+/// Find Next and Find Previous wrap around at the ends
+#[test]
+fn next_and_previous_wrap() {
+    let matches = [SearchMatch { start: 2, end: 4 }, SearchMatch { start: 10, end: 12 }];
+    assert_eq!(next_match(&matches, 0, false), Some(0));
+    assert_eq!(next_match(&matches, 4, false), Some(1));
+    assert_eq!(next_match(&matches, 12, false), Some(0));
+    assert_eq!(next_match(&matches, 10, true), Some(0));
+    assert_eq!(next_match(&matches, 2, true), Some(1));
+    assert_eq!(next_match(&[], 0, false), None);
+}
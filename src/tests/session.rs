@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use crate::session::{Session, TabSession};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fikby-session-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn sample_session() -> Session {
+    Session {
+        window_width: 1280,
+        window_height: 800,
+        maximized: false,
+        paned_position: Some(240),
+        explorer_root: Some(PathBuf::from("/project")),
+        expanded_dirs: vec![PathBuf::from("/project/src"), PathBuf::from("/project/src/ui")],
+        active_tab: 1,
+        tabs: vec![
+            TabSession {
+                path: Some(PathBuf::from("/project/src/main.rs")),
+                cursor_offset: 120,
+                scroll_line: 10,
+                ..TabSession::default()
+            },
+            TabSession {
+                snapshot_text: Some("unsaved notes\n".to_string()),
+                syntax_override: Some("Markdown".to_string()),
+                ..TabSession::default()
+            },
+        ],
+        recent_commands: vec!["save-all".to_string(), "convert-indentation::tabs".to_string()],
+    }
+}
+
+/// Tabs, layout and untitled snapshots survive a save/load cycle
+#[test]
+fn session_round_trip_with_snapshots() {
+    let dir = temp_dir("round-trip");
+    let session = sample_session();
+
+    session.save_to(&dir).unwrap();
+    let loaded = Session::load_from(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.tabs[1].snapshot.as_deref(), Some("untitled-1.txt"));
+    assert_eq!(loaded.tabs[1].snapshot_text.as_deref(), Some("unsaved notes\n"));
+    assert_eq!(loaded.tabs[0].snapshot, None);
+
+    let mut expected = session;
+    expected.tabs[1].snapshot = Some("untitled-1.txt".to_string());
+    assert_eq!(loaded, expected);
+}
+
+/// Snapshots from an earlier session are cleaned up once they are no longer referenced
+#[test]
+fn stale_snapshots_are_removed() {
+    let dir = temp_dir("stale");
+    sample_session().save_to(&dir).unwrap();
+
+    let mut session = sample_session();
+    session.tabs.truncate(1);
+    session.active_tab = 0;
+    session.save_to(&dir).unwrap();
+
+    let remaining = std::fs::read_dir(dir.join("snapshots")).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(remaining, 0);
+}
+
+/// An untitled tab whose snapshot is gone can't be restored and is dropped
+#[test]
+fn missing_snapshot_drops_tab() {
+    let dir = temp_dir("missing");
+    sample_session().save_to(&dir).unwrap();
+    std::fs::remove_file(dir.join("snapshots").join("untitled-1.txt")).unwrap();
+
+    let loaded = Session::load_from(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.tabs.len(), 1);
+    assert_eq!(loaded.active_tab, 0);
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+use crate::config::ThemeMode;
+use crate::settings::{wildcard_match, xdg_dir_from, Settings};
+
+/// Missing keys fall back to their defaults, so old or hand-written files keep loading
+#[test]
+fn partial_file_uses_defaults() {
+    let settings = Settings::from_toml("font_size = 14\ntheme = \"light\"\n").unwrap();
+
+    assert_eq!(settings.font_size, 14);
+    assert_eq!(settings.theme, ThemeMode::Light);
+    assert_eq!(settings.tab_width, Settings::default().tab_width);
+    assert_eq!(settings.large_file_threshold_bytes, Settings::default().large_file_threshold_bytes);
+}
+
+/// Saving and loading again gives back the same settings
+#[test]
+fn settings_round_trip_through_file() {
+    let dir = std::env::temp_dir().join(format!("fikby-settings-{}", std::process::id()));
+    let path = dir.join("settings.toml");
+
+    let settings = Settings {
+        font_family: "Fira Code".to_string(),
+        tab_width: 2,
+        insert_spaces: false,
+        wrap_by_default: true,
+        hidden_patterns: vec!["*.o".to_string()],
+        ..Settings::default()
+    };
+    settings.save_to(&path).unwrap();
+    let loaded = Settings::load_from(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded, settings);
+}
+
+/// Type errors are reported instead of silently ignored
+#[test]
+fn invalid_file_is_an_error() {
+    assert!(Settings::from_toml("tab_width = \"four\"").is_err());
+    assert!(Settings::from_toml("theme = \"sepia\"").is_err());
+}
+
+/// `$XDG_CONFIG_HOME` wins, otherwise `~/.config` is used
+#[test]
+fn config_dir_follows_xdg() {
+    assert_eq!(
+        xdg_dir_from(Some("/xdg".into()), Some("/home/me".into()), ".config"),
+        Some(PathBuf::from("/xdg/fikby"))
+    );
+    assert_eq!(
+        xdg_dir_from(Some("".into()), Some("/home/me".into()), ".config"),
+        Some(PathBuf::from("/home/me/.config/fikby"))
+    );
+    assert_eq!(xdg_dir_from(None, None, ".config"), None);
+}
+
+/// Font families are quoted for CSS, except generic ones
+#[test]
+fn font_css_quotes_families() {
+    let settings = Settings { font_family: "Fira Code, 'DejaVu Sans Mono',monospace".to_string(), ..Settings::default() };
+    assert_eq!(
+        settings.font_css(),
+        format!(
+            ".editor-view, .gutter {{ font-family: \"Fira Code\", \"DejaVu Sans Mono\", monospace; font-size: {}pt; }}",
+            settings.font_size
+        )
+    );
+}
+
+/// Dotfiles and configured patterns are hidden from the explorer
+#[test]
+fn hidden_file_rules() {
+    let mut settings = Settings {
+        hidden_patterns: vec!["target".to_string(), "*.o".to_string(), "tmp?".to_string()],
+        ..Settings::default()
+    };
+
+    assert!(settings.is_hidden(".git"));
+    assert!(settings.is_hidden("target"));
+    assert!(settings.is_hidden("main.o"));
+    assert!(settings.is_hidden("tmp1"));
+    assert!(!settings.is_hidden("tmp12"));
+    assert!(!settings.is_hidden("src"));
+    assert!(!settings.is_hidden("main.rs"));
+
+    settings.show_hidden_files = true;
+    assert!(!settings.is_hidden(".git"));
+
+    assert!(wildcard_match("*", ""));
+    assert!(wildcard_match("a*b*c", "aXbYbc"));
+    assert!(!wildcard_match("a*b", "ab_"));
+}
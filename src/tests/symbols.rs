@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use ropey::Rope;
+
+use crate::settings::Settings;
+use crate::symbols::{extract_symbols, identifier_at, SymbolIndex};
+
+/// Definitions are recognized per language, with the name's column, the defining line and
+/// the comment above (past attributes) as documentation
+#[test]
+fn extracts_definitions() {
+    let text = "\
+/// Counts words.
+///
+/// Spaces separate them.
+#[inline]
+pub(crate) const fn count_words(text: &str) -> usize {
+    let helper = 1;
+}
+// Not documentation
+struct Point { x: i32 }
+pub static mut TOTAL: usize = 0;
+macro_rules! square {
+";
+    let symbols = extract_symbols(Path::new("src/lib.rs"), text);
+    let found: Vec<(&str, &str, usize, usize)> =
+        symbols.iter().map(|s| (s.name.as_str(), s.kind, s.line, s.column)).collect();
+    assert_eq!(
+        found,
+        vec![("count_words", "function", 4, 20), ("Point", "struct", 8, 7), ("TOTAL", "constant", 9, 15), ("square", "macro", 10, 13)]
+    );
+    assert_eq!(symbols[0].signature, "pub(crate) const fn count_words(text: &str) -> usize");
+    assert_eq!(symbols[0].doc.as_deref(), Some("Counts words.\n\nSpaces separate them."));
+    assert_eq!(symbols[1].doc, None);
+
+    let python = "@cache\ndef area(r):\n    \"\"\"Area of a circle\n    of radius r.\"\"\"\nclass Shape:\n";
+    let symbols = extract_symbols(Path::new("geometry.py"), python);
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0].doc.as_deref(), Some("Area of a circle\nof radius r."));
+
+    let c = "/**\n * Adds.\n */\nstatic int add(int a, int b) {\n    return add(a, b);\n}\nint declared(void);\n";
+    let symbols = extract_symbols(Path::new("math.c"), c);
+    assert_eq!(symbols.iter().map(|s| (s.name.as_str(), s.line)).collect::<Vec<_>>(), vec![("add", 3)]);
+    assert_eq!(symbols[0].doc.as_deref(), Some("Adds."));
+
+    let ts = "export default async function load() {}\nexport const LIMIT: number = 3;\n";
+    let names: Vec<String> = extract_symbols(Path::new("a.ts"), ts).into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["load", "LIMIT"]);
+    assert!(extract_symbols(Path::new("notes.txt"), "fn nope() {}").is_empty());
+}
+
+/// The identifier under the pointer or just before the cursor; numbers aren't identifiers
+#[test]
+fn finds_identifier_at_offset() {
+    let text = Rope::from_str("é_x.len(42)");
+    assert_eq!(identifier_at(&text, 0), Some((0, 3)));
+    assert_eq!(identifier_at(&text, 3), Some((0, 3)));
+    assert_eq!(identifier_at(&text, 5), Some((4, 7)));
+    assert_eq!(identifier_at(&text, 9), None);
+    assert_eq!(identifier_at(&text, 11), None);
+}
+
+/// The index covers a folder and replaces a file's definitions when it's saved
+#[test]
+fn indexes_folder() {
+    let root = std::env::temp_dir().join(format!("fikby-symbols-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("src/lib.rs"), "pub fn shared() {}\nfn local() {}\n").unwrap();
+    std::fs::write(root.join("src/other.rs"), "fn shared() {}\n").unwrap();
+    std::fs::write(root.join("README.md"), "fn shared() {}\n").unwrap();
+
+    let mut index = SymbolIndex::build(&root, &Settings::default());
+    let mut paths: Vec<_> = index.definitions("shared").iter().map(|s| s.path.clone()).collect();
+    paths.sort();
+    assert_eq!(paths, vec![root.join("src/lib.rs"), root.join("src/other.rs")]);
+
+    index.update_file(&root.join("src/lib.rs"), "fn renamed() {}\n");
+    assert_eq!(index.definitions("shared").len(), 1);
+    assert!(index.definitions("local").is_empty());
+    assert_eq!(index.definitions("renamed")[0].line, 0);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
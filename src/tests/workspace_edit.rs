@@ -0,0 +1,70 @@
+use ropey::Rope;
+
+use crate::file_format;
+use crate::lsp::protocol::{Position, PositionEncoding, Range, TextEdit};
+use crate::workspace_edit::{self, Edit, EditError, FileChanges};
+
+fn text_edit(line: u32, start: u32, end: u32, text: &str) -> TextEdit {
+    let range = Range { start: Position { line, character: start }, end: Position { line, character: end } };
+    TextEdit { range, new_text: text.to_string() }
+}
+
+/// Edits are positioned in the server's encoding, sorted, and refused if they overlap
+#[test]
+fn resolves_and_applies_edits() {
+    let text = Rope::from_str("let 😀 = a;\nuse(a);\n");
+    let edits = [text_edit(1, 4, 5, "b"), text_edit(0, 9, 10, "b"), text_edit(1, 0, 0, "\r\n")];
+    let resolved = workspace_edit::resolve(&text, &edits, PositionEncoding::Utf16).unwrap();
+    let starts: Vec<usize> = resolved.iter().map(|edit| edit.start).collect();
+    assert_eq!(starts, [8, 11, 15]);
+    assert_eq!(resolved[1].text, "\n");
+    assert_eq!(workspace_edit::apply(&text, &resolved).to_string(), "let 😀 = b;\n\nuse(b);\n");
+
+    let overlapping = [text_edit(0, 0, 5, "x"), text_edit(0, 3, 4, "y")];
+    assert_eq!(workspace_edit::resolve(&text, &overlapping, PositionEncoding::Utf32), None);
+}
+
+/// Edits on the same line are previewed together, with what they inserted marked
+#[test]
+fn groups_edits_into_hunks() {
+    let text = Rope::from_str("fn old() {\n    old();\n    old()\n}\n");
+    let edits = [text_edit(0, 3, 6, "new"), text_edit(1, 4, 7, "new"), text_edit(2, 4, 7, "x")];
+    let file = FileChanges::new("a.rs".into(), text.clone(), &edits, PositionEncoding::Utf32).unwrap();
+    assert_eq!(file.edit_count(), 3);
+    assert_eq!(file.hunks.len(), 3);
+    let hunk = &file.hunks[1];
+    assert_eq!((hunk.line, hunk.before.as_str(), hunk.after.as_str()), (1, "    old();", "    new();"));
+    assert_eq!(hunk.inserted, [(4, 7)]);
+
+    // A line break replaced joins the lines it spans into one hunk
+    let line_break = Range { start: Position { line: 1, character: 10 }, end: Position { line: 2, character: 0 } };
+    let joined = [TextEdit { range: line_break, new_text: "  ".to_string() }, text_edit(2, 4, 7, "new")];
+    let file = FileChanges::new("a.rs".into(), text, &joined, PositionEncoding::Utf32).unwrap();
+    assert_eq!(file.hunks.len(), 1);
+    assert_eq!(file.hunks[0].before, "    old();\n    old()");
+    assert_eq!(file.hunks[0].after, "    old();      new()");
+    assert_eq!(file.hunks[0].inserted, [(10, 12), (16, 19)]);
+
+    assert!(file.chosen_edits(|_| false).is_empty());
+    assert_eq!(file.chosen_edits(|index| index == 0), file.hunks[0].edits);
+}
+
+/// Files on disk keep their line endings, and are left alone once they changed
+#[test]
+fn writes_edits_to_files() {
+    let dir = std::env::temp_dir().join(format!("fikby-workspace-edit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("crlf.rs");
+    std::fs::write(&path, "old();\r\nold();\r\n").unwrap();
+
+    let (text, _) = file_format::read_file(&path).unwrap();
+    let expected = Rope::from_str(&text);
+    let edits = [Edit { start: 0, end: 3, text: "new".to_string() }];
+    workspace_edit::write_file(&path, &expected, &edits).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new();\r\nold();\r\n");
+
+    let changed = workspace_edit::write_file(&path, &expected, &edits);
+    assert!(matches!(changed, Err(EditError::Changed)));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new();\r\nold();\r\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,289 @@
+use gtk4::prelude::*;
+use gtk4::{gdk, glib, EventSequenceState, GestureClick, Label, ListBox, Popover, PositionType, TextWindowType};
+use serde_json::{json, Value};
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use crate::diagnostics;
+use crate::editor::Editor;
+use crate::lsp::protocol::{self, CodeAction, Range};
+
+// How long the cursor rests before the lightbulb checks for actions there
+const LIGHTBULB_DELAY: Duration = Duration::from_millis(400);
+
+type ChosenHandler = Rc<dyn Fn(&Rc<Editor>, CodeAction)>;
+
+/// Code actions of the language server at the cursor: quick fixes for the diagnostics there,
+/// such as importing an item or filling in match arms, and refactorings. A lightbulb in the
+/// gutter shows where there are some; clicking it or Ctrl+. lists them in a menu at the
+/// cursor.
+pub struct CodeActionMenu {
+    popover: Popover,
+    list: ListBox,
+    /// The view the listed actions are for, and the actions
+    listed: RefCell<Option<(Weak<Editor>, Vec<CodeAction>)>>,
+    /// Waiting for the cursor to rest before checking for actions
+    timeout: RefCell<Option<glib::SourceId>>,
+    /// Bumped by every request for the menu and by closing it, so stale answers are dropped
+    generation: Cell<u64>,
+    /// Bumped by every check for the lightbulb, likewise
+    checks: Cell<u64>,
+    on_chosen: RefCell<Option<ChosenHandler>>,
+}
+
+impl CodeActionMenu {
+    pub fn new() -> Rc<Self> {
+        let popover = Popover::new();
+        popover.set_has_arrow(false);
+        popover.set_position(PositionType::Bottom);
+        popover.style_context().add_class("code-actions");
+
+        let list = ListBox::new();
+        list.set_selection_mode(gtk4::SelectionMode::Browse);
+        popover.set_child(Some(&list));
+
+        let menu = Rc::new(Self {
+            popover,
+            list,
+            listed: RefCell::new(None),
+            timeout: RefCell::new(None),
+            generation: Cell::new(0),
+            checks: Cell::new(0),
+            on_chosen: RefCell::new(None),
+        });
+
+        let menu_weak = Rc::downgrade(&menu);
+        menu.list.connect_row_activated(move |_, row| {
+            if let Some(menu) = menu_weak.upgrade() {
+                menu.choose(row.index());
+            }
+        });
+
+        // Escape or clicking elsewhere closes the menu; typing goes on in the view
+        let menu_weak = Rc::downgrade(&menu);
+        menu.popover.connect_closed(move |_| {
+            let Some(menu) = menu_weak.upgrade() else {
+                return;
+            };
+            let editor = menu.listed.borrow_mut().take().and_then(|(editor, _)| editor.upgrade());
+            if let Some(editor) = editor {
+                editor.main_view.grab_focus();
+            }
+        });
+        menu
+    }
+
+    /// Call `callback` with the view and the action chosen from the menu.
+    pub fn connect_chosen(&self, callback: impl Fn(&Rc<Editor>, CodeAction) + 'static) {
+        *self.on_chosen.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Check for actions wherever `editor`'s cursor rests, and open the menu from the
+    /// lightbulb.
+    pub fn attach(self: &Rc<Self>, editor: &Rc<Editor>) {
+        // Typing moves the cursor without setting its mark, so edits count as moves
+        let menu_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        editor.main_buffer.connect_mark_set(move |buffer, _iter, mark| {
+            let (Some(menu), Some(editor)) = (menu_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if *mark == buffer.get_insert() && editor.main_view.has_focus() {
+                menu.cursor_moved(&editor);
+            }
+        });
+        let menu_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        editor.main_buffer.connect_changed(move |_| {
+            let (Some(menu), Some(editor)) = (menu_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if editor.main_view.has_focus() {
+                menu.cursor_moved(&editor);
+            }
+        });
+
+        let click = GestureClick::new();
+        click.set_button(gdk::BUTTON_PRIMARY);
+        let menu_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        click.connect_pressed(move |gesture, _, x, y| {
+            let (Some(menu), Some(editor)) = (menu_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if editor.lightbulb_at(x, y) {
+                gesture.set_state(EventSequenceState::Claimed);
+                menu.show(&editor);
+            }
+        });
+        editor.line_numbers.add_controller(click);
+    }
+
+    /// List the actions at `editor`'s selection or cursor in the menu.
+    pub fn show(self: &Rc<Self>, editor: &Rc<Editor>) {
+        self.hide();
+        let generation = self.generation.get();
+        let menu_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        let asked = request(editor, true, move |actions| {
+            let (Some(menu), Some(editor)) = (menu_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if menu.generation.get() == generation {
+                menu.list_actions(&editor, actions);
+            }
+        });
+        if !asked {
+            self.list_actions(editor, Vec::new());
+        }
+    }
+
+    pub fn hide(&self) {
+        self.generation.set(self.generation.get() + 1);
+        self.listed.borrow_mut().take();
+        self.popover.popdown();
+        if self.popover.parent().is_some() {
+            self.popover.unparent();
+        }
+    }
+
+    fn list_actions(&self, editor: &Rc<Editor>, actions: Vec<CodeAction>) {
+        while let Some(row) = self.list.row_at_index(0) {
+            self.list.remove(&row);
+        }
+        if actions.is_empty() {
+            self.list.append(&menu_label("No code actions available here"));
+            if let Some(row) = self.list.row_at_index(0) {
+                row.set_activatable(false);
+                row.set_selectable(false);
+            }
+        }
+        for action in &actions {
+            self.list.append(&menu_label(&action.title));
+        }
+        *self.listed.borrow_mut() = Some((Rc::downgrade(editor), actions));
+
+        let view = &editor.main_view;
+        if self.popover.parent().as_ref() != Some(view.upcast_ref::<gtk4::Widget>()) {
+            if self.popover.parent().is_some() {
+                self.popover.unparent();
+            }
+            self.popover.set_parent(view);
+        }
+        let buffer = &editor.main_buffer;
+        let location = view.iter_location(&buffer.iter_at_mark(&buffer.get_insert()));
+        let (x, y) = view.buffer_to_window_coords(TextWindowType::Widget, location.x(), location.y());
+        self.popover.set_pointing_to(Some(&gdk::Rectangle::new(x, y, 1, location.height())));
+        self.popover.popup();
+        if let Some(row) = self.list.row_at_index(0) {
+            self.list.select_row(Some(&row));
+            row.grab_focus();
+        }
+    }
+
+    fn choose(&self, row: i32) {
+        let listed = self.listed.borrow_mut().take();
+        self.hide();
+        let Some((editor, mut actions)) = listed else {
+            return;
+        };
+        let Some(editor) = editor.upgrade() else {
+            return;
+        };
+        editor.main_view.grab_focus();
+        let Some(index) = usize::try_from(row).ok().filter(|&index| index < actions.len()) else {
+            return;
+        };
+        let action = actions.swap_remove(index);
+        let handler = self.on_chosen.borrow().clone();
+        if let Some(handler) = handler {
+            handler(&editor, action);
+        }
+    }
+
+    /// Hide the lightbulb if the cursor left its line, and check the cursor's place for
+    /// actions once it rests.
+    fn cursor_moved(self: &Rc<Self>, editor: &Rc<Editor>) {
+        self.cancel_timeout();
+        if editor.lightbulb() != Some(editor.cursor_position().0) {
+            editor.set_lightbulb(None);
+        }
+        let menu_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        let source = glib::timeout_add_local_once(LIGHTBULB_DELAY, move || {
+            let (Some(menu), Some(editor)) = (menu_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            menu.timeout.borrow_mut().take();
+            menu.check(&editor);
+        });
+        *self.timeout.borrow_mut() = Some(source);
+    }
+
+    /// Show the lightbulb on the cursor's line if there are actions at the cursor.
+    fn check(self: &Rc<Self>, editor: &Rc<Editor>) {
+        let check = self.checks.get() + 1;
+        self.checks.set(check);
+        let line = editor.cursor_position().0;
+        let menu_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        request(editor, false, move |actions| {
+            let (Some(menu), Some(editor)) = (menu_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if menu.checks.get() == check {
+                editor.set_lightbulb((!actions.is_empty()).then_some(line));
+            }
+        });
+    }
+
+    fn cancel_timeout(&self) {
+        if let Some(source) = self.timeout.borrow_mut().take() {
+            source.remove();
+        }
+    }
+}
+
+/// Ask `editor`'s language server for the actions at its selection or cursor, along with the
+/// diagnostics there, and call `done` with them. `invoked` tells the server they were asked
+/// for rather than checked for. Returns false without asking if there's no server or it
+/// has no code actions.
+fn request(editor: &Editor, invoked: bool, done: impl FnOnce(Vec<CodeAction>) + 'static) -> bool {
+    let Some(lsp) = editor.language_server() else {
+        return false;
+    };
+    if matches!(lsp.client.capabilities()["codeActionProvider"], Value::Null | Value::Bool(false)) {
+        return false;
+    }
+    let Some(text) = lsp.client.document_text(&lsp.uri) else {
+        return false;
+    };
+    let encoding = lsp.client.encoding();
+    let (start, end) = editor.selection_offsets();
+    let diagnostics: Vec<Value> =
+        editor.diagnostics_in(start, end).iter().map(|d| diagnostics::to_lsp(d, &text, encoding)).collect();
+    let range = Range {
+        start: protocol::position_at(&text, start, encoding),
+        end: protocol::position_at(&text, end, encoding),
+    };
+    let params = json!({
+        "textDocument": { "uri": lsp.uri },
+        "range": range,
+        "context": { "diagnostics": diagnostics, "triggerKind": if invoked { 1 } else { 2 } },
+    });
+    lsp.client.request("textDocument/codeAction", params, move |result| {
+        done(result.map(|result| protocol::code_actions(&result)).unwrap_or_default());
+    });
+    true
+}
+
+fn menu_label(text: &str) -> Label {
+    let label = Label::new(Some(text));
+    label.set_xalign(0.0);
+    label.set_margin_start(6);
+    label.set_margin_end(6);
+    label.set_margin_top(3);
+    label.set_margin_bottom(3);
+    label
+}
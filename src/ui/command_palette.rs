@@ -0,0 +1,144 @@
+use gtk4::prelude::*;
+use gtk4::{Box as GtkBox, Label, Orientation};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use super::palette::Palette;
+use super::{Workspace, FOLD_LEVELS};
+use crate::commands::{Command, CommandRegistry};
+use crate::quick_open::highlight_markup;
+
+/// Ctrl+Shift+P palette listing every application action with its shortcut.
+pub fn show_command_palette(workspace: &Rc<Workspace>) {
+    let Some(app) = workspace.window.application() else {
+        return;
+    };
+
+    // Actions taking a parameter only appear through the entries registered for them
+    let parameterless = app
+        .list_actions()
+        .into_iter()
+        .map(|name| name.to_string())
+        .filter(|name| {
+            app.lookup_action(name)
+                .is_some_and(|action| action.is_enabled() && action.parameter_type().is_none())
+        })
+        .filter(|name| name != "command-palette");
+    let available: Vec<Command> = workspace
+        .commands
+        .borrow()
+        .commands_for(parameterless)
+        .into_iter()
+        .filter(|command| app.lookup_action(&command.action).is_some_and(|action| action.is_enabled()))
+        .collect();
+
+    let palette = Rc::new(Palette::new(&workspace.window, "Type a command"));
+    let shown: Rc<RefCell<Vec<Command>>> = Rc::new(RefCell::new(Vec::new()));
+    let matcher = SkimMatcherV2::default();
+
+    let refresh = {
+        let (palette, shown, workspace, app) = (palette.clone(), shown.clone(), workspace.clone(), app.clone());
+        move |query: &str| {
+            let matches = workspace.commands.borrow().rank(&matcher, query, &available);
+            palette.set_rows(matches.iter().map(|m| {
+                let row = GtkBox::new(Orientation::Horizontal, 12);
+                let title = Label::new(None);
+                title.set_markup(&highlight_markup(&m.command.title, &m.indices));
+                title.set_xalign(0.0);
+                title.set_hexpand(true);
+                let shortcut = Label::new(Some(&shortcut_label(&app, &m.command)));
+                shortcut.style_context().add_class("dim-label");
+                row.append(&title);
+                row.append(&shortcut);
+                row.upcast()
+            }));
+            *shown.borrow_mut() = matches.into_iter().map(|m| m.command).collect();
+        }
+    };
+    refresh("");
+    palette.connect_query_changed(refresh);
+
+    {
+        let workspace = workspace.clone();
+        palette.connect_chosen(move |index| {
+            let Some(command) = shown.borrow().get(index).cloned() else {
+                return;
+            };
+            workspace.commands.borrow_mut().record_use(&command.id());
+            let target = command.target.as_ref().map(|t| t.to_variant());
+            app.activate_action(&command.action, target.as_ref());
+        });
+    }
+
+    palette.present();
+}
+
+/// Human-readable first accelerator of the command's action, e.g. "Ctrl+Shift+S".
+fn shortcut_label(app: &gtk4::Application, command: &Command) -> String {
+    app.accels_for_action(&format!("app.{}", command.id()))
+        .first()
+        .and_then(gtk4::accelerator_parse)
+        .map(|(key, modifiers)| gtk4::accelerator_get_label(key, modifiers).to_string())
+        .unwrap_or_default()
+}
+
+/// Palette titles for the built-in actions.
+pub fn register_builtin_commands(registry: &mut CommandRegistry) {
+    for (action, title) in [
+        ("new", "File: New File"),
+        ("open", "File: Open…"),
+        ("quick-open", "File: Quick Open…"),
+        ("save", "File: Save"),
+        ("save-as", "File: Save As…"),
+        ("save-all", "File: Save All"),
+        ("quit", "File: Quit"),
+        ("undo", "Edit: Undo"),
+        ("redo", "Edit: Redo"),
+        ("cut", "Edit: Cut"),
+        ("copy", "Edit: Copy"),
+        ("paste", "Edit: Paste"),
+        ("find", "Edit: Find"),
+        ("replace", "Edit: Replace"),
+        ("find-next", "Edit: Find Next"),
+        ("find-previous", "Edit: Find Previous"),
+        ("find-in-files", "Search: Find in Files"),
+        ("trigger-completion", "Edit: Trigger Completion"),
+        ("rename-symbol", "Edit: Rename Symbol…"),
+        ("code-actions", "Edit: Code Actions…"),
+        ("add-next-occurrence", "Selection: Add Next Occurrence"),
+        ("select-all-occurrences", "Selection: Select All Occurrences"),
+        ("go-to-line", "Go: Go to Line…"),
+        ("go-to-definition", "Go: Go to Definition"),
+        ("find-references", "Go: Find References"),
+        ("jump-to-bracket", "Go: Jump to Matching Bracket"),
+        ("navigate-back", "Go: Back"),
+        ("navigate-forward", "Go: Forward"),
+        ("toggle-line-ending", "Edit: Toggle Line Endings (LF/CRLF)"),
+        ("toggle-wrap", "View: Toggle Word Wrap"),
+        ("toggle-theme", "View: Toggle Theme"),
+        ("toggle-panel", "View: Toggle Panel"),
+        ("show-problems", "View: Problems"),
+        ("cargo-check", "Problems: Run cargo check"),
+        ("fold", "View: Fold"),
+        ("unfold", "View: Unfold"),
+        ("fold-all", "View: Fold All"),
+        ("unfold-all", "View: Unfold All"),
+        ("split-right", "View: Split Editor Right"),
+        ("split-down", "View: Split Editor Down"),
+        ("move-tab-to-next-group", "View: Move Tab to Next Group"),
+        ("settings", "Preferences: Open Settings"),
+        ("explorer-new-file", "Explorer: New File"),
+        ("explorer-new-folder", "Explorer: New Folder"),
+        ("explorer-rename", "Explorer: Rename"),
+        ("explorer-delete", "Explorer: Delete"),
+    ] {
+        registry.register(action, title);
+    }
+    registry.register_with_target("convert-indentation", "spaces", "Edit: Convert Indentation to Spaces");
+    registry.register_with_target("convert-indentation", "tabs", "Edit: Convert Indentation to Tabs");
+    for level in 1..=FOLD_LEVELS {
+        registry.register_with_target("fold-level", &level.to_string(), &format!("View: Fold Level {}", level));
+    }
+}
@@ -0,0 +1,611 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use gtk4::prelude::*;
+use gtk4::{
+    gdk, glib, Box as GtkBox, EventControllerFocus, EventControllerKey, Inhibit, Label, ListBox, Orientation, Popover,
+    PositionType, PropagationPhase, ScrolledWindow, TextIter, TextMark, TextWindowType,
+};
+use serde_json::json;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::{Rc, Weak};
+
+use crate::completion::{self, CompletionItem, CompletionMatch};
+use crate::editor::Editor;
+use crate::lsp::protocol;
+use crate::quick_open::highlight_markup;
+
+/// Height of the list before it scrolls
+const LIST_HEIGHT: i32 = 240;
+/// Rows moved by Page Up and Page Down
+const PAGE_ROWS: i32 = 10;
+
+/// Completion popup at the cursor: the language server's items merged with the words of the
+/// open buffers, filtered as typing goes on. It opens on the server's trigger characters or
+/// with Ctrl+Space. Up/Down pick an item, Enter or Tab inserts it, Escape closes it; a snippet
+/// then leaves its tab stops to fill in, Tab moving from one to the next.
+pub struct CompletionPopup {
+    popover: Popover,
+    list: ListBox,
+    scrolled: ScrolledWindow,
+    /// Detail and documentation of the selected item
+    info_label: Label,
+    /// Every open view, for the words of their buffers
+    editors: Rc<RefCell<Vec<Rc<Editor>>>>,
+    matcher: SkimMatcherV2,
+    session: RefCell<Option<Session>>,
+    /// Bumped by every request to the server and by closing, so stale responses are dropped
+    generation: Cell<u64>,
+    snippet: RefCell<Option<SnippetStops>>,
+}
+
+/// A completion in progress in one view.
+struct Session {
+    editor: Weak<Editor>,
+    /// Start of the word being completed; the word grows after it as typing goes on
+    start: TextMark,
+    words: Vec<CompletionItem>,
+    /// The server's items, then the words it didn't offer
+    items: Vec<CompletionItem>,
+    /// Listed items, best first
+    matches: Vec<CompletionMatch>,
+    /// The server's list wasn't complete, so it's asked again as typing goes on
+    incomplete: bool,
+}
+
+/// Tab stops of an inserted snippet, as start and end marks.
+struct SnippetStops {
+    editor: Weak<Editor>,
+    stops: Vec<(TextMark, TextMark)>,
+    current: usize,
+}
+
+impl CompletionPopup {
+    pub fn new(editors: Rc<RefCell<Vec<Rc<Editor>>>>) -> Rc<Self> {
+        let popover = Popover::new();
+        // Typing goes on in the view while it's open
+        popover.set_autohide(false);
+        popover.set_has_arrow(false);
+        popover.set_can_focus(false);
+        popover.set_position(PositionType::Bottom);
+        popover.style_context().add_class("completion");
+
+        let list = ListBox::new();
+        list.set_selection_mode(gtk4::SelectionMode::Browse);
+        list.set_can_focus(false);
+        let scrolled = ScrolledWindow::builder()
+            .child(&list)
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .propagate_natural_height(true)
+            .max_content_height(LIST_HEIGHT)
+            .min_content_width(320)
+            .build();
+
+        let info_label = Label::new(None);
+        info_label.set_xalign(0.0);
+        info_label.set_valign(gtk4::Align::Start);
+        info_label.set_wrap(true);
+        info_label.set_max_width_chars(60);
+        info_label.set_lines(14);
+        info_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        info_label.style_context().add_class("completion-info");
+
+        let content = GtkBox::new(Orientation::Horizontal, 8);
+        content.append(&scrolled);
+        content.append(&info_label);
+        popover.set_child(Some(&content));
+
+        let popup = Rc::new(Self {
+            popover,
+            list,
+            scrolled,
+            info_label,
+            editors,
+            matcher: SkimMatcherV2::default(),
+            session: RefCell::new(None),
+            generation: Cell::new(0),
+            snippet: RefCell::new(None),
+        });
+
+        {
+            let popup_weak = Rc::downgrade(&popup);
+            popup.list.connect_row_selected(move |_, row| {
+                if let (Some(popup), Some(row)) = (popup_weak.upgrade(), row) {
+                    popup.show_info(row.index());
+                }
+            });
+        }
+        {
+            let popup_weak = Rc::downgrade(&popup);
+            popup.list.connect_row_activated(move |_, row| {
+                if let Some(popup) = popup_weak.upgrade() {
+                    popup.accept(row.index());
+                }
+            });
+        }
+        popup
+    }
+
+    /// Watch `editor`'s typing, keys and focus.
+    pub fn attach(self: &Rc<Self>, editor: &Rc<Editor>) {
+        // Before the view and its own key handling, which would indent on Tab
+        let key_controller = EventControllerKey::new();
+        key_controller.set_propagation_phase(PropagationPhase::Capture);
+        let popup_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        key_controller.connect_key_pressed(move |_, keyval, _keycode, modifier| {
+            match (popup_weak.upgrade(), editor_weak.upgrade()) {
+                (Some(popup), Some(editor)) => popup.key_pressed(&editor, keyval, modifier),
+                _ => Inhibit(false),
+            }
+        });
+        editor.main_view.add_controller(key_controller);
+
+        // Clicking elsewhere moves the cursor out of the word
+        let popup_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        editor.main_buffer.connect_mark_set(move |buffer, _iter, mark| {
+            let (Some(popup), Some(editor)) = (popup_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if *mark == buffer.get_insert() && popup.is_active_in(&editor) && popup.typed_word(&editor).is_none() {
+                popup.hide();
+            }
+        });
+
+        let focus = EventControllerFocus::new();
+        let popup_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        focus.connect_leave(move |_| {
+            let (Some(popup), Some(editor)) = (popup_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if popup.is_active_in(&editor) {
+                popup.hide();
+            }
+        });
+        editor.main_view.add_controller(focus);
+    }
+
+    /// Complete the word at `editor`'s cursor: list the matching buffer words at once, and
+    /// the server's items when they arrive. `trigger` is the character that opened it, if
+    /// it wasn't asked for.
+    pub fn trigger(self: &Rc<Self>, editor: &Rc<Editor>, trigger: Option<char>) {
+        self.hide();
+        let buffer = &editor.main_buffer;
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let start = word_start(&cursor);
+        let typed = buffer.text(&start, &cursor, false);
+
+        let mut documents: Vec<Rc<Editor>> = Vec::new();
+        for view in self.editors.borrow().iter() {
+            if !*view.large_file.borrow() && !documents.iter().any(|d| d.shares_document(view)) {
+                documents.push(view.clone());
+            }
+        }
+        let texts: Vec<_> = documents.iter().map(|document| document.rope()).collect();
+        let words: Vec<CompletionItem> =
+            completion::buffer_words(&texts, &typed).iter().map(|word| CompletionItem::word(word)).collect();
+
+        *self.session.borrow_mut() = Some(Session {
+            editor: Rc::downgrade(editor),
+            start: buffer.create_mark(None, &start, true),
+            items: words.clone(),
+            words,
+            matches: Vec::new(),
+            incomplete: false,
+        });
+        self.refilter();
+        self.request(editor, trigger);
+    }
+
+    /// Close the popup and forget the completion.
+    pub fn hide(&self) {
+        self.generation.set(self.generation.get() + 1);
+        if let Some(session) = self.session.borrow_mut().take() {
+            if let Some(editor) = session.editor.upgrade() {
+                editor.main_buffer.delete_mark(&session.start);
+            }
+        }
+        self.popover.popdown();
+        if self.popover.parent().is_some() {
+            self.popover.unparent();
+        }
+    }
+
+    fn key_pressed(self: &Rc<Self>, editor: &Rc<Editor>, keyval: gdk::Key, modifier: gdk::ModifierType) -> Inhibit {
+        let plain = !modifier.intersects(gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::ALT_MASK);
+        let shift = modifier.contains(gdk::ModifierType::SHIFT_MASK);
+        if self.is_active_in(editor) && self.popover.is_visible() {
+            match keyval {
+                gdk::Key::Down | gdk::Key::KP_Down if plain => return self.move_selection(1),
+                gdk::Key::Up | gdk::Key::KP_Up if plain => return self.move_selection(-1),
+                gdk::Key::Page_Down if plain => return self.move_selection(PAGE_ROWS),
+                gdk::Key::Page_Up if plain => return self.move_selection(-PAGE_ROWS),
+                gdk::Key::Return | gdk::Key::KP_Enter | gdk::Key::Tab if plain && !shift => {
+                    if let Some(row) = self.list.selected_row() {
+                        self.accept(row.index());
+                    }
+                    return Inhibit(true);
+                }
+                gdk::Key::Escape => {
+                    self.hide();
+                    return Inhibit(true);
+                }
+                _ => {}
+            }
+        } else if self.in_snippet(editor) {
+            match keyval {
+                gdk::Key::Tab if plain && !shift => return self.next_stop(1),
+                gdk::Key::ISO_Left_Tab | gdk::Key::Tab if plain => return self.next_stop(-1),
+                gdk::Key::Escape => self.end_snippet(),
+                _ => {}
+            }
+        }
+
+        // Let the key do its work, then look at what it typed
+        let typed = keyval.to_unicode().filter(|c| !c.is_control());
+        if plain && (typed.is_some() || keyval == gdk::Key::BackSpace) {
+            let popup_weak = Rc::downgrade(self);
+            let editor_weak = Rc::downgrade(editor);
+            glib::idle_add_local_once(move || {
+                if let (Some(popup), Some(editor)) = (popup_weak.upgrade(), editor_weak.upgrade()) {
+                    popup.after_typing(&editor, typed);
+                }
+            });
+        }
+        Inhibit(false)
+    }
+
+    /// Follow the word as it's typed, or start completing after a trigger character.
+    fn after_typing(self: &Rc<Self>, editor: &Rc<Editor>, typed: Option<char>) {
+        let is_trigger = typed.is_some_and(|c| trigger_characters(editor).contains(&c.to_string()));
+        if self.is_active_in(editor) && self.typed_word(editor).is_some() {
+            let incomplete = self.session.borrow().as_ref().is_some_and(|session| session.incomplete);
+            self.refilter();
+            if incomplete {
+                self.request(editor, None);
+            }
+        } else if is_trigger {
+            self.trigger(editor, typed);
+        } else if self.is_active_in(editor) {
+            self.hide();
+        }
+    }
+
+    /// Ask `editor`'s language server for its items, if it completes.
+    fn request(self: &Rc<Self>, editor: &Rc<Editor>, trigger: Option<char>) {
+        let Some(lsp) = editor.language_server() else {
+            return;
+        };
+        if !lsp.client.capabilities()["completionProvider"].is_object() {
+            return;
+        }
+        let Some(text) = lsp.client.document_text(&lsp.uri) else {
+            return;
+        };
+        let incomplete = self.session.borrow().as_ref().is_some_and(|session| session.incomplete);
+        let context = match trigger {
+            Some(c) => json!({ "triggerKind": 2, "triggerCharacter": c.to_string() }),
+            None if incomplete => json!({ "triggerKind": 3 }),
+            None => json!({ "triggerKind": 1 }),
+        };
+        let buffer = &editor.main_buffer;
+        let offset = buffer.iter_at_mark(&buffer.get_insert()).offset() as usize;
+        let params = json!({
+            "textDocument": { "uri": lsp.uri },
+            "position": protocol::position_at(&text, offset, lsp.client.encoding()),
+            "context": context,
+        });
+
+        self.generation.set(self.generation.get() + 1);
+        let generation = self.generation.get();
+        let popup_weak = Rc::downgrade(self);
+        let client = Rc::downgrade(&lsp.client);
+        let uri = lsp.uri.clone();
+        lsp.client.request("textDocument/completion", params, move |result| {
+            let (Some(popup), Some(client), Ok(result)) = (popup_weak.upgrade(), client.upgrade(), result) else {
+                return;
+            };
+            if popup.generation.get() != generation {
+                return;
+            }
+            // Edit positions are in the text the server was asked about
+            let text = client.document_text(&uri);
+            let (items, incomplete) = completion::from_lsp_response(&result, text.as_ref(), client.encoding());
+            popup.set_server_items(items, incomplete);
+        });
+    }
+
+    fn set_server_items(&self, items: Vec<CompletionItem>, incomplete: bool) {
+        {
+            let mut session = self.session.borrow_mut();
+            let Some(session) = session.as_mut() else {
+                return;
+            };
+            let offered: HashSet<&str> = items.iter().map(|item| item.insert_text.as_str()).collect();
+            let words = session.words.iter().filter(|word| !offered.contains(word.label.as_str())).cloned();
+            session.items = items.iter().cloned().chain(words).collect();
+            session.incomplete = incomplete;
+        }
+        self.refilter();
+    }
+
+    /// List the items matching the word typed so far, and show or hide the popup.
+    fn refilter(&self) {
+        let editor = self.session.borrow().as_ref().and_then(|session| session.editor.upgrade());
+        let Some((editor, typed)) = editor.and_then(|editor| Some((editor.clone(), self.typed_word(&editor)?))) else {
+            self.hide();
+            return;
+        };
+
+        // Rows are built first: changing the list reports the selection, which reads the session
+        let (rows, pointless, start) = {
+            let mut session = self.session.borrow_mut();
+            let Some(session) = session.as_mut() else {
+                return;
+            };
+            session.matches = completion::filter(&self.matcher, &session.items, &typed);
+            let rows: Vec<GtkBox> = session.matches.iter().map(|m| item_row(&session.items[m.index], &m.indices)).collect();
+            // Nothing to offer but the word itself: wait for more typing, or for the server
+            let pointless = match session.matches.as_slice() {
+                [] => true,
+                [only] => session.items[only.index].insert_text == typed,
+                _ => false,
+            };
+            (rows, pointless, editor.main_buffer.iter_at_mark(&session.start))
+        };
+
+        while let Some(row) = self.list.first_child() {
+            self.list.remove(&row);
+        }
+        for row in rows {
+            self.list.append(&row);
+            // Rows are picked with the keyboard; focusing one would take typing from the view
+            if let Some(list_row) = row.parent() {
+                list_row.set_focusable(false);
+            }
+        }
+        if pointless {
+            self.popover.popdown();
+            return;
+        }
+        if let Some(row) = self.list.row_at_index(0) {
+            self.list.select_row(Some(&row));
+        }
+        self.scrolled.vadjustment().set_value(0.0);
+        self.show_at(&editor, &start);
+    }
+
+    fn show_at(&self, editor: &Editor, start: &TextIter) {
+        let view = &editor.main_view;
+        if self.popover.parent().as_ref() != Some(view.upcast_ref::<gtk4::Widget>()) {
+            if self.popover.parent().is_some() {
+                self.popover.unparent();
+            }
+            self.popover.set_parent(view);
+        }
+        let location = view.iter_location(start);
+        let (x, y) = view.buffer_to_window_coords(TextWindowType::Widget, location.x(), location.y());
+        self.popover.set_pointing_to(Some(&gdk::Rectangle::new(x, y, 1, location.height())));
+        self.popover.popup();
+    }
+
+    fn show_info(&self, row: i32) {
+        let session = self.session.borrow();
+        let item = session.as_ref().and_then(|session| {
+            let m = session.matches.get(usize::try_from(row).ok()?)?;
+            session.items.get(m.index)
+        });
+        let info: Vec<&str> = item
+            .map(|item| item.detail.iter().chain(item.documentation.iter()).map(String::as_str).collect())
+            .unwrap_or_default();
+        self.info_label.set_text(&info.join("\n\n"));
+        self.info_label.set_visible(!info.is_empty());
+    }
+
+    fn move_selection(&self, step: i32) -> Inhibit {
+        let count = self.session.borrow().as_ref().map_or(0, |session| session.matches.len()) as i32;
+        if count == 0 {
+            return Inhibit(true);
+        }
+        let current = self.list.selected_row().map(|row| row.index()).unwrap_or(0);
+        // Single steps wrap around; pages stop at the ends
+        let next = if step.abs() == 1 { (current + step).rem_euclid(count) } else { (current + step).clamp(0, count - 1) };
+        if let Some(row) = self.list.row_at_index(next) {
+            self.list.select_row(Some(&row));
+            // Scroll the row into view without focusing it
+            if let Some(bounds) = row.compute_bounds(&self.list) {
+                let adjustment = self.scrolled.vadjustment();
+                let (top, bottom) = (bounds.y() as f64, (bounds.y() + bounds.height()) as f64);
+                if top < adjustment.value() {
+                    adjustment.set_value(top);
+                } else if bottom > adjustment.value() + adjustment.page_size() {
+                    adjustment.set_value(bottom - adjustment.page_size());
+                }
+            }
+        }
+        Inhibit(true)
+    }
+
+    /// Insert the item listed at `row` in place of the typed word, along with its other
+    /// edits, as one undo step.
+    fn accept(&self, row: i32) {
+        let chosen = self.session.borrow().as_ref().and_then(|session| {
+            let m = session.matches.get(usize::try_from(row).ok()?)?;
+            let editor = session.editor.upgrade()?;
+            let start = editor.main_buffer.iter_at_mark(&session.start).offset();
+            Some((editor, start, session.items[m.index].clone()))
+        });
+        self.hide();
+        let Some((editor, word_start, item)) = chosen else {
+            return;
+        };
+        self.end_snippet();
+
+        let buffer = &editor.main_buffer;
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        // The server's range may start before the word, never after the cursor
+        let start = item.replace_start.map(|start| start as i32).filter(|&start| start <= cursor.offset()).unwrap_or(word_start);
+        let line_start = buffer.iter_at_line(cursor.line()).unwrap_or_else(|| buffer.start_iter());
+        let line = buffer.text(&line_start, &cursor, false);
+        let text =
+            completion::adjust_indentation(&item.insert_text, crate::indent::leading_whitespace(&line), &editor.indent.get().unit());
+        let (text, stops) = if item.is_snippet {
+            let snippet = completion::parse_snippet(&text);
+            (snippet.text, snippet.tab_stops)
+        } else {
+            let end = text.chars().count();
+            (text, vec![(end, end)])
+        };
+
+        buffer.begin_user_action();
+        let to_marks = |start: i32, end: i32| {
+            (
+                buffer.create_mark(None, &buffer.iter_at_offset(start), true),
+                buffer.create_mark(None, &buffer.iter_at_offset(end), false),
+            )
+        };
+        let replaced = to_marks(start, cursor.offset());
+        // Marks keep every range in place while the others are edited
+        let others: Vec<_> = item
+            .additional_edits
+            .iter()
+            .map(|edit| (to_marks(edit.start as i32, edit.end as i32), edit.text.as_str()))
+            .collect();
+        for ((start, end), text) in others.iter().map(|(marks, text)| (marks, *text)).chain([(&replaced, text.as_str())]) {
+            let mut from = buffer.iter_at_mark(start);
+            buffer.delete(&mut from, &mut buffer.iter_at_mark(end));
+            buffer.insert(&mut from, text);
+        }
+        let base = buffer.iter_at_mark(&replaced.0).offset();
+        for (start, end) in others.iter().map(|(marks, _)| marks).chain([&replaced]) {
+            buffer.delete_mark(start);
+            buffer.delete_mark(end);
+        }
+        let stops: Vec<(TextMark, TextMark)> =
+            stops.iter().map(|&(start, end)| to_marks(base + start as i32, base + end as i32)).collect();
+        buffer.end_user_action();
+
+        *self.snippet.borrow_mut() = Some(SnippetStops { editor: Rc::downgrade(&editor), stops, current: 0 });
+        self.select_stop(0);
+        editor.main_view.scroll_mark_onscreen(&buffer.get_insert());
+    }
+
+    /// Whether a snippet's tab stops are being filled in `editor`, with the cursor among them.
+    fn in_snippet(&self, editor: &Rc<Editor>) -> bool {
+        let snippet = self.snippet.borrow();
+        let Some(snippet) = snippet.as_ref().filter(|s| s.editor.upgrade().is_some_and(|e| Rc::ptr_eq(&e, editor))) else {
+            return false;
+        };
+        let buffer = &editor.main_buffer;
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let first = snippet.stops.iter().map(|(start, _)| buffer.iter_at_mark(start)).min();
+        let last = snippet.stops.iter().map(|(_, end)| buffer.iter_at_mark(end)).max();
+        matches!((first, last), (Some(first), Some(last)) if first <= cursor && cursor <= last)
+    }
+
+    fn next_stop(&self, step: i32) -> Inhibit {
+        let next = self.snippet.borrow().as_ref().map(|snippet| (snippet.current as i32 + step).max(0) as usize);
+        if let Some(next) = next {
+            self.select_stop(next);
+        }
+        Inhibit(true)
+    }
+
+    /// Select tab stop `index`; reaching the last one, where the cursor is left, ends the
+    /// snippet.
+    fn select_stop(&self, index: usize) {
+        let last = {
+            let mut snippet = self.snippet.borrow_mut();
+            let Some(snippet) = snippet.as_mut() else {
+                return;
+            };
+            let Some(editor) = snippet.editor.upgrade() else {
+                return;
+            };
+            let index = index.min(snippet.stops.len() - 1);
+            let buffer = &editor.main_buffer;
+            let (start, end) = &snippet.stops[index];
+            buffer.select_range(&buffer.iter_at_mark(end), &buffer.iter_at_mark(start));
+            snippet.current = index;
+            index == snippet.stops.len() - 1
+        };
+        if last {
+            self.end_snippet();
+        }
+    }
+
+    fn end_snippet(&self) {
+        if let Some(snippet) = self.snippet.borrow_mut().take() {
+            if let Some(editor) = snippet.editor.upgrade() {
+                for (start, end) in &snippet.stops {
+                    editor.main_buffer.delete_mark(start);
+                    editor.main_buffer.delete_mark(end);
+                }
+            }
+        }
+    }
+
+    fn is_active_in(&self, editor: &Rc<Editor>) -> bool {
+        self.session.borrow().as_ref().and_then(|session| session.editor.upgrade()).is_some_and(|e| Rc::ptr_eq(&e, editor))
+    }
+
+    /// The word typed since the completion started, or `None` once the cursor left it.
+    fn typed_word(&self, editor: &Editor) -> Option<String> {
+        let session = self.session.borrow();
+        let buffer = &editor.main_buffer;
+        let start = buffer.iter_at_mark(&session.as_ref()?.start);
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        if cursor < start || cursor.line() != start.line() {
+            return None;
+        }
+        let typed = buffer.text(&start, &cursor, false);
+        typed.chars().all(completion::is_word_char).then(|| typed.to_string())
+    }
+}
+
+/// Start of the word ending at `cursor`.
+fn word_start(cursor: &TextIter) -> TextIter {
+    let mut start = *cursor;
+    loop {
+        let mut before = start;
+        if !before.backward_char() || !completion::is_word_char(before.char()) {
+            return start;
+        }
+        start = before;
+    }
+}
+
+/// Characters after which `editor`'s language server offers completions.
+fn trigger_characters(editor: &Editor) -> Vec<String> {
+    let Some(lsp) = editor.language_server() else {
+        return Vec::new();
+    };
+    let capabilities = lsp.client.capabilities();
+    let characters = capabilities["completionProvider"]["triggerCharacters"].as_array().cloned().unwrap_or_default();
+    characters.iter().filter_map(|c| c.as_str().map(str::to_string)).collect()
+}
+
+/// A row: the kind's badge, the label with the matched characters in bold, and the detail.
+fn item_row(item: &CompletionItem, indices: &[usize]) -> GtkBox {
+    let row = GtkBox::new(Orientation::Horizontal, 6);
+    let badge = Label::new(Some(item.kind.badge()));
+    badge.set_width_chars(4);
+    badge.style_context().add_class("completion-kind");
+    badge.style_context().add_class(item.kind.category());
+    let label = Label::new(None);
+    label.set_markup(&highlight_markup(&item.label, indices));
+    label.set_xalign(0.0);
+    label.set_hexpand(true);
+    label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    row.append(&badge);
+    row.append(&label);
+    if let Some(detail) = &item.detail {
+        let detail_label = Label::new(Some(detail.lines().next().unwrap_or_default()));
+        detail_label.set_max_width_chars(30);
+        detail_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        detail_label.style_context().add_class("dim-label");
+        row.append(&detail_label);
+    }
+    row
+}
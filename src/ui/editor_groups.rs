@@ -0,0 +1,151 @@
+use gtk4::prelude::*;
+use gtk4::{Box as GtkBox, Notebook, Orientation, Paned, Widget};
+use std::cell::RefCell;
+
+/// The editor area: one group of tabs, or several split side by side or above each other.
+///
+/// Each group is a `Notebook`; splitting one puts it and a new group into a `Paned`, so
+/// splits can nest. Tabs can be dragged between groups.
+pub struct EditorGroups {
+    pub widget: GtkBox,
+    /// The group new tabs open in: the one last focused
+    active: RefCell<Notebook>,
+}
+
+impl EditorGroups {
+    pub fn new() -> Self {
+        let widget = GtkBox::new(Orientation::Vertical, 0);
+        widget.set_hexpand(true);
+        widget.set_vexpand(true);
+
+        let notebook = new_group();
+        widget.append(&notebook);
+
+        Self { widget, active: RefCell::new(notebook) }
+    }
+
+    /// Every group, left to right and top to bottom.
+    pub fn notebooks(&self) -> Vec<Notebook> {
+        let mut notebooks = Vec::new();
+        if let Some(child) = self.widget.first_child() {
+            collect_notebooks(&child, &mut notebooks);
+        }
+        notebooks
+    }
+
+    pub fn active(&self) -> Notebook {
+        self.active.borrow().clone()
+    }
+
+    pub fn set_active(&self, notebook: &Notebook) {
+        *self.active.borrow_mut() = notebook.clone();
+    }
+
+    /// The group holding the tab `page`.
+    pub fn notebook_containing(&self, page: &impl IsA<Widget>) -> Option<Notebook> {
+        self.notebooks().into_iter().find(|notebook| notebook.page_num(page).is_some())
+    }
+
+    /// Split the active group, adding an empty group to its right (`Horizontal`) or below it
+    /// (`Vertical`). The new group becomes the active one.
+    pub fn split(&self, orientation: Orientation) -> Notebook {
+        let active = self.active();
+        let notebook = new_group();
+
+        let paned = Paned::new(orientation);
+        paned.set_hexpand(true);
+        paned.set_vexpand(true);
+        paned.set_shrink_start_child(false);
+        paned.set_shrink_end_child(false);
+
+        self.replace(active.upcast_ref(), paned.upcast_ref());
+        paned.set_start_child(Some(&active));
+        paned.set_end_child(Some(&notebook));
+
+        // Split evenly once the new pane has a size
+        let paned_clone = paned.clone();
+        gtk4::glib::idle_add_local_once(move || {
+            let size = match paned_clone.orientation() {
+                Orientation::Horizontal => paned_clone.width(),
+                _ => paned_clone.height(),
+            };
+            paned_clone.set_position(size / 2);
+        });
+
+        self.set_active(&notebook);
+        notebook
+    }
+
+    /// Remove `notebook` if it has no tabs left and isn't the only group; its neighbour
+    /// takes over its space. Returns whether it was removed.
+    pub fn remove_if_empty(&self, notebook: &Notebook) -> bool {
+        if notebook.n_pages() > 0 {
+            return false;
+        }
+        let Some(paned) = notebook.parent().and_then(|parent| parent.downcast::<Paned>().ok()) else {
+            return false;
+        };
+        let sibling = if paned.start_child().as_ref() == Some(notebook.upcast_ref()) {
+            paned.end_child()
+        } else {
+            paned.start_child()
+        };
+        let Some(sibling) = sibling else {
+            return false;
+        };
+
+        paned.set_start_child(None::<&Widget>);
+        paned.set_end_child(None::<&Widget>);
+        self.replace(paned.upcast_ref(), &sibling);
+
+        if self.active() == *notebook {
+            let mut remaining = Vec::new();
+            collect_notebooks(&sibling, &mut remaining);
+            if let Some(first) = remaining.first() {
+                self.set_active(first);
+            }
+        }
+        true
+    }
+
+    /// Put `new` where `old` is, in its `Paned` or at the root.
+    fn replace(&self, old: &Widget, new: &Widget) {
+        match old.parent().and_then(|parent| parent.downcast::<Paned>().ok()) {
+            Some(paned) if paned.start_child().as_ref() == Some(old) => {
+                paned.set_start_child(None::<&Widget>);
+                paned.set_start_child(Some(new));
+            }
+            Some(paned) => {
+                paned.set_end_child(None::<&Widget>);
+                paned.set_end_child(Some(new));
+            }
+            None => {
+                self.widget.remove(old);
+                self.widget.append(new);
+            }
+        }
+    }
+}
+
+fn new_group() -> Notebook {
+    let notebook = Notebook::new();
+    notebook.set_scrollable(true);
+    notebook.set_vexpand(true);
+    notebook.set_hexpand(true);
+    // Set minimum height to prevent negative tab height calculations during window resize
+    // This prevents GTK warning about negative min-height and window minimize issues
+    notebook.set_size_request(-1, 100);
+    // Tabs can be dragged between notebooks of the same group name
+    notebook.set_group_name(Some("editors"));
+    notebook
+}
+
+fn collect_notebooks(widget: &Widget, notebooks: &mut Vec<Notebook>) {
+    if let Some(notebook) = widget.downcast_ref::<Notebook>() {
+        notebooks.push(notebook.clone());
+    } else if let Some(paned) = widget.downcast_ref::<Paned>() {
+        for child in [paned.start_child(), paned.end_child()].into_iter().flatten() {
+            collect_notebooks(&child, notebooks);
+        }
+    }
+}
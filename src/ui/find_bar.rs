@@ -0,0 +1,403 @@
+use gtk4::prelude::*;
+use gtk4::{gdk, Box as GtkBox, Button, Entry, EventControllerKey, Inhibit, Label, Orientation, TextMark, ToggleButton};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::editor::Editor;
+use crate::search::{self, Search, SearchMatch, SearchOptions};
+
+// Highlighting more matches than this slows typing down for little use; they still count
+const MAX_HIGHLIGHTED_MATCHES: usize = 10_000;
+
+/// Which match a search goes to.
+#[derive(Clone, Copy)]
+enum Step {
+    /// The first match from the start of the selection, so refining the query as you type
+    /// stays on the same match
+    Incremental,
+    Next,
+    Previous,
+}
+
+/// What one of the bar's buttons does.
+type FindBarAction = fn(&FindBar);
+
+/// Inline find and replace bar above the editor area. Searches the current tab as you type
+/// and highlights every match.
+pub struct FindBar {
+    pub widget: GtkBox,
+    find_entry: Entry,
+    replace_entry: Entry,
+    replace_row: GtkBox,
+    case_button: ToggleButton,
+    word_button: ToggleButton,
+    regex_button: ToggleButton,
+    preserve_case_button: ToggleButton,
+    in_selection_button: ToggleButton,
+    count_label: Label,
+    current_editor: Rc<RefCell<Option<Rc<Editor>>>>,
+    /// Editor whose matches are highlighted, to clear them once the search moves on
+    highlighted: RefCell<Weak<Editor>>,
+    /// Selection that searching and Replace All are limited to while "in selection" is on
+    scope: RefCell<Option<(Weak<Editor>, TextMark, TextMark)>>,
+}
+
+impl FindBar {
+    pub fn new(current_editor: Rc<RefCell<Option<Rc<Editor>>>>) -> Rc<Self> {
+        let widget = GtkBox::new(Orientation::Vertical, 4);
+        widget.style_context().add_class("find-bar");
+        widget.set_visible(false);
+
+        let find_row = GtkBox::new(Orientation::Horizontal, 4);
+        let find_entry = Entry::new();
+        find_entry.set_placeholder_text(Some("Find"));
+        find_entry.set_hexpand(true);
+        let count_label = Label::new(None);
+        count_label.set_width_chars(12);
+        count_label.style_context().add_class("dim-label");
+        let case_button = toggle("Aa", "Match Case");
+        let word_button = toggle("W", "Match Whole Word");
+        let regex_button = toggle(".*", "Use Regular Expression");
+        let previous_button = icon_button("go-up-symbolic", "Previous Match (Shift+Enter)");
+        let next_button = icon_button("go-down-symbolic", "Next Match (Enter)");
+        let in_selection_button = toggle("☰", "Find in Selection");
+        let close_button = icon_button("window-close-symbolic", "Close (Escape)");
+        find_row.append(&find_entry);
+        find_row.append(&count_label);
+        find_row.append(&case_button);
+        find_row.append(&word_button);
+        find_row.append(&regex_button);
+        find_row.append(&previous_button);
+        find_row.append(&next_button);
+        find_row.append(&in_selection_button);
+        find_row.append(&close_button);
+
+        let replace_row = GtkBox::new(Orientation::Horizontal, 4);
+        let replace_entry = Entry::new();
+        replace_entry.set_placeholder_text(Some("Replace ($1 for regex groups)"));
+        replace_entry.set_hexpand(true);
+        let preserve_case_button = toggle("AB", "Preserve Case");
+        let replace_button = Button::with_label("Replace");
+        let replace_all_button = Button::with_label("Replace All");
+        replace_row.append(&replace_entry);
+        replace_row.append(&preserve_case_button);
+        replace_row.append(&replace_button);
+        replace_row.append(&replace_all_button);
+
+        widget.append(&find_row);
+        widget.append(&replace_row);
+
+        let find_bar = Rc::new(Self {
+            widget,
+            find_entry,
+            replace_entry,
+            replace_row,
+            case_button,
+            word_button,
+            regex_button,
+            preserve_case_button,
+            in_selection_button,
+            count_label,
+            current_editor,
+            highlighted: RefCell::new(Weak::new()),
+            scope: RefCell::new(None),
+        });
+
+        // Search as you type, and again whenever an option changes
+        {
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            find_bar.find_entry.connect_changed(move |_| {
+                if let Some(find_bar) = find_bar_weak.upgrade() {
+                    find_bar.run(Some(Step::Incremental));
+                }
+            });
+        }
+        for button in [&find_bar.case_button, &find_bar.word_button, &find_bar.regex_button] {
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            button.connect_toggled(move |_| {
+                if let Some(find_bar) = find_bar_weak.upgrade() {
+                    find_bar.run(Some(Step::Incremental));
+                }
+            });
+        }
+        {
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            find_bar.in_selection_button.connect_toggled(move |button| {
+                if let Some(find_bar) = find_bar_weak.upgrade() {
+                    find_bar.set_scope(button.is_active());
+                }
+            });
+        }
+
+        // Escape anywhere in the bar closes it
+        {
+            let key_controller = EventControllerKey::new();
+            key_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            key_controller.connect_key_pressed(move |_, keyval, _keycode, _modifier| {
+                match (keyval, find_bar_weak.upgrade()) {
+                    (gdk::Key::Escape, Some(find_bar)) => {
+                        find_bar.hide();
+                        Inhibit(true)
+                    }
+                    _ => Inhibit(false),
+                }
+            });
+            find_bar.widget.add_controller(key_controller);
+        }
+
+        // Enter in the find field goes to the next match, Shift+Enter to the previous one
+        {
+            let key_controller = EventControllerKey::new();
+            key_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            key_controller.connect_key_pressed(move |_, keyval, _keycode, modifier| {
+                match (keyval, find_bar_weak.upgrade()) {
+                    (gdk::Key::Return | gdk::Key::KP_Enter, Some(find_bar)) => {
+                        let backwards = modifier.contains(gdk::ModifierType::SHIFT_MASK);
+                        find_bar.run(Some(if backwards { Step::Previous } else { Step::Next }));
+                        Inhibit(true)
+                    }
+                    _ => Inhibit(false),
+                }
+            });
+            find_bar.find_entry.add_controller(key_controller);
+        }
+
+        {
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            find_bar.replace_entry.connect_activate(move |_| {
+                if let Some(find_bar) = find_bar_weak.upgrade() {
+                    find_bar.replace();
+                }
+            });
+        }
+
+        let buttons: [(&Button, FindBarAction); 5] = [
+            (&previous_button, |find_bar| find_bar.run(Some(Step::Previous))),
+            (&next_button, |find_bar| find_bar.run(Some(Step::Next))),
+            (&close_button, FindBar::hide),
+            (&replace_button, FindBar::replace),
+            (&replace_all_button, FindBar::replace_all),
+        ];
+        for (button, action) in buttons {
+            let find_bar_weak = Rc::downgrade(&find_bar);
+            button.connect_clicked(move |_| {
+                if let Some(find_bar) = find_bar_weak.upgrade() {
+                    action(&find_bar);
+                }
+            });
+        }
+
+        find_bar
+    }
+
+    /// Open the bar, with the replace row if `replace`, searching for the selected text.
+    /// A selection over several lines is searched in instead.
+    pub fn show(&self, replace: bool) {
+        let selected = self.current_editor.borrow().as_ref().and_then(|editor| {
+            let buffer = &editor.main_buffer;
+            buffer.selection_bounds().map(|(start, end)| buffer.text(&start, &end, false).to_string())
+        });
+        match selected {
+            Some(text) if text.contains('\n') => self.in_selection_button.set_active(true),
+            Some(text) => self.find_entry.set_text(&text),
+            None => {}
+        }
+
+        self.replace_row.set_visible(replace);
+        self.widget.set_visible(true);
+        self.find_entry.grab_focus();
+        self.run(None);
+    }
+
+    /// Close the bar, clear the highlights and go back to the editor.
+    pub fn hide(&self) {
+        self.in_selection_button.set_active(false);
+        if let Some(editor) = self.highlighted.take().upgrade() {
+            editor.highlight_matches(&[]);
+        }
+        self.widget.set_visible(false);
+        if let Some(editor) = self.current_editor.borrow().as_ref() {
+            editor.main_view.grab_focus();
+        }
+    }
+
+    /// Find Next (F3) or Find Previous (Shift+F3); opens the bar if there's nothing to find.
+    pub fn find_next(&self, backwards: bool) {
+        if self.find_entry.text().is_empty() {
+            self.show(false);
+            return;
+        }
+        self.run(Some(if backwards { Step::Previous } else { Step::Next }));
+    }
+
+    /// Search the tab that became current, if the bar is open.
+    pub fn follow_editor(&self) {
+        if self.widget.is_visible() {
+            self.run(None);
+        }
+    }
+
+    fn options(&self) -> SearchOptions {
+        SearchOptions {
+            case_sensitive: self.case_button.is_active(),
+            whole_word: self.word_button.is_active(),
+            regex: self.regex_button.is_active(),
+            preserve_case: self.preserve_case_button.is_active(),
+        }
+    }
+
+    /// The search for the current query, or `None` if it's empty or an invalid regex (which
+    /// is shown in the bar).
+    fn compile(&self) -> Option<Search> {
+        let query = self.find_entry.text();
+        self.find_entry.style_context().remove_class("error");
+        self.count_label.set_tooltip_text(None);
+        if query.is_empty() {
+            self.count_label.set_text("");
+            return None;
+        }
+        match Search::new(&query, self.options()) {
+            Ok(search) => Some(search),
+            Err(e) => {
+                self.find_entry.style_context().add_class("error");
+                self.count_label.set_text("Invalid regex");
+                self.count_label.set_tooltip_text(Some(&e.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Matches in `editor`, limited to the "in selection" scope if it's on for that editor.
+    fn matches_in(&self, editor: &Rc<Editor>, search: &Search) -> Vec<SearchMatch> {
+        let mut matches = search.find_all(&editor.get_text());
+        if let Some((scope_editor, start, end)) = self.scope.borrow().as_ref() {
+            if scope_editor.as_ptr() == Rc::as_ptr(editor) {
+                let buffer = &editor.main_buffer;
+                let start = buffer.iter_at_mark(start).offset() as usize;
+                let end = buffer.iter_at_mark(end).offset() as usize;
+                matches.retain(|m| m.start >= start && m.end <= end);
+            }
+        }
+        matches
+    }
+
+    /// Search the current tab, highlight the matches and select the one `step` picks;
+    /// `None` leaves the selection alone.
+    fn run(&self, step: Option<Step>) {
+        let Some(editor) = self.current_editor.borrow().clone() else {
+            return;
+        };
+        let previous = self.highlighted.replace(Rc::downgrade(&editor)).upgrade();
+        if let Some(previous) = previous.filter(|previous| !Rc::ptr_eq(previous, &editor)) {
+            previous.highlight_matches(&[]);
+        }
+
+        let Some(search) = self.compile() else {
+            editor.highlight_matches(&[]);
+            return;
+        };
+        let matches = self.matches_in(&editor, &search);
+        editor.highlight_matches(&matches[..matches.len().min(MAX_HIGHLIGHTED_MATCHES)]);
+
+        if let Some(step) = step {
+            let (start, end) = editor.selection_offsets();
+            let (from, backwards) = match step {
+                Step::Incremental => (start, false),
+                Step::Next => (end, false),
+                Step::Previous => (start, true),
+            };
+            if let Some(index) = search::next_match(&matches, from, backwards) {
+                editor.select_match(matches[index]);
+            }
+        }
+        self.show_count(&editor, &matches);
+    }
+
+    /// "N of M" when a match is selected, otherwise how many there are.
+    fn show_count(&self, editor: &Editor, matches: &[SearchMatch]) {
+        let (start, end) = editor.selection_offsets();
+        let selected = matches.iter().position(|m| m.start == start && m.end == end);
+        let text = match (selected, matches.len()) {
+            (_, 0) => "No results".to_string(),
+            (Some(index), count) => format!("{} of {}", index + 1, count),
+            (None, 1) => "1 match".to_string(),
+            (None, count) => format!("{} matches", count),
+        };
+        self.count_label.set_text(&text);
+    }
+
+    /// Replace the selected match, then go to the next one.
+    fn replace(&self) {
+        let Some(editor) = self.current_editor.borrow().clone() else {
+            return;
+        };
+        let Some(search) = self.compile() else {
+            return;
+        };
+        let (start, end) = editor.selection_offsets();
+        let selected = self.matches_in(&editor, &search).into_iter().find(|m| m.start == start && m.end == end);
+        if let Some(selected) = selected {
+            let replacements = search.replacements(&editor.get_text(), &[selected], &self.replace_entry.text());
+            editor.replace_matches(&[selected], &replacements);
+        }
+        self.run(Some(Step::Next));
+    }
+
+    /// Replace every match, or every match in the selection with "in selection" on.
+    fn replace_all(&self) {
+        let Some(editor) = self.current_editor.borrow().clone() else {
+            return;
+        };
+        let Some(search) = self.compile() else {
+            return;
+        };
+        let matches = self.matches_in(&editor, &search);
+        let replacements = search.replacements(&editor.get_text(), &matches, &self.replace_entry.text());
+        let count = editor.replace_matches(&matches, &replacements);
+        self.run(None);
+        self.count_label.set_text(&format!("Replaced {}", count));
+    }
+
+    /// Limit the search to the current selection, or lift the limit.
+    fn set_scope(&self, on: bool) {
+        if let Some((editor, start, end)) = self.scope.take() {
+            if let Some(editor) = editor.upgrade() {
+                editor.main_buffer.delete_mark(&start);
+                editor.main_buffer.delete_mark(&end);
+            }
+        }
+        let editor = self.current_editor.borrow().clone();
+        if let (true, Some(editor)) = (on, editor) {
+            let buffer = &editor.main_buffer;
+            let Some((start, end)) = buffer.selection_bounds() else {
+                // Nothing to search in
+                self.in_selection_button.set_active(false);
+                return;
+            };
+            // The scope grows with text typed at either edge
+            let start = buffer.create_mark(None, &start, true);
+            let end = buffer.create_mark(None, &end, false);
+            *self.scope.borrow_mut() = Some((Rc::downgrade(&editor), start, end));
+        }
+        if self.widget.is_visible() {
+            self.run(None);
+        }
+    }
+}
+
+pub(super) fn toggle(label: &str, tooltip: &str) -> ToggleButton {
+    let button = ToggleButton::with_label(label);
+    button.set_tooltip_text(Some(tooltip));
+    button.set_focus_on_click(false);
+    button
+}
+
+fn icon_button(icon: &str, tooltip: &str) -> Button {
+    let button = Button::from_icon_name(icon);
+    button.set_tooltip_text(Some(tooltip));
+    button.set_has_frame(false);
+    button.set_focus_on_click(false);
+    button
+}
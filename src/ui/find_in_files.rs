@@ -0,0 +1,550 @@
+use gtk4::prelude::*;
+use gtk4::{
+    glib, Box as GtkBox, Button, CellRendererText, CellRendererToggle, Entry, Label, Orientation, ScrolledWindow,
+    ToggleButton, TreeIter, TreeModel, TreePath, TreeStore, TreeView, TreeViewColumn,
+};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::find_bar::toggle;
+use crate::find_in_files::{self, FileFilter, FileMatches, FileReplacements, LineMatch, Replacement};
+use crate::search::{Search, SearchMatch, SearchOptions};
+use crate::settings::Settings;
+
+// Column indices for the results TreeStore
+const COL_MARKUP: u32 = 0; // Row text
+const COL_INCLUDED: u32 = 1; // Checked for replacing
+const COL_FILE: u32 = 2; // Index into `results`
+const COL_MATCH: u32 = 3; // Index into the file's matches, -1 on file rows
+
+/// Streamed from the search thread.
+enum SearchEvent {
+    File(FileMatches),
+    /// The search finished; `true` if it stopped at `find_in_files::MAX_MATCHES`
+    Done(bool),
+}
+
+/// Find in Files panel: searches the files under a folder in the background and lists the
+/// matches by file. In replace mode each match shows its replacement and can be unchecked.
+pub struct FindInFilesPanel {
+    pub widget: GtkBox,
+    query_entry: Entry,
+    folder_entry: Entry,
+    include_entry: Entry,
+    exclude_entry: Entry,
+    case_button: ToggleButton,
+    word_button: ToggleButton,
+    regex_button: ToggleButton,
+    replace_mode_button: ToggleButton,
+    replace_row: GtkBox,
+    replace_entry: Entry,
+    preserve_case_button: ToggleButton,
+    replace_button: Button,
+    status_label: Label,
+    store: TreeStore,
+    tree_view: TreeView,
+    include_column: TreeViewColumn,
+    settings: Rc<RefCell<Settings>>,
+    /// Search the listed results came from, to compute replacements
+    search: RefCell<Option<Search>>,
+    /// Listed files, with full paths
+    results: RefCell<Vec<FileMatches>>,
+    /// Set to stop the running search's thread
+    cancelled: RefCell<Arc<AtomicBool>>,
+    /// Receives the running search's results on the main loop
+    receiver: RefCell<Option<glib::SourceId>>,
+}
+
+impl FindInFilesPanel {
+    pub fn new(settings: Rc<RefCell<Settings>>) -> Rc<Self> {
+        let widget = GtkBox::new(Orientation::Vertical, 4);
+        widget.style_context().add_class("find-in-files");
+
+        let query_row = GtkBox::new(Orientation::Horizontal, 4);
+        let replace_mode_button = toggle("⇄", "Toggle Replace");
+        let query_entry = Entry::new();
+        query_entry.set_placeholder_text(Some("Find in files"));
+        query_entry.set_hexpand(true);
+        let case_button = toggle("Aa", "Match Case");
+        let word_button = toggle("W", "Match Whole Word");
+        let regex_button = toggle(".*", "Use Regular Expression");
+        let search_button = Button::with_label("Search");
+        query_row.append(&replace_mode_button);
+        query_row.append(&query_entry);
+        query_row.append(&case_button);
+        query_row.append(&word_button);
+        query_row.append(&regex_button);
+        query_row.append(&search_button);
+
+        let replace_row = GtkBox::new(Orientation::Horizontal, 4);
+        replace_row.set_visible(false);
+        let replace_entry = Entry::new();
+        replace_entry.set_placeholder_text(Some("Replace ($1 for regex groups)"));
+        replace_entry.set_hexpand(true);
+        let preserve_case_button = toggle("AB", "Preserve Case");
+        let replace_button = Button::with_label("Replace Checked");
+        replace_row.append(&replace_entry);
+        replace_row.append(&preserve_case_button);
+        replace_row.append(&replace_button);
+
+        let scope_row = GtkBox::new(Orientation::Horizontal, 4);
+        let folder_entry = Entry::new();
+        folder_entry.set_placeholder_text(Some("Folder"));
+        folder_entry.set_hexpand(true);
+        let folder_button = Button::from_icon_name("folder-open-symbolic");
+        folder_button.set_tooltip_text(Some("Choose Folder"));
+        let include_entry = Entry::new();
+        include_entry.set_placeholder_text(Some("Files to include (e.g. *.rs, src/ui)"));
+        include_entry.set_hexpand(true);
+        let exclude_entry = Entry::new();
+        exclude_entry.set_placeholder_text(Some("Files to exclude"));
+        exclude_entry.set_hexpand(true);
+        scope_row.append(&folder_entry);
+        scope_row.append(&folder_button);
+        scope_row.append(&include_entry);
+        scope_row.append(&exclude_entry);
+
+        let status_label = Label::new(None);
+        status_label.set_xalign(0.0);
+        status_label.style_context().add_class("dim-label");
+
+        let store = TreeStore::new(&[
+            glib::Type::STRING, // Markup
+            glib::Type::BOOL,   // Included
+            glib::Type::I32,    // File index
+            glib::Type::I32,    // Match index
+        ]);
+        let tree_view = TreeView::with_model(&store);
+        tree_view.set_headers_visible(false);
+
+        // Checkboxes, only shown in replace mode
+        let include_column = TreeViewColumn::new();
+        let toggle_renderer = CellRendererToggle::new();
+        include_column.pack_start(&toggle_renderer, false);
+        include_column.add_attribute(&toggle_renderer, "active", COL_INCLUDED as i32);
+        include_column.set_visible(false);
+        tree_view.append_column(&include_column);
+
+        let text_column = TreeViewColumn::new();
+        let text_renderer = CellRendererText::new();
+        text_renderer.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        text_column.pack_start(&text_renderer, true);
+        text_column.add_attribute(&text_renderer, "markup", COL_MARKUP as i32);
+        tree_view.append_column(&text_column);
+
+        let scrolled = ScrolledWindow::builder()
+            .child(&tree_view)
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .vscrollbar_policy(gtk4::PolicyType::Automatic)
+            .vexpand(true)
+            .build();
+
+        widget.append(&query_row);
+        widget.append(&replace_row);
+        widget.append(&scope_row);
+        widget.append(&status_label);
+        widget.append(&scrolled);
+
+        let panel = Rc::new(Self {
+            widget,
+            query_entry,
+            folder_entry,
+            include_entry,
+            exclude_entry,
+            case_button,
+            word_button,
+            regex_button,
+            replace_mode_button,
+            replace_row,
+            replace_entry,
+            preserve_case_button,
+            replace_button,
+            status_label,
+            store,
+            tree_view,
+            include_column,
+            settings,
+            search: RefCell::new(None),
+            results: RefCell::new(Vec::new()),
+            cancelled: RefCell::new(Arc::new(AtomicBool::new(false))),
+            receiver: RefCell::new(None),
+        });
+
+        // Searching a whole folder is too slow to do per keystroke: Enter in any field or the
+        // button starts it
+        for entry in [&panel.query_entry, &panel.folder_entry, &panel.include_entry, &panel.exclude_entry] {
+            let panel_weak = Rc::downgrade(&panel);
+            entry.connect_activate(move |_| {
+                if let Some(panel) = panel_weak.upgrade() {
+                    panel.start();
+                }
+            });
+        }
+        {
+            let panel_weak = Rc::downgrade(&panel);
+            search_button.connect_clicked(move |_| {
+                if let Some(panel) = panel_weak.upgrade() {
+                    panel.start();
+                }
+            });
+        }
+
+        // The previews follow the replace field as it's typed
+        {
+            let panel_weak = Rc::downgrade(&panel);
+            panel.replace_mode_button.connect_toggled(move |button| {
+                if let Some(panel) = panel_weak.upgrade() {
+                    panel.replace_row.set_visible(button.is_active());
+                    panel.include_column.set_visible(button.is_active());
+                    panel.refresh_previews();
+                }
+            });
+        }
+        {
+            let panel_weak = Rc::downgrade(&panel);
+            panel.replace_entry.connect_changed(move |_| {
+                if let Some(panel) = panel_weak.upgrade() {
+                    panel.refresh_previews();
+                }
+            });
+        }
+        {
+            let panel_weak = Rc::downgrade(&panel);
+            panel.preserve_case_button.connect_toggled(move |button| {
+                if let Some(panel) = panel_weak.upgrade() {
+                    if let Some(search) = panel.search.borrow_mut().as_mut() {
+                        search.set_preserve_case(button.is_active());
+                    }
+                    panel.refresh_previews();
+                }
+            });
+        }
+
+        {
+            let store = panel.store.clone();
+            toggle_renderer.connect_toggled(move |_, path| {
+                if let Some(iter) = store.iter(&path) {
+                    let included: bool = store.get(&iter, COL_INCLUDED as i32);
+                    set_included(&store, &iter, COL_INCLUDED, !included);
+                }
+            });
+        }
+
+        {
+            let folder_entry = panel.folder_entry.clone();
+            folder_button.connect_clicked(move |button| {
+                let window = button.root().and_then(|root| root.downcast::<gtk4::Window>().ok());
+                let dialog = gtk4::FileChooserDialog::new(
+                    Some("Search in Folder"),
+                    window.as_ref(),
+                    gtk4::FileChooserAction::SelectFolder,
+                    &[("Cancel", gtk4::ResponseType::Cancel), ("Select", gtk4::ResponseType::Accept)],
+                );
+                let folder_entry = folder_entry.clone();
+                dialog.connect_response(move |dialog, response| {
+                    if response == gtk4::ResponseType::Accept {
+                        if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                            folder_entry.set_text(&path.to_string_lossy());
+                        }
+                    }
+                    dialog.close();
+                });
+                dialog.show();
+            });
+        }
+
+        panel
+    }
+
+    /// Focus the query field, searching for `query` if given. An empty folder field is set
+    /// to `root`.
+    pub fn focus(&self, root: Option<&Path>, query: Option<&str>) {
+        if let (true, Some(root)) = (self.folder_entry.text().is_empty(), root) {
+            self.folder_entry.set_text(&root.to_string_lossy());
+        }
+        if let Some(query) = query {
+            self.query_entry.set_text(query);
+        }
+        self.query_entry.grab_focus();
+    }
+
+    /// Call `callback` with the file, zero-based line and column of an activated match.
+    pub fn connect_result_activated(self: &Rc<Self>, callback: impl Fn(PathBuf, usize, usize) + 'static) {
+        let panel_weak = Rc::downgrade(self);
+        self.tree_view.connect_row_activated(move |tree_view, path, _column| {
+            let Some(panel) = panel_weak.upgrade() else {
+                return;
+            };
+            let Some((file, index)) = panel.row_indices(path) else {
+                return;
+            };
+            let Some(index) = index else {
+                // File rows fold their matches
+                if tree_view.row_expanded(path) {
+                    tree_view.collapse_row(path);
+                } else {
+                    tree_view.expand_row(path, false);
+                }
+                return;
+            };
+            let target = panel.results.borrow().get(file).map(|file| (file.path.clone(), file.matches[index].clone()));
+            if let Some((path, m)) = target {
+                callback(path, m.line, m.start_column);
+            }
+        });
+    }
+
+    /// Call `callback` with the checked matches and their replacements when Replace is
+    /// clicked.
+    pub fn connect_replace(self: &Rc<Self>, callback: impl Fn(Vec<FileReplacements>) + 'static) {
+        let panel_weak = Rc::downgrade(self);
+        self.replace_button.connect_clicked(move |_| {
+            if let Some(panel) = panel_weak.upgrade() {
+                let chosen = panel.chosen_replacements();
+                if !chosen.is_empty() {
+                    callback(chosen);
+                }
+            }
+        });
+    }
+
+    /// Cancel the running search, if any, and search again with the current fields.
+    pub fn start(self: &Rc<Self>) {
+        self.stop();
+        self.store.clear();
+        self.results.borrow_mut().clear();
+        self.search.borrow_mut().take();
+
+        let query = self.query_entry.text();
+        if query.is_empty() {
+            self.status_label.set_text("");
+            return;
+        }
+        let options = SearchOptions {
+            case_sensitive: self.case_button.is_active(),
+            whole_word: self.word_button.is_active(),
+            regex: self.regex_button.is_active(),
+            preserve_case: self.preserve_case_button.is_active(),
+        };
+        let search = match Search::new(&query, options) {
+            Ok(search) => search,
+            Err(e) => {
+                self.status_label.set_text(&e.to_string());
+                return;
+            }
+        };
+        let root = PathBuf::from(self.folder_entry.text().as_str());
+        if !root.is_dir() {
+            self.status_label.set_text("Choose a folder to search in");
+            return;
+        }
+        let filter = FileFilter::new(&self.include_entry.text(), &self.exclude_entry.text());
+        let settings = self.settings.borrow().clone();
+        let cancelled = self.cancelled.borrow().clone();
+        *self.search.borrow_mut() = Some(search.clone());
+        self.status_label.set_text("Searching…");
+        // Replacing half-listed results would be confusing; wait for the search to finish
+        self.replace_button.set_sensitive(false);
+
+        let (tx, rx) = glib::MainContext::channel::<SearchEvent>(glib::Priority::default());
+        {
+            let root = root.clone();
+            std::thread::Builder::new()
+                .name("find-in-files".to_string())
+                .spawn(move || {
+                    let truncated = find_in_files::search_files(&root, &settings, &filter, &search, &cancelled, |file| {
+                        let _ = tx.send(SearchEvent::File(file));
+                    });
+                    let _ = tx.send(SearchEvent::Done(truncated));
+                })
+                .expect("failed to spawn search thread");
+        }
+
+        let panel_weak = Rc::downgrade(self);
+        let (mut files, mut matches) = (0, 0);
+        let source = rx.attach(None, move |event| {
+            let Some(panel) = panel_weak.upgrade() else {
+                return glib::Continue(false);
+            };
+            match event {
+                SearchEvent::File(mut file) => {
+                    files += 1;
+                    matches += file.matches.len();
+                    file.path = root.join(&file.path);
+                    panel.append_file(&root, file);
+                    panel.status_label.set_text(&format!("Searching… {} results in {} files", matches, files));
+                    glib::Continue(true)
+                }
+                SearchEvent::Done(truncated) => {
+                    let status = match (matches, truncated) {
+                        (0, _) => "No results".to_string(),
+                        (_, true) => format!("Showing the first {} results; narrow the search to see the rest", matches),
+                        _ => format!("{} results in {} files", matches, files),
+                    };
+                    panel.status_label.set_text(&status);
+                    panel.replace_button.set_sensitive(true);
+                    panel.receiver.borrow_mut().take();
+                    glib::Continue(false)
+                }
+            }
+        });
+        *self.receiver.borrow_mut() = Some(source);
+    }
+
+    /// Stop the running search; results found so far stay listed.
+    fn stop(&self) {
+        self.cancelled.borrow().store(true, Ordering::Relaxed);
+        *self.cancelled.borrow_mut() = Arc::new(AtomicBool::new(false));
+        if let Some(source) = self.receiver.borrow_mut().take() {
+            source.remove();
+        }
+        self.replace_button.set_sensitive(true);
+    }
+
+    fn append_file(&self, root: &Path, file: FileMatches) {
+        let relative = file.path.strip_prefix(root).unwrap_or(&file.path);
+        let name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let dir = relative.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+        let markup = format!(
+            "<b>{}</b>  <span alpha=\"60%\">{}  ({})</span>",
+            glib::markup_escape_text(&name),
+            glib::markup_escape_text(&dir),
+            file.matches.len()
+        );
+
+        let file_index = self.results.borrow().len() as i32;
+        let parent = self.store.insert_with_values(
+            None,
+            None,
+            &[(COL_MARKUP, &markup), (COL_INCLUDED, &true), (COL_FILE, &file_index), (COL_MATCH, &-1i32)],
+        );
+        for (index, m) in file.matches.iter().enumerate() {
+            self.store.insert_with_values(
+                Some(&parent),
+                None,
+                &[
+                    (COL_MARKUP, &self.match_markup(m)),
+                    (COL_INCLUDED, &true),
+                    (COL_FILE, &file_index),
+                    (COL_MATCH, &(index as i32)),
+                ],
+            );
+        }
+        self.results.borrow_mut().push(file);
+        self.tree_view.expand_row(&self.store.path(&parent), false);
+    }
+
+    /// File index and, on match rows, match index of the row at `path`.
+    fn row_indices(&self, path: &TreePath) -> Option<(usize, Option<usize>)> {
+        let iter = self.store.iter(path)?;
+        Some(indices(self.store.upcast_ref(), &iter))
+    }
+
+    /// Re-render the match rows after the replace field or mode changed.
+    fn refresh_previews(&self) {
+        let results = self.results.borrow();
+        self.store.foreach(|model, _path, iter| {
+            if let (file, Some(index)) = indices(model, iter) {
+                let markup = self.match_markup(&results[file].matches[index]);
+                self.store.set_value(iter, COL_MARKUP, &markup.to_value());
+            }
+            false
+        });
+    }
+
+    /// Replacement text for `m`, or `None` outside replace mode.
+    fn replacement(&self, m: &LineMatch) -> Option<String> {
+        if !self.replace_mode_button.is_active() {
+            return None;
+        }
+        let at = SearchMatch { start: m.start_column, end: m.end_column };
+        Some(self.search.borrow().as_ref()?.replacement(&m.line_text, at, &self.replace_entry.text()))
+    }
+
+    /// Line number and preview of `m` with the match in bold, or in replace mode, struck out
+    /// and followed by its replacement.
+    fn match_markup(&self, m: &LineMatch) -> String {
+        let (start, end) = m.preview_match;
+        let part = |from: usize, to: usize| {
+            let text: String = m.preview.chars().skip(from).take(to - from).collect();
+            glib::markup_escape_text(&text).to_string()
+        };
+        let matched = match self.replacement(m) {
+            Some(replacement) => format!(
+                "<span strikethrough=\"true\" background=\"#e5534b\" bgalpha=\"30%\">{}</span><span background=\"#57ab5a\" bgalpha=\"30%\">{}</span>",
+                part(start, end),
+                glib::markup_escape_text(&replacement)
+            ),
+            None => format!("<b>{}</b>", part(start, end)),
+        };
+        format!(
+            "<span alpha=\"60%\">{}</span>  {}{}{}",
+            m.line + 1,
+            part(0, start),
+            matched,
+            part(end, m.preview.chars().count())
+        )
+    }
+
+    /// The checked matches with their replacements, by file.
+    fn chosen_replacements(&self) -> Vec<FileReplacements> {
+        let results = self.results.borrow();
+        let mut chosen: Vec<FileReplacements> = Vec::new();
+        self.store.foreach(|model, _path, iter| {
+            let (file, index) = indices(model, iter);
+            let included: bool = model.get(iter, COL_INCLUDED as i32);
+            let (Some(index), true) = (index, included) else {
+                return false;
+            };
+            let file = &results[file];
+            let m = &file.matches[index];
+            let Some(text) = self.replacement(m) else {
+                return false;
+            };
+            let replacement = Replacement::new(m, text);
+            match chosen.last_mut() {
+                Some(last) if last.path == file.path => last.replacements.push(replacement),
+                _ => chosen.push(FileReplacements { path: file.path.clone(), replacements: vec![replacement] }),
+            }
+            false
+        });
+        chosen
+    }
+}
+
+/// File index and, on match rows, match index of the row at `iter`.
+fn indices(model: &TreeModel, iter: &TreeIter) -> (usize, Option<usize>) {
+    let file: i32 = model.get(iter, COL_FILE as i32);
+    let index: i32 = model.get(iter, COL_MATCH as i32);
+    (file as usize, usize::try_from(index).ok())
+}
+
+/// Check or uncheck the row at `iter`, whose checkbox is in `column`: a file row takes its
+/// children along, and a file row is checked while any of its children is. Shared with the
+/// rename preview's tree.
+pub(super) fn set_included(store: &TreeStore, iter: &TreeIter, column: u32, included: bool) {
+    store.set_value(iter, column, &included.to_value());
+    if let Some(child) = store.iter_children(Some(iter)) {
+        loop {
+            store.set_value(&child, column, &included.to_value());
+            if !store.iter_next(&child) {
+                break;
+            }
+        }
+    }
+    if let Some(parent) = store.iter_parent(iter) {
+        let mut any = false;
+        if let Some(child) = store.iter_children(Some(&parent)) {
+            loop {
+                any |= store.get::<bool>(&child, column as i32);
+                if !store.iter_next(&child) {
+                    break;
+                }
+            }
+        }
+        store.set_value(&parent, column, &any.to_value());
+    }
+}
@@ -0,0 +1,94 @@
+use gtk4::prelude::*;
+use gtk4::{Dialog, Entry, Label, ResponseType};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::Workspace;
+use crate::navigation::parse_go_to;
+
+/// Ctrl+G dialog jumping to `line`, `line:column` or `file:line:column` as printed by
+/// compilers and other tools.
+pub fn show_go_to_line(workspace: &Rc<Workspace>) {
+    let dialog = Dialog::with_buttons(
+        Some("Go to Line"),
+        Some(&workspace.window),
+        gtk4::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Go", ResponseType::Accept)],
+    );
+    dialog.set_default_width(360);
+    dialog.set_default_response(ResponseType::Accept);
+
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_margin_top(10);
+    content_area.set_margin_bottom(10);
+    content_area.set_margin_start(10);
+    content_area.set_margin_end(10);
+
+    let entry = Entry::new();
+    entry.set_placeholder_text(Some("line, line:column or file:line:column"));
+    entry.set_activates_default(true);
+    let current = workspace.current_editor.borrow().clone();
+    let hint = Label::new(Some(&match current {
+        Some(editor) => {
+            let (line, column) = editor.cursor_position();
+            format!("Line {}, column {} of {} lines", line + 1, column + 1, editor.main_buffer.line_count())
+        }
+        None => "No file is open; type file:line".to_string(),
+    }));
+    hint.set_xalign(0.0);
+    hint.style_context().add_class("dim-label");
+    content_area.append(&entry);
+    content_area.append(&hint);
+
+    entry.connect_changed(|entry| entry.style_context().remove_class("error"));
+
+    let workspace = workspace.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let Some(target) = parse_go_to(&entry.text()) else {
+                entry.style_context().add_class("error");
+                return;
+            };
+            match target.path {
+                Some(path) => {
+                    let Some(path) = resolve(&workspace, &path) else {
+                        entry.style_context().add_class("error");
+                        hint.set_text(&format!("No file named {}", path.display()));
+                        return;
+                    };
+                    workspace.open_file_at(path, target.line, target.column);
+                }
+                None => {
+                    let editor = workspace.current_editor.borrow().clone();
+                    if let Some(editor) = editor {
+                        workspace.record_jump();
+                        editor.go_to(target.line, target.column);
+                    }
+                }
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+/// The file `path` names: absolute, or relative to the explorer's folder, the current file's
+/// folder or the working directory, tried in that order.
+fn resolve(workspace: &Workspace, path: &Path) -> Option<PathBuf> {
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let root = workspace.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+    let current_dir = workspace
+        .current_editor
+        .borrow()
+        .as_ref()
+        .and_then(|editor| editor.current_file.borrow().as_ref().and_then(|file| file.parent()).map(Path::to_path_buf));
+    [root, current_dir, std::env::current_dir().ok()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.is_file())
+}
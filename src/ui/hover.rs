@@ -0,0 +1,288 @@
+use gtk4::prelude::*;
+use gtk4::{gdk, glib, EventControllerMotion, EventControllerScroll, Label, Popover, PositionType, ScrolledWindow, TextWindowType};
+use serde_json::{json, Value};
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use super::symbol_index::SymbolIndexer;
+use crate::editor::Editor;
+use crate::lsp::protocol;
+use crate::markdown;
+use crate::symbols::{self, Symbol};
+
+// How long the pointer rests on a word before its information shows
+const HOVER_DELAY: Duration = Duration::from_millis(500);
+// Grace period for moving the pointer from the text onto the popover
+const HIDE_DELAY: Duration = Duration::from_millis(300);
+// Height of the popover before it scrolls
+const MAX_HEIGHT: i32 = 320;
+
+/// Hover information: resting the pointer on a word shows its type and documentation from
+/// the language server, or for files without one, the definition the symbol index finds for
+/// it, with its doc comment. Moving off the word hides it.
+pub struct HoverPopup {
+    popover: Popover,
+    label: Label,
+    indexer: Rc<SymbolIndexer>,
+    /// Waiting for the pointer to rest, or to hide after it left
+    timeout: RefCell<Option<glib::SourceId>>,
+    /// Bumped whenever the pointer moves to another word, so answers for the last one are
+    /// dropped
+    generation: Cell<u64>,
+    /// The view and char range of the word shown
+    shown: RefCell<Option<(Weak<Editor>, usize, usize)>>,
+    /// Set while the pointer is on the popover, whose motion the view sees too as its parent
+    on_popover: Cell<bool>,
+}
+
+impl HoverPopup {
+    pub fn new(indexer: Rc<SymbolIndexer>) -> Rc<Self> {
+        let popover = Popover::new();
+        popover.set_autohide(false);
+        popover.set_can_focus(false);
+        popover.set_position(PositionType::Top);
+        popover.style_context().add_class("hover");
+
+        let label = Label::new(None);
+        label.set_xalign(0.0);
+        label.set_wrap(true);
+        label.set_max_width_chars(80);
+        label.set_selectable(true);
+        label.set_can_focus(false);
+        let scrolled = ScrolledWindow::builder()
+            .child(&label)
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .propagate_natural_height(true)
+            .propagate_natural_width(true)
+            .max_content_height(MAX_HEIGHT)
+            .build();
+        popover.set_child(Some(&scrolled));
+
+        let popup = Rc::new(Self {
+            popover,
+            label,
+            indexer,
+            timeout: RefCell::new(None),
+            generation: Cell::new(0),
+            shown: RefCell::new(None),
+            on_popover: Cell::new(false),
+        });
+
+        // The popover stays while the pointer is on it, to scroll or select its text
+        let motion = EventControllerMotion::new();
+        let popup_weak = Rc::downgrade(&popup);
+        motion.connect_enter(move |_, _, _| {
+            if let Some(popup) = popup_weak.upgrade() {
+                popup.on_popover.set(true);
+                popup.cancel_timeout();
+            }
+        });
+        let popup_weak = Rc::downgrade(&popup);
+        motion.connect_leave(move |_| {
+            if let Some(popup) = popup_weak.upgrade() {
+                popup.on_popover.set(false);
+                popup.hide_soon();
+            }
+        });
+        popup.popover.add_controller(motion);
+        popup
+    }
+
+    /// Watch the pointer over `editor`'s text.
+    pub fn attach(self: &Rc<Self>, editor: &Rc<Editor>) {
+        let motion = EventControllerMotion::new();
+        let popup_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        motion.connect_motion(move |controller, x, y| {
+            let (Some(popup), Some(editor)) = (popup_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if !popup.on_popover.get() {
+                // Ctrl is held to click through to the definition, not to read about it
+                let jumping = controller.current_event_state().contains(gdk::ModifierType::CONTROL_MASK);
+                popup.pointer_moved(&editor, x, y, jumping);
+            }
+        });
+        let popup_weak = Rc::downgrade(self);
+        motion.connect_leave(move |_| {
+            if let Some(popup) = popup_weak.upgrade() {
+                popup.hide_soon();
+            }
+        });
+        editor.main_view.add_controller(motion);
+
+        let scroll = EventControllerScroll::new(gtk4::EventControllerScrollFlags::BOTH_AXES);
+        let popup_weak = Rc::downgrade(self);
+        scroll.connect_scroll(move |_, _, _| {
+            if let Some(popup) = popup_weak.upgrade() {
+                popup.hide();
+            }
+            gtk4::Inhibit(false)
+        });
+        editor.main_view.add_controller(scroll);
+
+        let popup_weak = Rc::downgrade(self);
+        editor.main_buffer.connect_changed(move |_| {
+            if let Some(popup) = popup_weak.upgrade() {
+                popup.hide();
+            }
+        });
+    }
+
+    pub fn hide(&self) {
+        self.cancel_timeout();
+        self.generation.set(self.generation.get() + 1);
+        self.shown.borrow_mut().take();
+        self.on_popover.set(false);
+        self.popover.popdown();
+        if self.popover.parent().is_some() {
+            self.popover.unparent();
+        }
+    }
+
+    fn pointer_moved(self: &Rc<Self>, editor: &Rc<Editor>, x: f64, y: f64, jumping: bool) {
+        let offset = word_under(editor, x, y);
+        let on_shown = self.shown.borrow().as_ref().is_some_and(|(shown, start, end)| {
+            shown.upgrade().is_some_and(|shown| Rc::ptr_eq(&shown, editor))
+                && offset.is_some_and(|offset| (*start..*end).contains(&offset))
+        });
+        if on_shown {
+            self.cancel_timeout();
+            return;
+        }
+        self.hide();
+        let Some(offset) = offset.filter(|_| !jumping) else {
+            return;
+        };
+        let generation = self.generation.get();
+        let popup_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        let source = glib::timeout_add_local_once(HOVER_DELAY, move || {
+            let (Some(popup), Some(editor)) = (popup_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            popup.timeout.borrow_mut().take();
+            if popup.generation.get() == generation {
+                popup.request(&editor, offset);
+            }
+        });
+        *self.timeout.borrow_mut() = Some(source);
+    }
+
+    /// Ask the server about the word at `offset`, or look it up in the symbol index if no
+    /// server handles the file.
+    fn request(self: &Rc<Self>, editor: &Rc<Editor>, offset: usize) {
+        let rope = editor.rope();
+        let Some((start, end)) = symbols::identifier_at(&rope, offset) else {
+            return;
+        };
+        let generation = self.generation.get();
+        let popup_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        let show = move |markup: String| {
+            let (Some(popup), Some(editor)) = (popup_weak.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if popup.generation.get() == generation {
+                popup.show(&editor, start, end, &markup);
+            }
+        };
+
+        if let Some(lsp) = editor.language_server() {
+            let capable = !matches!(lsp.client.capabilities()["hoverProvider"], Value::Null | Value::Bool(false));
+            let Some(text) = lsp.client.document_text(&lsp.uri).filter(|_| capable) else {
+                return;
+            };
+            let params = json!({
+                "textDocument": { "uri": lsp.uri },
+                "position": protocol::position_at(&text, offset, lsp.client.encoding()),
+            });
+            lsp.client.request("textDocument/hover", params, move |result| {
+                if let Some(markdown) = result.ok().as_ref().and_then(protocol::hover_markdown) {
+                    show(markdown::to_pango(&markdown));
+                }
+            });
+            return;
+        }
+
+        let name: String = rope.slice(start..end).chars().collect();
+        let file = editor.current_file.borrow().clone();
+        self.indexer.with_index(file.as_deref(), move |index| {
+            let definitions = index.definitions(&name);
+            if let Some(symbol) = definitions.first() {
+                show(symbol_markup(symbol, definitions.len() - 1));
+            }
+        });
+    }
+
+    fn show(&self, editor: &Rc<Editor>, start: usize, end: usize, markup: &str) {
+        let view = &editor.main_view;
+        if self.popover.parent().as_ref() != Some(view.upcast_ref::<gtk4::Widget>()) {
+            if self.popover.parent().is_some() {
+                self.popover.unparent();
+            }
+            self.popover.set_parent(view);
+        }
+        let buffer = &editor.main_buffer;
+        let (start_iter, end_iter) = (buffer.iter_at_offset(start as i32), buffer.iter_at_offset(end as i32));
+        let (first, last) = (view.iter_location(&start_iter), view.iter_location(&end_iter));
+        let (x, y) = view.buffer_to_window_coords(TextWindowType::Widget, first.x(), first.y());
+        // A word wrapped onto the next line points at its start
+        let width = if last.y() == first.y() { (last.x() - first.x()).max(1) } else { 1 };
+        self.popover.set_pointing_to(Some(&gdk::Rectangle::new(x, y, width, first.height())));
+        self.label.set_markup(markup);
+        *self.shown.borrow_mut() = Some((Rc::downgrade(editor), start, end));
+        self.popover.popup();
+    }
+
+    fn hide_soon(self: &Rc<Self>) {
+        self.cancel_timeout();
+        if self.shown.borrow().is_none() {
+            return;
+        }
+        let popup_weak = Rc::downgrade(self);
+        let source = glib::timeout_add_local_once(HIDE_DELAY, move || {
+            if let Some(popup) = popup_weak.upgrade() {
+                popup.timeout.borrow_mut().take();
+                popup.hide();
+            }
+        });
+        *self.timeout.borrow_mut() = Some(source);
+    }
+
+    fn cancel_timeout(&self) {
+        if let Some(source) = self.timeout.borrow_mut().take() {
+            source.remove();
+        }
+    }
+}
+
+/// Char offset of the text under `x`, `y`, if the pointer is on a character rather than past
+/// the end of a line or below the text.
+fn word_under(editor: &Editor, x: f64, y: f64) -> Option<usize> {
+    let view = &editor.main_view;
+    let iter = editor.iter_at_widget_coords(x, y)?;
+    let location = view.iter_location(&iter);
+    let (bx, by) = view.window_to_buffer_coords(TextWindowType::Widget, x as i32, y as i32);
+    let inside = bx >= location.x()
+        && bx < location.x() + location.width().max(1)
+        && by >= location.y()
+        && by < location.y() + location.height();
+    (inside && !iter.ends_line()).then_some(iter.offset() as usize)
+}
+
+/// A definition as hover markup: its defining line as code, then its documentation.
+fn symbol_markup(symbol: &Symbol, others: usize) -> String {
+    let mut text = format!("```\n{}\n```", symbol.signature);
+    if let Some(doc) = &symbol.doc {
+        text.push_str("\n\n");
+        text.push_str(doc);
+    }
+    let mut markup = markdown::to_pango(&text);
+    if others > 0 {
+        let more = if others == 1 { "1 more definition".to_string() } else { format!("{} more definitions", others) };
+        markup.push_str(&format!("\n\n<span alpha=\"60%\">{}</span>", more));
+    }
+    markup
+}
@@ -0,0 +1,166 @@
+use gtk4::glib;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::diagnostics::{self, Diagnostic};
+use crate::editor::Editor;
+use crate::lsp;
+use crate::lsp::client::{Attachment, Event, LspClient, Status};
+use crate::lsp::protocol::{self, PositionEncoding};
+use crate::settings::Settings;
+
+/// The workspace's language servers: one per language and project root, started when the
+/// first file it handles is opened and kept running until the window closes.
+pub struct LanguageServers {
+    settings: Rc<RefCell<Settings>>,
+    clients: RefCell<HashMap<(String, PathBuf), Rc<LspClient>>>,
+    on_diagnostics: Rc<RefCell<Option<DiagnosticsHandler>>>,
+    on_apply_edit: Rc<RefCell<Option<ApplyEditHandler>>>,
+    on_status: Rc<RefCell<Option<StatusHandler>>>,
+}
+
+type DiagnosticsHandler = Rc<dyn Fn(&Path, &str, Vec<Diagnostic>)>;
+type ApplyEditHandler = Rc<dyn Fn(&Value, PositionEncoding) -> bool>;
+type StatusHandler = Rc<dyn Fn(&str, &Status)>;
+
+impl LanguageServers {
+    pub fn new(settings: Rc<RefCell<Settings>>) -> Self {
+        Self {
+            settings,
+            clients: RefCell::new(HashMap::new()),
+            on_diagnostics: Rc::new(RefCell::new(None)),
+            on_apply_edit: Rc::new(RefCell::new(None)),
+            on_status: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Call `callback` with the file, the language id and the diagnostics whenever a server
+    /// publishes the diagnostics of a file.
+    pub fn connect_diagnostics(&self, callback: impl Fn(&Path, &str, Vec<Diagnostic>) + 'static) {
+        *self.on_diagnostics.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Call `callback` with the workspace edit and the position encoding whenever a server
+    /// asks for an edit to be made, e.g. while running a code action's command. It returns
+    /// whether the edit was made.
+    pub fn connect_apply_edit(&self, callback: impl Fn(&Value, PositionEncoding) -> bool + 'static) {
+        *self.on_apply_edit.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Call `callback` with the server's command whenever a server starts, runs or fails.
+    pub fn connect_status(&self, callback: impl Fn(&str, &Status) + 'static) {
+        *self.on_status.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Open `editor`'s document in the server for its file, or close it in its server if
+    /// there's none now: untitled, in large-file mode, or renamed to another language.
+    pub fn attach(&self, editor: &Editor) {
+        let path = editor.current_file.borrow().clone();
+        let target = path
+            .filter(|_| !*editor.large_file.borrow())
+            .and_then(|path| Some((self.client_for(&path)?, protocol::file_uri(&path))));
+        let current = editor.language_server();
+        let unchanged = match (&current, &target) {
+            (Some(current), Some((client, uri))) => Rc::ptr_eq(&current.client, client) && current.uri == *uri,
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            editor.set_language_server(target.map(|(client, uri)| Attachment { client, uri }));
+        }
+    }
+
+    /// Tell `editor`'s server that its document was saved.
+    pub fn did_save(&self, editor: &Editor) {
+        if let Some(lsp) = editor.language_server() {
+            lsp.client.save_document(&lsp.uri);
+        }
+    }
+
+    /// Ask every server to shut down, when the window closes.
+    pub fn shutdown(&self) {
+        for client in self.clients.borrow().values() {
+            client.shutdown();
+        }
+    }
+
+    /// The client of the server for `path`, started if it isn't running yet.
+    fn client_for(&self, path: &Path) -> Option<Rc<LspClient>> {
+        let (language_id, config) = lsp::server_for(&self.settings.borrow().language_servers, path)?;
+        let root = lsp::find_root(path, &config.root_markers);
+        let key = (language_id, root);
+        if let Some(client) = self.clients.borrow().get(&key) {
+            return Some(client.clone());
+        }
+
+        let command = config.command.clone();
+        // Each server process's reader thread passes its messages to the main loop
+        let (tx, rx) = glib::MainContext::channel::<Event>(glib::Priority::default());
+        let client = LspClient::new(&key.0, config, key.1.clone(), move || {
+            let tx = tx.clone();
+            Box::new(move |event| {
+                let _ = tx.send(event);
+            })
+        });
+        let client_weak = Rc::downgrade(&client);
+        rx.attach(None, move |event| {
+            let Some(client) = client_weak.upgrade() else {
+                return glib::Continue(false);
+            };
+            client.handle(event);
+            glib::Continue(true)
+        });
+        let client_weak = Rc::downgrade(&client);
+        let on_diagnostics = self.on_diagnostics.clone();
+        client.on_notification(move |method, params| {
+            if method != "textDocument/publishDiagnostics" {
+                return;
+            }
+            let (Some(client), Some(uri)) = (client_weak.upgrade(), params["uri"].as_str()) else {
+                return;
+            };
+            let Some(path) = protocol::uri_to_path(uri) else {
+                return;
+            };
+            // Diagnostics of an older version than the server has been sent are positioned in
+            // text that's gone; fresh ones follow
+            let version = params["version"].as_i64();
+            if version.is_some_and(|version| client.document_version(uri).is_some_and(|current| version < current)) {
+                return;
+            }
+            // Positions are in the text the server knows, which may lag behind the buffer
+            let text = client.document_text(uri);
+            let encoding = client.encoding();
+            let published = params["diagnostics"].as_array().into_iter().flatten();
+            let converted = published.filter_map(|d| diagnostics::from_lsp(d, text.as_ref(), encoding)).collect();
+            let handler = on_diagnostics.borrow().clone();
+            if let Some(handler) = handler {
+                handler(&path, &client.language_id, converted);
+            }
+        });
+        let client_weak = Rc::downgrade(&client);
+        let on_apply_edit = self.on_apply_edit.clone();
+        client.on_request(move |method, params| {
+            if method != "workspace/applyEdit" {
+                return None;
+            }
+            let client = client_weak.upgrade()?;
+            let handler = on_apply_edit.borrow().clone()?;
+            let applied = handler(&params["edit"], client.encoding());
+            Some(json!({ "applied": applied }))
+        });
+        let on_status = self.on_status.clone();
+        client.on_status(move |status| {
+            let handler = on_status.borrow().clone();
+            if let Some(handler) = handler {
+                handler(&command, status);
+            }
+        });
+        client.start();
+        self.clients.borrow_mut().insert(key, client.clone());
+        Some(client)
+    }
+}
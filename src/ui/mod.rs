@@ -1,29 +1,76 @@
 use gtk4::prelude::*;
 use gtk4::{
-    Application, ApplicationWindow, Box as GtkBox, Button, Label, Entry, Dialog, ResponseType,
+    Application, ApplicationWindow, Box as GtkBox, Button, Entry, Dialog, ResponseType,
     MenuButton, Notebook, Orientation, Paned, PopoverMenu,
     MessageDialog, MessageType, ButtonsType,
 };
 use gtk4::gio::SimpleAction;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::path::Path;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use syntect::highlighting::{Theme, ThemeSet};
 use syntect::parsing::SyntaxSet;
 
-use crate::config::ThemeMode;
+use crate::commands::CommandRegistry;
+use crate::diagnostics::DiagnosticStore;
+use crate::documents::DocumentRegistry;
 use crate::editor::Editor;
 use crate::file_explorer::FileExplorer;
-use crate::find_replace::FindReplaceDialog;
-
-pub fn build_ui(app: &Application) {
-    let ss = Rc::new(SyntaxSet::load_defaults_newlines());
+use crate::lsp::client::Status;
+use crate::navigation::NavigationHistory;
+use crate::settings::Settings;
+
+mod code_actions;
+mod command_palette;
+mod completion;
+mod editor_groups;
+mod find_bar;
+mod find_in_files;
+mod go_to_line;
+mod hover;
+mod language_servers;
+mod palette;
+mod problems;
+mod quick_open;
+mod references;
+mod rename;
+mod settings_dialog;
+mod status_bar;
+mod symbol_index;
+mod workspace;
+use code_actions::CodeActionMenu;
+use command_palette::{register_builtin_commands, show_command_palette};
+use completion::CompletionPopup;
+use editor_groups::EditorGroups;
+use find_bar::FindBar;
+use find_in_files::FindInFilesPanel;
+use go_to_line::show_go_to_line;
+use hover::HoverPopup;
+use language_servers::LanguageServers;
+use problems::ProblemsPanel;
+use quick_open::show_quick_open;
+use references::ReferencesPanel;
+use settings_dialog::show_settings_dialog;
+use symbol_index::SymbolIndexer;
+pub use status_bar::StatusBar;
+pub use workspace::Workspace;
+
+// Levels offered by Fold Level N (Ctrl+Alt+1 to Ctrl+Alt+7)
+const FOLD_LEVELS: usize = 7;
+
+/// An app action that runs on the current editor.
+type EditorAction = fn(&Editor);
+
+pub fn build_ui(app: &Application, settings: Settings) {
+    let ss = Arc::new(SyntaxSet::load_defaults_newlines());
     let ts = ThemeSet::load_defaults();
     
-    // Start with dark theme by default
-    let current_theme_mode = Rc::new(RefCell::new(ThemeMode::Dark));
-    let theme = Rc::new(ts.themes["base16-ocean.dark"].clone());
-    let current_theme: Rc<RefCell<Rc<Theme>>> = Rc::new(RefCell::new(theme.clone()));
+    // Start with the theme from the user's settings (dark by default)
+    let settings = Rc::new(RefCell::new(settings));
+    let theme = Arc::new(ts.themes[settings.borrow().theme.syntax_theme_name()].clone());
+    let current_theme: Rc<RefCell<Arc<Theme>>> = Rc::new(RefCell::new(theme.clone()));
 
     let window = ApplicationWindow::builder()
         .application(app)
@@ -77,6 +124,7 @@ pub fn build_ui(app: &Application) {
             .unwrap_or_else(|_| std::path::PathBuf::from("/"))
     });
     
+    file_explorer_rc.borrow_mut().apply_settings(&settings.borrow());
     file_explorer_rc.borrow_mut().set_root_directory(root_dir);
     
     // Setup context menu (will be connected to actions later)
@@ -86,74 +134,144 @@ pub fn build_ui(app: &Application) {
     paned.set_resize_start_child(false);
     paned.set_shrink_start_child(false);
 
-    // Editor area (Notebook for tabs)
-    let notebook = Notebook::new();
-    notebook.set_scrollable(true);
-    notebook.set_vexpand(true);
-    notebook.set_hexpand(true);
-    // Set minimum height to prevent negative tab height calculations during window resize
-    // This prevents GTK warning about negative min-height and window minimize issues
-    notebook.set_size_request(-1, 100);
+    // Editor area (groups of tabs, split on demand)
+    let groups = EditorGroups::new();
 
     // Status bar
-    let status_bar = GtkBox::new(Orientation::Horizontal, 10);
-    status_bar.style_context().add_class("status");
-
-    let status_label = Label::new(Some("Ln 1, Col 1"));
-    let status_info_label = Label::new(Some("Ready"));
-    status_info_label.set_hexpand(true);
-    status_info_label.set_halign(gtk4::Align::Start);
-
-    status_bar.append(&status_label);
-    status_bar.append(&status_info_label);
-
-    paned.set_end_child(Some(&notebook));
+    let status_bar = Rc::new(StatusBar::new(&ss));
+
+    // The find bar sits above the groups and is shown on demand
+    let editor_area = GtkBox::new(Orientation::Vertical, 0);
+    editor_area.append(&groups.widget);
+
+    // Bottom panel (Find in Files, Problems, References), below the editor area and hidden
+    // until used
+    let bottom_panel = Notebook::new();
+    bottom_panel.set_size_request(-1, 200);
+    bottom_panel.set_visible(false);
+    let find_in_files = FindInFilesPanel::new(settings.clone());
+    bottom_panel.append_page(&find_in_files.widget, Some(&gtk4::Label::new(Some("Search"))));
+    let problems = ProblemsPanel::new();
+    bottom_panel.append_page(&problems.widget, Some(&gtk4::Label::new(Some("Problems"))));
+    let references = ReferencesPanel::new();
+    bottom_panel.append_page(&references.widget, Some(&gtk4::Label::new(Some("References"))));
+
+    let editor_paned = Paned::new(Orientation::Vertical);
+    editor_paned.set_start_child(Some(&editor_area));
+    editor_paned.set_end_child(Some(&bottom_panel));
+    editor_paned.set_resize_end_child(false);
+    editor_paned.set_shrink_end_child(false);
+    paned.set_end_child(Some(&editor_paned));
     vbox.append(&paned);
-    vbox.append(&status_bar);
+    vbox.append(&status_bar.widget);
 
     window.set_child(Some(&vbox));
 
     // Store references in Rc<RefCell<>> for sharing
     let editors: Rc<RefCell<Vec<Rc<Editor>>>> = Rc::new(RefCell::new(Vec::new()));
     let current_editor: Rc<RefCell<Option<Rc<Editor>>>> = Rc::new(RefCell::new(None));
+    let find_bar = FindBar::new(current_editor.clone());
+    editor_area.prepend(&find_bar.widget);
+
+    let symbol_indexer = SymbolIndexer::new(settings.clone(), file_explorer_rc.clone());
+    let workspace = Rc::new(Workspace {
+        window: window.clone(),
+        paned: paned.clone(),
+        groups,
+        editors: editors.clone(),
+        current_editor: current_editor.clone(),
+        find_bar: find_bar.clone(),
+        completion: CompletionPopup::new(editors.clone()),
+        hover: HoverPopup::new(symbol_indexer.clone()),
+        code_actions: CodeActionMenu::new(),
+        bottom_panel,
+        find_in_files: find_in_files.clone(),
+        problems: problems.clone(),
+        references: references.clone(),
+        status_bar: status_bar.clone(),
+        file_explorer: file_explorer_rc.clone(),
+        ss: ss.clone(),
+        current_theme: current_theme.clone(),
+        settings: settings.clone(),
+        settings_monitor: RefCell::new(None),
+        documents: RefCell::new(DocumentRegistry::default()),
+        commands: RefCell::new(CommandRegistry::default()),
+        quit_confirmed: Cell::new(false),
+        history: RefCell::new(NavigationHistory::default()),
+        navigating: Cell::new(false),
+        language_servers: LanguageServers::new(settings.clone()),
+        diagnostics: RefCell::new(DiagnosticStore::default()),
+        symbol_indexer,
+    });
+    register_builtin_commands(&mut workspace.commands.borrow_mut());
+    workspace.connect_group(&workspace.groups.active());
+    workspace.watch_settings();
+
+    // Language servers starting, or failing for good, show in the status bar
+    {
+        let status_bar_clone = status_bar.clone();
+        workspace.language_servers.connect_status(move |command, status| {
+            let text = match status {
+                Status::Starting => format!("Starting {}…", command),
+                Status::Running => format!("{} is running", command),
+                Status::Failed(message) => format!("Language server failed: {}", message),
+                Status::Stopped => return,
+            };
+            status_bar_clone.status_info_label.set_text(&text);
+        });
+    }
+
+    // GO TO LINE AND BACK / FORWARD ACTIONS
+    {
+        let action = SimpleAction::new("go-to-line", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| show_go_to_line(&workspace_clone));
+        app.add_action(&action);
+
+        let action = SimpleAction::new("navigate-back", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| workspace_clone.navigate(false));
+        app.add_action(&action);
+
+        let action = SimpleAction::new("navigate-forward", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| workspace_clone.navigate(true));
+        app.add_action(&action);
+    }
+
+    // SETTINGS ACTION (also behind the gear button)
+    {
+        let action = SimpleAction::new("settings", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            show_settings_dialog(&workspace_clone);
+        });
+
+        app.add_action(&action);
+        settings_btn.set_tooltip_text(Some("Settings"));
+        settings_btn.set_action_name(Some("app.settings"));
+    }
+
+    // COMMAND PALETTE ACTION
+    {
+        let action = SimpleAction::new("command-palette", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            show_command_palette(&workspace_clone);
+        });
+
+        app.add_action(&action);
+    }
 
     // NEW FILE ACTION
     {
         let action = SimpleAction::new("new", None);
-        let notebook_clone = notebook.clone();
-        let editors_clone = editors.clone();
-        let current_editor_clone = current_editor.clone();
-        let ss_clone = ss.clone();
-        let current_theme_clone = current_theme.clone();
-        let status_label_clone = status_label.clone();
-        let status_info_label_clone = status_info_label.clone();
-
-        action.connect_activate(move |_, _| {
-            let theme_clone = current_theme_clone.borrow().clone();
-            let editor = Editor::new("Untitled", None, None, ss_clone.clone(), theme_clone);
-            
-            let page_index = notebook_clone.append_page(
-                &editor.content_row(),
-                Some(&editor.header),
-            );
-            
-            notebook_clone.set_current_page(Some(page_index));
-            
-            editor.update(&status_label_clone, &status_info_label_clone);
-            
-            editors_clone.borrow_mut().push(editor.clone());
-            *current_editor_clone.borrow_mut() = Some(editor.clone());
-
-            // Connect close button
-            let notebook_clone2 = notebook_clone.clone();
-            let editors_clone2 = editors_clone.clone();
-            let editor_clone = editor.clone();
-            editor.close_button.connect_clicked(move |_| {
-                if let Some(page_num) = notebook_clone2.page_num(&editor_clone.content_row()) {
-                    notebook_clone2.remove_page(Some(page_num));
-                    editors_clone2.borrow_mut().retain(|e| !Rc::ptr_eq(e, &editor_clone));
-                }
-            });
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            workspace_clone.new_untitled();
         });
 
         app.add_action(&action);
@@ -163,13 +281,7 @@ pub fn build_ui(app: &Application) {
     {
         let action = SimpleAction::new("open", None);
         let window_clone = window.clone();
-        let notebook_clone = notebook.clone();
-        let editors_clone = editors.clone();
-        let current_editor_clone = current_editor.clone();
-        let ss_clone = ss.clone();
-        let current_theme_clone = current_theme.clone();
-        let status_label_clone = status_label.clone();
-        let status_info_label_clone = status_info_label.clone();
+        let workspace_clone = workspace.clone();
 
         action.connect_activate(move |_, _| {
             let dialog = gtk4::FileChooserDialog::new(
@@ -179,51 +291,11 @@ pub fn build_ui(app: &Application) {
                 &[("Cancel", gtk4::ResponseType::Cancel), ("Open", gtk4::ResponseType::Accept)],
             );
 
-            let notebook_clone2 = notebook_clone.clone();
-            let editors_clone2 = editors_clone.clone();
-            let current_editor_clone2 = current_editor_clone.clone();
-            let ss_clone2 = ss_clone.clone();
-            let current_theme_clone2 = current_theme_clone.clone();
-            let status_label_clone2 = status_label_clone.clone();
-            let status_info_label_clone2 = status_info_label_clone.clone();
-
+            let workspace_clone2 = workspace_clone.clone();
             dialog.connect_response(move |dialog, response| {
                 if response == gtk4::ResponseType::Accept {
-                    if let Some(file) = dialog.file() {
-                        if let Some(path) = file.path() {
-                            if let Ok(content) = std::fs::read_to_string(&path) {
-                                let theme_clone = current_theme_clone2.borrow().clone();
-                                let editor = Editor::new(
-                                    "File",
-                                    Some(content),
-                                    Some(path.clone()),
-                                    ss_clone2.clone(),
-                                    theme_clone,
-                                );
-
-                                let page_index = notebook_clone2.append_page(
-                                    &editor.content_row(),
-                                    Some(&editor.header),
-                                );
-
-                                notebook_clone2.set_current_page(Some(page_index));
-                                editor.update(&status_label_clone2, &status_info_label_clone2);
-                                
-                                editors_clone2.borrow_mut().push(editor.clone());
-                                *current_editor_clone2.borrow_mut() = Some(editor.clone());
-
-                                // Connect close button
-                                let notebook_clone3 = notebook_clone2.clone();
-                                let editors_clone3 = editors_clone2.clone();
-                                let editor_clone = editor.clone();
-                                editor.close_button.connect_clicked(move |_| {
-                                    if let Some(page_num) = notebook_clone3.page_num(&editor_clone.content_row()) {
-                                        notebook_clone3.remove_page(Some(page_num));
-                                        editors_clone3.borrow_mut().retain(|e| !Rc::ptr_eq(e, &editor_clone));
-                                    }
-                                });
-                            }
-                        }
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        workspace_clone2.open_file(path);
                     }
                 }
                 dialog.close();
@@ -235,42 +307,28 @@ pub fn build_ui(app: &Application) {
         app.add_action(&action);
     }
 
+    // QUICK OPEN ACTION
+    {
+        let action = SimpleAction::new("quick-open", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            show_quick_open(&workspace_clone);
+        });
+
+        app.add_action(&action);
+    }
+
     // SAVE ACTION
     {
         let action = SimpleAction::new("save", None);
         let current_editor_clone = current_editor.clone();
-        let window_clone = window.clone();
+        let workspace_clone = workspace.clone();
 
         action.connect_activate(move |_, _| {
             if let Some(editor) = current_editor_clone.borrow().as_ref() {
-                let current_file = editor.current_file.borrow().clone();
-
-                if let Some(path) = current_file {
-                    // Save to existing file
-                    let _ = editor.save_to_path(&path);
-                } else {
-                    // Show save dialog
-                    let dialog = gtk4::FileChooserDialog::new(
-                        Some("Save File"),
-                        Some(&window_clone),
-                        gtk4::FileChooserAction::Save,
-                        &[("Cancel", gtk4::ResponseType::Cancel), ("Save", gtk4::ResponseType::Accept)],
-                    );
-
-                    let editor_clone = editor.clone();
-                    dialog.connect_response(move |dialog, response| {
-                        if response == gtk4::ResponseType::Accept {
-                            if let Some(file) = dialog.file() {
-                                if let Some(path) = file.path() {
-                                    let _ = editor_clone.save_to_path(&path);
-                                }
-                            }
-                        }
-                        dialog.close();
-                    });
-
-                    dialog.show();
-                }
+                // Untitled buffers ask for a file name first
+                workspace_clone.save_editor(editor, |_| {});
             }
         });
 
@@ -281,31 +339,24 @@ pub fn build_ui(app: &Application) {
     {
         let action = SimpleAction::new("save-as", None);
         let current_editor_clone = current_editor.clone();
-        let window_clone = window.clone();
+        let workspace_clone = workspace.clone();
 
         action.connect_activate(move |_, _| {
             if let Some(editor) = current_editor_clone.borrow().as_ref() {
-                let dialog = gtk4::FileChooserDialog::new(
-                    Some("Save File As"),
-                    Some(&window_clone),
-                    gtk4::FileChooserAction::Save,
-                    &[("Cancel", gtk4::ResponseType::Cancel), ("Save", gtk4::ResponseType::Accept)],
-                );
+                workspace_clone.save_editor_as(editor, |_| {});
+            }
+        });
 
-                let editor_clone = editor.clone();
-                dialog.connect_response(move |dialog, response| {
-                    if response == gtk4::ResponseType::Accept {
-                        if let Some(file) = dialog.file() {
-                            if let Some(path) = file.path() {
-                                let _ = editor_clone.save_to_path(&path);
-                            }
-                        }
-                    }
-                    dialog.close();
-                });
+        app.add_action(&action);
+    }
 
-                dialog.show();
-            }
+    // SAVE ALL ACTION
+    {
+        let action = SimpleAction::new("save-all", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            workspace_clone.save_all(|_| {});
         });
 
         app.add_action(&action);
@@ -396,54 +447,217 @@ pub fn build_ui(app: &Application) {
         app.add_action(&action);
     }
 
-    // FIND ACTION
+    // FIND / REPLACE ACTIONS (the inline find bar; replace also shows the replace row).
+    // Where searching starts is recorded, so Back returns there.
     {
         let action = SimpleAction::new("find", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| {
+            workspace_clone.record_jump();
+            workspace_clone.find_bar.show(false);
+        });
+        app.add_action(&action);
+
+        let action = SimpleAction::new("replace", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| {
+            workspace_clone.record_jump();
+            workspace_clone.find_bar.show(true);
+        });
+        app.add_action(&action);
+
+        let action = SimpleAction::new("find-next", None);
+        let find_bar_clone = find_bar.clone();
+        action.connect_activate(move |_, _| find_bar_clone.find_next(false));
+        app.add_action(&action);
+
+        let action = SimpleAction::new("find-previous", None);
+        let find_bar_clone = find_bar.clone();
+        action.connect_activate(move |_, _| find_bar_clone.find_next(true));
+        app.add_action(&action);
+    }
+
+    // FIND IN FILES ACTION (searches the explorer root unless another folder was chosen)
+    {
+        let action = SimpleAction::new("find-in-files", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            let selected = workspace_clone.current_editor.borrow().as_ref().and_then(|editor| {
+                let buffer = &editor.main_buffer;
+                let (start, end) = buffer.selection_bounds()?;
+                let text = buffer.text(&start, &end, false);
+                (!text.contains('\n')).then(|| text.to_string())
+            });
+            let root = workspace_clone.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+            workspace_clone.show_panel(&workspace_clone.find_in_files.widget);
+            workspace_clone.find_in_files.focus(root.as_deref(), selected.as_deref());
+        });
+
+        app.add_action(&action);
+    }
+    {
+        let workspace_clone = workspace.clone();
+        find_in_files.connect_result_activated(move |path, line, column| {
+            workspace_clone.open_file_at(path, line, column);
+        });
+
+        let workspace_clone = workspace.clone();
+        find_in_files.connect_replace(move |files| workspace_clone.replace_in_files(files));
+    }
+
+    // PROBLEMS ACTIONS (the diagnostics panel, and filling it with `cargo check`)
+    {
+        let action = SimpleAction::new("show-problems", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| workspace_clone.show_panel(&workspace_clone.problems.widget));
+        app.add_action(&action);
+
+        let action = SimpleAction::new("cargo-check", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| workspace_clone.run_cargo_check());
+        app.add_action(&action);
+
+        let workspace_clone = workspace.clone();
+        problems.connect_activated(move |path, line, column| {
+            workspace_clone.open_file_at(path, line, column);
+        });
+
+        let workspace_weak = Rc::downgrade(&workspace);
+        workspace.language_servers.connect_diagnostics(move |path, origin, diagnostics| {
+            if let Some(workspace) = workspace_weak.upgrade() {
+                workspace.set_diagnostics(path, origin, diagnostics);
+            }
+        });
+    }
+
+    // CODE NAVIGATION ACTIONS (Ctrl+click and hovering are handled by each view)
+    {
+        let action = SimpleAction::new("go-to-definition", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| {
+            let editor = workspace_clone.current_editor.borrow().clone();
+            if let Some(editor) = editor {
+                workspace_clone.go_to_definition(&editor, editor.cursor_offset() as usize);
+            }
+        });
+        app.add_action(&action);
+
+        let action = SimpleAction::new("find-references", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| {
+            let editor = workspace_clone.current_editor.borrow().clone();
+            if let Some(editor) = editor {
+                workspace_clone.find_references(&editor, editor.cursor_offset() as usize);
+            }
+        });
+        app.add_action(&action);
+
+        let workspace_clone = workspace.clone();
+        references.connect_activated(move |path, line, column| {
+            workspace_clone.open_file_at(path, line, column);
+        });
+    }
+
+    // RENAME AND CODE ACTIONS (the lightbulb in the gutter opens the same menu)
+    {
+        let action = SimpleAction::new("rename-symbol", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| {
+            let editor = workspace_clone.current_editor.borrow().clone();
+            if let Some(editor) = editor {
+                workspace_clone.rename_symbol(&editor, editor.cursor_offset() as usize);
+            }
+        });
+        app.add_action(&action);
+
+        let action = SimpleAction::new("code-actions", None);
+        let workspace_clone = workspace.clone();
+        action.connect_activate(move |_, _| {
+            let editor = workspace_clone.current_editor.borrow().clone();
+            if let Some(editor) = editor {
+                workspace_clone.code_actions.show(&editor);
+            }
+        });
+        app.add_action(&action);
+
+        let workspace_weak = Rc::downgrade(&workspace);
+        workspace.code_actions.connect_chosen(move |editor, action| {
+            if let Some(workspace) = workspace_weak.upgrade() {
+                workspace.run_code_action(editor, action);
+            }
+        });
+
+        let workspace_weak = Rc::downgrade(&workspace);
+        workspace.language_servers.connect_apply_edit(move |edit, encoding| {
+            let Some(workspace) = workspace_weak.upgrade() else {
+                return false;
+            };
+            workspace.apply_workspace_edit("Edit from the language server", edit, encoding)
+        });
+    }
+
+    // TOGGLE PANEL ACTION
+    {
+        let action = SimpleAction::new("toggle-panel", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            let panel = &workspace_clone.bottom_panel;
+            panel.set_visible(!panel.is_visible());
+        });
+
+        app.add_action(&action);
+    }
+
+    // COMPLETION ACTION (the popup also opens by itself on the server's trigger characters)
+    {
+        let action = SimpleAction::new("trigger-completion", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            let editor = workspace_clone.current_editor.borrow().clone();
+            if let Some(editor) = editor {
+                workspace_clone.completion.trigger(&editor, None);
+            }
+        });
+
+        app.add_action(&action);
+    }
+
+    // MULTI-CURSOR ACTIONS
+    {
+        let action = SimpleAction::new("add-next-occurrence", None);
         let current_editor_clone = current_editor.clone();
-        let window_clone = window.clone();
 
         action.connect_activate(move |_, _| {
             if let Some(editor) = current_editor_clone.borrow().as_ref() {
-                // Upcast ApplicationWindow to Window
-                let window_ref: &gtk4::Window = window_clone.upcast_ref();
-                let find_dialog = FindReplaceDialog::new(window_ref, editor.clone());
-                
-                // If there's selected text, use it as the initial find text
-                if let Some((start, end)) = editor.main_buffer.selection_bounds() {
-                    let selected = editor.main_buffer.text(&start, &end, false);
-                    if !selected.is_empty() && !selected.contains('\n') {
-                        find_dialog.set_find_text(&selected);
-                    }
-                }
-                
-                find_dialog.show();
+                editor.add_next_occurrence();
+            }
+        });
+
+        app.add_action(&action);
+
+        let action = SimpleAction::new("select-all-occurrences", None);
+        let current_editor_clone = current_editor.clone();
+
+        action.connect_activate(move |_, _| {
+            if let Some(editor) = current_editor_clone.borrow().as_ref() {
+                editor.select_all_occurrences();
             }
         });
 
         app.add_action(&action);
     }
 
-    // REPLACE ACTION (same as find, but with replace tab focused)
+    // JUMP TO MATCHING BRACKET ACTION
     {
-        let action = SimpleAction::new("replace", None);
+        let action = SimpleAction::new("jump-to-bracket", None);
         let current_editor_clone = current_editor.clone();
-        let window_clone = window.clone();
 
         action.connect_activate(move |_, _| {
             if let Some(editor) = current_editor_clone.borrow().as_ref() {
-                // Upcast ApplicationWindow to Window
-                let window_ref: &gtk4::Window = window_clone.upcast_ref();
-                let find_dialog = FindReplaceDialog::new(window_ref, editor.clone());
-                
-                // If there's selected text, use it as the initial find text
-                if let Some((start, end)) = editor.main_buffer.selection_bounds() {
-                    let selected = editor.main_buffer.text(&start, &end, false);
-                    if !selected.is_empty() && !selected.contains('\n') {
-                        find_dialog.set_find_text(&selected);
-                    }
-                }
-                
-                find_dialog.show();
+                editor.jump_to_matching_bracket();
             }
         });
 
@@ -467,69 +681,143 @@ pub fn build_ui(app: &Application) {
     // TOGGLE THEME ACTION
     {
         let action = SimpleAction::new("toggle-theme", None);
-        let current_theme_mode_clone = current_theme_mode.clone();
-        let current_theme_clone = current_theme.clone();
-        let editors_clone = editors.clone();
+        let workspace_clone = workspace.clone();
 
         action.connect_activate(move |_, _| {
-            // Toggle theme mode
-            let new_mode = {
-                let mut mode = current_theme_mode_clone.borrow_mut();
-                *mode = match *mode {
-                    ThemeMode::Dark => ThemeMode::Light,
-                    ThemeMode::Light => ThemeMode::Dark,
-                };
-                *mode
-            };
+            // Toggling is remembered in the settings file
+            let mut settings = workspace_clone.settings.borrow().clone();
+            settings.theme = settings.theme.toggled();
+            if let Err(e) = settings.save() {
+                eprintln!("Failed to save settings: {}", e);
+            }
+            workspace_clone.apply_settings(settings);
+        });
 
-            // Update CSS
-            crate::load_css(new_mode);
-
-            // Load new syntax highlighting theme
-            let ts = ThemeSet::load_defaults();
-            let theme_name = new_mode.syntax_theme_name();
-            let new_theme = if let Some(theme) = ts.themes.get(theme_name) {
-                Rc::new(theme.clone())
-            } else {
-                // Fallback to first available theme if the named theme doesn't exist
-                eprintln!("Warning: Theme '{}' not found, using fallback", theme_name);
-                Rc::new(ts.themes.values().next().unwrap().clone())
-            };
-            *current_theme_clone.borrow_mut() = new_theme.clone();
+        app.add_action(&action);
+    }
+
+    // SET LANGUAGE ACTION (per-tab syntax override; empty name restores auto-detection)
+    {
+        let action = SimpleAction::new("set-language", Some(gtk4::glib::VariantTy::STRING));
+        let current_editor_clone = current_editor.clone();
+        let status_bar_clone = status_bar.clone();
 
-            // Update all open editors with the new theme
-            for editor in editors_clone.borrow().iter() {
-                editor.set_theme(new_theme.clone());
+        action.connect_activate(move |_, param| {
+            let name = param.and_then(|v| v.get::<String>()).unwrap_or_default();
+            if let Some(editor) = current_editor_clone.borrow().as_ref() {
+                let override_name = if name.is_empty() { None } else { Some(name) };
+                editor.set_syntax_override(override_name);
+                status_bar_clone.show(&editor.update());
             }
         });
 
         app.add_action(&action);
     }
 
-    // Update current editor when switching tabs
+    // FOLDING ACTIONS (fold-level takes the level, "1" being the top level)
     {
-        let current_editor_clone = current_editor.clone();
-        let editors_clone = editors.clone();
-        let status_label_clone = status_label.clone();
-        let status_info_label_clone = status_info_label.clone();
-
-        notebook.connect_switch_page(move |_notebook, page, _page_num| {
-            let editors = editors_clone.borrow();
-            for editor in editors.iter() {
-                if editor.content_row().upcast_ref::<gtk4::Widget>() == page {
-                    *current_editor_clone.borrow_mut() = Some(editor.clone());
-                    editor.update(&status_label_clone, &status_info_label_clone);
-                    break;
+        let editor_actions: [(&str, EditorAction); 4] = [
+            ("fold", Editor::fold_at_cursor),
+            ("unfold", Editor::unfold_at_cursor),
+            ("fold-all", Editor::fold_all),
+            ("unfold-all", Editor::unfold_all),
+        ];
+        for (name, run) in editor_actions {
+            let action = SimpleAction::new(name, None);
+            let current_editor_clone = current_editor.clone();
+            action.connect_activate(move |_, _| {
+                if let Some(editor) = current_editor_clone.borrow().as_ref() {
+                    run(editor);
                 }
+            });
+            app.add_action(&action);
+        }
+
+        let action = SimpleAction::new("fold-level", Some(gtk4::glib::VariantTy::STRING));
+        let current_editor_clone = current_editor.clone();
+        action.connect_activate(move |_, param| {
+            let level = param.and_then(|v| v.get::<String>()).and_then(|level| level.parse().ok());
+            if let (Some(editor), Some(level)) = (current_editor_clone.borrow().as_ref(), level) {
+                editor.fold_level(level);
+            }
+        });
+        app.add_action(&action);
+    }
+
+    // CONVERT INDENTATION ACTION ("tabs" or "spaces")
+    {
+        let action = SimpleAction::new("convert-indentation", Some(gtk4::glib::VariantTy::STRING));
+        let current_editor_clone = current_editor.clone();
+        let status_bar_clone = status_bar.clone();
+
+        action.connect_activate(move |_, param| {
+            let target = param.and_then(|v| v.get::<String>()).unwrap_or_default();
+            if let Some(editor) = current_editor_clone.borrow().as_ref() {
+                editor.convert_indentation(target == "tabs");
+                status_bar_clone.show(&editor.update());
+            }
+        });
+
+        app.add_action(&action);
+    }
+
+    // Toggle line endings (LF/CRLF) of the current file
+    {
+        let action = SimpleAction::new("toggle-line-ending", None);
+        let current_editor_clone = current_editor.clone();
+        let status_bar_clone = status_bar.clone();
+
+        action.connect_activate(move |_, _| {
+            if let Some(editor) = current_editor_clone.borrow().as_ref() {
+                editor.set_line_ending(editor.format.get().line_ending.toggled());
+                status_bar_clone.show(&editor.update());
             }
         });
+
+        app.add_action(&action);
+    }
+
+    // SPLIT ACTIONS (the new pane shows the current document too)
+    {
+        let action = SimpleAction::new("split-right", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            workspace_clone.split(Orientation::Horizontal);
+        });
+
+        app.add_action(&action);
+
+        let action = SimpleAction::new("split-down", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            workspace_clone.split(Orientation::Vertical);
+        });
+
+        app.add_action(&action);
+    }
+
+    // MOVE TAB TO NEXT GROUP ACTION
+    {
+        let action = SimpleAction::new("move-tab-to-next-group", None);
+        let workspace_clone = workspace.clone();
+
+        action.connect_activate(move |_, _| {
+            workspace_clone.move_to_next_group();
+        });
+
+        app.add_action(&action);
     }
 
     // Set up keyboard shortcuts
     app.set_accels_for_action("app.new", &["<Ctrl>N"]);
     app.set_accels_for_action("app.open", &["<Ctrl>O"]);
+    app.set_accels_for_action("app.quick-open", &["<Ctrl>P"]);
+    app.set_accels_for_action("app.command-palette", &["<Ctrl><Shift>P"]);
     app.set_accels_for_action("app.save", &["<Ctrl>S"]);
     app.set_accels_for_action("app.save-as", &["<Ctrl><Shift>S"]);
+    app.set_accels_for_action("app.save-all", &["<Ctrl><Alt>S"]);
     app.set_accels_for_action("app.quit", &["<Ctrl>Q"]);
     app.set_accels_for_action("app.undo", &["<Ctrl>Z"]);
     app.set_accels_for_action("app.redo", &["<Ctrl><Shift>Z"]);
@@ -538,79 +826,42 @@ pub fn build_ui(app: &Application) {
     app.set_accels_for_action("app.paste", &["<Ctrl>V"]);
     app.set_accels_for_action("app.find", &["<Ctrl>F"]);
     app.set_accels_for_action("app.replace", &["<Ctrl>H"]);
-    app.set_accels_for_action("app.toggle-theme", &["<Ctrl>T"]);
-
-    // Create initial empty tab
-    let initial_editor = Editor::new("Untitled", None, None, ss.clone(), theme.clone());
-    notebook.append_page(&initial_editor.content_row(), Some(&initial_editor.header));
-    initial_editor.update(&status_label, &status_info_label);
-    
-    editors.borrow_mut().push(initial_editor.clone());
-    *current_editor.borrow_mut() = Some(initial_editor.clone());
-
-    // Connect close button for initial tab
-    {
-        let notebook_clone = notebook.clone();
-        let editors_clone = editors.clone();
-        let editor_clone = initial_editor.clone();
-        initial_editor.close_button.connect_clicked(move |_| {
-            if let Some(page_num) = notebook_clone.page_num(&editor_clone.content_row()) {
-                notebook_clone.remove_page(Some(page_num));
-                editors_clone.borrow_mut().retain(|e| !Rc::ptr_eq(e, &editor_clone));
-            }
-        });
+    app.set_accels_for_action("app.find-next", &["F3"]);
+    app.set_accels_for_action("app.find-previous", &["<Shift>F3"]);
+    app.set_accels_for_action("app.find-in-files", &["<Ctrl><Shift>F"]);
+    app.set_accels_for_action("app.toggle-panel", &["<Ctrl>J"]);
+    app.set_accels_for_action("app.show-problems", &["<Ctrl><Shift>M"]);
+    app.set_accels_for_action("app.trigger-completion", &["<Ctrl>space"]);
+    app.set_accels_for_action("app.go-to-definition", &["F12"]);
+    app.set_accels_for_action("app.find-references", &["<Shift>F12"]);
+    app.set_accels_for_action("app.rename-symbol", &["F2"]);
+    app.set_accels_for_action("app.code-actions", &["<Ctrl>period"]);
+    app.set_accels_for_action("app.add-next-occurrence", &["<Ctrl>D"]);
+    app.set_accels_for_action("app.select-all-occurrences", &["<Ctrl><Shift>L"]);
+    app.set_accels_for_action("app.jump-to-bracket", &["<Ctrl>M"]);
+    app.set_accels_for_action("app.go-to-line", &["<Ctrl>G"]);
+    app.set_accels_for_action("app.navigate-back", &["<Alt>Left"]);
+    app.set_accels_for_action("app.navigate-forward", &["<Alt>Right"]);
+    app.set_accels_for_action("app.fold", &["<Ctrl><Shift>bracketleft"]);
+    app.set_accels_for_action("app.unfold", &["<Ctrl><Shift>bracketright"]);
+    app.set_accels_for_action("app.fold-all", &["<Ctrl><Alt>bracketleft"]);
+    app.set_accels_for_action("app.unfold-all", &["<Ctrl><Alt>bracketright"]);
+    for level in 1..=FOLD_LEVELS {
+        app.set_accels_for_action(&format!("app.fold-level::{}", level), &[format!("<Ctrl><Alt>{}", level).as_str()]);
     }
+    app.set_accels_for_action("app.toggle-theme", &["<Ctrl>T"]);
+    app.set_accels_for_action("app.split-right", &["<Ctrl>backslash"]);
+    app.set_accels_for_action("app.split-down", &["<Ctrl><Shift>backslash"]);
+    app.set_accels_for_action("app.move-tab-to-next-group", &["<Ctrl><Alt>backslash"]);
 
     // Connect file explorer actions
     {
         // File activation (double-click or Enter)
-        let file_explorer_clone = file_explorer_rc.clone();
-        let notebook_clone = notebook.clone();
-        let editors_clone = editors.clone();
-        let current_editor_clone = current_editor.clone();
-        let ss_clone = ss.clone();
-        let current_theme_clone = current_theme.clone();
-        let status_label_clone = status_label.clone();
-        let status_info_label_clone = status_info_label.clone();
+        let workspace_clone = workspace.clone();
 
         file_explorer_rc.borrow().connect_row_activated(move |path_buf, is_dir| {
             if !is_dir {
-                // Open the file
-                if let Ok(content) = std::fs::read_to_string(&path_buf) {
-                    let theme_clone = current_theme_clone.borrow().clone();
-                    let editor = Editor::new(
-                        "File",
-                        Some(content),
-                        Some(path_buf.clone()),
-                        ss_clone.clone(),
-                        theme_clone,
-                    );
-
-                    let page_index = notebook_clone.append_page(
-                        &editor.content_row(),
-                        Some(&editor.header),
-                    );
-
-                    notebook_clone.set_current_page(Some(page_index));
-                    editor.update(&status_label_clone, &status_info_label_clone);
-                    
-                    editors_clone.borrow_mut().push(editor.clone());
-                    *current_editor_clone.borrow_mut() = Some(editor.clone());
-
-                    // Highlight the file in the explorer
-                    file_explorer_clone.borrow().highlight_file(&path_buf);
-
-                    // Connect close button
-                    let notebook_clone2 = notebook_clone.clone();
-                    let editors_clone2 = editors_clone.clone();
-                    let editor_clone = editor.clone();
-                    editor.close_button.connect_clicked(move |_| {
-                        if let Some(page_num) = notebook_clone2.page_num(&editor_clone.content_row()) {
-                            notebook_clone2.remove_page(Some(page_num));
-                            editors_clone2.borrow_mut().retain(|e| !Rc::ptr_eq(e, &editor_clone));
-                        }
-                    });
-                }
+                workspace_clone.open_file(path_buf);
             }
         });
 
@@ -627,23 +878,6 @@ pub fn build_ui(app: &Application) {
                 }
             }
         });
-
-        // Update file explorer highlighting when switching tabs
-        let file_explorer_clone3 = file_explorer_rc.clone();
-        let editors_clone2 = editors.clone();
-        let notebook_clone = notebook.clone();
-        notebook_clone.connect_switch_page(move |_notebook, page, _page_num| {
-            // Find which editor corresponds to this page
-            let editors = editors_clone2.borrow();
-            for editor in editors.iter() {
-                if editor.content_row().upcast_ref::<gtk4::Widget>() == page {
-                    if let Some(ref path) = *editor.current_file.borrow() {
-                        file_explorer_clone3.borrow().highlight_file(path);
-                    }
-                    break;
-                }
-            }
-        });
     }
 
     // File Explorer Context Menu Actions
@@ -750,6 +984,7 @@ pub fn build_ui(app: &Application) {
         let action = SimpleAction::new("explorer-delete", None);
         let window_clone = window.clone();
         let file_explorer_clone = file_explorer_rc.clone();
+        let workspace_clone = workspace.clone();
 
         action.connect_activate(move |_, _| {
             if let Some(selected_path) = file_explorer_clone.borrow().get_selected_path() {
@@ -763,14 +998,15 @@ pub fn build_ui(app: &Application) {
                     gtk4::DialogFlags::MODAL,
                     MessageType::Question,
                     ButtonsType::YesNo,
-                    &format!("Are you sure you want to delete '{}'?", file_name),
+                    format!("Are you sure you want to delete '{}'?", file_name),
                 );
 
-                let file_explorer_clone2 = file_explorer_clone.clone();
+                let workspace_clone2 = workspace_clone.clone();
                 let selected_path_clone = selected_path.clone();
                 dialog.connect_response(move |dialog, response| {
                     if response == ResponseType::Yes {
-                        if let Err(e) = file_explorer_clone2.borrow().delete_file(&selected_path_clone) {
+                        // Also closes the tabs of deleted files
+                        if let Err(e) = workspace_clone2.delete_path(&selected_path_clone) {
                             eprintln!("Failed to delete: {}", e);
                         }
                     }
@@ -787,6 +1023,7 @@ pub fn build_ui(app: &Application) {
         let action = SimpleAction::new("explorer-rename", None);
         let window_clone = window.clone();
         let file_explorer_clone = file_explorer_rc.clone();
+        let workspace_clone = workspace.clone();
 
         action.connect_activate(move |_, _| {
             if let Some(selected_path) = file_explorer_clone.borrow().get_selected_path() {
@@ -812,13 +1049,14 @@ pub fn build_ui(app: &Application) {
                 entry.set_margin_end(10);
                 content_area.append(&entry);
 
-                let file_explorer_clone2 = file_explorer_clone.clone();
+                let workspace_clone2 = workspace_clone.clone();
                 let selected_path_clone = selected_path.clone();
                 dialog.connect_response(move |dialog, response| {
                     if response == ResponseType::Accept {
                         let new_name = entry.text();
                         if !new_name.is_empty() && new_name.as_str() != current_name {
-                            if let Err(e) = file_explorer_clone2.borrow().rename_file(&selected_path_clone, &new_name) {
+                            // Also renames the tabs of the file or the files inside the folder
+                            if let Err(e) = workspace_clone2.rename_path(&selected_path_clone, &new_name) {
                                 eprintln!("Failed to rename: {}", e);
                             }
                         }
@@ -833,6 +1071,25 @@ pub fn build_ui(app: &Application) {
         app.add_action(&action);
     }
 
+    // Reopen the previous session's tabs, or start with an empty one
+    if !workspace.restore_session() {
+        workspace.new_untitled();
+    }
+
+    // Ask about unsaved files, then save the session when the window closes (also reached
+    // through the Quit action)
+    {
+        let workspace_clone = workspace.clone();
+        window.connect_close_request(move |_| {
+            if !workspace_clone.confirm_quit() {
+                return gtk4::Inhibit(true);
+            }
+            workspace_clone.save_session();
+            workspace_clone.language_servers.shutdown();
+            gtk4::Inhibit(false)
+        });
+    }
+
     window.present();
 }
 
@@ -844,8 +1101,10 @@ fn create_file_menu() -> MenuButton {
     let menu = gtk4::gio::Menu::new();
     menu.append(Some("New"), Some("app.new"));
     menu.append(Some("Open"), Some("app.open"));
+    menu.append(Some("Quick Open"), Some("app.quick-open"));
     menu.append(Some("Save"), Some("app.save"));
     menu.append(Some("Save As"), Some("app.save-as"));
+    menu.append(Some("Save All"), Some("app.save-all"));
     menu.append(Some("Quit"), Some("app.quit"));
 
     let popover = PopoverMenu::from_model(Some(&menu));
@@ -867,6 +1126,37 @@ fn create_edit_menu() -> MenuButton {
     menu.append(Some("Paste"), Some("app.paste"));
     menu.append(Some("Find"), Some("app.find"));
     menu.append(Some("Replace"), Some("app.replace"));
+    menu.append(Some("Find Next"), Some("app.find-next"));
+    menu.append(Some("Find Previous"), Some("app.find-previous"));
+    menu.append(Some("Find in Files"), Some("app.find-in-files"));
+
+    let go = gtk4::gio::Menu::new();
+    go.append(Some("Go to Line…"), Some("app.go-to-line"));
+    go.append(Some("Go to Definition"), Some("app.go-to-definition"));
+    go.append(Some("Find References"), Some("app.find-references"));
+    go.append(Some("Jump to Matching Bracket"), Some("app.jump-to-bracket"));
+    go.append(Some("Back"), Some("app.navigate-back"));
+    go.append(Some("Forward"), Some("app.navigate-forward"));
+    menu.append_section(None, &go);
+
+    let completion = gtk4::gio::Menu::new();
+    completion.append(Some("Trigger Completion"), Some("app.trigger-completion"));
+    menu.append_section(None, &completion);
+
+    let refactoring = gtk4::gio::Menu::new();
+    refactoring.append(Some("Rename Symbol…"), Some("app.rename-symbol"));
+    refactoring.append(Some("Code Actions…"), Some("app.code-actions"));
+    menu.append_section(None, &refactoring);
+
+    let selection = gtk4::gio::Menu::new();
+    selection.append(Some("Add Next Occurrence"), Some("app.add-next-occurrence"));
+    selection.append(Some("Select All Occurrences"), Some("app.select-all-occurrences"));
+    menu.append_section(None, &selection);
+
+    let indentation = gtk4::gio::Menu::new();
+    indentation.append(Some("Convert Indentation to Spaces"), Some("app.convert-indentation::spaces"));
+    indentation.append(Some("Convert Indentation to Tabs"), Some("app.convert-indentation::tabs"));
+    menu.append_section(None, &indentation);
 
     let popover = PopoverMenu::from_model(Some(&menu));
     menu_button.set_popover(Some(&popover));
@@ -880,8 +1170,30 @@ fn create_view_menu() -> MenuButton {
     menu_button.style_context().add_class("menubutton");
 
     let menu = gtk4::gio::Menu::new();
+    menu.append(Some("Command Palette"), Some("app.command-palette"));
     menu.append(Some("Toggle Word Wrap"), Some("app.toggle-wrap"));
     menu.append(Some("Toggle Theme"), Some("app.toggle-theme"));
+    menu.append(Some("Toggle Panel"), Some("app.toggle-panel"));
+    menu.append(Some("Problems"), Some("app.show-problems"));
+    menu.append(Some("Run cargo check"), Some("app.cargo-check"));
+
+    let splits = gtk4::gio::Menu::new();
+    splits.append(Some("Split Right"), Some("app.split-right"));
+    splits.append(Some("Split Down"), Some("app.split-down"));
+    splits.append(Some("Move Tab to Next Group"), Some("app.move-tab-to-next-group"));
+    menu.append_section(None, &splits);
+
+    let folding = gtk4::gio::Menu::new();
+    folding.append(Some("Fold"), Some("app.fold"));
+    folding.append(Some("Unfold"), Some("app.unfold"));
+    folding.append(Some("Fold All"), Some("app.fold-all"));
+    folding.append(Some("Unfold All"), Some("app.unfold-all"));
+    let levels = gtk4::gio::Menu::new();
+    for level in 1..=FOLD_LEVELS {
+        levels.append(Some(&format!("Level {}", level)), Some(&format!("app.fold-level::{}", level)));
+    }
+    folding.append_submenu(Some("Fold Level"), &levels);
+    menu.append_section(None, &folding);
 
     let popover = PopoverMenu::from_model(Some(&menu));
     menu_button.set_popover(Some(&popover));
@@ -0,0 +1,114 @@
+use gtk4::prelude::*;
+use gtk4::{gdk, ApplicationWindow, Box as GtkBox, Entry, EventControllerKey, Inhibit, ListBox, Orientation, ScrolledWindow, Window};
+use std::rc::Rc;
+
+/// Popup with a search entry over a list of results, shared by Quick Open and the command
+/// palette. Up/Down move the selection while typing, Enter or a click picks a row, Escape
+/// closes it.
+pub struct Palette {
+    pub window: Window,
+    pub entry: Entry,
+    pub list: ListBox,
+}
+
+impl Palette {
+    pub fn new(parent: &ApplicationWindow, placeholder: &str) -> Self {
+        let window = Window::builder()
+            .transient_for(parent)
+            .modal(true)
+            .decorated(false)
+            .default_width(600)
+            .default_height(400)
+            .build();
+        window.style_context().add_class("palette");
+
+        let vbox = GtkBox::new(Orientation::Vertical, 6);
+        vbox.set_margin_top(8);
+        vbox.set_margin_bottom(8);
+        vbox.set_margin_start(8);
+        vbox.set_margin_end(8);
+
+        let entry = Entry::new();
+        entry.set_placeholder_text(Some(placeholder));
+
+        let list = ListBox::new();
+        list.set_selection_mode(gtk4::SelectionMode::Browse);
+        let scrolled = ScrolledWindow::builder()
+            .child(&list)
+            .vexpand(true)
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .build();
+
+        vbox.append(&entry);
+        vbox.append(&scrolled);
+        window.set_child(Some(&vbox));
+
+        let key_controller = EventControllerKey::new();
+        key_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        {
+            let (window, list, entry) = (window.clone(), list.clone(), entry.clone());
+            key_controller.connect_key_pressed(move |_, keyval, _keycode, _modifier| {
+                let step = match keyval {
+                    gdk::Key::Escape => {
+                        window.close();
+                        return Inhibit(true);
+                    }
+                    gdk::Key::Down => 1,
+                    gdk::Key::Up => -1,
+                    _ => return Inhibit(false),
+                };
+                let current = list.selected_row().map(|row| row.index()).unwrap_or(0);
+                if let Some(row) = list.row_at_index((current + step).max(0)) {
+                    list.select_row(Some(&row));
+                    // Focusing the row scrolls it into view; then keep typing in the entry
+                    row.grab_focus();
+                    entry.grab_focus_without_selecting();
+                }
+                Inhibit(true)
+            });
+        }
+        window.add_controller(key_controller);
+
+        Self { window, entry, list }
+    }
+
+    /// Replace the rows and select the first one.
+    pub fn set_rows(&self, rows: impl IntoIterator<Item = gtk4::Widget>) {
+        while let Some(row) = self.list.first_child() {
+            self.list.remove(&row);
+        }
+        for row in rows {
+            self.list.append(&row);
+        }
+        self.list.select_row(self.list.row_at_index(0).as_ref());
+    }
+
+    /// Call `refresh` with the query whenever it changes.
+    pub fn connect_query_changed(&self, refresh: impl Fn(&str) + 'static) {
+        self.entry.connect_changed(move |entry| refresh(&entry.text()));
+    }
+
+    /// Close the palette and call `chosen` with the index of the picked row.
+    pub fn connect_chosen(&self, chosen: impl Fn(usize) + 'static) {
+        let pick = {
+            let (window, list) = (self.window.clone(), self.list.clone());
+            Rc::new(move || {
+                let Some(row) = list.selected_row() else {
+                    return;
+                };
+                window.close();
+                chosen(row.index() as usize);
+            })
+        };
+        {
+            let pick = pick.clone();
+            self.entry.connect_activate(move |_| pick());
+        }
+        self.list.connect_row_activated(move |_, _| pick());
+    }
+
+    pub fn present(&self) {
+        self.window.present();
+        self.entry.grab_focus();
+    }
+}
@@ -0,0 +1,253 @@
+use gtk4::prelude::*;
+use gtk4::{
+    glib, Box as GtkBox, Button, CellRendererText, Label, Orientation, ScrolledWindow, TreePath, TreeStore, TreeView,
+    TreeViewColumn,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::diagnostics::{self, Diagnostic, DiagnosticStore};
+use crate::editor::severity_color;
+
+// Column indices for the problems TreeStore
+const COL_MARKUP: u32 = 0; // Row text
+const COL_FILE: u32 = 1; // Index into `listed`
+const COL_DIAGNOSTIC: u32 = 2; // Index into the file's diagnostics, -1 on file rows
+
+type CheckResult = io::Result<BTreeMap<PathBuf, Vec<Diagnostic>>>;
+
+/// Problems panel: the diagnostics of every file, from the language servers and `cargo check`,
+/// listed by file. Activating one jumps to it.
+pub struct ProblemsPanel {
+    pub widget: GtkBox,
+    status_label: Label,
+    store: TreeStore,
+    tree_view: TreeView,
+    /// Listed files, with full paths
+    listed: RefCell<Vec<(PathBuf, Vec<Diagnostic>)>>,
+    /// Whether a `cargo check` is running
+    checking: Cell<bool>,
+    /// Progress or failure of `cargo check`, shown next to the counts
+    check_status: RefCell<String>,
+    /// Counts shown in the status, kept to redraw it when `check_status` changes
+    counts: RefCell<(usize, usize)>,
+}
+
+impl ProblemsPanel {
+    pub fn new() -> Rc<Self> {
+        let widget = GtkBox::new(Orientation::Vertical, 4);
+        widget.style_context().add_class("problems");
+
+        let header = GtkBox::new(Orientation::Horizontal, 4);
+        let status_label = Label::new(Some("No problems"));
+        status_label.set_xalign(0.0);
+        status_label.set_hexpand(true);
+        status_label.style_context().add_class("dim-label");
+        let check_button = Button::with_label("Run cargo check");
+        check_button.set_action_name(Some("app.cargo-check"));
+        header.append(&status_label);
+        header.append(&check_button);
+
+        let store = TreeStore::new(&[
+            glib::Type::STRING, // Markup
+            glib::Type::I32,    // File index
+            glib::Type::I32,    // Diagnostic index
+        ]);
+        let tree_view = TreeView::with_model(&store);
+        tree_view.set_headers_visible(false);
+
+        let text_column = TreeViewColumn::new();
+        let text_renderer = CellRendererText::new();
+        text_renderer.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        text_column.pack_start(&text_renderer, true);
+        text_column.add_attribute(&text_renderer, "markup", COL_MARKUP as i32);
+        tree_view.append_column(&text_column);
+
+        let scrolled = ScrolledWindow::builder()
+            .child(&tree_view)
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .vscrollbar_policy(gtk4::PolicyType::Automatic)
+            .vexpand(true)
+            .build();
+
+        widget.append(&header);
+        widget.append(&scrolled);
+
+        Rc::new(Self {
+            widget,
+            status_label,
+            store,
+            tree_view,
+            listed: RefCell::new(Vec::new()),
+            checking: Cell::new(false),
+            check_status: RefCell::new(String::new()),
+            counts: RefCell::new((0, 0)),
+        })
+    }
+
+    /// Call `callback` with the file, zero-based line and column of an activated problem.
+    pub fn connect_activated(self: &Rc<Self>, callback: impl Fn(PathBuf, usize, usize) + 'static) {
+        let panel_weak = Rc::downgrade(self);
+        self.tree_view.connect_row_activated(move |tree_view, path, _column| {
+            let Some(panel) = panel_weak.upgrade() else {
+                return;
+            };
+            let Some((file, index)) = panel.row_indices(path) else {
+                return;
+            };
+            let Some(index) = index else {
+                // File rows fold their problems
+                if tree_view.row_expanded(path) {
+                    tree_view.collapse_row(path);
+                } else {
+                    tree_view.expand_row(path, false);
+                }
+                return;
+            };
+            let target = panel.listed.borrow().get(file).map(|(path, diagnostics)| (path.clone(), diagnostics[index].clone()));
+            if let Some((path, d)) = target {
+                callback(path, d.line, d.column);
+            }
+        });
+    }
+
+    /// List the files of `store`, with paths shown relative to `root`. Files folded by the
+    /// user stay folded.
+    pub fn update(&self, store: &DiagnosticStore, root: Option<&Path>) {
+        let files = store.files();
+        let folded: Vec<PathBuf> = {
+            let listed = self.listed.borrow();
+            listed
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !self.tree_view.row_expanded(&TreePath::from_indices(&[*index as i32])))
+                .map(|(_, (path, _))| path.clone())
+                .collect()
+        };
+        self.store.clear();
+
+        for (file_index, (path, diagnostics)) in files.iter().enumerate() {
+            let relative = root.and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+            let name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let dir = relative.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+            let markup = format!(
+                "<b>{}</b>  <span alpha=\"60%\">{}  ({})</span>",
+                glib::markup_escape_text(&name),
+                glib::markup_escape_text(&dir),
+                diagnostics.len()
+            );
+            let parent = self.store.insert_with_values(
+                None,
+                None,
+                &[(COL_MARKUP, &markup), (COL_FILE, &(file_index as i32)), (COL_DIAGNOSTIC, &-1i32)],
+            );
+            for (index, d) in diagnostics.iter().enumerate() {
+                self.store.insert_with_values(
+                    Some(&parent),
+                    None,
+                    &[
+                        (COL_MARKUP, &diagnostic_markup(d)),
+                        (COL_FILE, &(file_index as i32)),
+                        (COL_DIAGNOSTIC, &(index as i32)),
+                    ],
+                );
+            }
+            if !folded.contains(path) {
+                self.tree_view.expand_row(&self.store.path(&parent), false);
+            }
+        }
+        *self.listed.borrow_mut() = files;
+        *self.counts.borrow_mut() = store.counts();
+        self.refresh_status();
+    }
+
+    /// Run `cargo check` in `root` in the background, and call `done` with its diagnostics
+    /// when it finishes. Does nothing while a check is running.
+    pub fn check(self: &Rc<Self>, root: PathBuf, done: impl Fn(BTreeMap<PathBuf, Vec<Diagnostic>>) + 'static) {
+        if self.checking.replace(true) {
+            return;
+        }
+        self.set_check_status("Running cargo check…");
+
+        let (tx, rx) = glib::MainContext::channel::<CheckResult>(glib::Priority::default());
+        std::thread::Builder::new()
+            .name("cargo-check".to_string())
+            .spawn(move || {
+                let _ = tx.send(diagnostics::cargo_check(&root));
+            })
+            .expect("failed to spawn cargo check thread");
+
+        let panel_weak = Rc::downgrade(self);
+        rx.attach(None, move |result| {
+            let Some(panel) = panel_weak.upgrade() else {
+                return glib::Continue(false);
+            };
+            panel.checking.set(false);
+            match result {
+                Ok(files) => {
+                    panel.set_check_status("");
+                    done(files);
+                }
+                Err(e) => panel.set_check_status(&format!("cargo check failed: {}", e)),
+            }
+            glib::Continue(false)
+        });
+    }
+
+    /// Show `status` about `cargo check` next to the counts.
+    pub fn set_check_status(&self, status: &str) {
+        *self.check_status.borrow_mut() = status.to_string();
+        self.refresh_status();
+    }
+
+    fn refresh_status(&self) {
+        let (errors, warnings) = *self.counts.borrow();
+        let mut status = match (errors, warnings) {
+            (0, 0) if self.listed.borrow().is_empty() => "No problems".to_string(),
+            _ => format!("{} errors, {} warnings", errors, warnings),
+        };
+        let check_status = self.check_status.borrow();
+        if !check_status.is_empty() {
+            status.push_str(&format!("  ·  {}", check_status));
+        }
+        self.status_label.set_text(&status);
+    }
+
+    /// File index and, on problem rows, diagnostic index of the row at `path`.
+    fn row_indices(&self, path: &TreePath) -> Option<(usize, Option<usize>)> {
+        let iter = self.store.iter(path)?;
+        let file: i32 = self.store.get(&iter, COL_FILE as i32);
+        let index: i32 = self.store.get(&iter, COL_DIAGNOSTIC as i32);
+        Some((file as usize, usize::try_from(index).ok()))
+    }
+}
+
+/// A problem row: a dot in the severity's color, the first line of the message, then where it
+/// came from and its one-based position.
+fn diagnostic_markup(d: &Diagnostic) -> String {
+    let color = severity_color(d.severity);
+    let hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        (color.red() * 255.0).round() as u8,
+        (color.green() * 255.0).round() as u8,
+        (color.blue() * 255.0).round() as u8
+    );
+    let first_line = d.message.lines().next().unwrap_or_default();
+    let origin = match (&d.source, &d.code) {
+        (Some(source), Some(code)) => format!("{}({})  ", source, code),
+        (Some(source), None) => format!("{}  ", source),
+        (None, Some(code)) => format!("{}  ", code),
+        (None, None) => String::new(),
+    };
+    format!(
+        "<span foreground=\"{}\">●</span>  {}  <span alpha=\"60%\">{}Ln {}, Col {}</span>",
+        hex,
+        glib::markup_escape_text(first_line),
+        glib::markup_escape_text(&origin),
+        d.line + 1,
+        d.column + 1
+    )
+}
@@ -0,0 +1,74 @@
+use gtk4::prelude::*;
+use gtk4::{glib, Label};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use super::palette::Palette;
+use super::Workspace;
+use crate::quick_open::{self, QuickOpenMatch};
+
+/// Ctrl+P picker: fuzzy-find a file under the explorer root and open it, or focus its tab if
+/// it's already open.
+pub fn show_quick_open(workspace: &Rc<Workspace>) {
+    let Some(root) = workspace.file_explorer.borrow().root_directory().map(Path::to_path_buf) else {
+        return;
+    };
+
+    let palette = Rc::new(Palette::new(&workspace.window, "Indexing files…"));
+    let files: Rc<RefCell<Vec<PathBuf>>> = Rc::new(RefCell::new(Vec::new()));
+    let shown: Rc<RefCell<Vec<QuickOpenMatch>>> = Rc::new(RefCell::new(Vec::new()));
+    let matcher = Rc::new(SkimMatcherV2::default());
+
+    let refresh = {
+        let (palette, files, shown) = (palette.clone(), files.clone(), shown.clone());
+        Rc::new(move |query: &str| {
+            let matches = quick_open::rank(&matcher, query, &files.borrow());
+            palette.set_rows(matches.iter().map(|m| {
+                let label = Label::new(None);
+                label.set_markup(&quick_open::highlight_markup(&m.display, &m.indices));
+                label.set_xalign(0.0);
+                label.set_ellipsize(gtk4::pango::EllipsizeMode::Start);
+                label.upcast()
+            }));
+            *shown.borrow_mut() = matches;
+        })
+    };
+
+    // Walk the tree off the main thread; large projects take a moment
+    let (tx, rx) = glib::MainContext::channel::<Vec<PathBuf>>(glib::Priority::default());
+    {
+        let root = root.clone();
+        let settings = workspace.settings.borrow().clone();
+        std::thread::Builder::new()
+            .name("quick-open-index".to_string())
+            .spawn(move || {
+                let _ = tx.send(quick_open::index_files(&root, &settings));
+            })
+            .expect("failed to spawn indexing thread");
+    }
+    {
+        let (palette, files, refresh) = (palette.clone(), files.clone(), refresh.clone());
+        rx.attach(None, move |indexed| {
+            palette.entry.set_placeholder_text(Some(&format!("Search {} files by name", indexed.len())));
+            *files.borrow_mut() = indexed;
+            refresh(&palette.entry.text());
+            glib::Continue(false)
+        });
+    }
+
+    palette.connect_query_changed(move |query| refresh(query));
+    {
+        let workspace = workspace.clone();
+        palette.connect_chosen(move |index| {
+            let path = shown.borrow().get(index).map(|m| root.join(&m.path));
+            if let Some(path) = path {
+                workspace.open_file(path);
+            }
+        });
+    }
+
+    palette.present();
+}
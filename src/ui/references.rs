@@ -0,0 +1,241 @@
+use gtk4::prelude::*;
+use gtk4::{glib, Box as GtkBox, CellRendererText, Label, Orientation, ScrolledWindow, TreePath, TreeStore, TreeView, TreeViewColumn};
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::find_in_files::{self, FileFilter, FileMatches, LineMatch};
+use crate::search::{Search, SearchError, SearchOptions};
+use crate::settings::Settings;
+
+// Column indices for the references TreeStore
+const COL_MARKUP: u32 = 0; // Row text
+const COL_FILE: u32 = 1; // Index into `listed`
+const COL_MATCH: u32 = 2; // Index into the file's matches, -1 on file rows
+
+/// References panel: where a symbol is used, or the definitions to choose from when there are
+/// several, listed by file. Activating one jumps to it.
+pub struct ReferencesPanel {
+    pub widget: GtkBox,
+    status_label: Label,
+    store: TreeStore,
+    tree_view: TreeView,
+    /// Listed files, with full paths
+    listed: RefCell<Vec<FileMatches>>,
+    /// Bumped by every new listing, so results of an older one are dropped
+    generation: Cell<u64>,
+    /// Set to stop the running word search's thread
+    cancelled: RefCell<Arc<AtomicBool>>,
+}
+
+impl ReferencesPanel {
+    pub fn new() -> Rc<Self> {
+        let widget = GtkBox::new(Orientation::Vertical, 4);
+        widget.style_context().add_class("references");
+
+        let status_label = Label::new(Some("Use Find References (Shift+F12) on a symbol to list its uses"));
+        status_label.set_xalign(0.0);
+        status_label.style_context().add_class("dim-label");
+
+        let store = TreeStore::new(&[
+            glib::Type::STRING, // Markup
+            glib::Type::I32,    // File index
+            glib::Type::I32,    // Match index
+        ]);
+        let tree_view = TreeView::with_model(&store);
+        tree_view.set_headers_visible(false);
+
+        let text_column = TreeViewColumn::new();
+        let text_renderer = CellRendererText::new();
+        text_renderer.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        text_column.pack_start(&text_renderer, true);
+        text_column.add_attribute(&text_renderer, "markup", COL_MARKUP as i32);
+        tree_view.append_column(&text_column);
+
+        let scrolled = ScrolledWindow::builder()
+            .child(&tree_view)
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .vscrollbar_policy(gtk4::PolicyType::Automatic)
+            .vexpand(true)
+            .build();
+
+        widget.append(&status_label);
+        widget.append(&scrolled);
+
+        Rc::new(Self {
+            widget,
+            status_label,
+            store,
+            tree_view,
+            listed: RefCell::new(Vec::new()),
+            generation: Cell::new(0),
+            cancelled: RefCell::new(Arc::new(AtomicBool::new(false))),
+        })
+    }
+
+    /// Call `callback` with the file, zero-based line and column of an activated row.
+    pub fn connect_activated(self: &Rc<Self>, callback: impl Fn(PathBuf, usize, usize) + 'static) {
+        let panel_weak = Rc::downgrade(self);
+        self.tree_view.connect_row_activated(move |tree_view, path, _column| {
+            let Some(panel) = panel_weak.upgrade() else {
+                return;
+            };
+            let Some((file, index)) = panel.row_indices(path) else {
+                return;
+            };
+            let Some(index) = index else {
+                // File rows fold their matches
+                if tree_view.row_expanded(path) {
+                    tree_view.collapse_row(path);
+                } else {
+                    tree_view.expand_row(path, false);
+                }
+                return;
+            };
+            let target = panel.listed.borrow().get(file).map(|file| (file.path.clone(), file.matches[index].clone()));
+            if let Some((path, m)) = target {
+                callback(path, m.line, m.start_column);
+            }
+        });
+    }
+
+    /// Clear the list and show `status` while results for it are looked for. Returns the
+    /// generation to pass to `show`.
+    pub fn start(&self, status: &str) -> u64 {
+        self.cancelled.borrow().store(true, Ordering::Relaxed);
+        *self.cancelled.borrow_mut() = Arc::new(AtomicBool::new(false));
+        self.generation.set(self.generation.get() + 1);
+        self.store.clear();
+        self.listed.borrow_mut().clear();
+        self.status_label.set_text(status);
+        self.generation.get()
+    }
+
+    /// List `files` under `title`, with paths shown relative to `root`, unless another
+    /// listing started since `generation`.
+    pub fn show(&self, generation: u64, title: &str, files: Vec<FileMatches>, root: Option<&Path>) {
+        if generation != self.generation.get() {
+            return;
+        }
+        self.store.clear();
+        let count: usize = files.iter().map(|file| file.matches.len()).sum();
+        for (file_index, file) in files.iter().enumerate() {
+            let relative = root.and_then(|root| file.path.strip_prefix(root).ok()).unwrap_or(&file.path);
+            let name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let dir = relative.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+            let markup = format!(
+                "<b>{}</b>  <span alpha=\"60%\">{}  ({})</span>",
+                glib::markup_escape_text(&name),
+                glib::markup_escape_text(&dir),
+                file.matches.len()
+            );
+            let parent = self.store.insert_with_values(
+                None,
+                None,
+                &[(COL_MARKUP, &markup), (COL_FILE, &(file_index as i32)), (COL_MATCH, &-1i32)],
+            );
+            for (index, m) in file.matches.iter().enumerate() {
+                self.store.insert_with_values(
+                    Some(&parent),
+                    None,
+                    &[(COL_MARKUP, &match_markup(m)), (COL_FILE, &(file_index as i32)), (COL_MATCH, &(index as i32))],
+                );
+            }
+            self.tree_view.expand_row(&self.store.path(&parent), false);
+        }
+        let status = match count {
+            0 => format!("{}: none found", title),
+            _ => format!("{}: {} in {} files", title, count, files.len()),
+        };
+        self.status_label.set_text(&status);
+        *self.listed.borrow_mut() = files;
+    }
+
+    /// List the whole-word, case-sensitive occurrences of `word` in the files under `root`,
+    /// searched in the background: the references there are without a language server.
+    pub fn search_word(self: &Rc<Self>, root: PathBuf, word: &str, settings: Settings) {
+        let title = format!("Occurrences of '{}'", word);
+        let generation = self.start(&format!("Searching for '{}'…", word));
+        let cancelled = self.cancelled.borrow().clone();
+        let panel_weak = Rc::downgrade(self);
+        let shown_root = root.clone();
+        let searched = find_word(root, word, settings, cancelled, move |files, truncated| {
+            let Some(panel) = panel_weak.upgrade() else {
+                return;
+            };
+            panel.show(generation, &title, files, Some(&shown_root));
+            if truncated && generation == panel.generation.get() {
+                let status = format!("{}: showing the first {}", title, find_in_files::MAX_MATCHES);
+                panel.status_label.set_text(&status);
+            }
+        });
+        if let Err(e) = searched {
+            self.status_label.set_text(&e.to_string());
+        }
+    }
+
+    /// File index and, on match rows, match index of the row at `path`.
+    fn row_indices(&self, path: &TreePath) -> Option<(usize, Option<usize>)> {
+        let iter = self.store.iter(path)?;
+        let file: i32 = self.store.get(&iter, COL_FILE as i32);
+        let index: i32 = self.store.get(&iter, COL_MATCH as i32);
+        Some((file as usize, usize::try_from(index).ok()))
+    }
+}
+
+/// Line number and preview of `m` with the match in bold.
+fn match_markup(m: &LineMatch) -> String {
+    let (start, end) = m.preview_match;
+    let part = |from: usize, to: usize| {
+        let text: String = m.preview.chars().skip(from).take(to - from).collect();
+        glib::markup_escape_text(&text).to_string()
+    };
+    format!(
+        "<span alpha=\"60%\">{}</span>  {}<b>{}</b>{}",
+        m.line + 1,
+        part(0, start),
+        part(start, end),
+        part(end, m.preview.chars().count())
+    )
+}
+
+/// Search the files under `root` for whole-word, case-sensitive occurrences of `word` on a
+/// thread of its own, and call `done` with them, by file with full paths, and whether the
+/// search stopped at `find_in_files::MAX_MATCHES`, unless `cancelled` is set first.
+pub fn find_word(
+    root: PathBuf,
+    word: &str,
+    settings: Settings,
+    cancelled: Arc<AtomicBool>,
+    done: impl FnOnce(Vec<FileMatches>, bool) + 'static,
+) -> Result<(), SearchError> {
+    let options = SearchOptions { case_sensitive: true, whole_word: true, ..SearchOptions::default() };
+    let search = Search::new(word, options)?;
+
+    let (tx, rx) = glib::MainContext::channel::<(Vec<FileMatches>, bool)>(glib::Priority::default());
+    std::thread::Builder::new()
+        .name("find-word".to_string())
+        .spawn(move || {
+            let mut files = Vec::new();
+            let filter = FileFilter::default();
+            let truncated = find_in_files::search_files(&root, &settings, &filter, &search, &cancelled, |mut file| {
+                file.path = root.join(&file.path);
+                files.push(file);
+            });
+            if !cancelled.load(Ordering::Relaxed) {
+                let _ = tx.send((files, truncated));
+            }
+        })
+        .expect("failed to spawn word search thread");
+
+    let mut done = Some(done);
+    rx.attach(None, move |(files, truncated)| {
+        if let Some(done) = done.take() {
+            done(files, truncated);
+        }
+        glib::Continue(false)
+    });
+    Ok(())
+}
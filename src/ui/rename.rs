@@ -0,0 +1,239 @@
+use gtk4::prelude::*;
+use gtk4::{
+    glib, CellRendererText, CellRendererToggle, Dialog, Entry, Label, ResponseType, ScrolledWindow, TreeStore, TreeView,
+    TreeViewColumn,
+};
+use std::collections::HashSet;
+use std::path::Path;
+use std::rc::Rc;
+
+use super::find_in_files::set_included;
+use super::Workspace;
+use crate::workspace_edit::{Edit, FileChanges, Hunk};
+
+// Column indices for the preview TreeStore
+const COL_MARKUP: u32 = 0; // Row text
+const COL_INCLUDED: u32 = 1; // Checked for applying
+const COL_TOOLTIP: u32 = 2; // The lines as they were, on hunk rows
+const COL_FILE: u32 = 3; // Index into the previewed files
+const COL_HUNK: u32 = 4; // Index into the file's hunks, -1 on file rows
+
+/// F2 dialog asking what to rename `name` to; `rename` gets the new name once one different
+/// from `name` is given.
+pub fn ask_new_name(workspace: &Rc<Workspace>, name: &str, rename: impl Fn(String) + 'static) {
+    let dialog = Dialog::with_buttons(
+        Some("Rename Symbol"),
+        Some(&workspace.window),
+        gtk4::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Rename", ResponseType::Accept)],
+    );
+    dialog.set_default_width(360);
+    dialog.set_default_response(ResponseType::Accept);
+
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_margin_top(10);
+    content_area.set_margin_bottom(10);
+    content_area.set_margin_start(10);
+    content_area.set_margin_end(10);
+
+    let entry = Entry::new();
+    entry.set_text(name);
+    entry.select_region(0, -1);
+    entry.set_activates_default(true);
+    let hint = Label::new(Some(&format!("Rename '{}' and its references; the changes are shown first", name)));
+    hint.set_xalign(0.0);
+    hint.set_wrap(true);
+    hint.style_context().add_class("dim-label");
+    content_area.append(&entry);
+    content_area.append(&hint);
+
+    entry.connect_changed(|entry| entry.style_context().remove_class("error"));
+
+    let name = name.to_string();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let new_name = entry.text().trim().to_string();
+            if new_name.is_empty() || new_name.contains(char::is_whitespace) {
+                entry.style_context().add_class("error");
+                return;
+            }
+            if new_name != name {
+                rename(new_name);
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+/// Preview of the edits to `files` under `title`, each group of changed lines with a
+/// checkbox. Applying calls `apply` with the checked edits of each file; `failed` lists the
+/// files whose edits couldn't be worked out, with why.
+pub fn show_preview(
+    workspace: &Rc<Workspace>,
+    title: &str,
+    files: Vec<FileChanges>,
+    failed: Vec<String>,
+    apply: impl Fn(Vec<(FileChanges, Vec<Edit>)>) + 'static,
+) {
+    let dialog = Dialog::with_buttons(
+        Some(title),
+        Some(&workspace.window),
+        gtk4::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Apply", ResponseType::Accept)],
+    );
+    dialog.set_default_size(720, 460);
+    dialog.set_default_response(ResponseType::Accept);
+
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_margin_top(10);
+    content_area.set_margin_bottom(10);
+    content_area.set_margin_start(10);
+    content_area.set_margin_end(10);
+
+    let count: usize = files.iter().map(FileChanges::edit_count).sum();
+    let summary = Label::new(Some(&format!(
+        "{} changes in {} files. Open files are changed in their tabs and can be undone there; \
+         other files are saved right away.",
+        count,
+        files.len()
+    )));
+    summary.set_xalign(0.0);
+    summary.set_wrap(true);
+    content_area.append(&summary);
+    if !failed.is_empty() {
+        let warning = Label::new(Some(&format!("Not changed:\n{}", failed.join("\n"))));
+        warning.set_xalign(0.0);
+        warning.set_wrap(true);
+        warning.style_context().add_class("dim-label");
+        content_area.append(&warning);
+    }
+
+    let store = TreeStore::new(&[
+        glib::Type::STRING, // Markup
+        glib::Type::BOOL,   // Included
+        glib::Type::STRING, // Tooltip
+        glib::Type::I32,    // File index
+        glib::Type::I32,    // Hunk index
+    ]);
+    let root = workspace.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+    for (file_index, file) in files.iter().enumerate() {
+        let relative = root.as_deref().and_then(|root| file.path.strip_prefix(root).ok()).unwrap_or(&file.path);
+        let name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let dir = relative.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+        let markup = format!(
+            "<b>{}</b>  <span alpha=\"60%\">{}  ({})</span>",
+            glib::markup_escape_text(&name),
+            glib::markup_escape_text(&dir),
+            file.edit_count()
+        );
+        let parent = store.insert_with_values(
+            None,
+            None,
+            &[(COL_MARKUP, &markup), (COL_INCLUDED, &true), (COL_FILE, &(file_index as i32)), (COL_HUNK, &-1i32)],
+        );
+        for (index, hunk) in file.hunks.iter().enumerate() {
+            let tooltip = format!("Was:\n<tt>{}</tt>", glib::markup_escape_text(&hunk.before));
+            store.insert_with_values(
+                Some(&parent),
+                None,
+                &[
+                    (COL_MARKUP, &hunk_markup(hunk)),
+                    (COL_INCLUDED, &true),
+                    (COL_TOOLTIP, &tooltip),
+                    (COL_FILE, &(file_index as i32)),
+                    (COL_HUNK, &(index as i32)),
+                ],
+            );
+        }
+    }
+
+    let tree_view = TreeView::with_model(&store);
+    tree_view.set_headers_visible(false);
+    tree_view.set_tooltip_column(COL_TOOLTIP as i32);
+
+    let include_column = TreeViewColumn::new();
+    let toggle_renderer = CellRendererToggle::new();
+    include_column.pack_start(&toggle_renderer, false);
+    include_column.add_attribute(&toggle_renderer, "active", COL_INCLUDED as i32);
+    tree_view.append_column(&include_column);
+
+    let text_column = TreeViewColumn::new();
+    let text_renderer = CellRendererText::new();
+    text_renderer.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    text_column.pack_start(&text_renderer, true);
+    text_column.add_attribute(&text_renderer, "markup", COL_MARKUP as i32);
+    tree_view.append_column(&text_column);
+    tree_view.expand_all();
+
+    {
+        let store = store.clone();
+        toggle_renderer.connect_toggled(move |_, path| {
+            if let Some(iter) = store.iter(&path) {
+                let included: bool = store.get(&iter, COL_INCLUDED as i32);
+                set_included(&store, &iter, COL_INCLUDED, !included);
+            }
+        });
+    }
+
+    let scrolled = ScrolledWindow::builder()
+        .child(&tree_view)
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .vscrollbar_policy(gtk4::PolicyType::Automatic)
+        .vexpand(true)
+        .build();
+    content_area.append(&scrolled);
+
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response != ResponseType::Accept {
+            return;
+        }
+        let mut chosen: HashSet<(usize, usize)> = HashSet::new();
+        store.foreach(|model, _path, iter| {
+            let file: i32 = model.get(iter, COL_FILE as i32);
+            let hunk: i32 = model.get(iter, COL_HUNK as i32);
+            let included: bool = model.get(iter, COL_INCLUDED as i32);
+            if let (Ok(hunk), true) = (usize::try_from(hunk), included) {
+                chosen.insert((file as usize, hunk));
+            }
+            false
+        });
+        let edits: Vec<(FileChanges, Vec<Edit>)> = files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| (file.clone(), file.chosen_edits(|hunk| chosen.contains(&(index, hunk)))))
+            .filter(|(_, edits)| !edits.is_empty())
+            .collect();
+        if !edits.is_empty() {
+            apply(edits);
+        }
+    });
+
+    dialog.show();
+}
+
+/// Line number and the changed lines as they'll be, with the inserted text in bold and line
+/// breaks as ⏎.
+fn hunk_markup(hunk: &Hunk) -> String {
+    let chars: Vec<char> = hunk.after.chars().collect();
+    let part = |from: usize, to: usize| {
+        let text: String = chars[from..to].iter().collect();
+        glib::markup_escape_text(&text).replace('\n', "⏎")
+    };
+    // Indentation is left out, unless an edit is in it
+    let indent = chars.iter().take_while(|c| c.is_whitespace()).count();
+    let mut shown = hunk.inserted.first().map_or(indent, |&(start, _)| indent.min(start));
+
+    let mut markup = format!("<span alpha=\"60%\">{}</span>  ", hunk.line + 1);
+    for &(start, end) in &hunk.inserted {
+        markup.push_str(&part(shown, start));
+        markup.push_str(&format!("<b>{}</b>", part(start, end)));
+        shown = end;
+    }
+    markup.push_str(&part(shown, chars.len()));
+    markup
+}
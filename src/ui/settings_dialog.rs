@@ -0,0 +1,140 @@
+use gtk4::prelude::*;
+use gtk4::{CheckButton, Dialog, DropDown, Entry, Grid, Label, ResponseType, SpinButton};
+use std::rc::Rc;
+
+use super::Workspace;
+use crate::config::ThemeMode;
+use crate::settings::Settings;
+
+const MIB: u64 = 1024 * 1024;
+const THEMES: [ThemeMode; 2] = [ThemeMode::Dark, ThemeMode::Light];
+
+/// Modal dialog editing the user settings; "Save" writes the settings file and applies it.
+pub fn show_settings_dialog(workspace: &Rc<Workspace>) {
+    let current = workspace.settings.borrow().clone();
+
+    let dialog = Dialog::with_buttons(
+        Some("Settings"),
+        Some(&workspace.window),
+        gtk4::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+    );
+    dialog.set_default_width(420);
+
+    let content_area = dialog.content_area();
+    content_area.set_margin_top(10);
+    content_area.set_margin_bottom(10);
+    content_area.set_margin_start(10);
+    content_area.set_margin_end(10);
+
+    let grid = Grid::builder()
+        .column_spacing(10)
+        .row_spacing(8)
+        .build();
+
+    let font_family = Entry::new();
+    font_family.set_text(&current.font_family);
+    font_family.set_hexpand(true);
+
+    let font_size = SpinButton::with_range(6.0, 72.0, 1.0);
+    font_size.set_value(current.font_size as f64);
+
+    let tab_width = SpinButton::with_range(1.0, 16.0, 1.0);
+    tab_width.set_value(current.tab_width as f64);
+
+    let insert_spaces = CheckButton::with_label("Insert spaces instead of tabs");
+    insert_spaces.set_active(current.insert_spaces);
+
+    let theme = DropDown::from_strings(&["Dark", "Light"]);
+    theme.set_selected(THEMES.iter().position(|t| *t == current.theme).unwrap_or(0) as u32);
+
+    let wrap_by_default = CheckButton::with_label("Wrap lines in new tabs");
+    wrap_by_default.set_active(current.wrap_by_default);
+
+    let auto_close_brackets = CheckButton::with_label("Auto-close brackets and quotes");
+    auto_close_brackets.set_active(current.auto_close_brackets);
+
+    let large_file_threshold = SpinButton::with_range(1.0, 4096.0, 1.0);
+    large_file_threshold.set_value(to_mib(current.large_file_threshold_bytes));
+
+    let large_file_warn = SpinButton::with_range(1.0, 65536.0, 1.0);
+    large_file_warn.set_value(to_mib(current.large_file_warn_bytes));
+
+    let show_hidden_files = CheckButton::with_label("Show hidden files");
+    show_hidden_files.set_active(current.show_hidden_files);
+
+    let hidden_patterns = Entry::new();
+    hidden_patterns.set_text(&current.hidden_patterns.join(", "));
+    hidden_patterns.set_placeholder_text(Some("target, node_modules, *.o"));
+
+    let rows: [(&str, &gtk4::Widget); 11] = [
+        ("Font:", font_family.upcast_ref()),
+        ("Font size:", font_size.upcast_ref()),
+        ("Tab width:", tab_width.upcast_ref()),
+        ("", insert_spaces.upcast_ref()),
+        ("Theme:", theme.upcast_ref()),
+        ("", wrap_by_default.upcast_ref()),
+        ("", auto_close_brackets.upcast_ref()),
+        ("Large file mode from (MB):", large_file_threshold.upcast_ref()),
+        ("Confirm opening from (MB):", large_file_warn.upcast_ref()),
+        ("", show_hidden_files.upcast_ref()),
+        ("Hide in explorer:", hidden_patterns.upcast_ref()),
+    ];
+    for (row, (label, widget)) in rows.iter().enumerate() {
+        if !label.is_empty() {
+            let label = Label::new(Some(label));
+            label.set_halign(gtk4::Align::End);
+            grid.attach(&label, 0, row as i32, 1, 1);
+        }
+        grid.attach(*widget, 1, row as i32, 1, 1);
+    }
+
+    content_area.append(&grid);
+
+    let workspace = workspace.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let settings = Settings {
+                font_family: font_family.text().trim().to_string(),
+                font_size: font_size.value_as_int() as u32,
+                tab_width: tab_width.value_as_int() as usize,
+                insert_spaces: insert_spaces.is_active(),
+                language_indent: current.language_indent.clone(),
+                language_servers: current.language_servers.clone(),
+                theme: THEMES[theme.selected() as usize % THEMES.len()],
+                wrap_by_default: wrap_by_default.is_active(),
+                auto_close_brackets: auto_close_brackets.is_active(),
+                large_file_threshold_bytes: from_mib(large_file_threshold.value(), current.large_file_threshold_bytes),
+                large_file_warn_bytes: from_mib(large_file_warn.value(), current.large_file_warn_bytes),
+                show_hidden_files: show_hidden_files.is_active(),
+                hidden_patterns: hidden_patterns
+                    .text()
+                    .split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect(),
+            };
+
+            if let Err(e) = settings.save() {
+                eprintln!("Failed to save settings: {}", e);
+            }
+            workspace.apply_settings(settings);
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+fn to_mib(bytes: u64) -> f64 {
+    (bytes as f64 / MIB as f64).round().max(1.0)
+}
+
+/// Keep the exact byte count from the file unless the user changed the rounded value.
+fn from_mib(value: f64, previous_bytes: u64) -> u64 {
+    if value == to_mib(previous_bytes) {
+        previous_bytes
+    } else {
+        value as u64 * MIB
+    }
+}
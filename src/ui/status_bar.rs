@@ -0,0 +1,136 @@
+use gtk4::prelude::*;
+use gtk4::{gio, Box as GtkBox, Button, Label, MenuButton, Orientation, PopoverMenu};
+use syntect::parsing::SyntaxSet;
+
+use crate::editor::EditorStatus;
+use crate::file_format::FileFormat;
+use crate::indent::IndentStyle;
+use crate::language;
+
+/// Bottom status bar: cursor position, language picker, mode indicator and file info.
+pub struct StatusBar {
+    pub widget: GtkBox,
+    pub status_label: Label,
+    pub language_button: MenuButton,
+    pub indent_label: Label,
+    pub encoding_label: Label,
+    pub line_ending_button: Button,
+    pub mode_label: Label,
+    pub status_info_label: Label,
+}
+
+impl StatusBar {
+    pub fn new(ss: &SyntaxSet) -> Self {
+        let widget = GtkBox::new(Orientation::Horizontal, 10);
+        widget.style_context().add_class("status");
+
+        let status_label = Label::new(Some("Ln 1, Col 1"));
+
+        let language_button = MenuButton::new();
+        language_button.set_label("Plain Text");
+        language_button.set_tooltip_text(Some("Select language"));
+        language_button.set_has_frame(false);
+        language_button.set_direction(gtk4::ArrowType::Up);
+        let popover = PopoverMenu::from_model(Some(&create_language_menu(ss)));
+        language_button.set_popover(Some(&popover));
+
+        let indent_label = Label::new(Some("Spaces: 4"));
+
+        let encoding_label = Label::new(Some("UTF-8"));
+
+        let line_ending_button = Button::with_label("LF");
+        line_ending_button.set_has_frame(false);
+        line_ending_button.set_tooltip_text(Some("Toggle line endings"));
+        line_ending_button.set_action_name(Some("app.toggle-line-ending"));
+
+        // Only shown while the current editor is in large-file mode
+        let mode_label = Label::new(Some("Large file"));
+        mode_label.set_tooltip_text(Some(
+            "Large file mode: only visible lines are highlighted and word wrap is disabled",
+        ));
+        mode_label.set_visible(false);
+
+        let status_info_label = Label::new(Some("Ready"));
+        status_info_label.set_hexpand(true);
+        status_info_label.set_halign(gtk4::Align::Start);
+
+        widget.append(&status_label);
+        widget.append(&language_button);
+        widget.append(&indent_label);
+        widget.append(&encoding_label);
+        widget.append(&line_ending_button);
+        widget.append(&mode_label);
+        widget.append(&status_info_label);
+
+        Self {
+            widget,
+            status_label,
+            language_button,
+            indent_label,
+            encoding_label,
+            line_ending_button,
+            mode_label,
+            status_info_label,
+        }
+    }
+
+    /// Show the cursor position, language and file info of an editor.
+    pub fn show(&self, status: &EditorStatus) {
+        self.status_label.set_text(&format!("Ln {}, Col {}", status.line + 1, status.column + 1));
+        self.set_language(&status.language, status.language_overridden);
+        self.set_large_file_mode(status.large_file);
+        self.set_indent(status.indent);
+        self.set_format(status.format);
+        self.status_info_label.set_text(&status.info);
+    }
+
+    pub fn set_large_file_mode(&self, enabled: bool) {
+        self.mode_label.set_visible(enabled);
+    }
+
+    pub fn set_indent(&self, style: IndentStyle) {
+        let text = if style.use_tabs {
+            format!("Tabs: {}", style.width)
+        } else {
+            format!("Spaces: {}", style.width)
+        };
+        self.indent_label.set_text(&text);
+    }
+
+    pub fn set_format(&self, format: FileFormat) {
+        self.encoding_label.set_text(format.encoding.label());
+        self.line_ending_button.set_label(format.line_ending.label());
+    }
+
+    pub fn set_language(&self, name: &str, is_override: bool) {
+        self.language_button.set_label(name);
+        let tooltip = if is_override {
+            format!("{} (selected manually)", name)
+        } else {
+            format!("{} (detected)", name)
+        };
+        self.language_button.set_tooltip_text(Some(&tooltip));
+    }
+}
+
+/// Menu listing every selectable syntax; each entry activates `app.set-language` with the
+/// syntax name, and "Auto Detect" clears the per-tab override (empty target).
+fn create_language_menu(ss: &SyntaxSet) -> gio::Menu {
+    let menu = gio::Menu::new();
+
+    let auto_section = gio::Menu::new();
+    let auto_item = gio::MenuItem::new(Some("Auto Detect"), None);
+    auto_item.set_action_and_target_value(Some("app.set-language"), Some(&"".to_variant()));
+    auto_section.append_item(&auto_item);
+    menu.append_section(None, &auto_section);
+
+    let languages = gio::Menu::new();
+    for name in language::language_names(ss) {
+        let item = gio::MenuItem::new(Some(&name), None);
+        item.set_action_and_target_value(Some("app.set-language"), Some(&name.to_variant()));
+        languages.append_item(&item);
+    }
+    menu.append_section(None, &languages);
+
+    menu
+}
@@ -0,0 +1,108 @@
+use gtk4::glib;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::file_explorer::FileExplorer;
+use crate::lsp;
+use crate::settings::Settings;
+use crate::symbols::{self, SymbolIndex};
+
+type Waiting = Box<dyn FnOnce(&SymbolIndex)>;
+
+enum State {
+    Empty,
+    Building { root: PathBuf, waiting: Vec<Waiting> },
+    Ready { root: PathBuf, index: Rc<RefCell<SymbolIndex>> },
+}
+
+/// The symbol index of one folder, for going to definitions and hovering without a language
+/// server. Built in the background the first time it's needed, then kept up to date as files
+/// are saved.
+pub struct SymbolIndexer {
+    settings: Rc<RefCell<Settings>>,
+    file_explorer: Rc<RefCell<FileExplorer>>,
+    state: RefCell<State>,
+}
+
+impl SymbolIndexer {
+    pub fn new(settings: Rc<RefCell<Settings>>, file_explorer: Rc<RefCell<FileExplorer>>) -> Rc<Self> {
+        Rc::new(Self { settings, file_explorer, state: RefCell::new(State::Empty) })
+    }
+
+    /// The folder names used in `file` are looked up in: the explorer's folder if `file` is in
+    /// it or untitled, else the file's project.
+    pub fn root_for(&self, file: Option<&Path>) -> Option<PathBuf> {
+        let explorer_root = self.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+        match (file, explorer_root) {
+            (Some(file), Some(root)) if file.starts_with(&root) => Some(root),
+            (Some(file), _) => Some(lsp::find_root(file, &[])),
+            (None, root) => root,
+        }
+    }
+
+    /// Call `callback` with the index of the folder `root_for(file)`, once it's built if it
+    /// isn't yet. Asking for another folder's replaces the index.
+    pub fn with_index(self: &Rc<Self>, file: Option<&Path>, callback: impl FnOnce(&SymbolIndex) + 'static) {
+        let Some(root) = self.root_for(file) else {
+            callback(&SymbolIndex::default());
+            return;
+        };
+        let root = root.as_path();
+
+        let ready = match &mut *self.state.borrow_mut() {
+            State::Ready { root: indexed, index } if indexed.as_path() == root => Some(index.clone()),
+            State::Building { root: building, waiting } if building.as_path() == root => {
+                waiting.push(Box::new(callback));
+                return;
+            }
+            _ => None,
+        };
+        if let Some(index) = ready {
+            callback(&index.borrow());
+            return;
+        }
+
+        *self.state.borrow_mut() = State::Building { root: root.to_path_buf(), waiting: vec![Box::new(callback)] };
+        let (tx, rx) = glib::MainContext::channel::<SymbolIndex>(glib::Priority::default());
+        {
+            let root = root.to_path_buf();
+            let settings = self.settings.borrow().clone();
+            std::thread::Builder::new()
+                .name("symbol-index".to_string())
+                .spawn(move || {
+                    let _ = tx.send(SymbolIndex::build(&root, &settings));
+                })
+                .expect("failed to spawn symbol index thread");
+        }
+
+        let indexer_weak = Rc::downgrade(self);
+        let root = root.to_path_buf();
+        rx.attach(None, move |index| {
+            let Some(indexer) = indexer_weak.upgrade() else {
+                return glib::Continue(false);
+            };
+            // Another folder's index was asked for since
+            if !matches!(&*indexer.state.borrow(), State::Building { root: building, .. } if *building == root) {
+                return glib::Continue(false);
+            }
+            let index = Rc::new(RefCell::new(index));
+            let state = indexer.state.replace(State::Ready { root: root.clone(), index: index.clone() });
+            if let State::Building { waiting, .. } = state {
+                for callback in waiting {
+                    callback(&index.borrow());
+                }
+            }
+            glib::Continue(false)
+        });
+    }
+
+    /// Reindex `path` after it was saved with `text`, if it's in the indexed folder.
+    pub fn file_saved(&self, path: &Path, text: &str) {
+        if let State::Ready { root, index } = &*self.state.borrow() {
+            if path.starts_with(root) && symbols::is_indexed(path) {
+                index.borrow_mut().update_file(path, text);
+            }
+        }
+    }
+}
@@ -0,0 +1,1582 @@
+use gtk4::prelude::*;
+use gtk4::{
+    gdk, gio, glib, ApplicationWindow, ButtonsType, EventControllerFocus, EventSequenceState, GestureClick, MessageDialog,
+    MessageType, Notebook, Orientation, Paned, PropagationPhase, ResponseType,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use ropey::Rope;
+use serde_json::{json, Value};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+
+use super::code_actions::CodeActionMenu;
+use super::completion::CompletionPopup;
+use super::editor_groups::EditorGroups;
+use super::find_bar::FindBar;
+use super::find_in_files::FindInFilesPanel;
+use super::hover::HoverPopup;
+use super::language_servers::LanguageServers;
+use super::problems::ProblemsPanel;
+use super::references::{find_word, ReferencesPanel};
+use super::rename;
+use super::symbol_index::SymbolIndexer;
+use super::StatusBar;
+use crate::commands::CommandRegistry;
+use crate::config::ThemeMode;
+use crate::diagnostics::{Diagnostic, DiagnosticStore};
+use crate::documents::{canonical_key, DocumentRegistry};
+use crate::editor::Editor;
+use crate::file_explorer::FileExplorer;
+use crate::file_format;
+use crate::find_in_files::{self, FileMatches, FileReplacements, LineMatch, ReplaceError};
+use crate::lsp;
+use crate::lsp::client::LspClient;
+use crate::lsp::protocol::{self, CodeAction, Position, PositionEncoding, Range, TextEdit};
+use crate::navigation::{Location, NavigationHistory};
+use crate::search::{Search, SearchOptions};
+use crate::session::{Session, TabSession};
+use crate::settings::Settings;
+use crate::symbols::{self, Symbol};
+use crate::workspace_edit::{self, Edit, EditError, FileChanges};
+
+/// Editor-area state shared by every action that creates, opens or closes tabs.
+pub struct Workspace {
+    pub window: ApplicationWindow,
+    pub paned: Paned,
+    /// Tab groups of the editor area, split horizontally or vertically
+    pub groups: EditorGroups,
+    /// One per tab; split panes showing the same document have one each
+    pub editors: Rc<RefCell<Vec<Rc<Editor>>>>,
+    pub current_editor: Rc<RefCell<Option<Rc<Editor>>>>,
+    /// Searches whichever tab is current
+    pub find_bar: Rc<FindBar>,
+    /// Completes the word at the cursor of whichever view is typed in
+    pub completion: Rc<CompletionPopup>,
+    /// Shows what's known about the word under the pointer
+    pub hover: Rc<HoverPopup>,
+    /// Lists the language server's fixes and refactorings at the cursor
+    pub code_actions: Rc<CodeActionMenu>,
+    /// Panel below the editor area, hidden until one of its pages is shown
+    pub bottom_panel: Notebook,
+    pub find_in_files: Rc<FindInFilesPanel>,
+    pub problems: Rc<ProblemsPanel>,
+    pub references: Rc<ReferencesPanel>,
+    pub status_bar: Rc<StatusBar>,
+    pub file_explorer: Rc<RefCell<FileExplorer>>,
+    pub ss: Arc<SyntaxSet>,
+    pub current_theme: Rc<RefCell<Arc<Theme>>>,
+    pub settings: Rc<RefCell<Settings>>,
+    /// Watches the settings file for hot-reloading; kept here so it lives as long as the window.
+    pub settings_monitor: RefCell<Option<gio::FileMonitor>>,
+    /// Tabs showing files, by canonical path; opening a file that's already open focuses it.
+    pub documents: RefCell<DocumentRegistry<Rc<Editor>>>,
+    /// Titles and recent uses for the command palette.
+    pub commands: RefCell<CommandRegistry>,
+    /// Set once the user agreed to quit, so the next close request goes through.
+    pub quit_confirmed: Cell<bool>,
+    /// Places jumps left from, for going back and forward
+    pub history: RefCell<NavigationHistory>,
+    /// Set while going back or forward, which mustn't record a jump itself
+    pub navigating: Cell<bool>,
+    pub language_servers: LanguageServers,
+    /// Problems reported by the language servers and `cargo check`, by canonical path
+    pub diagnostics: RefCell<DiagnosticStore>,
+    /// Definitions found by scanning the files, for navigating files without a server
+    pub symbol_indexer: Rc<SymbolIndexer>,
+}
+
+impl Workspace {
+    /// Append `editor` as a new tab of the active group, focus it and connect its close button.
+    pub fn add_editor(self: &Rc<Self>, editor: Rc<Editor>) {
+        let notebook = self.groups.active();
+        let page_index = notebook.append_page(&editor.content_row(), Some(&editor.header));
+        notebook.set_current_page(Some(page_index));
+        {
+            let settings = self.settings.borrow();
+            editor.apply_settings(&settings);
+            // A second view of a document keeps the first one's wrapping
+            if editor.view_count() == 1 {
+                editor.set_wrap(settings.wrap_by_default);
+            }
+        }
+        self.status_bar.show(&editor.update());
+
+        self.completion.attach(&editor);
+        self.hover.attach(&editor);
+        self.code_actions.attach(&editor);
+        self.editors.borrow_mut().push(editor.clone());
+        self.record_leaving(&editor);
+        *self.current_editor.borrow_mut() = Some(editor.clone());
+        self.register_document(&editor);
+
+        // Connect close button
+        let workspace = Rc::downgrade(self);
+        let editor_clone = editor.clone();
+        editor.close_button.connect_clicked(move |_| {
+            if let Some(workspace) = workspace.upgrade() {
+                workspace.close_editor(&editor_clone);
+            }
+        });
+
+        // Clicking into a pane makes its tab the current one, and its group the active one
+        let focus = EventControllerFocus::new();
+        let workspace = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(&editor);
+        focus.connect_enter(move |_| {
+            let (Some(workspace), Some(editor)) = (workspace.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            if let Some(notebook) = workspace.groups.notebook_containing(&editor.content_row()) {
+                workspace.set_current(&editor, &notebook);
+            }
+        });
+        editor.main_view.add_controller(focus);
+
+        // Ctrl+click goes to the definition of the clicked symbol
+        let click = GestureClick::new();
+        click.set_button(gdk::BUTTON_PRIMARY);
+        click.set_propagation_phase(PropagationPhase::Capture);
+        let workspace = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(&editor);
+        click.connect_pressed(move |gesture, n_press, x, y| {
+            if n_press != 1 || !gesture.current_event_state().contains(gdk::ModifierType::CONTROL_MASK) {
+                return;
+            }
+            let (Some(workspace), Some(editor)) = (workspace.upgrade(), editor_weak.upgrade()) else {
+                return;
+            };
+            gesture.set_state(EventSequenceState::Claimed);
+            if let Some(iter) = editor.iter_at_widget_coords(x, y) {
+                // Going back returns to the clicked place
+                editor.main_buffer.place_cursor(&iter);
+                workspace.go_to_definition(&editor, iter.offset() as usize);
+            }
+        });
+        editor.main_view.add_controller(click);
+    }
+
+    /// Track the current tab of `notebook`, a newly created group, and remove the group once
+    /// its last tab is closed or dragged away.
+    pub fn connect_group(self: &Rc<Self>, notebook: &Notebook) {
+        let workspace = Rc::downgrade(self);
+        notebook.connect_switch_page(move |notebook, page, _page_num| {
+            let Some(workspace) = workspace.upgrade() else {
+                return;
+            };
+            let editor = workspace
+                .editors
+                .borrow()
+                .iter()
+                .find(|e| e.content_row().upcast_ref::<gtk4::Widget>() == page)
+                .cloned();
+            if let Some(editor) = editor {
+                workspace.set_current(&editor, notebook);
+            }
+        });
+
+        notebook.connect_page_added(|notebook, page, _page_num| {
+            notebook.set_tab_reorderable(page, true);
+            notebook.set_tab_detachable(page, true);
+        });
+
+        let workspace = Rc::downgrade(self);
+        notebook.connect_page_removed(move |notebook, _page, _page_num| {
+            if notebook.n_pages() > 0 {
+                return;
+            }
+            // Not while GTK is still removing the page
+            let (workspace, notebook) = (workspace.clone(), notebook.clone());
+            glib::idle_add_local_once(move || {
+                if let Some(workspace) = workspace.upgrade() {
+                    workspace.groups.remove_if_empty(&notebook);
+                }
+            });
+        });
+    }
+
+    fn set_current(&self, editor: &Rc<Editor>, notebook: &Notebook) {
+        self.record_leaving(editor);
+        *self.current_editor.borrow_mut() = Some(editor.clone());
+        self.groups.set_active(notebook);
+        self.status_bar.show(&editor.update());
+        if let Some(path) = editor.current_file.borrow().as_ref() {
+            self.file_explorer.borrow().highlight_file(path);
+        }
+        self.find_bar.follow_editor();
+    }
+
+    /// The current tab's file and cursor position; `None` for untitled tabs.
+    fn current_location(&self) -> Option<Location> {
+        let editor = self.current_editor.borrow().clone()?;
+        let path = editor.current_file.borrow().clone()?;
+        let (line, column) = editor.cursor_position();
+        Some(Location { path, line, column })
+    }
+
+    /// Remember the current position before jumping away from it, so Back returns there.
+    pub fn record_jump(&self) {
+        if self.navigating.get() {
+            return;
+        }
+        if let Some(location) = self.current_location() {
+            self.history.borrow_mut().record(location);
+        }
+    }
+
+    /// Switching to another tab is a jump too.
+    fn record_leaving(&self, next: &Rc<Editor>) {
+        let is_other = self.current_editor.borrow().as_ref().is_some_and(|current| !Rc::ptr_eq(current, next));
+        if is_other {
+            self.record_jump();
+        }
+    }
+
+    /// Go back (or forward) to where the last jump left from. Files deleted since are
+    /// skipped.
+    pub fn navigate(self: &Rc<Self>, forward: bool) {
+        loop {
+            let current = self.current_location();
+            let location = if forward {
+                self.history.borrow_mut().forward(current)
+            } else {
+                self.history.borrow_mut().back(current)
+            };
+            let Some(location) = location else {
+                return;
+            };
+            if location.path.exists() {
+                self.navigating.set(true);
+                self.go_to_in_file(location.path, location.line, location.column);
+                self.navigating.set(false);
+                return;
+            }
+        }
+    }
+
+    /// Show the bottom panel with `page` in front.
+    pub fn show_panel(&self, page: &impl IsA<gtk4::Widget>) {
+        self.bottom_panel.set_current_page(self.bottom_panel.page_num(page));
+        self.bottom_panel.set_visible(true);
+    }
+
+    /// Split the active group and show the current document in the new pane as well.
+    pub fn split(self: &Rc<Self>, orientation: Orientation) {
+        let current = self.current_editor.borrow().clone();
+        let notebook = self.groups.split(orientation);
+        self.connect_group(&notebook);
+        if let Some(editor) = current {
+            let view = editor.split_view();
+            self.add_editor(view.clone());
+            view.main_view.grab_focus();
+        }
+    }
+
+    /// Move the current tab to the next group, splitting the editor area if there is only one.
+    pub fn move_to_next_group(self: &Rc<Self>) {
+        let Some(editor) = self.current_editor.borrow().clone() else {
+            return;
+        };
+        let page = editor.content_row();
+        let Some(source) = self.groups.notebook_containing(&page) else {
+            return;
+        };
+
+        let notebooks = self.groups.notebooks();
+        let target = if notebooks.len() > 1 {
+            let index = notebooks.iter().position(|n| *n == source).unwrap_or(0);
+            notebooks[(index + 1) % notebooks.len()].clone()
+        } else {
+            self.groups.set_active(&source);
+            let notebook = self.groups.split(Orientation::Horizontal);
+            self.connect_group(&notebook);
+            notebook
+        };
+
+        source.detach_tab(&page);
+        let index = target.append_page(&page, Some(&editor.header));
+        target.set_current_page(Some(index));
+        self.set_current(&editor, &target);
+        editor.main_view.grab_focus();
+    }
+
+    /// Make `editor`'s tab the current one of its group and focus it.
+    fn focus_editor(&self, editor: &Rc<Editor>) {
+        if let Some(notebook) = self.groups.notebook_containing(&editor.content_row()) {
+            if let Some(page) = notebook.page_num(&editor.content_row()) {
+                notebook.set_current_page(Some(page));
+            }
+            self.groups.set_active(&notebook);
+        }
+        editor.main_view.grab_focus();
+    }
+
+    /// Every open view of `editor`'s document, itself included.
+    fn views_of(&self, editor: &Rc<Editor>) -> Vec<Rc<Editor>> {
+        self.editors.borrow().iter().filter(|e| e.shares_document(editor)).cloned().collect()
+    }
+
+    /// Close `editor`'s tab, first asking whether to save it if it has unsaved changes and
+    /// no other pane shows it.
+    pub fn close_editor(self: &Rc<Self>, editor: &Rc<Editor>) {
+        if !*editor.dirty.borrow() || self.views_of(editor).len() > 1 {
+            self.remove_editor(editor);
+            return;
+        }
+
+        let dialog = MessageDialog::new(
+            Some(&self.window),
+            gtk4::DialogFlags::MODAL,
+            MessageType::Question,
+            ButtonsType::None,
+            format!("Save changes to '{}' before closing?", editor.display_name()),
+        );
+        dialog.set_secondary_text(Some("Your changes will be lost if you don't save them."));
+        dialog.add_button("Cancel", ResponseType::Cancel);
+        dialog.add_button("Don't Save", ResponseType::Reject);
+        dialog.add_button("Save", ResponseType::Accept);
+        dialog.set_default_response(ResponseType::Accept);
+
+        let workspace = self.clone();
+        let editor = editor.clone();
+        dialog.connect_response(move |dialog, response| {
+            dialog.close();
+            match response {
+                ResponseType::Accept => {
+                    let workspace_clone = workspace.clone();
+                    let editor_clone = editor.clone();
+                    workspace.save_editor(&editor, move |saved| {
+                        if saved {
+                            workspace_clone.remove_editor(&editor_clone);
+                        }
+                    });
+                }
+                ResponseType::Reject => workspace.remove_editor(&editor),
+                _ => {}
+            }
+        });
+
+        dialog.show();
+    }
+
+    fn remove_editor(&self, editor: &Rc<Editor>) {
+        self.completion.hide();
+        self.hover.hide();
+        self.code_actions.hide();
+        if let Some(notebook) = self.groups.notebook_containing(&editor.content_row()) {
+            if let Some(page_num) = notebook.page_num(&editor.content_row()) {
+                notebook.remove_page(Some(page_num));
+            }
+        }
+        editor.close_view();
+        self.editors.borrow_mut().retain(|e| !Rc::ptr_eq(e, editor));
+        self.documents.borrow_mut().retain(|e| !Rc::ptr_eq(e, editor));
+        // Another pane showing the same document takes over
+        let other_view = self.views_of(editor).into_iter().next();
+        if let Some(other_view) = other_view {
+            self.register_document(&other_view);
+        }
+        let is_current = self.current_editor.borrow().as_ref().is_some_and(|e| Rc::ptr_eq(e, editor));
+        if is_current {
+            *self.current_editor.borrow_mut() = None;
+        }
+    }
+
+    /// Record `editor` as the tab showing its current file, e.g. after Save As.
+    fn register_document(&self, editor: &Rc<Editor>) {
+        let mut documents = self.documents.borrow_mut();
+        documents.retain(|e| !Rc::ptr_eq(e, editor));
+        if let Some(path) = editor.current_file.borrow().as_ref() {
+            documents.insert(path, editor.clone());
+        }
+    }
+
+    /// Save `editor` to its file, or ask for one if it has none. `done` receives whether
+    /// the buffer was saved.
+    pub fn save_editor(self: &Rc<Self>, editor: &Rc<Editor>, done: impl FnOnce(bool) + 'static) {
+        let path = editor.current_file.borrow().clone();
+        match path {
+            Some(path) => {
+                let workspace = self.clone();
+                let editor_clone = editor.clone();
+                save_checked(&self.window, editor, &path, move |saved| {
+                    if saved {
+                        workspace.document_saved(&editor_clone);
+                    }
+                    done(saved);
+                });
+            }
+            None => self.save_editor_as(editor, done),
+        }
+    }
+
+    /// Tell the language server and the symbol index that `editor`'s document was saved.
+    fn document_saved(&self, editor: &Editor) {
+        self.language_servers.did_save(editor);
+        if let Some(path) = editor.current_file.borrow().as_ref() {
+            self.symbol_indexer.file_saved(path, &editor.rope().to_string());
+        }
+    }
+
+    /// Ask for a file name and save `editor` there. `done` receives whether it was saved.
+    pub fn save_editor_as(self: &Rc<Self>, editor: &Rc<Editor>, done: impl FnOnce(bool) + 'static) {
+        let dialog = gtk4::FileChooserDialog::new(
+            Some(&format!("Save '{}'", editor.display_name())),
+            Some(&self.window),
+            gtk4::FileChooserAction::Save,
+            &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+        );
+
+        let workspace = self.clone();
+        let editor = editor.clone();
+        // connect_response needs Fn; the callback must only run once
+        let done = Cell::new(Some(done));
+        dialog.connect_response(move |dialog, response| {
+            let path = dialog.file().and_then(|file| file.path());
+            dialog.close();
+            let saved = match path {
+                Some(path) if response == ResponseType::Accept => {
+                    // Two tabs can't both be the file; the other one would be overwritten behind its back
+                    let other = workspace.documents.borrow().get(&path).filter(|other| !other.shares_document(&editor));
+                    if let Some(other) = other {
+                        show_error(
+                            &workspace.window,
+                            &format!("'{}' is already open in another tab", other.display_name()),
+                            "Close that tab first, or save under a different name.",
+                        );
+                        false
+                    } else {
+                        save_reporting_errors(&workspace.window, &editor, &path)
+                    }
+                }
+                _ => false,
+            };
+            if saved {
+                workspace.register_document(&editor);
+                workspace.language_servers.attach(&editor);
+                workspace.document_saved(&editor);
+            }
+            if let Some(done) = done.take() {
+                done(saved);
+            }
+        });
+
+        dialog.show();
+    }
+
+    /// Save every tab with unsaved changes, asking for names for untitled ones one at a
+    /// time. `done` receives whether all of them were saved.
+    pub fn save_all(self: &Rc<Self>, done: impl FnOnce(bool) + 'static) {
+        let dirty: VecDeque<_> = self
+            .documents_in_tab_order()
+            .into_iter()
+            .filter(|e| *e.dirty.borrow())
+            .collect();
+        self.save_sequentially(dirty, true, Box::new(done));
+    }
+
+    /// Called on every close request of the window; returns whether it may close now.
+    ///
+    /// Unsaved untitled buffers are kept as session snapshots, so only files on disk with
+    /// unsaved changes are asked about. Once the user decides, the window is closed again.
+    pub fn confirm_quit(self: &Rc<Self>) -> bool {
+        if self.quit_confirmed.get() {
+            return true;
+        }
+
+        let unsaved: VecDeque<_> = self
+            .documents_in_tab_order()
+            .into_iter()
+            .filter(|e| *e.dirty.borrow() && e.current_file.borrow().is_some())
+            .collect();
+        if unsaved.is_empty() {
+            return true;
+        }
+
+        let message = if unsaved.len() == 1 {
+            format!("Save changes to '{}' before quitting?", unsaved[0].display_name())
+        } else {
+            format!("{} files have unsaved changes. Save them before quitting?", unsaved.len())
+        };
+        let names: Vec<String> = unsaved.iter().map(|e| e.display_name()).collect();
+
+        let dialog = MessageDialog::new(
+            Some(&self.window),
+            gtk4::DialogFlags::MODAL,
+            MessageType::Question,
+            ButtonsType::None,
+            &message,
+        );
+        dialog.set_secondary_text(Some(&format!(
+            "{}\n\nYour changes will be lost if you don't save them.",
+            names.join("\n")
+        )));
+        dialog.add_button("Cancel", ResponseType::Cancel);
+        dialog.add_button("Quit Without Saving", ResponseType::Reject);
+        dialog.add_button(if unsaved.len() == 1 { "Save" } else { "Save All" }, ResponseType::Accept);
+        dialog.set_default_response(ResponseType::Accept);
+
+        let workspace = self.clone();
+        let unsaved = RefCell::new(Some(unsaved));
+        dialog.connect_response(move |dialog, response| {
+            dialog.close();
+            let quit = {
+                let workspace = workspace.clone();
+                move || {
+                    workspace.quit_confirmed.set(true);
+                    workspace.window.close();
+                }
+            };
+            match response {
+                ResponseType::Accept => {
+                    let Some(unsaved) = unsaved.borrow_mut().take() else {
+                        return;
+                    };
+                    workspace.save_sequentially(unsaved, true, Box::new(move |all_saved| {
+                        if all_saved {
+                            quit();
+                        }
+                    }));
+                }
+                ResponseType::Reject => quit(),
+                _ => {}
+            }
+        });
+
+        dialog.show();
+        false
+    }
+
+    /// Save `pending` one after another, waiting for each Save As dialog before the next.
+    fn save_sequentially(
+        self: &Rc<Self>,
+        mut pending: VecDeque<Rc<Editor>>,
+        all_saved: bool,
+        done: Box<dyn FnOnce(bool)>,
+    ) {
+        let Some(editor) = pending.pop_front() else {
+            done(all_saved);
+            return;
+        };
+
+        let workspace = self.clone();
+        self.save_editor(&editor, move |saved| workspace.save_sequentially(pending, all_saved && saved, done));
+    }
+
+    pub fn new_untitled(self: &Rc<Self>) {
+        let theme = self.current_theme.borrow().clone();
+        let editor = Editor::new("Untitled", None, None, self.ss.clone(), theme);
+        self.add_editor(editor);
+    }
+
+    /// Open `path` in a new tab, or switch to its tab if it's already open.
+    ///
+    /// Files at or above the `large_file_warn_bytes` setting ask for confirmation first, and
+    /// files at or above `large_file_threshold_bytes` open in large-file mode.
+    pub fn open_file(self: &Rc<Self>, path: PathBuf) {
+        if self.focus_file(&path) {
+            return;
+        }
+        let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size < self.settings.borrow().large_file_warn_bytes {
+            self.open_file_unchecked(&path, size);
+            return;
+        }
+
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("this file")
+            .to_string();
+        let dialog = MessageDialog::new(
+            Some(&self.window),
+            gtk4::DialogFlags::MODAL,
+            MessageType::Warning,
+            ButtonsType::OkCancel,
+            format!("'{}' is {} MB. Open it anyway?", file_name, size / (1024 * 1024)),
+        );
+        dialog.set_title(Some("Open Large File"));
+        dialog.set_secondary_text(Some(
+            "It will open in large file mode: only the visible lines are highlighted and word wrap is disabled.",
+        ));
+
+        let workspace = self.clone();
+        dialog.connect_response(move |dialog, response| {
+            if response == ResponseType::Ok {
+                workspace.open_file_unchecked(&path, size);
+            }
+            dialog.close();
+        });
+
+        dialog.show();
+    }
+
+    /// Open `path` like `open_file` and put the cursor at zero-based `line` and `column`,
+    /// recording the jump.
+    pub fn open_file_at(self: &Rc<Self>, path: PathBuf, line: usize, column: usize) {
+        self.record_jump();
+        self.go_to_in_file(path, line, column);
+    }
+
+    fn go_to_in_file(self: &Rc<Self>, path: PathBuf, line: usize, column: usize) {
+        self.open_file(path.clone());
+        // Large files wait for confirmation first; then there's nothing to move yet
+        let Some(document) = self.documents.borrow().get(&path) else {
+            return;
+        };
+        let current = self.current_editor.borrow().clone();
+        if let Some(editor) = current.filter(|editor| editor.shares_document(&document)) {
+            editor.go_to(line, column);
+        }
+    }
+
+    /// Switch to the tab showing `path`, if there is one, preferring one in the active group.
+    pub fn focus_file(&self, path: &Path) -> bool {
+        let Some(editor) = self.documents.borrow().get(path) else {
+            return false;
+        };
+        let active = self.groups.active();
+        let editor = self
+            .views_of(&editor)
+            .into_iter()
+            .find(|view| active.page_num(&view.content_row()).is_some())
+            .unwrap_or(editor);
+        self.focus_editor(&editor);
+        true
+    }
+
+    /// Rename `path` through the file explorer and move the tabs of the file, or of the
+    /// files inside the folder, along with it.
+    pub fn rename_path(&self, path: &Path, new_name: &str) -> std::io::Result<()> {
+        let old_key = canonical_key(path);
+        let expanded = self.file_explorer.borrow().expanded_directories();
+        let new_path = self.file_explorer.borrow().rename_file(path, new_name)?;
+
+        // The explorer rebuilt its tree; expand what was open again, following the rename
+        let expanded: Vec<PathBuf> = expanded
+            .into_iter()
+            .map(|dir| match dir.strip_prefix(path) {
+                Ok(rest) if !rest.as_os_str().is_empty() => new_path.join(rest),
+                Ok(_) => new_path.clone(),
+                Err(_) => dir,
+            })
+            .collect();
+        self.file_explorer.borrow().expand_directories(&expanded);
+
+        let moved = self.documents.borrow_mut().rename(&old_key, &new_path);
+        for (editor, new_file) in moved {
+            editor.set_file_path(new_file);
+            self.language_servers.attach(&editor);
+        }
+        self.highlight_current_file();
+        Ok(())
+    }
+
+    /// Delete `path` through the file explorer. Tabs of deleted files close unless they have
+    /// unsaved changes; those stay open, flagged as deleted, so they can be saved again.
+    pub fn delete_path(&self, path: &Path) -> std::io::Result<()> {
+        let key = canonical_key(path);
+        let expanded = self.file_explorer.borrow().expanded_directories();
+        self.file_explorer.borrow().delete_file(path)?;
+        self.file_explorer.borrow().expand_directories(&expanded);
+
+        let affected = self.documents.borrow().under(&key);
+        for editor in affected {
+            if *editor.dirty.borrow() {
+                editor.check_disk();
+            } else {
+                for view in self.views_of(&editor) {
+                    self.remove_editor(&view);
+                }
+            }
+        }
+        self.highlight_current_file();
+        Ok(())
+    }
+
+    /// Make the replacements checked in the Find in Files panel, after confirmation. Open
+    /// files change in their tabs, where they can be undone and still need saving; the rest
+    /// are rewritten on disk atomically. Ends with a summary of the changed files.
+    pub fn replace_in_files(self: &Rc<Self>, files: Vec<FileReplacements>) {
+        let count: usize = files.iter().map(|file| file.replacements.len()).sum();
+        let dialog = MessageDialog::new(
+            Some(&self.window),
+            gtk4::DialogFlags::MODAL,
+            MessageType::Question,
+            ButtonsType::OkCancel,
+            format!("Replace {} occurrences in {} files?", count, files.len()),
+        );
+        dialog.set_secondary_text(Some(
+            "Open files are changed in their tabs and can be undone there. Other files are saved right away.",
+        ));
+
+        let workspace = self.clone();
+        dialog.connect_response(move |dialog, response| {
+            dialog.close();
+            if response == ResponseType::Ok {
+                workspace.apply_file_replacements(&files);
+            }
+        });
+        dialog.show();
+    }
+
+    fn apply_file_replacements(self: &Rc<Self>, files: &[FileReplacements]) {
+        let (mut changed, mut failed, mut replaced) = (Vec::new(), Vec::new(), 0);
+        for file in files {
+            let open = self.documents.borrow().get(&file.path);
+            let result = match open {
+                Some(editor) if editor.apply_replacements(&file.replacements) => Ok(()),
+                Some(_) => Err(ReplaceError::Changed),
+                None => find_in_files::replace_in_file(&file.path, &file.replacements),
+            };
+            match result {
+                Ok(()) => {
+                    replaced += file.replacements.len();
+                    changed.push(format!("{} ({})", self.shown_path(&file.path), file.replacements.len()));
+                }
+                Err(e) => failed.push(format!("{}: {}", self.shown_path(&file.path), e)),
+            }
+        }
+
+        // Long lists are cut short; the results panel has the details
+        const LISTED: usize = 15;
+        let list = |lines: &[String]| {
+            let mut text = lines.iter().take(LISTED).cloned().collect::<Vec<_>>().join("\n");
+            if lines.len() > LISTED {
+                text.push_str(&format!("\n…and {} more", lines.len() - LISTED));
+            }
+            text
+        };
+        let mut detail = list(&changed);
+        if !failed.is_empty() {
+            detail.push_str(&format!("\n\nNot changed:\n{}", list(&failed)));
+        }
+        let dialog = MessageDialog::new(
+            Some(&self.window),
+            gtk4::DialogFlags::MODAL,
+            if failed.is_empty() { MessageType::Info } else { MessageType::Warning },
+            ButtonsType::Close,
+            format!("Replaced {} occurrences in {} files", replaced, changed.len()),
+        );
+        dialog.set_secondary_text(Some(detail.trim_start()));
+        dialog.connect_response(|dialog, _| dialog.close());
+        dialog.show();
+
+        // What's left to replace, if anything
+        self.find_in_files.start();
+    }
+
+    /// `path` relative to the explorer's folder if it's inside it, for messages.
+    fn shown_path(&self, path: &Path) -> String {
+        let root = self.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+        let relative = root.as_deref().and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        relative.display().to_string()
+    }
+
+    fn highlight_current_file(&self) {
+        let path = self
+            .current_editor
+            .borrow()
+            .as_ref()
+            .and_then(|editor| editor.current_file.borrow().clone());
+        if let Some(path) = path {
+            self.file_explorer.borrow().highlight_file(&path);
+        }
+    }
+
+    fn open_file_unchecked(self: &Rc<Self>, path: &Path, size: u64) -> Option<Rc<Editor>> {
+        let theme = self.current_theme.borrow().clone();
+        let (content, format) = match file_format::read_file(path) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+                show_error(&self.window, &format!("Could not open '{}'", name), &e.to_string());
+                return None;
+            }
+        };
+
+        let title = path.file_name().and_then(|n| n.to_str()).unwrap_or("Untitled");
+        let threshold = self.settings.borrow().large_file_threshold_bytes;
+        let editor = if size >= threshold {
+            let editor = Editor::new(title, None, Some(path.to_path_buf()), self.ss.clone(), theme);
+            editor.set_format(format);
+            self.add_editor(editor.clone());
+            editor.load_large_file(Rope::from_str(&content));
+            self.status_bar.show(&editor.update());
+            editor
+        } else {
+            let editor = Editor::new(title, Some(content), Some(path.to_path_buf()), self.ss.clone(), theme);
+            editor.set_format(format);
+            self.add_editor(editor.clone());
+            self.language_servers.attach(&editor);
+            editor.set_diagnostics(self.diagnostics.borrow().for_file(&canonical_key(path)));
+            editor
+        };
+
+        // Highlight the file in the explorer
+        self.file_explorer.borrow().highlight_file(path);
+        Some(editor)
+    }
+
+    /// Replace what `origin` (a language server) reported for `path`.
+    pub fn set_diagnostics(&self, path: &Path, origin: &str, diagnostics: Vec<Diagnostic>) {
+        let key = canonical_key(path);
+        self.diagnostics.borrow_mut().set(&key, origin, diagnostics);
+        self.refresh_diagnostics(&[key]);
+    }
+
+    /// Replace everything `origin` (`cargo check`) reported with `files`.
+    pub fn replace_diagnostics(&self, origin: &str, files: BTreeMap<PathBuf, Vec<Diagnostic>>) {
+        let files = files.into_iter().map(|(path, diagnostics)| (canonical_key(&path), diagnostics)).collect();
+        let changed = self.diagnostics.borrow_mut().replace_origin(origin, files);
+        self.refresh_diagnostics(&changed);
+    }
+
+    /// Run `cargo check` in the Cargo project of the current file, else of the explorer
+    /// folder, and list its problems.
+    pub fn run_cargo_check(self: &Rc<Self>) {
+        let current = self.current_editor.borrow().as_ref().and_then(|editor| editor.current_file.borrow().clone());
+        let explorer_root = self.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+        let markers = ["Cargo.toml".to_string()];
+        let root = current
+            .map(|path| lsp::find_root(&path, &markers))
+            .filter(|root| root.join("Cargo.toml").is_file())
+            .or(explorer_root);
+        self.show_panel(&self.problems.widget);
+        let Some(root) = root else {
+            self.problems.set_check_status("Open a file or folder of a Cargo project to check it");
+            return;
+        };
+        let workspace_weak = Rc::downgrade(self);
+        self.problems.check(root, move |files| {
+            if let Some(workspace) = workspace_weak.upgrade() {
+                workspace.replace_diagnostics("cargo", files);
+            }
+        });
+    }
+
+    /// Show the diagnostics of the open documents among `paths`, and list all of them.
+    fn refresh_diagnostics(&self, paths: &[PathBuf]) {
+        let store = self.diagnostics.borrow();
+        for path in paths {
+            if let Some(editor) = self.documents.borrow().get(path) {
+                editor.set_diagnostics(store.for_file(path));
+            }
+        }
+        let root = self.file_explorer.borrow().root_directory().map(canonical_key);
+        self.problems.update(&store, root.as_deref());
+    }
+
+    /// Jump to the definition of the symbol at char `offset` of `editor`, as its language
+    /// server finds it, else the symbol index. When there are several, they're listed in the
+    /// References panel to pick from.
+    pub fn go_to_definition(self: &Rc<Self>, editor: &Rc<Editor>, offset: usize) {
+        let Some(name) = identifier_at(editor, offset) else {
+            return;
+        };
+        let show = {
+            let name = name.clone();
+            move |workspace: &Rc<Self>, files| workspace.show_definitions(&name, files)
+        };
+        if self.request_locations(editor, "textDocument/definition", "definitionProvider", offset, None, show) {
+            return;
+        }
+        let file = editor.current_file.borrow().clone();
+        let workspace_weak = Rc::downgrade(self);
+        self.symbol_indexer.with_index(file.as_deref(), move |index| {
+            if let Some(workspace) = workspace_weak.upgrade() {
+                workspace.show_definitions(&name, symbol_matches(index.definitions(&name)));
+            }
+        });
+    }
+
+    /// List the references to the symbol at char `offset` of `editor` in the References
+    /// panel, as its language server finds them, else every whole-word occurrence of its name
+    /// in the folder the symbol index would cover.
+    pub fn find_references(self: &Rc<Self>, editor: &Rc<Editor>, offset: usize) {
+        let Some(name) = identifier_at(editor, offset) else {
+            return;
+        };
+        self.show_panel(&self.references.widget);
+        let generation = self.references.start(&format!("Finding references to '{}'…", name));
+        let title = format!("References to '{}'", name);
+        let context = json!({ "includeDeclaration": true });
+        let show = move |workspace: &Rc<Self>, files| {
+            let root = workspace.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+            workspace.references.show(generation, &title, files, root.as_deref());
+        };
+        if self.request_locations(editor, "textDocument/references", "referencesProvider", offset, Some(context), show) {
+            return;
+        }
+        let file = editor.current_file.borrow().clone();
+        match self.symbol_indexer.root_for(file.as_deref()) {
+            Some(root) => self.references.search_word(root, &name, self.settings.borrow().clone()),
+            None => self.references.show(generation, &format!("Occurrences of '{}'", name), Vec::new(), None),
+        }
+    }
+
+    /// Ask `editor`'s language server for the locations `method` gives for char `offset`, and
+    /// call `done` with them as matches by file. Returns false without asking if there's no
+    /// server or it doesn't have `capability` (yet).
+    fn request_locations(
+        self: &Rc<Self>,
+        editor: &Editor,
+        method: &str,
+        capability: &str,
+        offset: usize,
+        context: Option<Value>,
+        done: impl FnOnce(&Rc<Self>, Vec<FileMatches>) + 'static,
+    ) -> bool {
+        let Some(lsp) = editor.language_server() else {
+            return false;
+        };
+        if matches!(lsp.client.capabilities()[capability], Value::Null | Value::Bool(false)) {
+            return false;
+        }
+        let Some(text) = lsp.client.document_text(&lsp.uri) else {
+            return false;
+        };
+        let mut params = json!({
+            "textDocument": { "uri": lsp.uri },
+            "position": protocol::position_at(&text, offset, lsp.client.encoding()),
+        });
+        if let Some(context) = context {
+            params["context"] = context;
+        }
+        let workspace_weak = Rc::downgrade(self);
+        let client_weak = Rc::downgrade(&lsp.client);
+        lsp.client.request(method, params, move |result| {
+            let (Some(workspace), Some(client)) = (workspace_weak.upgrade(), client_weak.upgrade()) else {
+                return;
+            };
+            let locations = result.map(|result| protocol::locations(&result)).unwrap_or_default();
+            done(&workspace, location_matches(&client, locations));
+        });
+        true
+    }
+
+    /// Jump to the definition in `files` if there's one, or list them if there are several.
+    fn show_definitions(self: &Rc<Self>, name: &str, files: Vec<FileMatches>) {
+        let count: usize = files.iter().map(|file| file.matches.len()).sum();
+        let first = files.iter().find_map(|file| Some((file.path.clone(), file.matches.first()?.clone())));
+        match (count, first) {
+            (1, Some((path, m))) => self.open_file_at(path, m.line, m.start_column),
+            (0, _) | (_, None) => {
+                self.status_bar.status_info_label.set_text(&format!("No definition found for '{}'", name));
+            }
+            _ => {
+                let root = self.file_explorer.borrow().root_directory().map(Path::to_path_buf);
+                let generation = self.references.start("");
+                self.references.show(generation, &format!("Definitions of '{}'", name), files, root.as_deref());
+                self.show_panel(&self.references.widget);
+            }
+        }
+    }
+
+    /// Rename the symbol at char `offset` of `editor` and its references, as its language
+    /// server works them out, else every whole-word occurrence of its name in the folder the
+    /// symbol index would cover. The changes are previewed before any is made.
+    pub fn rename_symbol(self: &Rc<Self>, editor: &Rc<Editor>, offset: usize) {
+        let Some(name) = identifier_at(editor, offset) else {
+            self.status_bar.status_info_label.set_text("No symbol to rename at the cursor");
+            return;
+        };
+        let workspace_weak = Rc::downgrade(self);
+        let editor_weak = Rc::downgrade(editor);
+        let old_name = name.clone();
+        rename::ask_new_name(self, &name, move |new_name| {
+            if let (Some(workspace), Some(editor)) = (workspace_weak.upgrade(), editor_weak.upgrade()) {
+                workspace.request_rename(&editor, offset, &old_name, new_name);
+            }
+        });
+    }
+
+    fn request_rename(self: &Rc<Self>, editor: &Rc<Editor>, offset: usize, name: &str, new_name: String) {
+        let title = format!("Rename '{}' to '{}'", name, new_name);
+        if let Some(lsp) = editor.language_server() {
+            let text = lsp.client.document_text(&lsp.uri);
+            let capable = !matches!(lsp.client.capabilities()["renameProvider"], Value::Null | Value::Bool(false));
+            if let (Some(text), true) = (text, capable) {
+                let encoding = lsp.client.encoding();
+                let params = json!({
+                    "textDocument": { "uri": lsp.uri },
+                    "position": protocol::position_at(&text, offset, encoding),
+                    "newName": new_name,
+                });
+                let workspace_weak = Rc::downgrade(self);
+                let name = name.to_string();
+                lsp.client.request("textDocument/rename", params, move |result| {
+                    let Some(workspace) = workspace_weak.upgrade() else {
+                        return;
+                    };
+                    match result {
+                        Ok(edit) => workspace.preview_edit(&title, protocol::workspace_edit(&edit), encoding),
+                        Err(e) => show_error(&workspace.window, &format!("Could not rename '{}'", name), &e.message),
+                    }
+                });
+                return;
+            }
+        }
+
+        let file = editor.current_file.borrow().clone();
+        let Some(root) = self.symbol_indexer.root_for(file.as_deref()) else {
+            let message = "Save the file or open a folder to rename without a language server";
+            self.status_bar.status_info_label.set_text(message);
+            return;
+        };
+        let options = SearchOptions { case_sensitive: true, whole_word: true, ..SearchOptions::default() };
+        let search = match Search::new(name, options) {
+            Ok(search) => search,
+            Err(e) => {
+                show_error(&self.window, &format!("Could not rename '{}'", name), &e.to_string());
+                return;
+            }
+        };
+        let workspace_weak = Rc::downgrade(self);
+        let settings = self.settings.borrow().clone();
+        let name_clone = name.to_string();
+        let searched = find_word(root.clone(), name, settings, Arc::new(AtomicBool::new(false)), move |files, truncated| {
+            let Some(workspace) = workspace_weak.upgrade() else {
+                return;
+            };
+            // Renaming only the occurrences found would leave the rest behind
+            if truncated {
+                let detail = format!(
+                    "It occurs more than {} times in the folder; rename it with a language server.",
+                    find_in_files::MAX_MATCHES
+                );
+                show_error(&workspace.window, &format!("Could not rename '{}'", name_clone), &detail);
+                return;
+            }
+            // Open files are renamed in their buffers, which may differ from the disk
+            let open = workspace.documents.borrow().under(&canonical_key(&root));
+            let mut files: Vec<FileMatches> =
+                files.into_iter().filter(|file| workspace.documents.borrow().get(&file.path).is_none()).collect();
+            for editor in open {
+                if let Some(path) = editor.current_file.borrow().clone() {
+                    let matches = find_in_files::search_text(&search, &editor.rope().to_string());
+                    if !matches.is_empty() {
+                        files.push(FileMatches { path, matches });
+                    }
+                }
+            }
+            let edits = files
+                .into_iter()
+                .map(|file| (file.path, file.matches.iter().map(|m| word_edit(m, &new_name)).collect()))
+                .collect();
+            // Match columns count chars, as UTF-32 positions do
+            workspace.preview_edit(&title, edits, PositionEncoding::Utf32);
+        });
+        if let Err(e) = searched {
+            show_error(&self.window, &format!("Could not rename '{}'", name), &e.to_string());
+        }
+    }
+
+    /// Show the changes `files` would get under `title`, and make the ones chosen.
+    fn preview_edit(self: &Rc<Self>, title: &str, files: Vec<(PathBuf, Vec<TextEdit>)>, encoding: PositionEncoding) {
+        let (changes, failed) = self.file_changes(files, encoding);
+        if changes.is_empty() {
+            if failed.is_empty() {
+                self.status_bar.status_info_label.set_text(&format!("{}: nothing to change", title));
+            } else {
+                show_error(&self.window, &format!("{}: no file could be changed", title), &failed.join("\n"));
+            }
+            return;
+        }
+        let workspace_weak = Rc::downgrade(self);
+        let shown_title = title.to_string();
+        rename::show_preview(self, title, changes, failed, move |chosen| {
+            if let Some(workspace) = workspace_weak.upgrade() {
+                workspace.finish_edit(&shown_title, &chosen);
+            }
+        });
+    }
+
+    /// Make the edits of the language server's workspace `edit`, named `title`, as a whole:
+    /// nothing is changed if the edits of any file can't be worked out. Returns whether
+    /// they were made.
+    pub fn apply_workspace_edit(&self, title: &str, edit: &Value, encoding: PositionEncoding) -> bool {
+        let (changes, failed) = self.file_changes(protocol::workspace_edit(edit), encoding);
+        if !failed.is_empty() {
+            show_error(&self.window, &format!("Could not apply '{}'", title), &failed.join("\n"));
+            return false;
+        }
+        let chosen: Vec<(FileChanges, Vec<Edit>)> = changes
+            .into_iter()
+            .map(|file| {
+                let edits = file.chosen_edits(|_| true);
+                (file, edits)
+            })
+            .collect();
+        self.finish_edit(title, &chosen)
+    }
+
+    /// Make the edits `chosen` for each file and report how it went. Returns whether all
+    /// of them were made.
+    fn finish_edit(&self, title: &str, chosen: &[(FileChanges, Vec<Edit>)]) -> bool {
+        let failed = self.apply_edits(chosen);
+        if !failed.is_empty() {
+            show_error(&self.window, &format!("{}: some files were not changed", title), &failed.join("\n"));
+            return false;
+        }
+        let count: usize = chosen.iter().map(|(_, edits)| edits.len()).sum();
+        self.status_bar.status_info_label.set_text(&format!("{}: {} changes in {} files", title, count, chosen.len()));
+        true
+    }
+
+    /// The changes of `files` against their text now: the buffer of an open file, else the
+    /// file on disk. Files whose edits can't be worked out are listed with why instead.
+    fn file_changes(
+        &self,
+        files: Vec<(PathBuf, Vec<TextEdit>)>,
+        encoding: PositionEncoding,
+    ) -> (Vec<FileChanges>, Vec<String>) {
+        let (mut changes, mut failed) = (Vec::new(), Vec::new());
+        for (path, edits) in files {
+            let open = self.documents.borrow().get(&path);
+            let text = match open {
+                Some(editor) => editor.rope(),
+                None => match file_format::read_file(&path) {
+                    Ok((text, _)) => Rope::from_str(&text),
+                    Err(e) => {
+                        failed.push(format!("{}: {}", self.shown_path(&path), e));
+                        continue;
+                    }
+                },
+            };
+            let shown = self.shown_path(&path);
+            match FileChanges::new(path, text, &edits, encoding) {
+                Some(file) => changes.push(file),
+                None => failed.push(format!("{}: {}", shown, EditError::Overlapping)),
+            }
+        }
+        (changes, failed)
+    }
+
+    /// Make the `edits` of each file: in the tab of an open one, where they can be undone,
+    /// else in the file on disk. Returns the files left unchanged, with why.
+    fn apply_edits(&self, files: &[(FileChanges, Vec<Edit>)]) -> Vec<String> {
+        let mut failed = Vec::new();
+        for (file, edits) in files {
+            let open = self.documents.borrow().get(&file.path);
+            let result = match open {
+                Some(editor) if editor.rope() == file.text => {
+                    editor.apply_edits(edits);
+                    Ok(())
+                }
+                Some(_) => Err(EditError::Changed),
+                None => workspace_edit::write_file(&file.path, &file.text, edits),
+            };
+            if let Err(e) = result {
+                failed.push(format!("{}: {}", self.shown_path(&file.path), e));
+            }
+        }
+        failed
+    }
+
+    /// Run the code action `action` chosen in `editor`: resolve its edit if the server left
+    /// that for later, make the edit, then run its command.
+    pub fn run_code_action(self: &Rc<Self>, editor: &Rc<Editor>, action: CodeAction) {
+        let Some(lsp) = editor.language_server() else {
+            return;
+        };
+        let resolvable = lsp.client.capabilities()["codeActionProvider"]["resolveProvider"] == Value::Bool(true);
+        if action.edit.is_none() && action.raw.get("data").is_some() && resolvable {
+            let workspace_weak = Rc::downgrade(self);
+            let editor_weak = Rc::downgrade(editor);
+            lsp.client.request("codeAction/resolve", action.raw.clone(), move |result| {
+                let (Some(workspace), Some(editor)) = (workspace_weak.upgrade(), editor_weak.upgrade()) else {
+                    return;
+                };
+                // Without a resolved edit there may still be a command to run
+                let resolved = result.ok().and_then(|resolved| protocol::code_actions(&json!([resolved])).pop());
+                workspace.finish_code_action(&editor, resolved.unwrap_or(action));
+            });
+            return;
+        }
+        self.finish_code_action(editor, action);
+    }
+
+    fn finish_code_action(&self, editor: &Editor, action: CodeAction) {
+        let Some(lsp) = editor.language_server() else {
+            return;
+        };
+        if let Some(edit) = &action.edit {
+            if !self.apply_workspace_edit(&action.title, edit, lsp.client.encoding()) {
+                return;
+            }
+        }
+        let Some(command) = &action.command else {
+            return;
+        };
+        let mut params = json!({ "command": command["command"] });
+        if let Some(arguments) = command.get("arguments") {
+            params["arguments"] = arguments.clone();
+        }
+        let title = action.title;
+        lsp.client.request("workspace/executeCommand", params, move |result| {
+            if let Err(e) = result {
+                eprintln!("Warning: code action '{}' failed: {}", title, e.message);
+            }
+        });
+    }
+
+    /// Open editors in the order their tabs appear, group by group.
+    pub fn editors_in_tab_order(&self) -> Vec<Rc<Editor>> {
+        let editors = self.editors.borrow();
+        self.groups
+            .notebooks()
+            .iter()
+            .flat_map(|notebook| (0..notebook.n_pages()).filter_map(|page| notebook.nth_page(Some(page))))
+            .filter_map(|page| {
+                editors
+                    .iter()
+                    .find(|e| e.content_row().upcast_ref::<gtk4::Widget>() == &page)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// The first tab of each open document, in tab order; other panes of a document are
+    /// left out.
+    pub fn documents_in_tab_order(&self) -> Vec<Rc<Editor>> {
+        let mut documents: Vec<Rc<Editor>> = Vec::new();
+        for editor in self.editors_in_tab_order() {
+            if !documents.iter().any(|d| d.shares_document(&editor)) {
+                documents.push(editor);
+            }
+        }
+        documents
+    }
+
+    /// Save the window layout and open tabs. Unsaved untitled buffers are kept as snapshots;
+    /// empty untitled tabs are not worth restoring.
+    pub fn save_session(&self) {
+        let (window_width, window_height) = self.window.default_size();
+        let current = self.current_editor.borrow().clone();
+        let explorer = self.file_explorer.borrow();
+
+        let mut session = Session {
+            window_width,
+            window_height,
+            maximized: self.window.is_maximized(),
+            paned_position: Some(self.paned.position()),
+            explorer_root: explorer.root_directory().map(Path::to_path_buf),
+            expanded_dirs: explorer.expanded_directories(),
+            active_tab: 0,
+            tabs: Vec::new(),
+            recent_commands: self.commands.borrow().recent().to_vec(),
+        };
+
+        for editor in self.documents_in_tab_order() {
+            let path = editor.current_file.borrow().clone();
+            let snapshot_text = match &path {
+                None if *editor.dirty.borrow() => Some(editor.get_text()).filter(|text| !text.is_empty()),
+                _ => None,
+            };
+            if path.is_none() && snapshot_text.is_none() {
+                continue;
+            }
+
+            if current.as_ref().is_some_and(|current| current.shares_document(&editor)) {
+                session.active_tab = session.tabs.len();
+            }
+            session.tabs.push(TabSession {
+                path,
+                snapshot: None,
+                snapshot_text,
+                cursor_offset: editor.cursor_offset(),
+                scroll_line: editor.scroll_line(),
+                syntax_override: editor.syntax_override.borrow().clone(),
+            });
+        }
+
+        if let Err(e) = session.save() {
+            eprintln!("Failed to save session: {}", e);
+        }
+    }
+
+    /// Restore the previous session's layout and tabs. Returns false if no tab was restored.
+    ///
+    /// Must run after the explorer's row-expanded handler is connected, since expanding a
+    /// folder is what loads its children.
+    pub fn restore_session(self: &Rc<Self>) -> bool {
+        let session = Session::load();
+        self.commands.borrow_mut().set_recent(session.recent_commands.clone());
+
+        self.window.set_default_size(session.window_width, session.window_height);
+        if session.maximized {
+            self.window.maximize();
+        }
+        if let Some(position) = session.paned_position {
+            self.paned.set_position(position);
+        }
+        if let Some(root) = session.explorer_root.filter(|root| root.is_dir()) {
+            self.file_explorer.borrow_mut().set_root_directory(root);
+        }
+        self.file_explorer.borrow().expand_directories(&session.expanded_dirs);
+
+        let mut active = None;
+        for (index, tab) in session.tabs.iter().enumerate() {
+            let editor = if let Some(path) = &tab.path {
+                if self.documents.borrow().get(path).is_some() {
+                    continue;
+                }
+                // Files confirmed as large last time open without asking again
+                let Ok(meta) = std::fs::metadata(path) else {
+                    continue;
+                };
+                match self.open_file_unchecked(path, meta.len()) {
+                    Some(editor) => editor,
+                    None => continue,
+                }
+            } else if let Some(text) = &tab.snapshot_text {
+                let theme = self.current_theme.borrow().clone();
+                let editor = Editor::new("Untitled", Some(text.clone()), None, self.ss.clone(), theme);
+                self.add_editor(editor.clone());
+                editor.mark_dirty();
+                editor
+            } else {
+                continue;
+            };
+
+            if tab.syntax_override.is_some() {
+                editor.set_syntax_override(tab.syntax_override.clone());
+            }
+            // Large files are still streaming in, so the position may not exist yet
+            if !*editor.large_file.borrow() {
+                editor.restore_position(tab.cursor_offset, tab.scroll_line);
+            }
+            if index == session.active_tab || active.is_none() {
+                active = Some(editor);
+            }
+        }
+
+        let Some(editor) = active else {
+            return false;
+        };
+        self.focus_editor(&editor);
+        // Opening the tabs isn't something to go back through
+        self.history.borrow_mut().clear();
+        true
+    }
+
+    /// Switch the UI CSS and the syntax theme of every open editor.
+    pub fn apply_theme(&self, mode: ThemeMode) {
+        crate::load_css(mode);
+
+        // Load new syntax highlighting theme
+        let ts = ThemeSet::load_defaults();
+        let theme_name = mode.syntax_theme_name();
+        let new_theme = if let Some(theme) = ts.themes.get(theme_name) {
+            Arc::new(theme.clone())
+        } else {
+            // Fallback to first available theme if the named theme doesn't exist
+            eprintln!("Warning: Theme '{}' not found, using fallback", theme_name);
+            Arc::new(ts.themes.values().next().unwrap().clone())
+        };
+        *self.current_theme.borrow_mut() = new_theme.clone();
+
+        // Update all open documents with the new theme
+        for editor in self.documents_in_tab_order() {
+            editor.set_theme(new_theme.clone());
+        }
+    }
+
+    /// Make `settings` current and apply whatever changed to the open UI.
+    pub fn apply_settings(&self, settings: Settings) {
+        let theme_changed = settings.theme != self.settings.borrow().theme;
+        *self.settings.borrow_mut() = settings.clone();
+
+        if theme_changed {
+            self.apply_theme(settings.theme);
+        }
+        crate::load_font_css(&settings);
+        for editor in self.editors.borrow().iter() {
+            editor.apply_settings(&settings);
+        }
+        // Files of languages whose server was just configured (or turned off) move over
+        for editor in self.documents_in_tab_order() {
+            self.language_servers.attach(&editor);
+        }
+        self.file_explorer.borrow_mut().apply_settings(&settings);
+    }
+
+    /// Reload the settings whenever the file changes on disk. Invalid files are reported and
+    /// ignored so a half-typed edit doesn't reset everything to defaults.
+    pub fn watch_settings(self: &Rc<Self>) {
+        let Some(path) = Settings::path() else {
+            return;
+        };
+        // The monitor needs an existing directory to notice the file being created
+        if let Some(dir) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                eprintln!("Failed to create {}: {}", dir.display(), e);
+            }
+        }
+
+        let monitor = match gio::File::for_path(&path).monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE) {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Failed to watch {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let workspace = Rc::downgrade(self);
+        monitor.connect_changed(move |_, _, _, event| {
+            if !matches!(event, gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::Created) {
+                return;
+            }
+            let Some(workspace) = workspace.upgrade() else {
+                return;
+            };
+            match Settings::load_from(&path) {
+                Ok(settings) if settings != *workspace.settings.borrow() => workspace.apply_settings(settings),
+                Ok(_) => {}
+                Err(e) => eprintln!("Warning: {} ({}), keeping current settings", e, path.display()),
+            }
+        });
+
+        *self.settings_monitor.borrow_mut() = Some(monitor);
+    }
+}
+
+fn show_error(window: &ApplicationWindow, message: &str, detail: &str) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Error,
+        ButtonsType::Close,
+        message,
+    );
+    dialog.set_secondary_text(Some(detail));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+fn save_reporting_errors(window: &ApplicationWindow, editor: &Rc<Editor>, path: &Path) -> bool {
+    match editor.save_to_path(&path.to_path_buf()) {
+        Ok(()) => true,
+        Err(e) => {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+            show_error(
+                window,
+                &format!("Could not save '{}'", name),
+                &format!("{}\n\nYour changes are still in the editor.", e),
+            );
+            false
+        }
+    }
+}
+
+/// Save `editor` to its own `path`, asking first if another program changed the file since
+/// it was loaded, so those changes aren't overwritten by accident.
+fn save_checked(window: &ApplicationWindow, editor: &Rc<Editor>, path: &Path, done: impl FnOnce(bool) + 'static) {
+    if !editor.changed_on_disk() {
+        done(save_reporting_errors(window, editor, path));
+        return;
+    }
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::None,
+        format!("'{}' was changed by another program since it was opened.", editor.display_name()),
+    );
+    dialog.set_secondary_text(Some("Saving will overwrite those changes."));
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Overwrite", ResponseType::Accept);
+
+    let window = window.clone();
+    let editor = editor.clone();
+    let path = path.to_path_buf();
+    let done = Cell::new(Some(done));
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        let saved = response == ResponseType::Accept && save_reporting_errors(&window, &editor, &path);
+        if let Some(done) = done.take() {
+            done(saved);
+        }
+    });
+
+    dialog.show();
+}
+
+/// The identifier at char `offset` of `editor`'s text.
+fn identifier_at(editor: &Editor, offset: usize) -> Option<String> {
+    let rope = editor.rope();
+    let (start, end) = symbols::identifier_at(&rope, offset)?;
+    Some(rope.slice(start..end).to_string())
+}
+
+/// Replacement of the word match `m` with `new_name`, positioned in UTF-32.
+fn word_edit(m: &LineMatch, new_name: &str) -> TextEdit {
+    let position = |column: usize| Position { line: m.line as u32, character: column as u32 };
+    TextEdit {
+        range: Range { start: position(m.start_column), end: position(m.end_column) },
+        new_text: new_name.to_string(),
+    }
+}
+
+/// A server's `locations` as matches by file, each previewing its line. Columns are
+/// converted through the text the server knows: the open document, else the file on disk.
+fn location_matches(client: &LspClient, locations: Vec<(PathBuf, Range)>) -> Vec<FileMatches> {
+    let mut by_file: BTreeMap<PathBuf, Vec<Range>> = BTreeMap::new();
+    for (path, range) in locations {
+        by_file.entry(path).or_default().push(range);
+    }
+    let encoding = client.encoding();
+    by_file
+        .into_iter()
+        .filter_map(|(path, mut ranges)| {
+            let text = client
+                .document_text(&protocol::file_uri(&path))
+                .or_else(|| file_format::read_file(&path).ok().map(|(text, _)| Rope::from_str(&text)))?;
+            ranges.sort_by_key(|range| range.start);
+            let matches = ranges
+                .iter()
+                .filter(|range| (range.start.line as usize) < text.len_lines())
+                .map(|range| {
+                    let line = range.start.line as usize;
+                    let line_start = text.line_to_char(line);
+                    let start = protocol::char_at(&text, range.start, encoding) - line_start;
+                    // A range running onto other lines is shown to the end of its first
+                    let end = if range.end.line == range.start.line {
+                        protocol::char_at(&text, range.end, encoding) - line_start
+                    } else {
+                        usize::MAX
+                    };
+                    let line_text = text.line(line).to_string();
+                    LineMatch::new(line, line_text.trim_end_matches(['\n', '\r']), start, end)
+                })
+                .collect();
+            Some(FileMatches { path, matches })
+        })
+        .collect()
+}
+
+/// Definitions from the symbol index as matches by file, previewing their defining lines.
+fn symbol_matches(symbols: &[Symbol]) -> Vec<FileMatches> {
+    let mut files: Vec<FileMatches> = Vec::new();
+    for symbol in symbols {
+        let length = symbol.name.chars().count();
+        let at = symbol.signature.find(&symbol.name).map_or(0, |i| symbol.signature[..i].chars().count());
+        let m = LineMatch {
+            start_column: symbol.column,
+            end_column: symbol.column + length,
+            ..LineMatch::new(symbol.line, &symbol.signature, at, at + length)
+        };
+        match files.iter_mut().find(|file| file.path == symbol.path) {
+            Some(file) => file.matches.push(m),
+            None => files.push(FileMatches { path: symbol.path.clone(), matches: vec![m] }),
+        }
+    }
+    files
+}
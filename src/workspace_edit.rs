@@ -0,0 +1,158 @@
+//! Edits across files, such as a language server's rename: resolved against the text of each
+//! file, previewed as changed lines, then made in open buffers or in the files on disk.
+
+use std::path::{Path, PathBuf};
+
+use ropey::Rope;
+use thiserror::Error;
+
+use crate::file_format::{self, UnencodableChar};
+use crate::lsp::protocol::{self, PositionEncoding, TextEdit};
+
+/// A replacement of chars `start..end` with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// `edits` as char offsets in `text`, in order, or `None` if any of them overlap. Insertions
+/// at the same place keep the order they were given in.
+pub fn resolve(text: &Rope, edits: &[TextEdit], encoding: PositionEncoding) -> Option<Vec<Edit>> {
+    let mut resolved: Vec<Edit> = edits
+        .iter()
+        .map(|edit| {
+            let start = protocol::char_at(text, edit.range.start, encoding);
+            let end = protocol::char_at(text, edit.range.end, encoding).max(start);
+            // Buffers and decoded files use `\n` line endings only
+            Edit { start, end, text: edit.new_text.replace("\r\n", "\n") }
+        })
+        .collect();
+    resolved.sort_by_key(|edit| edit.start);
+    if resolved.windows(2).any(|pair| pair[0].end > pair[1].start) {
+        return None;
+    }
+    Some(resolved)
+}
+
+/// `text` with `edits`, in order and not overlapping, made.
+pub fn apply(text: &Rope, edits: &[Edit]) -> Rope {
+    let mut edited = text.clone();
+    // Back to front, so the offsets of the edits still to go stay valid
+    for edit in edits.iter().rev() {
+        edited.remove(edit.start..edit.end);
+        edited.insert(edit.start, &edit.text);
+    }
+    edited
+}
+
+/// Edits on the same or neighbouring lines, previewed and chosen together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// First line the edits touch, zero-based
+    pub line: usize,
+    /// The lines the edits touch, before and after them, without the last line break
+    pub before: String,
+    pub after: String,
+    /// Char ranges of `after` the edits inserted
+    pub inserted: Vec<(usize, usize)>,
+    pub edits: Vec<Edit>,
+}
+
+/// `edits` of `text`, in order and not overlapping, grouped by the lines they touch.
+pub fn hunks(text: &Rope, edits: &[Edit]) -> Vec<Hunk> {
+    let mut groups: Vec<(usize, usize, Vec<Edit>)> = Vec::new();
+    for edit in edits {
+        let (first, last) = (text.char_to_line(edit.start), text.char_to_line(edit.end));
+        match groups.last_mut() {
+            Some((_, group_last, group)) if first <= *group_last => {
+                *group_last = (*group_last).max(last);
+                group.push(edit.clone());
+            }
+            _ => groups.push((first, last, vec![edit.clone()])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(first, last, edits)| {
+            let (start, end) = (text.line_to_char(first), line_end(text, last));
+            let mut after = String::new();
+            let mut inserted = Vec::with_capacity(edits.len());
+            let mut after_chars = 0;
+            let mut copied = start;
+            for edit in &edits {
+                after.extend(text.slice(copied..edit.start).chars());
+                after_chars += edit.start - copied;
+                after.push_str(&edit.text);
+                let length = edit.text.chars().count();
+                inserted.push((after_chars, after_chars + length));
+                after_chars += length;
+                copied = edit.end;
+            }
+            after.extend(text.slice(copied..end).chars());
+            Hunk { line: first, before: text.slice(start..end).to_string(), after, inserted, edits }
+        })
+        .collect()
+}
+
+/// Char offset of the end of `line`'s text, before its line break.
+fn line_end(text: &Rope, line: usize) -> usize {
+    let slice = text.line(line);
+    let length = slice.len_chars();
+    let line_break = usize::from(length > 0 && slice.char(length - 1) == '\n');
+    text.line_to_char(line) + length - line_break
+}
+
+/// The edits to one file, as hunks of the text they were resolved against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChanges {
+    pub path: PathBuf,
+    pub text: Rope,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FileChanges {
+    /// `edits` of `path`, whose text is `text`; `None` if they overlap.
+    pub fn new(path: PathBuf, text: Rope, edits: &[TextEdit], encoding: PositionEncoding) -> Option<Self> {
+        let edits = resolve(&text, edits, encoding)?;
+        let hunks = hunks(&text, &edits);
+        Some(Self { path, text, hunks })
+    }
+
+    pub fn edit_count(&self) -> usize {
+        self.hunks.iter().map(|hunk| hunk.edits.len()).sum()
+    }
+
+    /// The edits of the hunks `chosen` picks, by index, in order.
+    pub fn chosen_edits(&self, chosen: impl Fn(usize) -> bool) -> Vec<Edit> {
+        let hunks = self.hunks.iter().enumerate().filter(|&(index, _)| chosen(index));
+        hunks.flat_map(|(_, hunk)| hunk.edits.iter().cloned()).collect()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EditError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("the file changed since the edit was computed")]
+    Changed,
+    #[error("the edits overlap")]
+    Overlapping,
+    #[error(transparent)]
+    Unencodable(#[from] UnencodableChar),
+}
+
+/// Make `edits` in the file at `path` on disk, keeping its encoding and line endings. The
+/// file is replaced atomically, and left alone if its text is no longer `expected`.
+pub fn write_file(path: &Path, expected: &Rope, edits: &[Edit]) -> Result<(), EditError> {
+    let (text, format) = file_format::read_file(path)?;
+    let text = Rope::from_str(&text);
+    if text != *expected {
+        return Err(EditError::Changed);
+    }
+    let edited = apply(&text, edits).to_string();
+    file_format::write_atomic(path, &file_format::encode(&edited, format)?)?;
+    Ok(())
+}